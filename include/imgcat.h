@@ -0,0 +1,35 @@
+/* C interface of the imgcat library, built with `cargo build --release --features ffi`. */
+#ifndef IMGCAT_H
+#define IMGCAT_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* How an image is displayed. Every field may be NULL or 0 for the default. */
+typedef struct imgcat_options {
+    /* width in the form of -W, e.g. "40", "250px", "50%" or "auto" */
+    const char *width;
+    /* height in the form of -H */
+    const char *height;
+    /* non-zero to stretch the image to the width and height */
+    int stretch;
+    /* "auto", "iterm2", "kitty", "sixel", "regis", "fbdev" or "blocks" */
+    const char *protocol;
+} imgcat_options;
+
+/* Display the image file at path on stdout. options may be NULL.
+ * Return 0 on success, -1 for invalid arguments, and the exit status of imgcat for the error otherwise. */
+int imgcat_display_file(const char *path, const imgcat_options *options);
+
+/* Display the image of len bytes at data on stdout. options may be NULL.
+ * Return 0 on success, -1 for invalid arguments such as a NULL data, and the exit status of imgcat for the error otherwise. */
+int imgcat_display_bytes(const unsigned char *data, size_t len, const imgcat_options *options);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,151 @@
+//! The colors of terminals without truecolor: the 256 colors of xterm and the 16 of ANSI,
+//! which the renderers drawing with text reduce images to.
+
+use std::sync::OnceLock;
+use crate::bitmap::Bitmap;
+use crate::terminal::ColorDepth;
+
+/// The 16 colors of ANSI as xterm shows them by default. Terminals let them be changed by
+/// themes, so they are only used where no other colors are available.
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0], [205, 0, 0], [0, 205, 0], [205, 205, 0],
+    [0, 0, 238], [205, 0, 205], [0, 205, 205], [229, 229, 229],
+    [127, 127, 127], [255, 0, 0], [0, 255, 0], [255, 255, 0],
+    [92, 92, 255], [255, 0, 255], [0, 255, 255], [255, 255, 255],
+];
+
+/// Levels of the channels of the 6x6x6 color cube of xterm, colors 16 to 231.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Colors of the palette of `depth` an image is reduced to, with their numbers. The 16
+/// colors of ANSI are left out of the 256 colors, as themes change them.
+fn palette(depth: ColorDepth) -> &'static [(u8, [u8; 3])] {
+    static COLORS_256: OnceLock<Vec<(u8, [u8; 3])>> = OnceLock::new();
+    static COLORS_16: OnceLock<Vec<(u8, [u8; 3])>> = OnceLock::new();
+    match depth {
+        ColorDepth::Colors16 => COLORS_16.get_or_init(|| (0..16).zip(ANSI_COLORS).collect()),
+        _ => COLORS_256.get_or_init(|| {
+            let cube = (0..216u8).map(|i| {
+                let rgb = [i / 36, i / 6 % 6, i % 6].map(|level| CUBE_LEVELS[level as usize]);
+                (16 + i, rgb)
+            });
+            let grays = (0..24u8).map(|i| (232 + i, [8 + 10 * i; 3]));
+            cube.chain(grays).collect()
+        }),
+    }
+}
+
+/// The number and color of the color of the palette of `depth` closest to `rgb`.
+fn nearest(depth: ColorDepth, rgb: [u8; 3]) -> (u8, [u8; 3]) {
+    let distance = |c: &[u8; 3]| (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum::<i32>();
+    palette(depth).iter().copied().min_by_key(|(_, c)| distance(c)).unwrap_or((0, [0; 3]))
+}
+
+/// Parameters of the SGR escape sequence setting the foreground color, or the background
+/// color when `background` is set, to `rgb` or the closest color the terminal shows.
+pub fn sgr(depth: ColorDepth, rgb: [u8; 3], background: bool) -> String {
+    let [r, g, b] = rgb;
+    match depth {
+        ColorDepth::Truecolor => format!("{};2;{r};{g};{b}", if background { 48 } else { 38 }),
+        ColorDepth::Colors256 => format!("{};5;{}", if background { 48 } else { 38 }, nearest(depth, rgb).0),
+        ColorDepth::Colors16 => {
+            let n = nearest(depth, rgb).0;
+            let base = match (background, n < 8) {
+                (false, true) => 30,
+                (false, false) => 90 - 8,
+                (true, true) => 40,
+                (true, false) => 100 - 8,
+            };
+            (base + n).to_string()
+        }
+    }
+}
+
+/// Reduce the opaque pixels of `bitmap` to the colors of the palette of `depth`, diffusing
+/// the error of each pixel to its neighbors with Floyd-Steinberg dithering, so that
+/// gradients come out as patterns of the colors around them rather than bands.
+pub fn dither(bitmap: &mut Bitmap, depth: ColorDepth) {
+    if depth == ColorDepth::Truecolor {
+        return;
+    }
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    let opaque = |pixels: &[u8], i: usize| pixels[i * 4 + 3] >= 128;
+    let mut colors: Vec<[f32; 3]> = bitmap.pixels.chunks(4).map(|p| [p[0], p[1], p[2]].map(f32::from)).collect();
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if !opaque(&bitmap.pixels, i) {
+                continue;
+            }
+            let wanted = colors[i].map(|c| c.round().clamp(0.0, 255.0) as u8);
+            let (_, got) = nearest(depth, wanted);
+            bitmap.pixels[i * 4..i * 4 + 3].copy_from_slice(&got);
+            let error: [f32; 3] = std::array::from_fn(|c| colors[i][c] - got[c] as f32);
+            // 透明的像素不显示，不把误差扩散给它们。
+            let neighbors = [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+            for (dx, dy, weight) in neighbors {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                let j = ny * width + nx as usize;
+                if opaque(&bitmap.pixels, j) {
+                    (0..3).for_each(|c| colors[j][c] += error[c] * weight / 16.0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sgr_of_each_depth() {
+        assert_eq!(sgr(ColorDepth::Truecolor, [1, 2, 3], false), "38;2;1;2;3");
+        assert_eq!(sgr(ColorDepth::Truecolor, [1, 2, 3], true), "48;2;1;2;3");
+        // 256 色不用主题可以改的前 16 种颜色。
+        assert_eq!(sgr(ColorDepth::Colors256, [255, 0, 0], false), "38;5;196");
+        assert_eq!(sgr(ColorDepth::Colors256, [0, 0, 0], true), "48;5;16");
+        assert_eq!(sgr(ColorDepth::Colors256, [128, 128, 128], false), "38;5;244");
+        assert_eq!(sgr(ColorDepth::Colors256, [95, 135, 175], false), "38;5;67");
+        assert_eq!(sgr(ColorDepth::Colors16, [205, 0, 0], false), "31");
+        assert_eq!(sgr(ColorDepth::Colors16, [205, 0, 0], true), "41");
+        assert_eq!(sgr(ColorDepth::Colors16, [250, 250, 250], false), "97");
+        assert_eq!(sgr(ColorDepth::Colors16, [250, 250, 250], true), "107");
+    }
+
+    #[test]
+    fn palettes() {
+        assert_eq!(palette(ColorDepth::Colors16).len(), 16);
+        let colors = palette(ColorDepth::Colors256);
+        assert_eq!(colors.len(), 240);
+        assert_eq!(colors[0], (16, [0, 0, 0]));
+        assert_eq!(colors[215], (231, [255, 255, 255]));
+        assert_eq!(colors[239], (255, [238, 238, 238]));
+    }
+
+    #[test]
+    fn dithering() {
+        let mut bitmap = Bitmap::new(16, 16);
+        for (i, pixel) in bitmap.pixels.chunks_mut(4).enumerate() {
+            // 左半边是不透明的灰色，右半边透明。
+            let alpha = if i % 16 < 8 { 255 } else { 0 };
+            pixel.copy_from_slice(&[100, 100, 100, alpha]);
+        }
+        let original = bitmap.pixels.clone();
+        dither(&mut bitmap, ColorDepth::Truecolor);
+        assert_eq!(bitmap.pixels, original);
+
+        dither(&mut bitmap, ColorDepth::Colors16);
+        let opaque: Vec<_> = bitmap.pixels.chunks(4).filter(|p| p[3] == 255).collect();
+        assert!(opaque.iter().all(|p| ANSI_COLORS.contains(&[p[0], p[1], p[2]])));
+        // 灰色由调色板中的颜色混合而成，平均亮度和原来相近。
+        let mean = opaque.iter().map(|p| p[0] as f64).sum::<f64>() / opaque.len() as f64;
+        assert!((mean - 100.0).abs() < 10.0, "{mean}");
+        assert!(opaque.windows(2).any(|w| w[0] != w[1]));
+        let mut transparent = bitmap.pixels.chunks(4).zip(original.chunks(4)).filter(|(p, _)| p[3] == 0);
+        assert!(transparent.all(|(p, o)| p == o));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::io::Cursor;
+use std::time::Duration;
+use image::{AnimationDecoder, ImageDecoder, ImageFormat, ImageReader, Limits, RgbaImage};
+use image::codecs::png::PngDecoder;
+use crate::error::{ImgcatError, MAX_DECODED_LEN};
+use crate::probe::{self, Format};
+
+/// Keeps the bitmaps of an image between the times it is drawn, as `imgcat --daemon` does
+/// for the files its clients draw again and again. The renderers drawing with text look up
+/// the image decoded, then scaled, before decoding or scaling it themselves.
+pub trait BitmapCache: fmt::Debug {
+    /// The image `data` decoded and scaled to `size`, or at its own size with `None`, if kept.
+    fn get(&self, data: &[u8], size: Option<(u32, u32)>) -> Option<Bitmap>;
+
+    /// Keep `bitmap`, the image `data` decoded at `size` as with [`get`](Self::get).
+    fn put(&self, data: &[u8], size: Option<(u32, u32)>, bitmap: &Bitmap);
+}
+
+/// A decoded image, as 8-bit RGBA pixels in row-major order.
+#[derive(Clone)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    /// A fully transparent bitmap.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {width, height, pixels: vec![0; width as usize * height as usize * 4]}
+    }
+
+    /// Decode an image: PNG, BMP, JPEG, GIF or WebP, the first frame of animated ones.
+    pub fn decode(data: &[u8]) -> crate::Result<Self> {
+        let Some(info) = probe::probe(data) else {
+            return Err(ImgcatError::Decode("unrecognized image format".to_string()));
+        };
+        // 头部给出的尺寸可能大得离谱，先检查再分配内存。
+        let size = info.width as u64 * info.height as u64 * 4;
+        if size > MAX_DECODED_LEN {
+            return Err(ImgcatError::PayloadTooLarge {size, limit: MAX_DECODED_LEN});
+        }
+        let mut reader = ImageReader::with_format(Cursor::new(data), image_format(info.format));
+        reader.limits(limits());
+        let decoded = reader.decode().map_err(|e| ImgcatError::Decode(e.to_string()))?;
+        Ok(decoded.into_rgba8().into())
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        let i = (y * self.width as usize + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width as usize + x) * 4;
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    /// The bitmap rotated by 90 degrees clockwise.
+    pub fn rotate90(&self) -> Self {
+        let mut rotated = Self::new(self.height, self.width);
+        for y in 0..self.height as usize {
+            for x in 0..self.width as usize {
+                rotated.set(self.height as usize - 1 - y, x, self.get(x, y));
+            }
+        }
+        rotated
+    }
+
+    /// The bitmap scaled to `width` x `height`, each pixel averaging those of the
+    /// area it covers.
+    pub fn resize(&self, width: u32, height: u32) -> Self {
+        let mut resized = Self::new(width, height);
+        for y in 0..height {
+            let rows = span(y, height, self.height);
+            for x in 0..width {
+                let columns = span(x, width, self.width);
+                let mut sum = [0u32; 4];
+                for sy in rows.clone() {
+                    for sx in columns.clone() {
+                        for (s, c) in sum.iter_mut().zip(self.get(sx, sy)) {
+                            *s += c as u32;
+                        }
+                    }
+                }
+                let n = (rows.len() * columns.len()) as u32;
+                resized.set(x as usize, y as usize, sum.map(|s| (s / n) as u8));
+            }
+        }
+        resized
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        self.encode(ImageFormat::Png)
+    }
+
+    pub fn to_bmp(&self) -> Vec<u8> {
+        self.encode(ImageFormat::Bmp)
+    }
+
+    fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        // 像素数总是与尺寸相符，写入内存也不会失败。
+        image::write_buffer_with_format(&mut out, &self.pixels, self.width, self.height, image::ColorType::Rgba8, format)
+            .expect("encoding a bitmap in memory failed");
+        out.into_inner()
+    }
+}
+
+impl From<RgbaImage> for Bitmap {
+    fn from(image: RgbaImage) -> Self {
+        Self {width: image.width(), height: image.height(), pixels: image.into_raw()}
+    }
+}
+
+/// A frame of an animation, composed over the whole image as it is shown.
+#[derive(Clone)]
+pub struct Frame {
+    pub bitmap: Bitmap,
+    /// how long the frame is shown
+    pub delay: Duration,
+}
+
+/// The frames of an animated image.
+#[derive(Clone)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// number of times the animation is played, 0 for forever
+    pub plays: u32,
+}
+
+impl Animation {
+    /// Decode the frames of an animated image. Only APNG images can be decoded.
+    pub fn decode(data: &[u8]) -> crate::Result<Self> {
+        let Some(info) = probe::probe(data).filter(|info| info.format == Format::Png) else {
+            return Err(ImgcatError::Decode("decoding animations is only supported for APNG images".to_string()));
+        };
+        // 每一帧都是一张完整的位图，按帧数估算占用的内存。
+        let frames = probe::frame_count(data, info.format).unwrap_or(1) as u64;
+        let size = info.width as u64 * info.height as u64 * 4 * frames;
+        if size > MAX_DECODED_LEN {
+            return Err(ImgcatError::PayloadTooLarge {size, limit: MAX_DECODED_LEN});
+        }
+        let not_animated = || ImgcatError::Decode("not an animated PNG image".to_string());
+        let plays = probe::apng_plays(data).ok_or_else(not_animated)?;
+        let decode = || -> image::ImageResult<Option<Vec<image::Frame>>> {
+            let mut decoder = PngDecoder::new(Cursor::new(data))?;
+            decoder.set_limits(limits())?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames().collect_frames().map(Some)
+        };
+        let frames = decode().map_err(|e| ImgcatError::Decode(e.to_string()))?.ok_or_else(not_animated)?;
+        let frames = frames.into_iter()
+            .map(|frame| Frame {delay: frame.delay().into(), bitmap: frame.into_buffer().into()})
+            .collect();
+        Ok(Animation {frames, plays})
+    }
+}
+
+/// The format the image crate decodes images in `format` as.
+fn image_format(format: Format) -> ImageFormat {
+    match format {
+        Format::Png => ImageFormat::Png,
+        Format::Jpeg => ImageFormat::Jpeg,
+        Format::Gif => ImageFormat::Gif,
+        Format::Bmp => ImageFormat::Bmp,
+        Format::Webp => ImageFormat::WebP,
+    }
+}
+
+/// The memory the image crate may allocate while decoding an image.
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODED_LEN);
+    limits
+}
+
+/// The source pixels covered by pixel `i` of `new` along an axis of `old` pixels.
+fn span(i: u32, new: u32, old: u32) -> std::ops::Range<usize> {
+    let start = i as u64 * old as u64 / new as u64;
+    let end = ((i as u64 + 1) * old as u64).div_ceil(new as u64).max(start + 1);
+    start as usize..end as usize
+}
@@ -0,0 +1,303 @@
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use plotters::prelude::*;
+use crate::bitmap::Bitmap;
+use crate::i18n::tr;
+
+/// Size of the chart in pixels.
+const WIDTH: u32 = 480;
+const HEIGHT: u32 = 280;
+/// Space around the plot area, in pixels.
+const MARGIN: u32 = 10;
+
+/// Colors of the series, those of matplotlib.
+const COLORS: [[u8; 3]; 10] = [
+    [31, 119, 180], [255, 127, 14], [44, 160, 44], [214, 39, 40], [148, 103, 189],
+    [140, 86, 75], [227, 119, 194], [127, 127, 127], [188, 189, 34], [23, 190, 207],
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Line,
+    Scatter,
+    Bar,
+}
+
+/// Rows of delimited text, with the names of the columns when the first row is a header.
+pub struct Table {
+    header: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Read CSV or TSV text, split on `delimiter`, or on tabs when the first line has
+    /// some and commas otherwise. Fields may be quoted with `"`. The first row is taken
+    /// as a header when some of its fields aren't numbers.
+    pub fn parse(text: &str, delimiter: Option<char>) -> Self {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty()).peekable();
+        let delimiter = delimiter.unwrap_or_else(|| match lines.peek() {
+            Some(first) if first.contains('\t') => '\t',
+            _ => ',',
+        });
+        let mut rows: Vec<Vec<String>> = lines.map(|line| split_fields(line, delimiter)).collect();
+        let header = rows.first()
+            .filter(|first| first.iter().any(|f| number(f).is_none()))
+            .cloned();
+        if header.is_some() {
+            rows.remove(0);
+        }
+        Table {header, rows}
+    }
+
+    /// The index of the column named `name`, or numbered `name` from 1.
+    fn column(&self, name: &str) -> anyhow::Result<usize> {
+        if let Some(i) = self.header.as_ref().and_then(|h| h.iter().position(|c| c == name)) {
+            return Ok(i);
+        }
+        match name.parse::<usize>() {
+            Ok(n) if n >= 1 => Ok(n - 1),
+            _ => bail!(tr!("no column {}", format!("{name:?}"))),
+        }
+    }
+
+    fn column_name(&self, i: usize) -> String {
+        self.header.as_ref()
+            .and_then(|h| h.get(i).cloned())
+            .unwrap_or_else(|| format!("column {}", i + 1))
+    }
+
+    /// The numbers of column `i`, `None` for rows where it isn't one.
+    fn numbers(&self, i: usize) -> Vec<Option<f64>> {
+        self.rows.iter().map(|row| row.get(i).and_then(|f| number(f))).collect()
+    }
+}
+
+fn number(field: &str) -> Option<f64> {
+    field.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// The fields of a line of delimited text, with the quotes around quoted fields removed.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+/// A chart drawn from a table, and the text describing its series and axes.
+pub struct Chart {
+    pub bitmap: Bitmap,
+    pub legend: String,
+}
+
+/// Draw the columns `ys`, all the numeric columns other than `x` if there are none, against
+/// column `x`, or against the row numbers if it isn't given or isn't numeric.
+pub fn draw(table: &Table, x: Option<&str>, ys: &[String], kind: Kind) -> anyhow::Result<Chart> {
+    if table.rows.is_empty() {
+        bail!(tr!("no rows to plot"));
+    }
+    let x_column = x.map(|x| table.column(x)).transpose()?;
+    let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let y_columns: Vec<usize> = match ys {
+        [] => (0..columns)
+            .filter(|i| Some(*i) != x_column && table.numbers(*i).iter().any(Option::is_some))
+            .collect(),
+        ys => ys.iter().map(|y| table.column(y)).collect::<anyhow::Result<_>>()?,
+    };
+    if y_columns.is_empty() {
+        bail!(tr!("no numeric columns to plot"));
+    }
+    let x_values: Vec<Option<f64>> = match x_column.map(|i| table.numbers(i)) {
+        Some(values) if values.iter().all(Option::is_some) => values,
+        _ => (1..=table.rows.len()).map(|n| Some(n as f64)).collect(),
+    };
+    let x_name = match x_column {
+        Some(i) if x_values == table.numbers(i) => table.column_name(i),
+        _ => "row".to_string(),
+    };
+    let series: Vec<Vec<(f64, f64)>> = y_columns.iter()
+        .map(|&i| x_values.iter().zip(table.numbers(i)).filter_map(|(x, y)| Some(((*x)?, y?))).collect())
+        .collect();
+    let points = series.iter().flatten();
+    let (mut x_min, mut x_max) = bounds(points.clone().map(|p| p.0)).context(tr!("no numbers to plot"))?;
+    let (mut y_min, mut y_max) = bounds(points.map(|p| p.1)).context(tr!("no numbers to plot"))?;
+    if kind == Kind::Bar {
+        // 柱子从 0 开始，两端各留出半个柱子的宽度。
+        (y_min, y_max) = (y_min.min(0.0), y_max.max(0.0));
+        let step = smallest_step(&x_values).unwrap_or(1.0);
+        (x_min, x_max) = (x_min - step / 2.0, x_max + step / 2.0);
+    }
+    let (x_min, x_max, x_step) = nice_range(x_min, x_max);
+    let (y_min, y_max, y_step) = nice_range(y_min, y_max);
+
+    let step = smallest_step(&x_values).unwrap_or(1.0);
+    let mut pixels = vec![255; WIDTH as usize * HEIGHT as usize * 3];
+    plot(&mut pixels, &series, kind, step, (x_min, x_max, x_step), (y_min, y_max, y_step))
+        .context(tr!("failed to draw the chart"))?;
+    let mut bitmap = Bitmap::new(WIDTH, HEIGHT);
+    for (rgba, rgb) in bitmap.pixels.chunks_exact_mut(4).zip(pixels.chunks_exact(3)) {
+        rgba.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+    }
+
+    let mut legend = String::new();
+    for (n, &i) in y_columns.iter().enumerate() {
+        let [r, g, b] = COLORS[n % COLORS.len()];
+        legend += &format!("\x1b[38;2;{r};{g};{b}m■\x1b[0m {}  ", table.column_name(i));
+    }
+    legend += &format!(
+        "\n{x_name}: {} to {}, grid every {}; y: {} to {}, grid every {}",
+        format_number(x_min), format_number(x_max), format_number(x_step),
+        format_number(y_min), format_number(y_max), format_number(y_step),
+    );
+    Ok(Chart {bitmap, legend})
+}
+
+fn bounds(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    values.fold(None, |bounds, v| match bounds {
+        None => Some((v, v)),
+        Some((min, max)) => Some((v.min(min), v.max(max))),
+    })
+}
+
+/// The smallest distance between consecutive x values, the width available to a bar.
+fn smallest_step(values: &[Option<f64>]) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().flatten().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    sorted.windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|d| *d > 0.0)
+        .min_by(f64::total_cmp)
+}
+
+/// `min` and `max` widened to multiples of a step of 1, 2 or 5 times a power of ten
+/// giving about five grid lines, and the step.
+fn nice_range(min: f64, max: f64) -> (f64, f64, f64) {
+    let (min, max) = if max > min { (min, max) } else { (min - 1.0, max + 1.0) };
+    let rough = (max - min) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0].into_iter()
+        .map(|m| m * magnitude)
+        .find(|s| *s >= rough)
+        .unwrap_or(10.0 * magnitude);
+    ((min / step).floor() * step, (max / step).ceil() * step, step)
+}
+
+fn ticks(min: f64, max: f64, step: f64) -> impl Iterator<Item = f64> {
+    let count = ((max - min) / step).round() as usize;
+    (0..=count).map(move |i| min + i as f64 * step)
+}
+
+fn format_number(n: f64) -> String {
+    // 去掉浮点误差带来的长尾数。
+    let rounded = format!("{:.6}", n);
+    let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_string(),
+        t => t.to_string(),
+    }
+}
+
+/// Draw `series` with plotters into `pixels`, RGB rows of the size of the chart, over
+/// grid lines at the ticks of the x and y ranges and the axes through 0. Bars are placed
+/// side by side within 80% of the `step` between consecutive x values.
+fn plot(
+    pixels: &mut [u8],
+    series: &[Vec<(f64, f64)>],
+    kind: Kind,
+    step: f64,
+    (x_min, x_max, x_step): (f64, f64, f64),
+    (y_min, y_max, y_step): (f64, f64, f64),
+) -> anyhow::Result<()> {
+    let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let area = root.margin(MARGIN, MARGIN, MARGIN, MARGIN);
+    let mut chart = ChartBuilder::on(&area).build_cartesian_2d(x_min..x_max, y_min..y_max)?;
+    let grid = RGBColor(225, 225, 225);
+    chart.draw_series(ticks(x_min, x_max, x_step).map(|x| PathElement::new([(x, y_min), (x, y_max)], grid)))?;
+    chart.draw_series(ticks(y_min, y_max, y_step).map(|y| PathElement::new([(x_min, y), (x_max, y)], grid)))?;
+    // 坐标轴画在 0 处，0 不在范围内时画在边上。
+    let (axis_x, axis_y) = (0.0_f64.clamp(x_min, x_max), 0.0_f64.clamp(y_min, y_max));
+    let axis = RGBColor(100, 100, 100);
+    chart.draw_series([
+        PathElement::new([(axis_x, y_min), (axis_x, y_max)], axis),
+        PathElement::new([(x_min, axis_y), (x_max, axis_y)], axis),
+    ])?;
+
+    for (n, points) in series.iter().enumerate() {
+        let [r, g, b] = COLORS[n % COLORS.len()];
+        let color = RGBColor(r, g, b);
+        match kind {
+            Kind::Line => {
+                chart.draw_series(LineSeries::new(points.iter().copied(), color.stroke_width(2)))?;
+                if let [point] = points[..] {
+                    chart.draw_series([Circle::new(point, 3, color.filled())])?;
+                }
+            }
+            Kind::Scatter => {
+                chart.draw_series(points.iter().map(|&point| Circle::new(point, 3, color.filled())))?;
+            }
+            Kind::Bar => {
+                let width = step * 0.8 / series.len() as f64;
+                chart.draw_series(points.iter().map(|&(x, y)| {
+                    let left = x - step * 0.4 + n as f64 * width;
+                    Rectangle::new([(left, y), (left + width, axis_y)], color.filled())
+                }))?;
+            }
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nice_ranges() {
+        assert_eq!(nice_range(0.0, 10.0), (0.0, 10.0, 2.0));
+        assert_eq!(nice_range(3.0, 97.0), (0.0, 100.0, 20.0));
+        assert_eq!(nice_range(-0.3, 0.7), (-0.4, 0.8, 0.2));
+        // 所有值相同时上下各扩展 1。
+        assert_eq!(nice_range(5.0, 5.0), (4.0, 6.0, 0.5));
+    }
+
+    #[test]
+    fn ticks_cover_the_range() {
+        let ticks: Vec<f64> = ticks(0.0, 1.0, 0.2).map(|t| (t * 10.0).round() / 10.0).collect();
+        assert_eq!(ticks, [0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+    }
+
+    #[test]
+    fn tables() {
+        let table = Table::parse("name,\"a, b\"\nx,1\ny,2\n", None);
+        assert_eq!(table.header, Some(vec!["name".to_string(), "a, b".to_string()]));
+        assert_eq!(table.column("a, b").unwrap(), 1);
+        assert_eq!(table.column("1").unwrap(), 0);
+        assert_eq!(table.numbers(1), [Some(1.0), Some(2.0)]);
+        // 全是数字的第一行不是表头。
+        let table = Table::parse("1\t2\n3\t4", None);
+        assert!(table.header.is_none());
+        assert_eq!(table.numbers(1), [Some(2.0), Some(4.0)]);
+    }
+
+    #[test]
+    fn bars_start_at_zero() {
+        let table = Table::parse("x,y\n1,2\n2,4\n3,-1\n", None);
+        let chart = draw(&table, Some("x"), &[], Kind::Bar).unwrap();
+        assert_eq!((chart.bitmap.width, chart.bitmap.height), (WIDTH, HEIGHT));
+        assert!(chart.legend.ends_with("x: 0 to 4, grid every 1; y: -1 to 4, grid every 1"), "{}", chart.legend);
+    }
+}
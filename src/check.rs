@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::time::Duration;
+use crate::bitmap::Bitmap;
+use crate::layout::Length;
+use crate::terminal::ColorDepth;
+use crate::render::{Regis, Renderer};
+use imgcat::Options;
+use crate::{print_inline, terminal, Cli, Image};
+
+/// How long to wait for the terminal to answer a query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Values of `$TERM_PROGRAM` of terminals known to implement iTerm2's inline images protocol.
+const ITERM2_TERMINALS: [&str; 7] = ["iTerm.app", "WezTerm", "vscode", "mintty", "Tabby", "rio", "WarpTerminal"];
+
+/// Probe the terminal and print a report of what it supports, followed by a
+/// small test image drawn with each protocol it seems to support, to `out`.
+pub fn run(out: &mut impl Write, args: &Cli) -> anyhow::Result<()> {
+    let program = terminal::var("TERM_PROGRAM").ok();
+    let term = terminal::var("TERM").unwrap_or_default();
+    let name = match (&program, terminal::var("TERM_PROGRAM_VERSION")) {
+        (Some(program), Ok(version)) => format!("{program} {version}"),
+        (Some(program), Err(_)) => program.clone(),
+        (None, _) => "unknown".to_string(),
+    };
+    report(out, "terminal", format!("{name}, TERM={term}"))?;
+
+    let size = terminal::size();
+    let (cell_width, cell_height) = size.cell_size();
+    let pixels = if size.width > 0 && size.height > 0 {
+        format!("{}x{} px, cells of {cell_width:.1}x{cell_height:.1} px", size.width, size.height)
+    } else {
+        "pixel size not reported, assuming cells of 8x16 px".to_string()
+    };
+    report(out, "size", format!("{}x{} cells, {pixels}", size.columns, size.rows))?;
+
+    let iterm2 = program.as_deref().is_some_and(|p| ITERM2_TERMINALS.contains(&p))
+        || terminal::var("LC_TERMINAL").is_ok_and(|t| t == "iTerm2");
+
+    let attributes = terminal::device_attributes(QUERY_TIMEOUT);
+    let sixel = attributes.as_ref().is_some_and(|a| a.iter().any(|p| p == "4"));
+    // VS Code 的终端只有在设置中打开后才显示图片，同时也才支持 sixel。
+    let iterm2_report = match (iterm2, program.as_deref(), &attributes) {
+        (true, Some("vscode"), Some(_)) if !sixel => "off: turn on terminal.integrated.enableImages in the settings of VS Code",
+        (true, _, _) => "supported",
+        (false, _, _) => "unknown, see the test image below",
+    };
+    report(out, "iTerm2 images", iterm2_report)?;
+
+    let truecolor = terminal::var("COLORTERM").is_ok_and(|c| c == "truecolor" || c == "24bit");
+    let colors = match (terminal::color_depth(), truecolor) {
+        (ColorDepth::Truecolor, true) => "truecolor".to_string(),
+        (ColorDepth::Truecolor, false) => "truecolor, though not advertised in $COLORTERM".to_string(),
+        (depth, _) => format!("{}, half blocks are dithered to them", depth.name()),
+    };
+    report(out, "colors", colors)?;
+
+    let sixel_report = match (&attributes, sixel) {
+        (None, _) => "unknown, the terminal didn't answer".to_string(),
+        (Some(_), false) => "no".to_string(),
+        (Some(_), true) => match color_registers() {
+            Some(n) => format!("yes, {n} color registers"),
+            None => "yes".to_string(),
+        },
+    };
+    report(out, "sixel", sixel_report)?;
+
+    let regis = attributes.as_ref().is_some_and(|a| a.iter().any(|p| p == "3"));
+    let regis_report = match (&attributes, regis) {
+        (None, _) => "unknown, the terminal didn't answer",
+        (Some(_), false) => "no",
+        (Some(_), true) => "yes",
+    };
+    report(out, "ReGIS", regis_report)?;
+
+    let tmux = if terminal::var_os("TMUX").is_none() {
+        "not running inside tmux".to_string()
+    } else {
+        match tmux_passthrough() {
+            Some(true) => "inside tmux, passthrough allowed".to_string(),
+            Some(false) => "inside tmux, passthrough off: run `tmux set -g allow-passthrough on`".to_string(),
+            None => "inside tmux".to_string(),
+        }
+    };
+    report(out, "tmux", tmux)?;
+
+    writeln!(out)?;
+    writeln!(out, "iTerm2 test image:")?;
+    let image = Image {
+        data: test_pattern().to_png().into(),
+        filename: Some("test.png".to_string()),
+        path: None,
+        file_type: None,
+    };
+    print_inline(out, &image, args, Some(Length::Cells(16)), Some(Length::Cells(4)))?;
+    writeln!(out)?;
+    if sixel {
+        writeln!(out, "sixel test image:")?;
+        // 一条 6 像素高的 sixel 色带，红绿蓝各 32 像素。
+        writeln!(out, "\x1bPq#1;2;100;0;0#2;2;0;100;0#3;2;0;0;100#1!32~#2!32~#3!32~\x1b\\")?;
+    }
+    if regis {
+        writeln!(out, "ReGIS test image:")?;
+        let options = Options {width: Some(Length::Cells(16)), height: Some(Length::Cells(4)), ..Options::default()};
+        Regis.emit(out, &mut &image.data[..], &options)?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn report(out: &mut impl Write, name: &str, value: impl AsRef<str>) -> io::Result<()> {
+    writeln!(out, "{:<14} {}", format!("{name}:"), value.as_ref())
+}
+
+/// Number of sixel color registers, asked with XTSMGRAPHICS.
+fn color_registers() -> Option<u32> {
+    let reply = terminal::query("\x1b[?1;1;0S", b'S', QUERY_TIMEOUT)?;
+    let reply = std::str::from_utf8(&reply).ok()?;
+    let params: Vec<&str> = reply.strip_prefix("\x1b[?")?.trim_end_matches('S').split(';').collect();
+    match params.as_slice() {
+        ["1", "0", n] => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Whether tmux forwards the escape sequences of applications to the terminal.
+/// Versions before 3.3 don't have the option and always do.
+fn tmux_passthrough() -> Option<bool> {
+    let output = terminal::command("tmux")
+        .args(["show", "-gv", "allow-passthrough"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return Some(true);
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim() != "off")
+}
+
+/// A gradient with a hue sweep across and brightness down, where any
+/// stretching, cropping or color problem is easy to spot.
+fn test_pattern() -> Bitmap {
+    let mut bitmap = Bitmap::new(64, 16);
+    for y in 0..16 {
+        for x in 0..64 {
+            let t = x as f64 / 63.0;
+            let v = 1.0 - y as f64 / 20.0;
+            let channel = |phase: f64| {
+                let c = ((t + phase) * std::f64::consts::TAU).cos() * 0.5 + 0.5;
+                (c * v * 255.0) as u8
+            };
+            bitmap.set(x, y, [channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0), 255]);
+        }
+    }
+    bitmap
+}
@@ -0,0 +1,173 @@
+use std::io::{self, Write};
+use std::process::Stdio;
+use anyhow::{bail, Context};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use crate::i18n::tr;
+use crate::terminal;
+
+/// Read an image from the system clipboard as PNG bytes.
+///
+/// The platform clipboard tools are used instead of linking against a native
+/// clipboard library: `osascript` on macOS, as `pbpaste` only reads text, `wl-paste`
+/// or `xclip` on Linux and PowerShell on Windows.
+pub fn read_image() -> anyhow::Result<Vec<u8>> {
+    let data = read_platform_image()?;
+    if data.is_empty() {
+        bail!(tr!("the clipboard does not contain an image"));
+    }
+    Ok(data)
+}
+
+#[cfg(target_os = "macos")]
+fn read_platform_image() -> anyhow::Result<Vec<u8>> {
+    // osascript 以 «data PNGf89504E47...» 的形式输出剪贴板中的 PNG 数据，需要自行解析十六进制。
+    let out = run("osascript", &["-e", "the clipboard as «class PNGf»"])?;
+    let text = String::from_utf8_lossy(&out);
+    let hex = text.trim()
+        .strip_prefix("«data PNGf")
+        .and_then(|x| x.strip_suffix('»'))
+        .context(tr!("the clipboard does not contain an image"))?;
+    decode_hex(hex)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_platform_image() -> anyhow::Result<Vec<u8>> {
+    if terminal::var_os("WAYLAND_DISPLAY").is_some() {
+        run("wl-paste", &["--no-newline", "--type", "image/png"])
+    } else {
+        run("xclip", &["-selection", "clipboard", "-target", "image/png", "-out"])
+    }
+}
+
+#[cfg(windows)]
+fn read_platform_image() -> anyhow::Result<Vec<u8>> {
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Windows.Forms;\
+        $img = [System.Windows.Forms.Clipboard]::GetImage();\
+        if ($img -ne $null) {\
+            $ms = New-Object System.IO.MemoryStream;\
+            $img.Save($ms, [System.Drawing.Imaging.ImageFormat]::Png);\
+            [Convert]::ToBase64String($ms.ToArray())\
+        }";
+    let out = run("powershell", &["-NoProfile", "-STA", "-Command", SCRIPT])?;
+    let text = String::from_utf8_lossy(&out);
+    BASE64_STANDARD.decode(text.trim())
+        .context(tr!("failed to decode the clipboard image"))
+}
+
+/// Put an image of the media type `mime` on the system clipboard, with the same tools
+/// as [`read_image`].
+pub fn write_image(data: &[u8], mime: &str) -> anyhow::Result<()> {
+    write_platform_image(data, mime)
+}
+
+#[cfg(target_os = "macos")]
+fn write_platform_image(data: &[u8], mime: &str) -> anyhow::Result<()> {
+    let class = match mime {
+        "image/png" => "PNGf",
+        "image/jpeg" => "JPEG",
+        "image/gif" => "GIFf",
+        _ => bail!(tr!("{} images can't be put on the clipboard", mime)),
+    };
+    // osascript 只能从文件中读取二进制数据。
+    let path = std::env::temp_dir().join(format!("imgcat-clipboard-{}", std::process::id()));
+    std::fs::write(&path, data)?;
+    let script = format!("set the clipboard to (read (POSIX file \"{}\") as «class {class}»)", path.display());
+    let result = run("osascript", &["-e", &script]);
+    let _ = std::fs::remove_file(&path);
+    result.map(drop)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn write_platform_image(data: &[u8], mime: &str) -> anyhow::Result<()> {
+    if terminal::var_os("WAYLAND_DISPLAY").is_some() {
+        run_with_input("wl-copy", &["--type", mime], data)
+    } else {
+        run_with_input("xclip", &["-selection", "clipboard", "-target", mime, "-in"], data)
+    }
+}
+
+#[cfg(windows)]
+fn write_platform_image(data: &[u8], _mime: &str) -> anyhow::Result<()> {
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Windows.Forms;\
+        $bytes = [Convert]::FromBase64String([Console]::In.ReadToEnd());\
+        $ms = New-Object System.IO.MemoryStream(,$bytes);\
+        [System.Windows.Forms.Clipboard]::SetImage([System.Drawing.Image]::FromStream($ms))";
+    run_with_input("powershell", &["-NoProfile", "-STA", "-Command", SCRIPT], BASE64_STANDARD.encode(data).as_bytes())
+}
+
+/// Whether imgcat runs on another host than the terminal, logged in with SSH, so that the
+/// system clipboard isn't the one of the terminal's host.
+pub fn remote() -> bool {
+    terminal::var_os("SSH_CONNECTION").is_some() || terminal::var_os("SSH_TTY").is_some()
+}
+
+/// Write the escape sequences putting an image of the media type `mime` on the clipboard
+/// of the terminal, with kitty's clipboard protocol, which unlike OSC 52 takes other
+/// types than text.
+pub fn write_kitty(out: &mut impl Write, data: &[u8], mime: &str) -> io::Result<()> {
+    write_osc(out, "5522;type=write")?;
+    let mime = BASE64_STANDARD.encode(mime);
+    // 数据分块发送，每块编码后 4096 字节。
+    for chunk in data.chunks(3072) {
+        write_osc(out, &format!("5522;type=wdata:mime={mime};{}", BASE64_STANDARD.encode(chunk)))?;
+    }
+    write_osc(out, "5522;type=wdata")?;
+    out.flush()
+}
+
+/// Write the OSC 52 escape sequence putting `text` on the clipboard of the terminal.
+pub fn write_osc52(out: &mut impl Write, text: &str) -> io::Result<()> {
+    write_osc(out, &format!("52;c;{}", BASE64_STANDARD.encode(text)))?;
+    out.flush()
+}
+
+/// Write an OSC escape sequence, wrapped to pass through tmux when running inside it.
+fn write_osc(out: &mut impl Write, body: &str) -> io::Result<()> {
+    if imgcat::in_tmux() {
+        return write!(out, "\x1bPtmux;\x1b\x1b]{body}\x1b\x1b\\\x1b\\");
+    }
+    write!(out, "\x1b]{body}\x1b\\")
+}
+
+fn run_with_input(program: &str, args: &[&str], input: &[u8]) -> anyhow::Result<()> {
+    // wl-copy 和 xclip 在后台继续运行以提供剪贴板内容，不能等待它们的输出。
+    let mut child = terminal::command(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| tr!("failed to run {}, is it installed?", program))?;
+    child.stdin.take().unwrap().write_all(input)?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(tr!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+fn run(program: &str, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let out = terminal::command(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| tr!("failed to run {}, is it installed?", program))?;
+    if !out.status.success() {
+        bail!(tr!("the clipboard does not contain an image ({} exited with {})", program, out.status));
+    }
+    Ok(out.stdout)
+}
+
+#[cfg(target_os = "macos")]
+fn decode_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .context(tr!("malformed clipboard data"))
+        })
+        .collect()
+}
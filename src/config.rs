@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use crate::i18n::tr;
+use crate::terminal;
+
+/// Path of the configuration file: `$IMGCAT_CONFIG`, or `imgcat/config.toml`
+/// in `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn path() -> Option<PathBuf> {
+    if let Some(path) = terminal::var_os("IMGCAT_CONFIG") {
+        return Some(path.into());
+    }
+    let dir = terminal::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| terminal::var_os("HOME").or_else(|| terminal::var_os("USERPROFILE")).map(|h| PathBuf::from(h).join(".config")))?;
+    Some(dir.join("imgcat").join("config.toml"))
+}
+
+/// The configuration file.
+#[derive(Deserialize, Default)]
+struct Config {
+    #[serde(flatten)]
+    settings: Settings,
+    /// `[terminal.NAME]`, for the terminal whose TERM_PROGRAM is NAME
+    #[serde(default)]
+    terminal: HashMap<String, Settings>,
+    /// `[preset.NAME]`, the size of the --preset NAME
+    #[serde(default)]
+    preset: HashMap<String, Preset>,
+}
+
+/// The defaults set at the top level of the file, or for a terminal.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct Settings {
+    width: Option<Size>,
+    height: Option<Size>,
+    protocol: Option<String>,
+    #[serde(default)]
+    network: Network,
+    #[serde(default)]
+    cache: Cache,
+    /// the other long options, in the order of the file
+    #[serde(flatten)]
+    options: toml::Table,
+}
+
+/// `[network]`: how remote images are fetched.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Network {
+    /// --timeout, in seconds
+    timeout: Option<f64>,
+    /// --delay-between, in seconds
+    delay_between: Option<f64>,
+    /// --max-rps
+    max_rps: Option<f64>,
+}
+
+/// `[cache]`: what the daemon keeps between the command lines it runs.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Cache {
+    /// --cache-size, bytes or a size such as `"64M"`
+    size: Option<Size>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Preset {
+    width: Option<Size>,
+    height: Option<Size>,
+}
+
+/// A size given as a number, `width = 40`, or as text, `width = "50%"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Size {
+    Number(u64),
+    Text(String),
+}
+
+impl Size {
+    fn value(&self) -> String {
+        match self {
+            Size::Number(n) => n.to_string(),
+            Size::Text(text) => text.clone(),
+        }
+    }
+}
+
+impl Settings {
+    /// The settings as command line options, with the long options they name.
+    fn to_args(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let Network {timeout, delay_between, max_rps} = self.network;
+        let mut args: Vec<(String, String)> = [
+            ("width", self.width.as_ref().map(Size::value)),
+            ("height", self.height.as_ref().map(Size::value)),
+            ("protocol", self.protocol.clone()),
+            ("timeout", timeout.map(|n| n.to_string())),
+            ("delay-between", delay_between.map(|n| n.to_string())),
+            ("max-rps", max_rps.map(|n| n.to_string())),
+            ("cache-size", self.cache.size.as_ref().map(Size::value)),
+        ]
+        .into_iter()
+        .filter_map(|(option, value)| Some((option.to_string(), format!("--{option}={}", value?))))
+        .collect();
+        for (key, value) in &self.options {
+            // TOML 的键习惯用下划线，选项名用连字符。
+            let option = key.replace('_', "-");
+            let arg = match value {
+                toml::Value::Boolean(true) => format!("--{option}"),
+                toml::Value::Boolean(false) => continue,
+                toml::Value::String(s) => format!("--{option}={s}"),
+                toml::Value::Integer(n) => format!("--{option}={n}"),
+                toml::Value::Float(n) => format!("--{option}={n}"),
+                _ => bail!(tr!("invalid value of {}, expected a string, number or boolean", key)),
+            };
+            args.push((option, arg));
+        }
+        Ok(args)
+    }
+}
+
+/// Read and parse the configuration file, `None` if there is none.
+fn read() -> anyhow::Result<Option<(PathBuf, Config)>> {
+    let Some(path) = path() else {
+        return Ok(None);
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        // 没有配置文件是正常情况，除非用户通过 IMGCAT_CONFIG 显式指定了它。
+        Err(e) if e.kind() == io::ErrorKind::NotFound && terminal::var_os("IMGCAT_CONFIG").is_none() => {
+            return Ok(None);
+        }
+        Err(e) => return Err(e).with_context(|| tr!("failed to read {}", path.display())),
+    };
+    let config = toml::from_str(&text).with_context(|| tr!("invalid configuration file {}", path.display()))?;
+    Ok(Some((path, config)))
+}
+
+/// Read the configuration file and turn it into command line options, which
+/// go before the actual arguments so that those take precedence.
+///
+/// The file is TOML: top-level keys name long options, e.g. `width = "50%"` or
+/// `print-path = true`, the `[network]` table holds `timeout`, `delay-between` and
+/// `max-rps`, and the `[cache]` table the `size` of `--cache-size`. The settings of a
+/// `[terminal.NAME]` table apply only when `$TERM_PROGRAM` is NAME. `options` are the
+/// long options the keys may name.
+pub fn load_args(options: &[&str]) -> anyhow::Result<Vec<String>> {
+    let Some((path, config)) = read()? else {
+        return Ok(Vec::new());
+    };
+    let program = terminal::var("TERM_PROGRAM").ok();
+    // 针对当前终端的设置放在通用设置之后，从而覆盖它们。
+    let specific = program.and_then(|name| config.terminal.get(&name));
+    let mut args = config.settings.to_args()?;
+    if let Some(settings) = specific {
+        args.extend(settings.to_args()?);
+    }
+    if let Some((option, _)) = args.iter().find(|(option, _)| !options.contains(&option.as_str())) {
+        bail!(tr!("unknown option {} in {}", option, path.display()));
+    }
+    Ok(args.into_iter().map(|(_, arg)| arg).collect())
+}
+
+/// The width and height the `[preset.NAME]` table of the configuration file
+/// sets for the preset, `None` for those it leaves alone.
+pub fn preset(name: &str) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let Some((_, config)) = read()? else {
+        return Ok((None, None));
+    };
+    Ok(match config.preset.get(name) {
+        Some(preset) => (preset.width.as_ref().map(Size::value), preset.height.as_ref().map(Size::value)),
+        None => (None, None),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# 所有终端
+width = "50%"   # 一半宽
+print_path = true
+quiet = false
+'file-type' = 'image/png'
+max-size = 10_000
+
+[network]
+timeout = 10
+max-rps = 0.5
+
+[cache]
+size = "64M"
+
+[terminal."iTerm.app"]
+protocol = "iterm2"
+
+[preset.thumb]
+width = 20
+height = "10"
+
+[terminal.WezTerm]
+height = 12
+link = "https://example.com/#top"
+network.delay-between = 2
+"#;
+
+    const OPTIONS: [&str; 13] = [
+        "width", "height", "print-path", "quiet", "file-type", "max-size", "border", "protocol", "link",
+        "timeout", "delay-between", "max-rps", "cache-size",
+    ];
+
+    /// The width and height of a preset.
+    type Sizes = (Option<String>, Option<String>);
+
+    /// The options `config` gives in the terminal `program`, and the sizes of the presets
+    /// `thumb` and `large`.
+    fn load(config: &str, program: &str) -> anyhow::Result<(Vec<String>, [Sizes; 2])> {
+        let path = std::env::temp_dir().join(format!("imgcat-config-test-{}-{program}.toml", std::process::id()));
+        fs::write(&path, config).unwrap();
+        let vars = [("IMGCAT_CONFIG", path.as_os_str()), ("TERM_PROGRAM", program.as_ref())];
+        terminal::set_environment(Some(vars.iter().map(|(name, value)| (name.into(), value.into())).collect()));
+        let loaded = load_args(&OPTIONS).and_then(|args| Ok((args, [preset("thumb")?, preset("large")?])));
+        terminal::set_environment(None);
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn options_of_each_terminal() {
+        // 针对终端的设置排在通用设置之后。
+        let general = [
+            "--width=50%",
+            "--timeout=10",
+            "--max-rps=0.5",
+            "--cache-size=64M",
+            "--print-path",
+            "--file-type=image/png",
+            "--max-size=10000",
+        ];
+        let (iterm, presets) = load(CONFIG, "iTerm.app").unwrap();
+        assert_eq!(iterm, [&general[..], &["--protocol=iterm2"]].concat());
+        assert_eq!(presets, [(Some("20".to_string()), Some("10".to_string())), (None, None)]);
+        let (wezterm, _) = load(CONFIG, "WezTerm").unwrap();
+        assert_eq!(wezterm, [&general[..], &["--height=12", "--delay-between=2", "--link=https://example.com/#top"]].concat());
+        let (other, _) = load(CONFIG, "Apple_Terminal").unwrap();
+        assert_eq!(other, general);
+    }
+
+    #[test]
+    fn invalid() {
+        let configs = [
+            "unknown = 1",
+            "width = ",
+            "width = \"50%",
+            "[table]\nwidth = 1",
+            "[network]\nretries = 3",
+            "[network]\ntimeout = \"soon\"",
+            "[cache]\nsize = true",
+            "[preset.thumb]\ncolumns = 4",
+            "link = { url = \"https://example.com/\" }",
+            "border = [\"rounded\"]",
+            "[terminal.WezTerm]\nunknown = true",
+        ];
+        for config in configs {
+            assert!(load(config, "WezTerm").is_err(), "{config}");
+        }
+    }
+}
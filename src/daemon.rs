@@ -0,0 +1,687 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use imgcat::bitmap::Bitmap;
+use imgcat::BitmapCache;
+use crate::{Cli, Command};
+
+/// Status the daemon answers for a command line it doesn't serve, which the client then runs itself.
+pub const RUN_LOCALLY: i32 = -1;
+
+/// The bitmaps decoded and scaled by the command lines the daemon runs, unset in other processes.
+static CACHE: OnceLock<Lru> = OnceLock::new();
+
+/// A file as it was when its image was decoded: its bitmaps are only taken while it is unchanged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FileKey {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// What a bitmap kept in the cache was decoded from and scaled to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Key {
+    file: FileKey,
+    /// hash of the data decoded, which a frame or conversion of the file doesn't share with it
+    data: u64,
+    /// size it was scaled to, `None` at its own size
+    size: Option<(u32, u32)>,
+}
+
+/// Bitmaps kept across command lines, the least recently used ones dropped once they take
+/// more than `capacity` bytes.
+struct Lru {
+    capacity: usize,
+    /// most recently used last
+    entries: Mutex<VecDeque<(Key, Bitmap)>>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Lru {capacity, entries: Mutex::new(VecDeque::new())}
+    }
+
+    fn get(&self, key: &Key) -> Option<Bitmap> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let i = entries.iter().position(|(k, _)| k == key)?;
+        let entry = entries.remove(i)?;
+        let bitmap = entry.1.clone();
+        entries.push_back(entry);
+        Some(bitmap)
+    }
+
+    fn put(&self, key: Key, bitmap: &Bitmap) {
+        if bitmap.pixels.len() > self.capacity {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        // 文件改变之后，之前的位图再也用不到了。
+        entries.retain(|(k, _)| k.file.path != key.file.path || (k.file == key.file && k != &key));
+        entries.push_back((key, bitmap.clone()));
+        let mut len: usize = entries.iter().map(|(_, bitmap)| bitmap.pixels.len()).sum();
+        while len > self.capacity {
+            let Some((_, dropped)) = entries.pop_front() else {
+                break;
+            };
+            len -= dropped.pixels.len();
+        }
+    }
+}
+
+/// The bitmaps kept of one file, handed to the renderers as [`imgcat::Options::cache`].
+pub struct Cached<'a> {
+    lru: &'a Lru,
+    file: FileKey,
+}
+
+impl Cached<'_> {
+    fn key(&self, data: &[u8], size: Option<(u32, u32)>) -> Key {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        Key {file: self.file.clone(), data: hasher.finish(), size}
+    }
+}
+
+impl fmt::Debug for Cached<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cached").field("file", &self.file).finish_non_exhaustive()
+    }
+}
+
+impl BitmapCache for Cached<'_> {
+    fn get(&self, data: &[u8], size: Option<(u32, u32)>) -> Option<Bitmap> {
+        self.lru.get(&self.key(data, size))
+    }
+
+    fn put(&self, data: &[u8], size: Option<(u32, u32)>, bitmap: &Bitmap) {
+        self.lru.put(self.key(data, size), bitmap);
+    }
+}
+
+/// The bitmaps the daemon keeps of the file `path`, `None` outside of the daemon and for
+/// inputs that aren't files.
+pub fn cache(path: Option<&str>) -> Option<Cached<'static>> {
+    cache_in(CACHE.get().filter(|lru| lru.capacity > 0)?, path?)
+}
+
+fn cache_in<'a>(lru: &'a Lru, path: &str) -> Option<Cached<'a>> {
+    // 相对路径按各个客户端自己的工作目录解析。
+    let path = std::fs::canonicalize(path).ok()?;
+    let metadata = std::fs::metadata(&path).ok().filter(|m| m.is_file())?;
+    let file = FileKey {path, modified: metadata.modified().ok()?, len: metadata.len()};
+    Some(Cached {lru, file})
+}
+
+/// Whether the daemon can run `args` for a client. Everything reading keys or
+/// querying the terminal needs the controlling terminal of the client, and watching
+/// files would keep running after the client is gone.
+pub fn serves(args: &Cli) -> bool {
+    !(args.daemon
+        || args.interactive
+        || args.pager
+        || args.popup
+        || args.download
+        || args.select
+        || args.watch.is_some()
+        || args.monitor.is_some()
+        || args.serve.is_some()
+        || args.previewer.is_some()
+        || args.vifm_preview.is_some()
+        || args.yazi_preview.is_some()
+        || args.mc_preview
+        || matches!(args.command, Some(Command::Check | Command::Compare {interactive: true, ..} | Command::Layer {..})))
+}
+
+/// Whether `argv` asks to be run by the daemon, with `--use-daemon` or `$IMGCAT_USE_DAEMON`,
+/// found without parsing the whole command line.
+fn uses_daemon(argv: &[OsString]) -> bool {
+    let given = argv.iter()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--use-daemon");
+    // 与其他开关的环境变量一样，0、no、off 等假值表示没有给出。
+    given || std::env::var("IMGCAT_USE_DAEMON").is_ok_and(|value| {
+        !["", "n", "no", "f", "false", "off", "0"].contains(&value.to_ascii_lowercase().as_str())
+    })
+}
+
+/// The socket given by `--socket` in `argv`, or by `$IMGCAT_SOCKET`, without parsing the whole command line.
+fn socket_arg(argv: &[OsString]) -> Option<PathBuf> {
+    let mut args = argv.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_str()?;
+        if arg == "--" {
+            break;
+        }
+        if arg == "--socket" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--socket=") {
+            return Some(path.into());
+        }
+    }
+    std::env::var_os("IMGCAT_SOCKET").map(PathBuf::from)
+}
+
+#[cfg(unix)]
+pub use unix::{forward, serve};
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, MutexGuard};
+    use std::{env, fs, mem, panic, ptr, thread};
+    use anyhow::{bail, Context};
+    use imgcat::terminal;
+    use log::{debug, error, info};
+    use crate::i18n::tr;
+    use super::{socket_arg, uses_daemon, Lru, CACHE, RUN_LOCALLY};
+
+    /// Version of the protocol, sent with the file descriptors of the client.
+    const VERSION: u8 = 1;
+
+    /// Longest request taken from a client, in bytes: far more than the command lines and
+    /// environments the system lets a client have, so that a bogus length can't exhaust
+    /// the memory of the daemon.
+    const MAX_REQUEST_LEN: usize = 16 << 20;
+
+    /// Directory of the default socket: `imgcat` in `$XDG_RUNTIME_DIR`, or `imgcat-UID`
+    /// in the temporary directory, which other users can write to.
+    fn socket_dir() -> PathBuf {
+        match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => Path::new(&dir).join("imgcat"),
+            None => env::temp_dir().join(format!("imgcat-{}", unsafe { libc::getuid() })),
+        }
+    }
+
+    /// Socket of the daemon: `--socket`, or `daemon.sock` in [`socket_dir`].
+    fn socket_path(argv: &[OsString]) -> PathBuf {
+        socket_arg(argv).unwrap_or_else(|| socket_dir().join("daemon.sock"))
+    }
+
+    /// Create `dir` accessible to the user only, or make sure that it already is,
+    /// so that no other user can put a socket of their own in it.
+    fn private_dir(dir: &Path) -> anyhow::Result<()> {
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e).with_context(|| tr!("failed to create {}", dir.display())),
+        }
+        let metadata = fs::symlink_metadata(dir).with_context(|| tr!("failed to read {}", dir.display()))?;
+        if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } || metadata.mode() & 0o077 != 0 {
+            bail!(tr!("{} must be a directory of the user that other users can't access", dir.display()));
+        }
+        Ok(())
+    }
+
+    /// The user running the process at the other end of `stream`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+        let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                ptr::addr_of_mut!(credentials).cast(),
+                &mut len,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(credentials.uid)
+    }
+
+    /// The user running the process at the other end of `stream`.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+        let (mut uid, mut gid) = (0, 0);
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+
+    /// Whether the process at the other end of `stream` runs as the same user as this one:
+    /// the standard streams and environment are only ever handed to, and taken from, it.
+    fn same_user(stream: &UnixStream) -> bool {
+        peer_uid(stream).is_ok_and(|uid| uid == unsafe { libc::getuid() })
+    }
+
+    /// Have the daemon run the command line `argv`, if it asks to with `--use-daemon` and
+    /// a daemon of the same user is listening, and return the exit status.
+    ///
+    /// The daemon gets the standard streams, working directory and environment of
+    /// this process, so the images are written to this terminal as if imgcat ran here.
+    /// Return `None` when no daemon is listening or it leaves the command line to this process.
+    pub fn forward(argv: &[OsString]) -> Option<i32> {
+        if !uses_daemon(argv) || argv.iter().skip(1).any(|arg| arg == "--daemon") {
+            return None;
+        }
+        let socket = socket_path(argv);
+        let mut stream = UnixStream::connect(&socket).ok()?;
+        if !same_user(&stream) {
+            eprintln!("{}", tr!("imgcat daemon: {} is served by another user, running on its own", socket.display()));
+            return None;
+        }
+        match request(&mut stream, argv) {
+            Ok(RUN_LOCALLY) => None,
+            Ok(status) => Some(status),
+            Err(e) => {
+                eprintln!("imgcat daemon: {e:#}");
+                Some(1)
+            }
+        }
+    }
+
+    fn request(stream: &mut UnixStream, argv: &[OsString]) -> anyhow::Result<i32> {
+        let mut message = Vec::new();
+        let mut push = |field: &[u8]| {
+            message.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            message.extend_from_slice(field);
+        };
+        push(env::current_dir()?.as_os_str().as_bytes());
+        push(&(argv.len() as u32).to_le_bytes());
+        argv.iter().for_each(|arg| push(arg.as_bytes()));
+        let vars: Vec<OsString> = env::vars_os()
+            .map(|(name, value)| [name, value].join("=".as_ref()))
+            .collect();
+        push(&(vars.len() as u32).to_le_bytes());
+        vars.iter().for_each(|var| push(var.as_bytes()));
+        send_fds(stream, &[0, 1, 2], &[VERSION]).with_context(|| tr!("failed to send the standard streams"))?;
+        stream.write_all(&(message.len() as u32).to_le_bytes())?;
+        stream.write_all(&message)?;
+        let mut status = [0; 4];
+        stream.read_exact(&mut status).with_context(|| tr!("the daemon closed the connection"))?;
+        Ok(i32::from_le_bytes(status))
+    }
+
+    /// A command line received from a client.
+    struct Request {
+        /// the client's stdin, stdout and stderr
+        fds: [OwnedFd; 3],
+        cwd: PathBuf,
+        argv: Vec<OsString>,
+        vars: Vec<(OsString, OsString)>,
+    }
+
+    /// Read a request, or `None` if the client hung up without sending one, as
+    /// another daemon checking whether this one is listening does.
+    fn receive(stream: &mut UnixStream) -> anyhow::Result<Option<Request>> {
+        let Some((version, fds)) = recv_fds(stream)? else {
+            return Ok(None);
+        };
+        if version != VERSION {
+            bail!(tr!("unsupported protocol version {}", version));
+        }
+        let fds: [OwnedFd; 3] = match fds.try_into() {
+            Ok(fds) => fds,
+            Err(fds) => bail!(tr!("expected 3 file descriptors, got {}", fds.len())),
+        };
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_REQUEST_LEN {
+            bail!(tr!("request of {} bytes is longer than {}", len, MAX_REQUEST_LEN));
+        }
+        let mut message = vec![0; len];
+        stream.read_exact(&mut message)?;
+        let mut fields = split_fields(&message)?.into_iter();
+        let mut field = || fields.next().with_context(|| tr!("truncated request"));
+        let cwd = PathBuf::from(OsString::from_vec(field()?));
+        let argc = count(field()?)?;
+        let argv = (0..argc).map(|_| field().map(OsString::from_vec)).collect::<anyhow::Result<_>>()?;
+        let varc = count(field()?)?;
+        let vars = (0..varc)
+            .map(|_| {
+                let var = field()?;
+                let (name, value) = var.split_at(var.iter().position(|&b| b == b'=').with_context(|| tr!("invalid variable"))?);
+                Ok((OsString::from_vec(name.to_vec()), OsString::from_vec(value[1..].to_vec())))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(Request {fds, cwd, argv, vars}))
+    }
+
+    /// Split a message into its fields, each preceded by its length.
+    fn split_fields(mut message: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut fields = Vec::new();
+        while !message.is_empty() {
+            let len = message.get(..4).with_context(|| tr!("truncated request"))?;
+            let len = u32::from_le_bytes(len.try_into()?) as usize;
+            fields.push(message.get(4..4 + len).with_context(|| tr!("truncated request"))?.to_vec());
+            message = &message[4 + len..];
+        }
+        Ok(fields)
+    }
+
+    fn count(field: Vec<u8>) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(field.try_into().ok().with_context(|| tr!("invalid count"))?))
+    }
+
+    /// Listen on `socket`, or on the default one, and run the command lines of the clients
+    /// of the same user with `run`, each on a thread of its own, keeping the HTTP connections,
+    /// up to `cache_len` bytes of bitmaps and everything else loaded in between.
+    pub fn serve(socket: Option<&Path>, cache_len: usize, run: fn(Vec<OsString>, bool) -> anyhow::Result<i32>) -> anyhow::Result<()> {
+        let socket = match socket {
+            Some(socket) => socket.to_path_buf(),
+            None => {
+                private_dir(&socket_dir())?;
+                socket_path(&[])
+            }
+        };
+        let socket = socket.as_path();
+        if UnixStream::connect(socket).is_ok() {
+            bail!(tr!("a daemon is already listening on {}", socket.display()));
+        }
+        // 上一个守护进程退出时留下的 socket 文件无法再连接，可以直接删除。
+        if fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+            fs::remove_file(socket).with_context(|| tr!("failed to remove {}", socket.display()))?;
+        }
+        let listener = UnixListener::bind(socket)
+            .with_context(|| tr!("failed to listen on {}", socket.display()))?;
+        CACHE.get_or_init(|| Lru::new(cache_len));
+        // 客户端的命令行 panic 时，信息写到客户端的 stderr。
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| match terminal::session() {
+            Some(_) => {
+                let _ = writeln!(terminal::stderr(), "{info}");
+            }
+            None => default_hook(info),
+        }));
+        info!("listening on {}", socket.display());
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("{}", tr!("failed to accept a connection: {}", e));
+                    continue;
+                }
+            };
+            // 每个连接由自己的线程处理，运行时间长的命令行不会挡住其他客户端。
+            let spawned = thread::Builder::new()
+                .name("imgcat client".to_string())
+                .spawn(move || serve_client(stream, run));
+            if let Err(e) = spawned {
+                error!("{}", tr!("failed to start a thread: {}", e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive the request of the client at the other end of `stream`, run it, and answer
+    /// its exit status.
+    fn serve_client(mut stream: UnixStream, run: fn(Vec<OsString>, bool) -> anyhow::Result<i32>) {
+        if !same_user(&stream) {
+            error!("{}", tr!("refused a connection from another user"));
+            return;
+        }
+        let request = match receive(&mut stream) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(e) => {
+                error!("{}", tr!("invalid request: {}", format!("{e:#}")));
+                return;
+            }
+        };
+        debug!("running {:?}", request.argv);
+        let status = handle(request, run);
+        let _ = stream.write_all(&status.to_le_bytes());
+    }
+
+    /// Run a request on this thread, with the standard streams, working directory and
+    /// environment of the client.
+    ///
+    /// Those of the daemon are left alone, as the command lines of other clients run at the
+    /// same time: the streams and environment of the client are reached through
+    /// [`terminal::stdout`], [`terminal::var`] and the like, and the working directory is
+    /// changed for this thread only.
+    fn handle(request: Request, run: fn(Vec<OsString>, bool) -> anyhow::Result<i32>) -> i32 {
+        let Request {fds, cwd, argv, vars} = request;
+        terminal::enter(Some(Arc::new(terminal::Session {vars, streams: Some(fds.map(File::from))})));
+        let status = match enter_dir(&cwd) {
+            Ok(_guard) => match panic::catch_unwind(|| run(argv, true)) {
+                Ok(Ok(status)) => status,
+                Ok(Err(e)) => crate::report_error(&e),
+                // panic 的信息已经由 serve 设置的 hook 输出到客户端的 stderr。
+                Err(_) => 101,
+            },
+            Err(e) => crate::report_error(&anyhow::Error::new(e).context(tr!("failed to enter {}", cwd.display()))),
+        };
+        let _ = terminal::stdout().flush();
+        terminal::enter(None);
+        status
+    }
+
+    /// Held while a command line runs where a thread can't have a working directory of its
+    /// own: the command lines of the clients then run one after another.
+    #[cfg(not(target_os = "linux"))]
+    static WORKING_DIR: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Make `dir` the working directory of this thread and of the threads it spawns, until
+    /// the guard returned is dropped.
+    #[cfg(target_os = "linux")]
+    fn enter_dir(dir: &Path) -> io::Result<Option<MutexGuard<'static, ()>>> {
+        // 线程不再和进程共享工作目录，chdir 只影响这个线程和它之后启动的线程。
+        if unsafe { libc::unshare(libc::CLONE_FS) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        env::set_current_dir(dir)?;
+        Ok(None)
+    }
+
+    /// Make `dir` the working directory of the process until the guard returned is dropped,
+    /// other command lines waiting for it meanwhile.
+    #[cfg(not(target_os = "linux"))]
+    fn enter_dir(dir: &Path) -> io::Result<Option<MutexGuard<'static, ()>>> {
+        let guard = WORKING_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_current_dir(dir)?;
+        Ok(Some(guard))
+    }
+
+    /// Send `fds` along with `data` as an `SCM_RIGHTS` control message.
+    fn send_fds(stream: &UnixStream, fds: &[RawFd], data: &[u8]) -> io::Result<()> {
+        let fds_len = mem::size_of_val(fds) as u32;
+        let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+        let mut iov = libc::iovec {iov_base: data.as_ptr() as *mut _, iov_len: data.len()};
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = control.len() as _;
+        unsafe {
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = libc::SOL_SOCKET;
+            (*header).cmsg_type = libc::SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(header).cast(), fds.len());
+            if libc::sendmsg(stream.as_raw_fd(), &message, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive one byte and the file descriptors sent with it by [`send_fds`], or `None` at the end of the stream.
+    fn recv_fds(stream: &UnixStream) -> io::Result<Option<(u8, Vec<OwnedFd>)>> {
+        const MAX_FDS: u32 = 3;
+        let mut byte = 0u8;
+        let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(MAX_FDS * mem::size_of::<RawFd>() as u32) } as usize];
+        let mut iov = libc::iovec {iov_base: ptr::addr_of_mut!(byte).cast(), iov_len: 1};
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = control.len() as _;
+        let mut fds = Vec::new();
+        unsafe {
+            match libc::recvmsg(stream.as_raw_fd(), &mut message, 0) {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => return Ok(None),
+                _ => {}
+            }
+            let mut header = libc::CMSG_FIRSTHDR(&message);
+            while !header.is_null() {
+                if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+                    let data = libc::CMSG_DATA(header).cast::<RawFd>();
+                    let count = ((*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+                    fds.extend((0..count).map(|i| OwnedFd::from_raw_fd(data.add(i).read_unaligned())));
+                }
+                header = libc::CMSG_NXTHDR(&message, header);
+            }
+        }
+        Ok(Some((byte, fds)))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::os::fd::AsFd;
+        use std::os::unix::fs::MetadataExt;
+        use super::*;
+
+        #[test]
+        fn request_round_trip() {
+            let (mut client, mut daemon) = UnixStream::pair().unwrap();
+            let argv: Vec<OsString> = ["imgcat", "-W", "40", "photo with spaces.jpg", ""].map(Into::into).to_vec();
+            let sent = argv.clone();
+            let client = thread::spawn(move || request(&mut client, &sent).unwrap());
+            let request = receive(&mut daemon).unwrap().unwrap();
+            assert_eq!(request.argv, argv);
+            assert_eq!(request.cwd, env::current_dir().unwrap());
+            assert_eq!(request.vars, env::vars_os().collect::<Vec<_>>());
+            let [_, stdout, _] = request.fds.map(File::from);
+            let ours = File::from(io::stdout().as_fd().try_clone_to_owned().unwrap());
+            assert_eq!(stdout.metadata().unwrap().ino(), ours.metadata().unwrap().ino());
+            daemon.write_all(&7i32.to_le_bytes()).unwrap();
+            assert_eq!(client.join().unwrap(), 7);
+        }
+
+        #[test]
+        fn receive_nothing_from_a_client_hanging_up() {
+            let (client, mut daemon) = UnixStream::pair().unwrap();
+            drop(client);
+            assert!(receive(&mut daemon).unwrap().is_none());
+        }
+
+        #[test]
+        fn receive_rejects_long_requests() {
+            let (mut client, mut daemon) = UnixStream::pair().unwrap();
+            send_fds(&client, &[0, 1, 2], &[VERSION]).unwrap();
+            client.write_all(&(MAX_REQUEST_LEN as u32 + 1).to_le_bytes()).unwrap();
+            let Err(e) = receive(&mut daemon) else {
+                panic!("took a request longer than {MAX_REQUEST_LEN} bytes");
+            };
+            assert!(e.to_string().contains(&MAX_REQUEST_LEN.to_string()), "{e}");
+        }
+
+        #[test]
+        fn receive_rejects_missing_streams() {
+            let (client, mut daemon) = UnixStream::pair().unwrap();
+            send_fds(&client, &[1], &[VERSION]).unwrap();
+            assert!(receive(&mut daemon).is_err());
+        }
+
+        #[test]
+        fn receive_rejects_truncated_requests() {
+            let (mut client, mut daemon) = UnixStream::pair().unwrap();
+            send_fds(&client, &[0, 1, 2], &[VERSION]).unwrap();
+            // cwd 字段声明了 100 字节，实际只有 2 字节。
+            let message = [&100u32.to_le_bytes()[..], b"/x"].concat();
+            client.write_all(&(message.len() as u32).to_le_bytes()).unwrap();
+            client.write_all(&message).unwrap();
+            assert!(receive(&mut daemon).is_err());
+        }
+    }
+}
+
+/// The daemon only runs on unix, elsewhere every invocation runs on its own.
+#[cfg(not(unix))]
+pub fn forward(_argv: &[OsString]) -> Option<i32> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn serve(_socket: Option<&std::path::Path>, _cache_len: usize, _run: fn(Vec<OsString>, bool) -> anyhow::Result<i32>) -> anyhow::Result<()> {
+    anyhow::bail!(crate::i18n::tr!("--daemon is only supported on unix systems"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    /// The key of a bitmap of `path` as it was at `modified`.
+    fn key(path: &str, modified: u64, size: Option<(u32, u32)>) -> Key {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(modified);
+        Key {file: FileKey {path: path.into(), modified, len: 1}, data: 0, size}
+    }
+
+    /// A bitmap of 16 bytes.
+    fn bitmap() -> Bitmap {
+        Bitmap::new(2, 2)
+    }
+
+    #[test]
+    fn lru_drops_least_recently_used() {
+        let lru = Lru::new(3 * 16);
+        lru.put(key("a", 0, None), &bitmap());
+        lru.put(key("b", 0, None), &bitmap());
+        lru.put(key("c", 0, None), &bitmap());
+        assert!(lru.get(&key("a", 0, None)).is_some());
+        lru.put(key("d", 0, None), &bitmap());
+        assert!(lru.get(&key("b", 0, None)).is_none());
+        for path in ["a", "c", "d"] {
+            assert!(lru.get(&key(path, 0, None)).is_some(), "{path}");
+        }
+    }
+
+    #[test]
+    fn lru_keeps_sizes_of_a_file_until_it_changes() {
+        let lru = Lru::new(1 << 20);
+        lru.put(key("a", 0, None), &bitmap());
+        lru.put(key("a", 0, Some((1, 1))), &Bitmap::new(1, 1));
+        assert_eq!(lru.get(&key("a", 0, Some((1, 1)))).map(|b| b.width), Some(1));
+        assert!(lru.get(&key("a", 0, None)).is_some());
+        lru.put(key("a", 1, None), &bitmap());
+        assert!(lru.get(&key("a", 0, None)).is_none());
+        assert!(lru.get(&key("a", 0, Some((1, 1)))).is_none());
+        assert!(lru.get(&key("a", 1, None)).is_some());
+    }
+
+    #[test]
+    fn lru_skips_bitmaps_larger_than_it() {
+        let lru = Lru::new(8);
+        lru.put(key("a", 0, None), &bitmap());
+        assert!(lru.get(&key("a", 0, None)).is_none());
+    }
+
+    #[test]
+    fn cache_keys_files_by_contents() {
+        let path = std::env::temp_dir().join(format!("imgcat-daemon-test-{}.png", std::process::id()));
+        std::fs::write(&path, b"image").unwrap();
+        let lru = Lru::new(1 << 20);
+        let cached = cache_in(&lru, path.to_str().unwrap()).unwrap();
+        cached.put(b"image", None, &bitmap());
+        assert!(cached.get(b"image", None).is_some());
+        // 同一个文件的一帧或转换后的图片不是同一张位图。
+        assert!(cached.get(b"frame", None).is_none());
+        std::fs::write(&path, b"other image").unwrap();
+        let changed = cache_in(&lru, path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(changed.get(b"image", None).is_none());
+        assert!(cache_in(&lru, "no such file").is_none());
+    }
+}
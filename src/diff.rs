@@ -0,0 +1,92 @@
+use anyhow::bail;
+use crate::bitmap::Bitmap;
+use crate::i18n::tr;
+
+/// Largest difference of a channel still considered equal, to absorb encoder noise.
+const TOLERANCE: u8 = 2;
+
+/// How much two images differ.
+pub struct Comparison {
+    /// number of pixels differing by more than the tolerance in any channel
+    pub differing: usize,
+    pub total: usize,
+    /// mean absolute difference of the channels, from 0 to 255
+    pub mean_delta: f64,
+    /// Hamming distance between the difference hashes of the images, from 0 to 64
+    pub hash_distance: u32,
+}
+
+impl Comparison {
+    /// Share of differing pixels, in percent.
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.differing as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// Compare two images of the same size pixel by pixel, returning the
+/// comparison and an image of `b` faded to gray with the differing pixels in red.
+pub fn compare(a: &Bitmap, b: &Bitmap) -> anyhow::Result<(Comparison, Bitmap)> {
+    if (a.width, a.height) != (b.width, b.height) {
+        bail!(tr!("the images have different dimensions: {}x{} and {}x{}", a.width, a.height, b.width, b.height));
+    }
+    let mut highlighted = Bitmap::new(b.width, b.height);
+    let mut differing = 0;
+    let mut delta_sum = 0u64;
+    for (i, (pa, pb)) in a.pixels.chunks(4).zip(b.pixels.chunks(4)).enumerate() {
+        let deltas: Vec<u8> = pa.iter().zip(pb).map(|(x, y)| x.abs_diff(*y)).collect();
+        delta_sum += deltas.iter().map(|d| *d as u64).sum::<u64>();
+        let out = &mut highlighted.pixels[i * 4..i * 4 + 4];
+        if deltas.iter().any(|d| *d > TOLERANCE) {
+            differing += 1;
+            out.copy_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // 相同的像素淡化成浅灰色，只作为定位差异的背景。
+            let gray = (luminance(pb) * 0.3 + 255.0 * 0.7) as u8;
+            out.copy_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+    let total = a.pixels.len() / 4;
+    let comparison = Comparison {
+        differing,
+        total,
+        mean_delta: delta_sum as f64 / (total * 4).max(1) as f64,
+        hash_distance: (difference_hash(a) ^ difference_hash(b)).count_ones(),
+    };
+    Ok((comparison, highlighted))
+}
+
+fn luminance(p: &[u8]) -> f64 {
+    0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64
+}
+
+/// The difference hash of an image: shrunk to 9x8 gray pixels, each bit tells
+/// whether a pixel is brighter than its right neighbour. Similar looking images
+/// have hashes differing in few bits.
+fn difference_hash(bitmap: &Bitmap) -> u64 {
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    // 缩小时取每个区域的平均亮度。
+    let cell = |cx: usize, cy: usize| -> f64 {
+        let (x0, x1) = (cx * width / 9, ((cx + 1) * width / 9).max(cx * width / 9 + 1).min(width));
+        let (y0, y1) = (cy * height / 8, ((cy + 1) * height / 8).max(cy * height / 8 + 1).min(height));
+        let mut sum = 0.0;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                sum += luminance(&bitmap.get(x, y));
+            }
+        }
+        sum / ((x1 - x0) * (y1 - y0)).max(1) as f64
+    };
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = hash << 1 | (cell(x, y) > cell(x + 1, y)) as u64;
+        }
+    }
+    hash
+}
@@ -0,0 +1,59 @@
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+/// Hash functions available to --checksum.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Algorithm {
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    /// The digest of `data` as lowercase hex.
+    pub fn hex_digest(self, data: &[u8]) -> String {
+        let digest: [u8; 32] = match self {
+            Algorithm::Sha256 => Sha256::digest(data).into(),
+            Algorithm::Blake3 => blake3::hash(data).into(),
+        };
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The input of the official BLAKE3 test vectors: bytes counting up modulo 251.
+    fn counting(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        let hex = |data: &[u8]| Algorithm::Sha256.hex_digest(data);
+        assert_eq!(hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // 56 字节：长度放不进最后一个块，需要补一个块。
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        );
+        assert_eq!(hex(&[b'a'; 1_000_000]), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    }
+
+    #[test]
+    fn blake3_vectors() {
+        let hex = |data: &[u8]| Algorithm::Blake3.hex_digest(data);
+        assert_eq!(hex(b""), "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+        assert_eq!(hex(b"abc"), "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+        assert_eq!(hex(&counting(1025)), "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444");
+        assert_eq!(hex(&counting(3072)), "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2");
+    }
+}
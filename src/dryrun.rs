@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use anyhow::{bail, Context};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use crate::{format_size, http_client, layout, local_path, pace, probe, stream, terminal, Cli, Image, InputKind};
+use crate::location::{mirrors, Location};
+use crate::i18n::tr;
+
+/// Number of bytes read from the start of each input, enough for the headers
+/// of the supported formats unless a JPEG carries a large EXIF thumbnail.
+const PEEK_LEN: u64 = 64 * 1024;
+
+/// The start of an input and its total size, if known.
+struct Peek {
+    head: Vec<u8>,
+    size: Option<u64>,
+    /// the input is an MJPEG stream
+    stream: bool,
+}
+
+/// Check every input without drawing anything: resolve it, read the start of
+/// it, sniff its format and compute the size it would be displayed at.
+///
+/// Print one line per input and return the inputs that failed, with their errors.
+pub fn run<'a>(inputs: &'a [(String, InputKind)], args: &Cli) -> io::Result<Vec<(&'a String, anyhow::Error)>> {
+    let size = terminal::size();
+    let mut out = terminal::stdout();
+    let mut failures = Vec::new();
+    for (path, kind) in inputs {
+        let peek = match peek(path, *kind) {
+            Ok(peek) => peek,
+            Err(e) => {
+                writeln!(out, "{path}: {}: {e:#}", tr!("error"))?;
+                failures.push((path, e));
+                continue;
+            }
+        };
+        let bytes = peek.size.map_or(tr!("unknown size").to_string(), |n| format_size(n as usize));
+        if peek.stream {
+            writeln!(out, "{path}: {}", tr!("MJPEG stream"))?;
+            continue;
+        }
+        let Some(info) = probe::probe(&peek.head) else {
+            writeln!(out, "{path}: {}, {bytes}", tr!("unknown format"))?;
+            continue;
+        };
+        let cells = layout::display_cells(&peek.head, args.width, args.height, args.preserve_aspect_ratio, &size)
+            .map_or(tr!("unknown display size").to_string(), |(columns, rows)| tr!("{}x{} cells", columns, rows));
+        writeln!(out, "{path}: {} {}x{}, {bytes}, {cells}", info.format, info.width, info.height)?;
+    }
+    Ok(failures)
+}
+
+fn peek(path: &str, kind: InputKind) -> anyhow::Result<Peek> {
+    // 镜像依次尝试，报告第一个能读取的。
+    if let Some(urls) = mirrors(path, kind) {
+        let mut failure = None;
+        for (url, _) in urls {
+            match peek(url, InputKind::Url) {
+                Ok(peek) => return Ok(peek),
+                Err(e) => failure = Some(e),
+            }
+        }
+        return Err(failure.expect("at least two mirrors"));
+    }
+    match Location::resolve(path, kind)? {
+        Location::Url(u) => {
+            // 只请求开头的一部分；不支持 Range 的服务器会返回整个文件，此时只读取开头。
+            pace::wait();
+            let response = http_client()?
+                .get(u)
+                .header(RANGE, format!("bytes=0-{}", PEEK_LEN - 1))
+                .send()
+                .with_context(|| tr!("failed to connect to {}", path))?;
+            if !response.status().is_success() {
+                bail!(tr!("{} answered {}", path, response.status()));
+            }
+            let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok());
+            let size = header(CONTENT_RANGE)
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|total| total.parse().ok())
+                .or_else(|| header(CONTENT_LENGTH).and_then(|n| n.parse().ok()));
+            if stream::is_mjpeg(&response) {
+                return Ok(Peek {head: Vec::new(), size: None, stream: true});
+            }
+            let mut head = Vec::new();
+            response.take(PEEK_LEN).read_to_end(&mut head)
+                .with_context(|| tr!("failed to fetch image data from {}", path))?;
+            Ok(Peek {head, size, stream: false})
+        }
+        Location::Git(object) => {
+            let image = Image::from_git(path, object)?;
+            Ok(Peek {size: Some(image.len() as u64), head: image.data.to_vec(), stream: false})
+        }
+        Location::Data(url) => {
+            let image = Image::from_data_url(url)?;
+            Ok(Peek {size: Some(image.len() as u64), head: image.data.to_vec(), stream: false})
+        }
+        Location::File(path) => {
+            let f = local_path(path);
+            let file = File::open(f.as_ref()).with_context(|| tr!("failed to open file {}", f))?;
+            // 管道等不是普通文件的输入没有预先知道的大小。
+            let size = file.metadata().ok().filter(|m| m.is_file()).map(|m| m.len());
+            let mut head = Vec::new();
+            file.take(PEEK_LEN).read_to_end(&mut head)
+                .with_context(|| tr!("failed to read from file {}", f))?;
+            Ok(Peek {head, size, stream: false})
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+use thiserror::Error;
+
+/// Largest image decoded, in bytes of RGBA pixels.
+pub const MAX_DECODED_LEN: u64 = 1 << 30;
+
+/// The errors of the library, telling apart what went wrong so that callers, and
+/// the exit status of imgcat, can react to each kind differently.
+#[derive(Debug, Error)]
+pub enum ImgcatError {
+    /// reading the image or writing to the terminal failed
+    #[error(transparent)]
+    Io(io::Error),
+    /// downloading the image failed
+    #[cfg(feature = "network")]
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// the image is malformed, or in a format that can't be decoded
+    #[error("{0}")]
+    Decode(String),
+    /// the terminal can't do what was asked
+    #[error("{0}")]
+    UnsupportedTerminal(String),
+    /// the image is too large to be handled
+    #[error("the image takes {size} bytes, more than the limit of {limit}")]
+    PayloadTooLarge { size: u64, limit: u64 },
+}
+
+impl ImgcatError {
+    /// Exit status of imgcat when it stops because of this error. 1, 2 and 3 are
+    /// taken by other failures and by command line errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ImgcatError::Io(_) => 4,
+            #[cfg(feature = "network")]
+            ImgcatError::Http(_) => 5,
+            ImgcatError::Decode(_) => 6,
+            ImgcatError::UnsupportedTerminal(_) => 7,
+            ImgcatError::PayloadTooLarge {..} => 8,
+        }
+    }
+}
+
+/// Takes back the error of the library an I/O error carries, as the renderers return the
+/// errors of decoding images as I/O errors.
+impl From<io::Error> for ImgcatError {
+    fn from(e: io::Error) -> Self {
+        match e.get_ref().is_some_and(|inner| inner.is::<ImgcatError>()) {
+            true => *e.into_inner().and_then(|inner| inner.downcast().ok()).expect("checked above"),
+            false => ImgcatError::Io(e),
+        }
+    }
+}
+
+/// Carries the error of the library through [`Write`](std::io::Write) and [`Read`](std::io::Read),
+/// whose implementations only return I/O errors.
+impl From<ImgcatError> for io::Error {
+    fn from(e: ImgcatError) -> Self {
+        match e {
+            ImgcatError::Io(e) => e,
+            ImgcatError::Decode(_) | ImgcatError::PayloadTooLarge {..} => io::Error::new(io::ErrorKind::InvalidData, e),
+            ImgcatError::UnsupportedTerminal(_) => io::Error::new(io::ErrorKind::Unsupported, e),
+            #[cfg(feature = "network")]
+            ImgcatError::Http(_) => io::Error::other(e),
+        }
+    }
+}
+
+pub type Result<T, E = ImgcatError> = std::result::Result<T, E>;
@@ -0,0 +1,228 @@
+use std::io::Cursor;
+use ::exif::{Field, In, Reader, Tag, Value};
+
+/// A curated set of EXIF fields of a JPEG, PNG or WebP image, as (name, value) pairs
+/// ready to be printed: camera, lens, exposure, ISO, taken-at, orientation and GPS position.
+///
+/// Fields missing from the image are left out, so this is empty for images without EXIF data.
+pub fn fields(data: &[u8]) -> Vec<(&'static str, String)> {
+    let Ok(exif) = Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return Vec::new();
+    };
+    let find = |tag| exif.get_field(tag, In::PRIMARY);
+    let text = |tag| find(tag).and_then(ascii);
+    let rational = |tag| find(tag).and_then(|f| rational_at(f, 0));
+
+    let mut fields = Vec::new();
+    let make = text(Tag::Make);
+    let model = text(Tag::Model);
+    let camera = match (make, model) {
+        // 很多相机的型号本身已经包含了厂商名，例如 "Canon EOS R5"。
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+    if let Some(camera) = camera {
+        fields.push(("Camera", camera));
+    }
+    if let Some(lens) = text(Tag::LensModel) {
+        fields.push(("Lens", lens));
+    }
+    let mut exposure = Vec::new();
+    if let Some(time) = rational(Tag::ExposureTime) {
+        exposure.push(if time > 0.0 && time < 1.0 {
+            format!("1/{:.0} s", 1.0 / time)
+        } else {
+            format!("{time} s")
+        });
+    }
+    if let Some(f) = rational(Tag::FNumber) {
+        exposure.push(format!("f/{f:.1}"));
+    }
+    if let Some(focal) = rational(Tag::FocalLength) {
+        exposure.push(format!("{focal:.0} mm"));
+    }
+    if !exposure.is_empty() {
+        fields.push(("Exposure", exposure.join(", ")));
+    }
+    if let Some(iso) = find(Tag::PhotographicSensitivity).and_then(|f| f.value.get_uint(0)) {
+        fields.push(("ISO", iso.to_string()));
+    }
+    if let Some(taken) = text(Tag::DateTimeOriginal) {
+        // EXIF 的日期格式是 "YYYY:MM:DD HH:MM:SS"。
+        let taken = match taken.split_once(' ') {
+            Some((date, time)) => format!("{} {time}", date.replace(':', "-")),
+            None => taken,
+        };
+        fields.push(("Taken", taken));
+    }
+    // 1 是正常方向，几乎所有相机都会写出，不必显示。
+    let orientation = match find(Tag::Orientation).and_then(|f| f.value.get_uint(0)) {
+        Some(2) => Some("mirrored horizontally"),
+        Some(3) => Some("rotated 180°"),
+        Some(4) => Some("mirrored vertically"),
+        Some(5) => Some("mirrored horizontally, rotated 270° clockwise"),
+        Some(6) => Some("rotated 90° clockwise"),
+        Some(7) => Some("mirrored horizontally, rotated 90° clockwise"),
+        Some(8) => Some("rotated 270° clockwise"),
+        _ => None,
+    };
+    if let Some(orientation) = orientation {
+        fields.push(("Orientation", orientation.to_string()));
+    }
+    let coordinate = |reference, value| {
+        let f = find(value)?;
+        let degrees = rational_at(f, 0)? + rational_at(f, 1)? / 60.0 + rational_at(f, 2)? / 3600.0;
+        Some(format!("{degrees:.5}° {}", text(reference)?))
+    };
+    if let (Some(latitude), Some(longitude)) = (
+        coordinate(Tag::GPSLatitudeRef, Tag::GPSLatitude),
+        coordinate(Tag::GPSLongitudeRef, Tag::GPSLongitude),
+    ) {
+        fields.push(("GPS", format!("{latitude}, {longitude}")));
+    }
+    fields
+}
+
+/// The text of an ASCII field, without the padding some cameras add.
+fn ascii(field: &Field) -> Option<String> {
+    let Value::Ascii(lines) = &field.value else {
+        return None;
+    };
+    let text = String::from_utf8_lossy(lines.first()?);
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The `index`-th RATIONAL of a field.
+fn rational_at(field: &Field, index: usize) -> Option<f64> {
+    match &field.value {
+        Value::Rational(values) => values.get(index).filter(|r| r.denom != 0).map(|r| r.to_f64()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::exif::experimental::Writer;
+    use ::exif::Rational;
+    use super::*;
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {tag, ifd_num: In::PRIMARY, value}
+    }
+
+    fn ascii(text: &str) -> Value {
+        Value::Ascii(vec![text.as_bytes().to_vec()])
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Value {
+        Value::Rational(values.iter().map(|&(num, denom)| Rational {num, denom}).collect())
+    }
+
+    /// The TIFF structure holding `fields`, in either byte order.
+    fn tiff(fields: &[Field], little_endian: bool) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write(&mut out, little_endian).unwrap();
+        out.into_inner()
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut data = b"\xff\xd8\xff\xe1".to_vec();
+        data.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(tiff);
+        data.extend_from_slice(b"\xff\xd9");
+        data
+    }
+
+    fn png(tiff: &[u8]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01\x08\x02\0\0\0\0\0\0\0".to_vec();
+        data.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+        data.extend_from_slice(b"eXIf");
+        data.extend_from_slice(tiff);
+        data.extend_from_slice(b"\0\0\0\0\0\0\0\0IEND\xae\x42\x60\x82");
+        data
+    }
+
+    fn webp(tiff: &[u8]) -> Vec<u8> {
+        let mut chunks = b"VP8X\x0a\0\0\0\x08\0\0\0\0\0\0\0\0\0EXIF".to_vec();
+        chunks.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        chunks.extend_from_slice(tiff);
+        if tiff.len() % 2 == 1 {
+            chunks.push(0);
+        }
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&chunks);
+        data
+    }
+
+    #[test]
+    fn fields_of_each_container() {
+        let fields = [
+            field(Tag::Make, ascii("Canon")),
+            field(Tag::Model, ascii("Canon EOS R5")),
+            field(Tag::LensModel, ascii("RF24-105mm F4 L IS USM  ")),
+            field(Tag::ExposureTime, rationals(&[(1, 250)])),
+            field(Tag::FNumber, rationals(&[(28, 10)])),
+            field(Tag::FocalLength, rationals(&[(50, 1)])),
+            field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+            field(Tag::DateTimeOriginal, ascii("2024:05:01 12:30:00")),
+            field(Tag::GPSLatitudeRef, ascii("N")),
+            field(Tag::GPSLatitude, rationals(&[(35, 1), (30, 1), (36, 1)])),
+            field(Tag::GPSLongitudeRef, ascii("E")),
+            field(Tag::GPSLongitude, rationals(&[(139, 1), (0, 1), (0, 1)])),
+        ];
+        let expected = [
+            ("Camera", "Canon EOS R5"),
+            ("Lens", "RF24-105mm F4 L IS USM"),
+            ("Exposure", "1/250 s, f/2.8, 50 mm"),
+            ("ISO", "400"),
+            ("Taken", "2024-05-01 12:30:00"),
+            ("GPS", "35.51000° N, 139.00000° E"),
+        ];
+        for little_endian in [true, false] {
+            let tiff = tiff(&fields, little_endian);
+            for data in [jpeg(&tiff), png(&tiff), webp(&tiff)] {
+                let fields = super::fields(&data);
+                let fields: Vec<_> = fields.iter().map(|(name, value)| (*name, value.as_str())).collect();
+                assert_eq!(fields, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn orientation_in_both_byte_orders() {
+        for little_endian in [true, false] {
+            for (orientation, expected) in [
+                (1, None),
+                (3, Some("rotated 180°")),
+                (6, Some("rotated 90° clockwise")),
+                (8, Some("rotated 270° clockwise")),
+                (9, None),
+            ] {
+                let tiff = tiff(&[field(Tag::Orientation, Value::Short(vec![orientation]))], little_endian);
+                assert_eq!(&tiff[..2], if little_endian { b"II" } else { b"MM" });
+                let fields = fields(&jpeg(&tiff));
+                let found = fields.iter().find(|(name, _)| *name == "Orientation").map(|(_, value)| value.as_str());
+                assert_eq!(found, expected, "{orientation}");
+            }
+        }
+    }
+
+    #[test]
+    fn images_without_exif() {
+        assert!(fields(b"").is_empty());
+        assert!(fields(b"\xff\xd8\xff\xd9").is_empty());
+        assert!(fields(&jpeg(b"MM\0\x2a\0\0")).is_empty());
+        // 只有厂商或型号之一时照样显示。
+        let tiff = tiff(&[field(Tag::Model, ascii("X100V")), field(Tag::FNumber, rationals(&[(2, 0)]))], true);
+        assert_eq!(fields(&jpeg(&tiff)), [("Camera", "X100V".to_string())]);
+    }
+}
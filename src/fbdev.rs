@@ -0,0 +1,160 @@
+//! The Linux framebuffer device, written to by the `fbdev` renderer on consoles without
+//! a display server.
+
+use std::io;
+use crate::bitmap::Bitmap;
+
+/// An open framebuffer device and the layout of its pixels.
+#[cfg(target_os = "linux")]
+pub struct Framebuffer {
+    file: std::fs::File,
+    var: VarScreeninfo,
+    /// bytes from the start of a line to the start of the next one
+    line_length: usize,
+}
+
+/// `struct fb_bitfield` of linux/fb.h: where a channel lies in a pixel.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Bitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+/// `struct fb_var_screeninfo` of linux/fb.h, of which only the resolution and the pixel
+/// format are used.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct VarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: Bitfield,
+    green: Bitfield,
+    blue: Bitfield,
+    transp: Bitfield,
+    rest: [u32; 20],
+}
+
+/// `struct fb_fix_screeninfo` of linux/fb.h, of which only the line length is used.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FixScreeninfo {
+    id: [u8; 16],
+    smem_start: libc::c_ulong,
+    smem_len: u32,
+    kind: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: libc::c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+#[cfg(target_os = "linux")]
+const FBIOGET_VSCREENINFO: libc::c_ulong = 0x4600;
+#[cfg(target_os = "linux")]
+const FBIOGET_FSCREENINFO: libc::c_ulong = 0x4602;
+
+#[cfg(target_os = "linux")]
+impl Framebuffer {
+    /// Open the framebuffer named by `$FRAMEBUFFER`, as for fbi and fbv, or `/dev/fb0`.
+    pub fn open() -> io::Result<Framebuffer> {
+        use std::os::fd::AsRawFd;
+        let path = crate::terminal::var("FRAMEBUFFER").unwrap_or_else(|_| "/dev/fb0".to_string());
+        let file = std::fs::OpenOptions::new().read(true).write(true).open(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to open {path}: {e}")))?;
+        let mut var = VarScreeninfo::default();
+        let mut fix: FixScreeninfo = unsafe { std::mem::zeroed() };
+        // 两个结构体和内核中的布局相同，ioctl 只写入它们。
+        let ok = unsafe {
+            libc::ioctl(file.as_raw_fd(), FBIOGET_VSCREENINFO as _, &mut var) == 0
+                && libc::ioctl(file.as_raw_fd(), FBIOGET_FSCREENINFO as _, &mut fix) == 0
+        };
+        if !ok {
+            let error = io::Error::last_os_error();
+            return Err(io::Error::new(error.kind(), format!("{path} is not a framebuffer: {error}")));
+        }
+        if !matches!(var.bits_per_pixel, 16 | 24 | 32) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{path} has {} bits per pixel, only 16, 24 and 32 are supported", var.bits_per_pixel),
+            ));
+        }
+        Ok(Framebuffer {file, var, line_length: fix.line_length as usize})
+    }
+
+    /// Width and height of the visible screen in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.var.xres, self.var.yres)
+    }
+
+    /// Draw `bitmap` centered on the visible screen, blended over black where it is
+    /// transparent, and cropped to the screen.
+    pub fn draw_centered(&self, bitmap: &Bitmap) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        let (xres, yres) = self.size();
+        let (width, height) = (bitmap.width.min(xres), bitmap.height.min(yres));
+        let left = self.var.xoffset + (xres - width) / 2;
+        let top = self.var.yoffset + (yres - height) / 2;
+        let bytes = self.var.bits_per_pixel as usize / 8;
+        let mut line = Vec::with_capacity(width as usize * bytes);
+        for y in 0..height {
+            line.clear();
+            for x in 0..width {
+                let [r, g, b, a] = bitmap.get(x as usize, y as usize);
+                let value = self.pixel([r, g, b].map(|c| (c as u32 * a as u32 / 255) as u8));
+                line.extend_from_slice(&value.to_le_bytes()[..bytes]);
+            }
+            let offset = (top + y) as usize * self.line_length + left as usize * bytes;
+            self.file.write_at(&line, offset as u64)?;
+        }
+        Ok(())
+    }
+
+    /// The value of an opaque pixel of color `rgb` in the pixel format of the framebuffer.
+    fn pixel(&self, [r, g, b]: [u8; 3]) -> u32 {
+        let channel = |value: u8, field: Bitfield| match field.length {
+            0 => 0,
+            length => (value as u32 >> 8u32.saturating_sub(length)) << field.offset,
+        };
+        let opaque = match self.var.transp.length {
+            0 => 0,
+            length => ((1u32 << length.min(31)) - 1) << self.var.transp.offset,
+        };
+        channel(r, self.var.red) | channel(g, self.var.green) | channel(b, self.var.blue) | opaque
+    }
+}
+
+/// The framebuffer is a device of Linux.
+#[cfg(not(target_os = "linux"))]
+pub struct Framebuffer;
+
+#[cfg(not(target_os = "linux"))]
+impl Framebuffer {
+    pub fn open() -> io::Result<Framebuffer> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the framebuffer is only available on Linux"))
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    pub fn draw_centered(&self, _bitmap: &Bitmap) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::ffi::{c_char, c_int, CStr};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::slice;
+use crate::{ImageDisplay, ImgcatError, Length, Protocol};
+
+/// Returned for invalid arguments, such as a width that can't be parsed.
+const INVALID_ARGUMENT: c_int = -1;
+
+/// How an image is displayed, `imgcat_options` in `imgcat.h`. Every field may be
+/// NULL or 0 for the default.
+#[repr(C)]
+pub struct ImgcatOptions {
+    /// width in the form of -W, e.g. `40`, `250px`, `50%` or `auto`
+    pub width: *const c_char,
+    /// height in the form of -H
+    pub height: *const c_char,
+    /// non-zero to stretch the image to the width and height
+    pub stretch: c_int,
+    /// `auto`, `iterm2`, `kitty`, `sixel`, `regis`, `fbdev` or `blocks`
+    pub protocol: *const c_char,
+}
+
+/// Display the image file at `path` on stdout.
+///
+/// Return 0 on success, -1 for invalid arguments, and the exit status of imgcat
+/// for the error otherwise.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, and `options` NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn imgcat_display_file(path: *const c_char, options: *const ImgcatOptions) -> c_int {
+    let Some(path) = string(path) else {
+        return INVALID_ARGUMENT;
+    };
+    let Some(display) = image_display(options) else {
+        return INVALID_ARGUMENT;
+    };
+    let name = Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+    let display = display.filename(name).build();
+    let result = File::open(path).map_err(ImgcatError::from).and_then(|mut file| {
+        // 命名管道等不是普通文件，它们的长度为 0，不作为图片的大小。
+        let metadata = file.metadata()?;
+        let size = metadata.is_file().then_some(metadata.len());
+        write_stdout(&display, &mut file, size)
+    });
+    status(result)
+}
+
+/// Display the image of `len` bytes at `data` on stdout.
+///
+/// Return 0 on success, -1 for invalid arguments, and the exit status of imgcat
+/// for the error otherwise.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `options` be NULL or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn imgcat_display_bytes(data: *const u8, len: usize, options: *const ImgcatOptions) -> c_int {
+    if data.is_null() {
+        return INVALID_ARGUMENT;
+    }
+    let Some(display) = image_display(options) else {
+        return INVALID_ARGUMENT;
+    };
+    let data = slice::from_raw_parts(data, len);
+    status(write_stdout(&display.build(), &mut &data[..], Some(len as u64)))
+}
+
+fn write_stdout(display: &ImageDisplay, source: &mut impl Read, size: Option<u64>) -> crate::Result<()> {
+    let mut out = io::stdout().lock();
+    display.display_sized(&mut out, source, size)?;
+    Ok(out.flush()?)
+}
+
+fn status(result: crate::Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.exit_code(),
+    }
+}
+
+/// The string at `ptr`, `None` if it is NULL or not UTF-8.
+unsafe fn string<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+/// A builder set up with `options`, `None` if any of them is invalid.
+unsafe fn image_display(options: *const ImgcatOptions) -> Option<crate::ImageDisplayBuilder> {
+    let mut builder = ImageDisplay::builder();
+    let Some(options) = options.as_ref() else {
+        return Some(builder);
+    };
+    if !options.width.is_null() {
+        builder = builder.width(string(options.width)?.parse::<Length>().ok()?);
+    }
+    if !options.height.is_null() {
+        builder = builder.height(string(options.height)?.parse::<Length>().ok()?);
+    }
+    if !options.protocol.is_null() {
+        builder = builder.protocol(string(options.protocol)?.parse::<Protocol>().ok()?);
+    }
+    Some(builder.preserve_aspect(options.stretch == 0))
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use crate::i18n::tr;
+
+/// Monospace fonts looked for when none is given, those installed by default or by common
+/// packages on Linux, macOS and Windows.
+const DEFAULT_FONTS: [&str; 12] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu-sans-mono-fonts/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation-mono/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansMono-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansMono-Regular.ttf",
+    "/usr/share/fonts/truetype/ubuntu/UbuntuMono-R.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/System/Library/Fonts/Monaco.ttf",
+    "C:\\Windows\\Fonts\\consola.ttf",
+];
+
+/// The first of the default fonts that exists.
+pub fn find_default() -> Option<PathBuf> {
+    DEFAULT_FONTS.iter().map(PathBuf::from).find(|path| path.is_file())
+}
+
+/// A TrueType or OpenType font rasterized by fontdue at a fixed size into cells of the same
+/// width, the advance of its space, as in a terminal.
+pub struct Font {
+    font: fontdue::Font,
+    size: f32,
+    /// width and height of a cell in pixels
+    pub cell: (usize, usize),
+    /// distance from the top of a cell to the baseline in pixels
+    baseline: i32,
+    glyphs: HashMap<char, Vec<f32>>,
+}
+
+impl Font {
+    /// Load the font, or the first font of the collection, at `path`, sized so that its em
+    /// is `size` pixels.
+    pub fn open(path: &Path, size: f64) -> anyhow::Result<Self> {
+        let data = fs::read(path).with_context(|| tr!("failed to read font {}", path.display()))?;
+        let size = size as f32;
+        let settings = fontdue::FontSettings {scale: size, ..Default::default()};
+        let font = fontdue::Font::from_bytes(data, settings)
+            .map_err(anyhow::Error::msg)
+            .and_then(|font| Self::new(font, size))
+            .with_context(|| tr!("failed to load font {}", path.display()))?;
+        Ok(font)
+    }
+
+    fn new(font: fontdue::Font, size: f32) -> anyhow::Result<Self> {
+        let line = font.horizontal_line_metrics(size).context(tr!("no horizontal metrics in the font"))?;
+        // 用空格的宽度作为格子宽度，等宽字体中所有字符都一样宽。
+        let advance = font.metrics(' ', size).advance_width;
+        Ok(Font {
+            cell: ((advance.round() as usize).max(1), (line.new_line_size.ceil() as usize).max(1)),
+            baseline: line.ascent.round() as i32,
+            font,
+            size,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    /// The coverage of the pixels of the cell of `c`, row by row, from 0 to 1. Characters
+    /// missing from the font are drawn as its glyph 0, usually a box.
+    pub fn glyph(&mut self, c: char) -> &[f32] {
+        let Font {font, size, cell: (width, height), baseline, glyphs} = self;
+        glyphs.entry(c).or_insert_with(|| {
+            let (metrics, bitmap) = font.rasterize(c, *size);
+            let mut coverage = vec![0.0; *width * *height];
+            // 字形位图的左下角在基线上 (xmin, ymin) 处，超出格子的部分被裁掉。
+            let top = *baseline - metrics.ymin - metrics.height as i32;
+            for (i, &value) in bitmap.iter().enumerate() {
+                let x = metrics.xmin + (i % metrics.width) as i32;
+                let y = top + (i / metrics.width) as i32;
+                if (0..*width as i32).contains(&x) && (0..*height as i32).contains(&y) {
+                    coverage[y as usize * *width + x as usize] = value as f32 / 255.0;
+                }
+            }
+            coverage
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_fill_their_cells() {
+        // 没有安装默认字体的系统上跳过。
+        let Some(path) = find_default() else {
+            return;
+        };
+        let mut font = Font::open(&path, 16.0).unwrap();
+        let (width, height) = font.cell;
+        assert!((7..=11).contains(&width) && (16..=22).contains(&height), "{width}x{height}");
+        assert!(font.glyph(' ').iter().all(|&c| c == 0.0));
+        let coverage = font.glyph('l').to_vec();
+        assert_eq!(coverage.len(), width * height);
+        assert!(coverage.iter().all(|c| (0.0..=1.0).contains(c)));
+        // l 是一道竖线：从上到下大多数行都有笔画，左边一列空着。
+        let rows = (0..height).filter(|y| coverage[y * width..(y + 1) * width].iter().any(|&c| c > 0.5)).count();
+        assert!(rows >= height / 2, "{rows} of {height}");
+        assert!((0..height).all(|y| coverage[y * width] < 0.5));
+        // 下划线在基线以下，不会画到格子的上半部分。
+        let underscore = font.glyph('_');
+        assert!(underscore[..width * height / 2].iter().all(|&c| c == 0.0));
+        assert!(underscore[width * height / 2..].iter().any(|&c| c > 0.5));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("imgcat-font-test-{}.ttf", std::process::id()));
+        fs::write(&path, b"not a font").unwrap();
+        let result = Font::open(&path, 16.0);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
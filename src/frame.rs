@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+use clap::ValueEnum;
+
+/// Characters used to draw the border around an image.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderStyle {
+    Single,
+    Double,
+    Rounded,
+    Heavy,
+    Ascii,
+}
+
+impl BorderStyle {
+    /// Top left, top right, bottom left and bottom right corners, horizontal and vertical lines.
+    fn chars(self) -> [char; 6] {
+        match self {
+            BorderStyle::Single => ['┌', '┐', '└', '┘', '─', '│'],
+            BorderStyle::Double => ['╔', '╗', '╚', '╝', '═', '║'],
+            BorderStyle::Rounded => ['╭', '╮', '╰', '╯', '─', '│'],
+            BorderStyle::Heavy => ['┏', '┓', '┗', '┛', '━', '┃'],
+            BorderStyle::Ascii => ['+', '+', '+', '+', '-', '|'],
+        }
+    }
+}
+
+/// Padding and border drawn around an image.
+pub struct Frame {
+    pub border: Option<BorderStyle>,
+    pub padding: u16,
+}
+
+impl Frame {
+    /// Whether anything is drawn around the image at all.
+    pub fn is_empty(&self) -> bool {
+        self.border.is_none() && self.padding == 0
+    }
+
+    /// Number of cells between the outer edge of the frame and the image, on every side.
+    pub fn inset(&self) -> u16 {
+        self.padding + self.border.is_some() as u16
+    }
+
+    /// Draw the frame to `out` around an area of `columns` x `rows` cells, `indent`
+    /// columns from the left edge of the screen, leaving the cursor on the line below it.
+    pub fn draw(&self, out: &mut impl Write, indent: u16, columns: u16, rows: u16) -> io::Result<()> {
+        let [tl, tr, bl, br, h, v] = self.border.map_or([' '; 6], BorderStyle::chars);
+        let inner = (columns + 2 * self.padding) as usize;
+        let indent = " ".repeat(indent as usize);
+        let horizontal = h.to_string().repeat(inner);
+        let blank = " ".repeat(inner);
+        if self.border.is_some() {
+            writeln!(out, "{indent}{tl}{horizontal}{tr}")?;
+        }
+        for _ in 0..rows + 2 * self.padding {
+            if self.border.is_some() {
+                writeln!(out, "{indent}{v}{blank}{v}")?;
+            } else {
+                writeln!(out)?;
+            }
+        }
+        if self.border.is_some() {
+            writeln!(out, "{indent}{bl}{horizontal}{br}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(frame: Frame, indent: u16, columns: u16, rows: u16) -> String {
+        let mut out = Vec::new();
+        frame.draw(&mut out, indent, columns, rows).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn frames() {
+        let frame = Frame {border: Some(BorderStyle::Rounded), padding: 1};
+        assert_eq!(frame.inset(), 2);
+        assert_eq!(draw(frame, 2, 3, 1), "  ╭─────╮\n  │     │\n  │     │\n  │     │\n  ╰─────╯\n");
+        assert_eq!(draw(Frame {border: Some(BorderStyle::Ascii), padding: 0}, 0, 2, 1), "+--+\n|  |\n+--+\n");
+        // 只有内边距时只留出空行。
+        let frame = Frame {border: None, padding: 1};
+        assert_eq!((frame.is_empty(), frame.inset()), (false, 1));
+        assert_eq!(draw(frame, 4, 10, 2), "\n\n\n\n");
+        assert!(Frame {border: None, padding: 0}.is_empty());
+    }
+}
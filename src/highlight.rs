@@ -0,0 +1,202 @@
+use std::sync::OnceLock;
+use clap::ValueEnum;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{self, Color};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use two_face::theme::{EmbeddedLazyThemeSet, EmbeddedThemeName};
+use crate::bitmap::Bitmap;
+use crate::font::Font;
+use crate::markdown;
+
+/// A line of text as pieces of a color.
+pub type Line = Vec<([u8; 3], String)>;
+
+/// The colors of the text and of the background.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    /// dark in dark terminals and light otherwise
+    Auto,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// The OneHalfDark and OneHalfLight themes of bat.
+    fn colors(self) -> &'static highlighting::Theme {
+        static THEMES: OnceLock<EmbeddedLazyThemeSet> = OnceLock::new();
+        let themes = THEMES.get_or_init(two_face::theme::extra);
+        themes.get(match self {
+            Theme::Light => EmbeddedThemeName::OneHalfLight,
+            _ => EmbeddedThemeName::OneHalfDark,
+        })
+    }
+
+    fn background(self) -> [u8; 3] {
+        self.colors().settings.background.map_or([40, 44, 52], rgb)
+    }
+
+    fn foreground(self) -> [u8; 3] {
+        self.colors().settings.foreground.map_or([171, 178, 191], rgb)
+    }
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    [color.r, color.g, color.b]
+}
+
+/// The syntaxes of bat, which add TOML, INI, TypeScript, Kotlin, Swift and others to those
+/// of Sublime Text that syntect has.
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(two_face::syntax::extra_newlines)
+}
+
+/// The name of the language of the file at `path` by its extension, or by its name for
+/// files such as `Makefile`.
+pub fn language(path: &str) -> Option<&'static str> {
+    if markdown::is_markdown(path) {
+        return Some("markdown");
+    }
+    let name = path.rsplit(['/', '\\']).next()?;
+    let syntaxes = syntaxes();
+    let syntax = name.rsplit_once('.')
+        .and_then(|(_, extension)| syntaxes.find_syntax_by_extension(extension))
+        .or_else(|| syntaxes.find_syntax_by_extension(name))?;
+    Some(&syntax.name)
+}
+
+/// Split `text` into lines of colored pieces by the syntax of `language`, a name or extension
+/// of a language, or by its first line, such as `#!/bin/sh`, if not given. Text in other
+/// languages is left plain.
+pub fn highlight(text: &str, language: Option<&str>, theme: Theme) -> Vec<Line> {
+    let text = text.replace('\t', "    ");
+    let syntaxes = syntaxes();
+    let syntax = match language {
+        Some(language) => syntaxes.find_syntax_by_token(language),
+        None => syntaxes.find_syntax_by_first_line(text.lines().next().unwrap_or("")),
+    };
+    let mut highlighter = HighlightLines::new(syntax.unwrap_or_else(|| syntaxes.find_syntax_plain_text()), theme.colors());
+    let mut failed = false;
+    LinesWithEndings::from(&text).map(|line| {
+        let pieces = match failed {
+            false => highlighter.highlight_line(line, syntaxes).ok(),
+            true => None,
+        };
+        // 语法规则出错后其余各行不再着色。
+        let Some(pieces) = pieces else {
+            failed = true;
+            return vec![(theme.foreground(), line.trim_end_matches(['\n', '\r']).to_string())];
+        };
+        pieces.into_iter()
+            .map(|(style, piece)| (rgb(style.foreground), piece.trim_end_matches(['\n', '\r']).to_string()))
+            .filter(|(_, piece)| !piece.is_empty())
+            .collect()
+    }).collect()
+}
+
+/// Draw at most `max_lines` rows of the lines, wrapped at `columns` characters, in cells of
+/// the font over the background of the theme.
+pub fn draw(lines: &[Line], font: &mut Font, theme: Theme, columns: usize, max_lines: usize) -> Bitmap {
+    let columns = columns.max(1);
+    let mut rows: Vec<Vec<([u8; 3], char)>> = Vec::new();
+    for line in lines {
+        let chars: Vec<([u8; 3], char)> = line.iter()
+            .flat_map(|(color, text)| text.chars().map(|c| (*color, c)))
+            .filter(|(_, c)| !c.is_control())
+            .collect();
+        if chars.is_empty() {
+            rows.push(Vec::new());
+        }
+        rows.extend(chars.chunks(columns).map(<[_]>::to_vec));
+        if rows.len() >= max_lines {
+            break;
+        }
+    }
+    rows.truncate(max_lines.max(1));
+    let (cell_width, cell_height) = font.cell;
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0).max(1) + 2;
+    let height = rows.len() + 1;
+    let mut bitmap = Bitmap::new((width * cell_width) as u32, (height * cell_height) as u32);
+    let [r, g, b] = theme.background();
+    for pixel in bitmap.pixels.chunks_mut(4) {
+        pixel.copy_from_slice(&[r, g, b, 255]);
+    }
+    // 上下各留半行，左右各留一格。
+    let top = cell_height / 2;
+    for (y, row) in rows.iter().enumerate() {
+        for (x, &(color, c)) in row.iter().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let (left, top) = ((x + 1) * cell_width, top + y * cell_height);
+            for (i, &coverage) in font.glyph(c).iter().enumerate() {
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let (px, py) = (left + i % cell_width, top + i / cell_width);
+                let [r, g, b, _] = bitmap.get(px, py);
+                let mix = |old: u8, new: u8| (old as f32 + (new as f32 - old as f32) * coverage).round() as u8;
+                bitmap.set(px, py, [mix(r, color[0]), mix(g, color[1]), mix(b, color[2]), 255]);
+            }
+        }
+    }
+    bitmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The color of the first piece that is `text`.
+    fn color_of(lines: &[Line], text: &str) -> [u8; 3] {
+        lines.iter().flatten().find(|(_, piece)| piece == text).unwrap().0
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(language("src/main.rs"), Some("Rust"));
+        assert_eq!(language("Cargo.toml"), Some("TOML"));
+        assert_eq!(language("docs/README.md"), Some("markdown"));
+        assert_eq!(language("Makefile"), Some("Makefile"));
+        assert_eq!(language("notes.unknown"), None);
+    }
+
+    #[test]
+    fn colors_by_syntax() {
+        let lines = highlight("fn main() {\n    let s = \"hi\"; // greet\n}\n", Some("rust"), Theme::Dark);
+        assert_eq!(lines.len(), 3);
+        let text: Vec<String> = lines.iter().map(|line| line.iter().map(|(_, piece)| piece.as_str()).collect()).collect();
+        assert_eq!(text, ["fn main() {", "    let s = \"hi\"; // greet", "}"]);
+        let keyword = color_of(&lines, "let");
+        assert_eq!(color_of(&lines, "fn"), keyword);
+        assert_ne!(color_of(&lines, "main"), keyword);
+        let string = lines[1].iter().find(|(_, piece)| piece.contains("hi")).unwrap().0;
+        let comment = lines[1].iter().find(|(_, piece)| piece.contains("greet")).unwrap().0;
+        assert!(string != keyword && comment != keyword && string != comment);
+    }
+
+    #[test]
+    fn plain_without_a_language() {
+        let lines = highlight("let x = 1;\tdone\n", None, Theme::Light);
+        assert_eq!(lines, [vec![(Theme::Light.foreground(), "let x = 1;    done".to_string())]]);
+        let lines = highlight("#!/bin/sh\necho hi\n", None, Theme::Dark);
+        assert!(lines[1].len() > 1);
+    }
+
+    #[test]
+    fn draws_wrapped_rows() {
+        let Some(path) = crate::font::find_default() else {
+            return;
+        };
+        let mut font = Font::open(&path, 12.0).unwrap();
+        let (width, height) = font.cell;
+        let lines = highlight("abcdef\n\nxyz\n", None, Theme::Dark);
+        let bitmap = draw(&lines, &mut font, Theme::Dark, 4, 3);
+        // 第一行折成两行，第三行被截掉；左右各多一格，下面多一行。
+        assert_eq!((bitmap.width, bitmap.height), ((6 * width) as u32, (4 * height) as u32));
+        let [r, g, b] = Theme::Dark.background();
+        assert_eq!(bitmap.get(0, 0), [r, g, b, 255]);
+        assert!(bitmap.pixels.chunks(4).any(|p| p[..3] != [r, g, b]));
+    }
+}
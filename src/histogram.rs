@@ -0,0 +1,58 @@
+use clap::ValueEnum;
+use crate::bitmap::Bitmap;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 100;
+const BACKGROUND: [u8; 4] = [32, 32, 32, 255];
+
+/// What a histogram counts.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Channels {
+    /// the red, green and blue channels, overlaid
+    Rgb,
+    /// the perceived brightness of the pixels
+    Luminance,
+}
+
+/// Count the values of the channels over the opaque pixels of `bitmap` and
+/// draw them as a 256x100 image, one column per value.
+///
+/// The RGB histograms are drawn additively, so where all three overlap the
+/// bars are white. Values piling up in the first or last column reveal clipping.
+pub fn render(bitmap: &Bitmap, channels: Channels) -> Bitmap {
+    let mut counts = [[0u32; 256]; 3];
+    for pixel in bitmap.pixels.chunks(4).filter(|p| p[3] > 0) {
+        match channels {
+            Channels::Rgb => {
+                for (c, count) in counts.iter_mut().enumerate() {
+                    count[pixel[c] as usize] += 1;
+                }
+            }
+            Channels::Luminance => {
+                // Rec. 709 的亮度系数。
+                let y = 0.2126 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.0722 * pixel[2] as f64;
+                counts[0][y.round() as usize] += 1;
+            }
+        }
+    }
+    if let Channels::Luminance = channels {
+        counts[1] = counts[0];
+        counts[2] = counts[0];
+    }
+    let max = counts.iter().flatten().copied().max().unwrap_or(0).max(1);
+
+    let mut histogram = Bitmap::new(WIDTH, HEIGHT);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let mut rgba = BACKGROUND;
+            let level = (HEIGHT - y) as u64 * max as u64;
+            for (c, count) in counts.iter().enumerate() {
+                if count[x as usize] as u64 * HEIGHT as u64 >= level {
+                    rgba[c] = 220;
+                }
+            }
+            histogram.set(x as usize, y as usize, rgba);
+        }
+    }
+    histogram
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use scraper::{ElementRef, Html, Selector};
+
+/// An image referenced by a Markdown document or an HTML page.
+#[derive(Debug)]
+pub struct Reference {
+    /// the alt text, shown as the caption of the image
+    pub alt: String,
+    /// the URL or path of the image, as written in the document
+    pub target: String,
+}
+
+/// What imgcat shows of an HTML page.
+#[derive(Debug, Default)]
+pub struct Page {
+    /// the images in document order, each once
+    pub images: Vec<Reference>,
+    /// the `href` of the `<base>` tag, against which relative URLs are resolved
+    pub base: Option<String>,
+}
+
+/// The images of an HTML page: those of `<img>` tags, and the largest candidates of
+/// `<source>` tags, as in `<picture>` elements. Comments, scripts, styles and templates
+/// are skipped.
+pub fn page(text: &str) -> Page {
+    let document = Html::parse_document(text);
+    let mut page = Page::default();
+    let mut seen = HashSet::new();
+    for element in document.select(&selector("img, source, base")) {
+        // <template> 的内容不会显示，html5ever 仍把它们解析为元素。
+        if element.ancestors().filter_map(ElementRef::wrap).any(|e| e.value().name() == "template") {
+            continue;
+        }
+        let reference = match element.value().name() {
+            "img" => image(element),
+            "source" => {
+                // <video> 和 <audio> 中的 <source> 不是图片。
+                let media = attribute(element, "type").is_some_and(|t| t.starts_with("video/") || t.starts_with("audio/"));
+                attribute(element, "srcset")
+                    .and_then(largest_candidate)
+                    .or_else(|| attribute(element, "src"))
+                    .filter(|_| !media)
+                    .map(|target| Reference {alt: String::new(), target: target.to_string()})
+            }
+            _ => {
+                page.base = page.base.or_else(|| attribute(element, "href").map(str::to_string));
+                continue;
+            }
+        };
+        if let Some(reference) = reference.filter(|r| seen.insert(r.target.clone())) {
+            page.images.push(reference);
+        }
+    }
+    page
+}
+
+/// The images of the `<img>` tags of an HTML fragment, such as the HTML in a Markdown
+/// document, in document order.
+pub fn img_tags(fragment: &str) -> Vec<Reference> {
+    Html::parse_fragment(fragment).select(&selector("img")).filter_map(image).collect()
+}
+
+/// The image of an `<img>` tag: the `data-src` of lazily loaded images, the `src`, or the
+/// largest candidate of the `srcset`, whichever is given first.
+fn image(element: ElementRef) -> Option<Reference> {
+    let target = attribute(element, "data-src")
+        .or_else(|| attribute(element, "src"))
+        .or_else(|| attribute(element, "srcset").and_then(largest_candidate))
+        .filter(|t| !t.is_empty())?;
+    Some(Reference {
+        alt: attribute(element, "alt").map(collapse_whitespace).unwrap_or_default(),
+        target: target.to_string(),
+    })
+}
+
+fn selector(selectors: &str) -> Selector {
+    Selector::parse(selectors).expect("invalid selector")
+}
+
+/// The URL of the candidate of a `srcset` with the largest width or pixel density.
+fn largest_candidate(srcset: &str) -> Option<&str> {
+    let mut best: Option<(f64, &str)> = None;
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        // URL 本身可能含有逗号，只有其后的逗号才分隔候选项。
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = rest[..url_end].trim_end_matches(',');
+        let descriptors_end = if rest[..url_end].ends_with(',') {
+            url_end
+        } else {
+            rest[url_end..].find(',').map_or(rest.len(), |i| url_end + i)
+        };
+        let descriptor = rest[url_end..descriptors_end].trim();
+        let size = descriptor
+            .strip_suffix(['w', 'x'])
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1.0);
+        if best.is_none_or(|(largest, _)| size > largest) {
+            best = Some((size, url));
+        }
+        rest = &rest[descriptors_end..];
+    }
+    best.map(|(_, url)| url)
+}
+
+/// The value of the attribute `name` of an element, with its character references
+/// decoded by the parser.
+fn attribute<'a>(element: ElementRef<'a>, name: &str) -> Option<&'a str> {
+    element.value().attr(name)
+}
+
+/// `text` on a single line, as alt texts may be wrapped.
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(page: &Page) -> Vec<&str> {
+        page.images.iter().map(|r| r.target.as_str()).collect()
+    }
+
+    #[test]
+    fn quoted_and_encoded_sources() {
+        let page = page(r#"<p><img src="a b.png" alt="A  &amp;
+            B"><IMG SRC='c.png?x=1&amp;y=2'><img src=d&#46;png><img src="&#x65;&quot;.png">
+            <img src="https://example.com/f.png?size=&lt;10&gt;"></p>"#);
+        assert_eq!(targets(&page), ["a b.png", "c.png?x=1&y=2", "d.png", "e\".png", "https://example.com/f.png?size=<10>"]);
+        assert_eq!(page.images[0].alt, "A & B");
+        assert_eq!(page.images[1].alt, "");
+        // 写错的字符引用按原样保留。
+        assert_eq!(targets(&super::page(r#"<img src="g&unknown;.png">"#)), ["g&unknown;.png"]);
+    }
+
+    #[test]
+    fn sources_of_each_tag() {
+        let page = page(r#"<html><head><base href="https://example.com/a/"><base href="ignored/">
+            <style>img { content: "<img src=style.png>" }</style>
+            <script>document.write('<img src="script.png">')</script></head>
+            <body><!-- <img src="comment.png"> -->
+            <template><img src="template.png"></template>
+            <img data-src="lazy.png" src="placeholder.gif">
+            <img srcset="small.png 480w, large.png 1080w, medium.png 800w">
+            <img srcset="a,1.png 1x, a,2.png 2x" src="fallback.png">
+            <img alt="no source"><img src="">
+            <picture><source srcset="wide.webp 2x, narrow.webp" type="image/webp"><img src="lazy.png"></picture>
+            <video><source src="clip.mp4" type="video/mp4"></video>"#);
+        assert_eq!(targets(&page), ["lazy.png", "large.png", "fallback.png", "wide.webp"]);
+        assert_eq!(page.base.as_deref(), Some("https://example.com/a/"));
+        assert_eq!(targets(&super::page(r#"<img srcset="a,1.png 1x, a,2.png 2x">"#)), ["a,2.png"]);
+    }
+
+    #[test]
+    fn fragments() {
+        let images = img_tags(r#"<p align="center"><img src="logo.svg" alt="Logo"> <img src='b.png'></p>"#);
+        let images: Vec<_> = images.iter().map(|r| (r.alt.as_str(), r.target.as_str())).collect();
+        assert_eq!(images, [("Logo", "logo.svg"), ("", "b.png")]);
+        assert!(img_tags("<b>bold</b>").is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use std::cell::Cell;
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use clap::ValueEnum;
+use phf::{phf_map, Map};
+use crate::terminal;
+
+/// Language of the messages imgcat prints: errors, warnings and summaries.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    /// from LC_ALL, LC_MESSAGES or LANG
+    Auto,
+    /// English
+    En,
+    /// Chinese
+    Zh,
+}
+
+/// The language set by [`init`] for the process, or 0 before it is called.
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+thread_local! {
+    /// The language of the command line running on this thread, 0 for that of the process.
+    static THREAD_LANG: Cell<u8> = const { Cell::new(0) };
+}
+
+/// Set the language of the messages. Called for every command line, since the
+/// daemon runs them with the environment of each client, on threads of their own.
+pub fn init(lang: Lang) {
+    let lang = match lang {
+        Lang::Auto => detect(),
+        lang => lang,
+    };
+    if terminal::session().is_none() {
+        LANG.store(lang as u8, Ordering::Relaxed);
+    }
+    set_lang(lang);
+}
+
+/// Translate the messages of this thread into `lang`, as the one that spawned it does.
+pub fn set_lang(lang: Lang) {
+    THREAD_LANG.set(lang as u8);
+}
+
+/// The language of the messages.
+pub fn lang() -> Lang {
+    let lang = match THREAD_LANG.get() {
+        0 => LANG.load(Ordering::Relaxed),
+        lang => lang,
+    };
+    match lang {
+        x if x == Lang::En as u8 => Lang::En,
+        x if x == Lang::Zh as u8 => Lang::Zh,
+        // 解析命令行之前的错误也要翻译。
+        _ => detect(),
+    }
+}
+
+/// The language of the locale, from the first of LC_ALL, LC_MESSAGES and LANG that is set,
+/// unless `IMGCAT_LANG` says otherwise.
+fn detect() -> Lang {
+    if let Ok(lang) = terminal::var("IMGCAT_LANG") {
+        if let Ok(lang @ (Lang::En | Lang::Zh)) = Lang::from_str(&lang, true) {
+            return lang;
+        }
+    }
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| terminal::var(name).ok())
+        .find(|x| !x.is_empty())
+        .unwrap_or_default();
+    match locale.starts_with("zh") {
+        true => Lang::Zh,
+        false => Lang::En,
+    }
+}
+
+/// The translation of `message` in the current language. The English messages are
+/// their own catalog; the ones missing from the Chinese catalog are left in English.
+pub fn translate(message: &'static str) -> &'static str {
+    match lang() {
+        Lang::Zh => ZH.get(message).copied().unwrap_or(message),
+        _ => message,
+    }
+}
+
+/// Substitute `args` into `template`: `{}` takes the next argument and `{N}` the one
+/// at index N, so that translations can put them in another order. `{{` and `}}`
+/// stand for braces.
+pub fn format(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(after) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+            out.push_str(&rest[..1]);
+            rest = after;
+            continue;
+        }
+        let placeholder = rest.strip_prefix('{')
+            .and_then(|x| x.split_once('}'))
+            .filter(|(index, _)| index.bytes().all(|b| b.is_ascii_digit()));
+        match placeholder {
+            Some((index, after)) => {
+                let index = index.parse().unwrap_or_else(|_| {
+                    next += 1;
+                    next - 1
+                });
+                if let Some(arg) = args.get(index) {
+                    let _ = write!(out, "{arg}");
+                }
+                rest = after;
+            }
+            None => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Translate a message and substitute its arguments with [`format`](crate::i18n::format).
+/// The message has to be a literal, the key of the catalogs.
+macro_rules! tr {
+    ($message:literal) => {
+        $crate::i18n::translate($message)
+    };
+    ($message:literal, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::format($crate::i18n::translate($message), &[$(&$arg),+])
+    };
+}
+pub(crate) use tr;
+
+/// The Chinese catalog, keyed by the English messages.
+static ZH: Map<&'static str, &'static str> = phf_map! {
+    // 错误报告
+    "Error" => "错误",
+    "Caused by" => "原因",
+    "{} of {} inputs failed:" => "{1} 个输入中有 {0} 个失败：",
+    "imgcat needs an image: give files or URLs, or pipe one into it." => "imgcat 需要一张图片：请给出文件或 URL，或者通过管道输入。",
+    "For example `imgcat photo.png` or `curl -s URL | imgcat`. See `imgcat --help` for more." => "例如 `imgcat photo.png` 或 `curl -s URL | imgcat`。更多用法见 `imgcat --help`。",
+    // 输入
+    "invalid URL {}" => "无效的 URL {}",
+    "unsupported URL scheme {} in {}" => "{1} 中的 URL 协议 {0} 不受支持",
+    "failed to fetch image data from {}" => "无法从 {} 获取图片数据",
+    "failed to open file {}" => "无法打开文件 {}",
+    "failed to read from {}" => "无法读取 {}",
+    "failed to read from file {}" => "无法读取文件 {}",
+    "failed to read {}" => "无法读取 {}",
+    "failed to write {}" => "无法写入 {}",
+    "invalid time between requests of {} seconds" => "无效的请求间隔 {} 秒",
+    "failed to connect to {}" => "无法连接到 {}",
+    "{} answered {}" => "{} 返回了 {}",
+    "{}, trying the next mirror" => "{}，尝试下一个镜像",
+    "{} is a video stream, not an image" => "{} 是视频流，不是图片",
+    "invalid git input {}, expected git:REV:path" => "无效的 git 输入 {}，应为 git:REV:path",
+    "failed to run git, is it installed?" => "无法运行 git，是否已经安装？",
+    "failed to read {} at revision {}: {}" => "无法读取 {} 在版本 {} 中的内容：{}",
+    "invalid data: URL, expected a comma" => "无效的 data: URL，缺少逗号",
+    "invalid base64 in data: URL" => "data: URL 中的 base64 无效",
+    "failed to read stdin" => "无法读取标准输入",
+    "invalid base64 on stdin" => "标准输入中的 base64 无效",
+    "failed to read image from clipboard" => "无法从剪贴板读取图片",
+    "failed to capture the screen" => "无法截取屏幕",
+    "failed to create HTTP client" => "无法创建 HTTP 客户端",
+    "failed to start the threads" => "无法启动线程",
+    "failed to stream {}" => "无法读取视频流 {}",
+    "failed to play stream {}" => "无法播放视频流 {}",
+    "failed to decode {}" => "无法解码 {}",
+    "failed to query the currently playing track" => "无法查询正在播放的曲目",
+    "no media player is playing" => "没有正在播放的媒体播放器",
+    "the current track has no album art" => "当前曲目没有专辑封面",
+    "failed to decode the album art" => "无法解码专辑封面",
+    "the screenshot was cancelled" => "截图已取消",
+    "only full screen captures are supported on Windows" => "Windows 上只支持截取整个屏幕",
+    "failed to decode the screenshot" => "无法解码截图",
+    "the URL template {} does not contain {{}}" => "URL 模板 {} 中没有 {{}}",
+    "the file to preview is missing" => "缺少要预览的文件",
+    "the interactive viewer needs input files or URLs" => "交互式查看器需要输入文件或 URL",
+    // 输出
+    "unsupported output format of {}, expected a .png or .bmp file" => "不支持 {} 的输出格式，应为 .png 或 .bmp 文件",
+    "failed to convert to PNG for this terminal: {}" => "无法为这个终端转换为 PNG：{}",
+    "unsupported output {}, using the {} protocol" => "不支持输出 {}，改用 {} 协议",
+    "no image to select" => "没有可以选择的图片",
+    "failed to read the selection from the terminal" => "无法从终端读取选择",
+    "select an image [1-{}]: " => "选择一张图片 [1-{}]：",
+    "invalid selection: {}" => "无效的选择：{}",
+    "{} takes {}, show it anyway? [y/N] " => "{} 有 {}，仍然显示吗？[y/N] ",
+    "{} is larger than --confirm-above, give --yes to show it" => "{} 超过了 --confirm-above，加上 --yes 才会显示",
+    "no monospace font found, give a TrueType font with --font" => "找不到等宽字体，请用 --font 指定 TrueType 字体",
+    "failed to read font {}" => "无法读取字体 {}",
+    "failed to load font {}" => "无法加载字体 {}",
+    "no horizontal metrics in the font" => "字体中没有水平度量信息",
+    "no column {}" => "没有列 {}",
+    "no rows to plot" => "没有可以绘制的行",
+    "no numeric columns to plot" => "没有可以绘制的数值列",
+    "no numbers to plot" => "没有可以绘制的数字",
+    "failed to draw the chart" => "无法绘制图表",
+    "{} bytes are too many for a QR code at level {}" => "{} 字节超出了纠错级别为 {} 的二维码的容量",
+    "the images have different dimensions: {}x{} and {}x{}" => "图片尺寸不同：{}x{} 和 {}x{}",
+    // --dry-run
+    "error" => "错误",
+    "unknown size" => "大小未知",
+    "MJPEG stream" => "MJPEG 视频流",
+    "unknown format" => "格式未知",
+    "unknown display size" => "显示尺寸未知",
+    "{}x{} cells" => "{}x{} 个单元格",
+    // 警告
+    "the terminal of VS Code shows no images: turn on terminal.integrated.enableImages in its settings, with terminal.integrated.gpuAcceleration not off; drawing with half blocks meanwhile" => "VS Code 的终端不显示图片：请在设置中打开 terminal.integrated.enableImages，并且不要关闭 terminal.integrated.gpuAcceleration；暂时用半块字符绘制",
+    "{}: {} mismatch, expected {}, got {}" => "{0}：{1} 不匹配，应为 {2}，实际为 {3}",
+    "{}: failed to copy to the clipboard: {}" => "{}：无法复制到剪贴板：{}",
+    // 剪贴板
+    "not an image" => "不是图片",
+    "the terminal only takes text on its clipboard" => "终端的剪贴板只接受文本",
+    "the clipboard does not contain an image" => "剪贴板中没有图片",
+    "the clipboard does not contain an image ({} exited with {})" => "剪贴板中没有图片（{} 退出状态为 {}）",
+    "failed to decode the clipboard image" => "无法解码剪贴板中的图片",
+    "{} images can't be put on the clipboard" => "{} 图片不能放到剪贴板上",
+    "failed to run {}, is it installed?" => "无法运行 {}，是否已经安装？",
+    "{} exited with {}" => "{} 退出状态为 {}",
+    "failed to open a window for {}" => "无法为 {} 打开窗口",
+    "imgcat was built without the window feature" => "imgcat 编译时没有启用 window 功能",
+    "malformed clipboard data" => "剪贴板数据格式错误",
+    // 配置文件
+    "invalid configuration file {}" => "无效的配置文件 {}",
+    "unknown option {} in {}" => "{1} 中有未知选项 {0}",
+    "invalid value of {}, expected a string, number or boolean" => "{} 的值无效，应为字符串、数字或布尔值",
+    "invalid size '{}' in [preset.{}] of the configuration file: {}" => "配置文件的 [preset.{1}] 中尺寸 '{0}' 无效：{2}",
+    // 监视
+    "failed to initialize inotify" => "无法初始化 inotify",
+    "failed to watch {}" => "无法监视 {}",
+    "failed to read inotify events" => "无法读取 inotify 事件",
+    "failed to read directory {}" => "无法读取目录 {}",
+    // tmux 弹出窗口
+    "--popup only works inside tmux" => "--popup 只能在 tmux 中使用",
+    "--popup shows a single image" => "--popup 只显示一张图片",
+    "--popup can't show images read from stdin" => "--popup 不能显示从标准输入读取的图片",
+    "tmux has no client to show the popup on" => "tmux 没有可以显示弹出窗口的客户端",
+    "invalid {}: {}" => "无效的 {}：{}",
+    // 编辑器
+    "--serve is only supported on unix systems" => "--serve 只支持 unix 系统",
+    "a server is already listening on {}" => "已有服务在 {} 上监听",
+    "failed to read a command" => "无法读取命令",
+    "invalid command {}" => "无效的命令 {}",
+    "failed to open the terminal" => "无法打开终端",
+    "{}: unknown image format" => "{}：未知的图片格式",
+    "no image {}" => "没有图片 {}",
+    "invalid message from Neovim" => "Neovim 发来了无效的消息",
+    "Neovim: {}" => "Neovim：{}",
+    "unknown error" => "未知错误",
+    "not connected to Neovim" => "没有连接到 Neovim",
+    // 守护进程
+    "failed to create {}" => "无法创建 {}",
+    "{} must be a directory of the user that other users can't access" => "{} 必须是当前用户的目录，且其他用户无法访问",
+    "imgcat daemon: {} is served by another user, running on its own" => "imgcat 守护进程：{} 属于另一个用户，改为自己运行",
+    "failed to send the standard streams" => "无法发送标准流",
+    "the daemon closed the connection" => "守护进程关闭了连接",
+    "unsupported protocol version {}" => "不支持的协议版本 {}",
+    "expected 3 file descriptors, got {}" => "应为 3 个文件描述符，收到 {} 个",
+    "request of {} bytes is longer than {}" => "{} 字节的请求超过了 {} 字节",
+    "truncated request" => "请求不完整",
+    "invalid variable" => "无效的环境变量",
+    "invalid count" => "无效的数量",
+    "a daemon is already listening on {}" => "已有守护进程在 {} 上监听",
+    "failed to remove {}" => "无法删除 {}",
+    "failed to listen on {}" => "无法在 {} 上监听",
+    "failed to accept a connection: {}" => "无法接受连接：{}",
+    "failed to start a thread: {}" => "无法启动线程：{}",
+    "refused a connection from another user" => "拒绝了另一个用户的连接",
+    "invalid request: {}" => "无效的请求：{}",
+    "failed to enter {}" => "无法进入 {}",
+    "--daemon is only supported on unix systems" => "--daemon 只支持 unix 系统",
+};
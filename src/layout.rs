@@ -0,0 +1,138 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::probe;
+use crate::terminal;
+
+/// A width or height as given by -W/-H.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Length {
+    Cells(u32),
+    Pixels(u32),
+    Percent(u8),
+    Auto,
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let invalid = || "expected N (character cells), Npx (pixels), N% (percent of the terminal) or auto".to_string();
+        if value == "auto" {
+            return Ok(Length::Auto);
+        }
+        let (number, length): (&str, fn(u32) -> Option<Length>) = if let Some(n) = value.strip_suffix("px") {
+            (n, |n| Some(Length::Pixels(n)))
+        } else if let Some(n) = value.strip_suffix('%') {
+            (n, |n| u8::try_from(n).ok().filter(|n| *n <= 100).map(Length::Percent))
+        } else {
+            (value, |n| Some(Length::Cells(n)))
+        };
+        // 只接受不带符号的十进制整数，"+5"、"1e3" 之类交给终端只会被悄悄忽略。
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        match number.parse::<u32>() {
+            Ok(0) => Err("the size must be greater than zero".to_string()),
+            Ok(n) => length(n).ok_or_else(|| "a percentage can't exceed 100%".to_string()),
+            Err(_) => Err(invalid()),
+        }
+    }
+}
+
+/// The length in the form of the iTerm2 protocol, which is also the form of -W/-H.
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::Cells(n) => write!(f, "{n}"),
+            Length::Pixels(n) => write!(f, "{n}px"),
+            Length::Percent(n) => write!(f, "{n}%"),
+            Length::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl Length {
+    /// The length in pixels, where `cell` is the size of a character cell and
+    /// `total` the size of the terminal along the same axis.
+    fn pixels(self, cell: f64, total: f64) -> Option<f64> {
+        match self {
+            Length::Cells(n) => Some(n as f64 * cell),
+            Length::Pixels(n) => Some(n as f64),
+            Length::Percent(n) => Some(total * n as f64 / 100.0),
+            Length::Auto => None,
+        }
+    }
+}
+
+/// Estimate how many columns and rows the terminal uses to display an image,
+/// from its inherent size and the requested width and height.
+///
+/// The terminal fits the image into the requested box, and shrinks it to the
+/// width of the terminal when it would not fit otherwise.
+pub fn display_cells(
+    data: &[u8],
+    width: Option<Length>,
+    height: Option<Length>,
+    preserve_aspect_ratio: bool,
+    size: &terminal::Size,
+) -> Option<(u16, u16)> {
+    let (cell_w, cell_h) = size.cell_size();
+    let (total_w, total_h) = (size.columns as f64 * cell_w, size.rows as f64 * cell_h);
+    let requested_w = width.unwrap_or(Length::Auto).pixels(cell_w, total_w);
+    let requested_h = height.unwrap_or(Length::Auto).pixels(cell_h, total_h);
+    let natural = probe::probe(data)
+        .map(|i| (i.width as f64, i.height as f64))
+        .filter(|(w, h)| *w > 0.0 && *h > 0.0);
+
+    let (mut w, mut h) = match (requested_w, requested_h, natural) {
+        (Some(w), Some(h), Some((iw, ih))) if preserve_aspect_ratio => {
+            let scale = (w / iw).min(h / ih);
+            (iw * scale, ih * scale)
+        }
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Some((iw, ih))) => (w, if preserve_aspect_ratio { ih * w / iw } else { ih }),
+        (None, Some(h), Some((iw, ih))) => (if preserve_aspect_ratio { iw * h / ih } else { iw }, h),
+        (None, None, Some((iw, ih))) => (iw, ih),
+        _ => return None,
+    };
+    if w > total_w {
+        if preserve_aspect_ratio {
+            h *= total_w / w;
+        }
+        w = total_w;
+    }
+    let columns = (w / cell_w).ceil().clamp(1.0, size.columns.max(1) as f64);
+    let rows = (h / cell_h).ceil().max(1.0);
+    Some((columns as u16, rows.min(u16::MAX as f64) as u16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths() {
+        assert_eq!("80".parse(), Ok(Length::Cells(80)));
+        assert_eq!(" 80 ".parse(), Ok(Length::Cells(80)));
+        assert_eq!("640px".parse(), Ok(Length::Pixels(640)));
+        assert_eq!("50%".parse(), Ok(Length::Percent(50)));
+        assert_eq!("100%".parse(), Ok(Length::Percent(100)));
+        assert_eq!("auto".parse(), Ok(Length::Auto));
+        for length in [Length::Cells(80), Length::Pixels(640), Length::Percent(50), Length::Auto] {
+            assert_eq!(length.to_string().parse(), Ok(length));
+        }
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        assert_eq!("101%".parse::<Length>(), Err("a percentage can't exceed 100%".to_string()));
+        assert_eq!("1000%".parse::<Length>(), Err("a percentage can't exceed 100%".to_string()));
+        assert_eq!("0".parse::<Length>(), Err("the size must be greater than zero".to_string()));
+        assert_eq!("0px".parse::<Length>(), Err("the size must be greater than zero".to_string()));
+        // 空字符串、单独的单位、符号、小数和超出范围的数字都不接受。
+        for value in ["", " ", "px", "%", "+5", "-5", "1.5", "1e3", "5em", "auto%", "99999999999"] {
+            assert!(value.parse::<Length>().unwrap_err().starts_with("expected N"), "{value:?}");
+        }
+    }
+}
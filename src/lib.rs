@@ -0,0 +1,388 @@
+//! Display images inline in terminals implementing iTerm2's Inline Images Protocol.
+//!
+//! [`display`] writes the escape sequence showing an image to any writer, so that
+//! other tools can draw images without running the imgcat binary. [`ImageDisplay`]
+//! keeps the options of a series of images, built step by step. The [`render`]
+//! module draws them with the other protocols: kitty's, sixel, ReGIS, half blocks,
+//! and on the Linux framebuffer.
+//! With the `async` feature, the `nonblocking` module downloads and writes images
+//! with reqwest and tokio, for applications running on a tokio runtime, and the
+//! `ffi` feature exports C functions declared in `include/imgcat.h`, and the
+//! `python` feature builds the `imgcat` Python module.
+//! The other modules give the size of the terminal, the format and dimensions of
+//! images, and the number of cells an image will take.
+
+use std::io::{self, Read, Write};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use base64::write::EncoderWriter;
+use unicode_normalization::UnicodeNormalization;
+
+mod ansi;
+pub mod bitmap;
+mod error;
+mod fbdev;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod layout;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod probe;
+#[cfg(feature = "python")]
+mod python;
+pub mod render;
+pub mod split;
+pub mod terminal;
+
+pub use bitmap::BitmapCache;
+pub use error::{ImgcatError, Result};
+pub use layout::Length;
+pub use render::{Protocol, Renderer, Symbols};
+pub use terminal::ColorDepth;
+
+/// How an image is displayed by [`display`].
+#[derive(Clone, Debug)]
+pub struct Options<'a> {
+    /// width of the image, chosen by the terminal if not given
+    pub width: Option<Length>,
+    /// height of the image, chosen by the terminal if not given
+    pub height: Option<Length>,
+    /// keep the proportions of the image when both the width and height are given
+    pub preserve_aspect_ratio: bool,
+    /// file name of the image, shown by the terminal when it is downloaded
+    pub filename: Option<&'a str>,
+    /// file type hint, e.g. `image/png` or `.png`
+    pub file_type: Option<&'a str>,
+    /// size of the image in bytes, only used by the terminal to show the progress
+    pub size: Option<u64>,
+    /// URI the image links to, where the terminal supports OSC 8 hyperlinks
+    pub link: Option<&'a str>,
+    /// width of a character cell divided by its height, for the renderers drawing with
+    /// text, measured from the terminal if not given
+    pub cell_aspect: Option<f64>,
+    /// characters the renderers drawing with text draw the image with
+    pub symbols: Symbols,
+    /// number of colors the renderers drawing with text reduce the image to, detected from
+    /// the environment if not given
+    pub colors: Option<ColorDepth>,
+    /// where the renderers drawing with text keep the image decoded and scaled
+    pub cache: Option<&'a dyn BitmapCache>,
+}
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            preserve_aspect_ratio: true,
+            filename: None,
+            file_type: None,
+            size: None,
+            link: None,
+            cell_aspect: None,
+            symbols: Symbols::Blocks,
+            colors: None,
+            cache: None,
+        }
+    }
+}
+
+/// Displays images with the protocol and options it was built with, see [`ImageDisplay::builder`].
+#[derive(Clone, Debug)]
+pub struct ImageDisplay {
+    width: Option<Length>,
+    height: Option<Length>,
+    preserve_aspect_ratio: bool,
+    filename: Option<String>,
+    file_type: Option<String>,
+    link: Option<String>,
+    cell_aspect: Option<f64>,
+    symbols: Symbols,
+    colors: Option<ColorDepth>,
+    protocol: Protocol,
+}
+
+impl ImageDisplay {
+    /// A builder of the default display: sized by the terminal, keeping the aspect
+    /// ratio, with the protocol detected from the environment.
+    pub fn builder() -> ImageDisplayBuilder {
+        ImageDisplayBuilder {
+            display: ImageDisplay {
+                width: None,
+                height: None,
+                preserve_aspect_ratio: true,
+                filename: None,
+                file_type: None,
+                link: None,
+                cell_aspect: None,
+                symbols: Symbols::Blocks,
+                colors: None,
+                protocol: Protocol::Auto,
+            },
+        }
+    }
+
+    /// Draw the image read from `source` by writing to `writer`, and return the
+    /// number of bytes read from `source`.
+    pub fn display(&self, writer: &mut impl Write, source: &mut impl Read) -> Result<u64> {
+        self.display_sized(writer, source, None)
+    }
+
+    /// Draw the image read from `source`, telling the terminal it is `size` bytes
+    /// long so that it can show the progress.
+    pub fn display_sized(&self, writer: &mut impl Write, source: &mut impl Read, size: Option<u64>) -> Result<u64> {
+        let options = Options {
+            width: self.width,
+            height: self.height,
+            preserve_aspect_ratio: self.preserve_aspect_ratio,
+            filename: self.filename.as_deref(),
+            file_type: self.file_type.as_deref(),
+            size,
+            link: self.link.as_deref(),
+            cell_aspect: self.cell_aspect,
+            symbols: self.symbols.clone(),
+            colors: self.colors,
+            cache: None,
+        };
+        Ok(render::renderer(self.protocol).emit(writer, source, &options)?)
+    }
+}
+
+/// Builds an [`ImageDisplay`].
+#[derive(Clone, Debug)]
+pub struct ImageDisplayBuilder {
+    display: ImageDisplay,
+}
+
+impl ImageDisplayBuilder {
+    /// Width of the images, chosen by the terminal if not given.
+    pub fn width(mut self, width: Length) -> Self {
+        self.display.width = Some(width);
+        self
+    }
+
+    /// Height of the images, chosen by the terminal if not given.
+    pub fn height(mut self, height: Length) -> Self {
+        self.display.height = Some(height);
+        self
+    }
+
+    /// Whether to keep the proportions of the images when both the width and height are given.
+    pub fn preserve_aspect(mut self, preserve: bool) -> Self {
+        self.display.preserve_aspect_ratio = preserve;
+        self
+    }
+
+    /// File name of the images, shown by the terminal when they are downloaded.
+    pub fn filename(mut self, name: impl Into<String>) -> Self {
+        self.display.filename = Some(name.into());
+        self
+    }
+
+    /// File type hint, e.g. `image/png` or `.png`.
+    pub fn file_type(mut self, file_type: impl Into<String>) -> Self {
+        self.display.file_type = Some(file_type.into());
+        self
+    }
+
+    /// URI the images link to, where the terminal supports OSC 8 hyperlinks.
+    pub fn link(mut self, uri: impl Into<String>) -> Self {
+        self.display.link = Some(uri.into());
+        self
+    }
+
+    /// Width of a character cell divided by its height, for the renderers drawing with text,
+    /// measured from the terminal if not given.
+    pub fn cell_aspect(mut self, aspect: f64) -> Self {
+        self.display.cell_aspect = Some(aspect);
+        self
+    }
+
+    /// Characters the renderers drawing with text draw the images with, half blocks by default.
+    pub fn symbols(mut self, symbols: Symbols) -> Self {
+        self.display.symbols = symbols;
+        self
+    }
+
+    /// Number of colors the renderers drawing with text reduce the images to, detected from
+    /// the environment if not given.
+    pub fn colors(mut self, depth: ColorDepth) -> Self {
+        self.display.colors = Some(depth);
+        self
+    }
+
+    /// Protocol the images are drawn with.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.display.protocol = protocol;
+        self
+    }
+
+    pub fn build(self) -> ImageDisplay {
+        self.display
+    }
+}
+
+/// Write the escape sequence displaying the image read from `source` to `writer`,
+/// wrapped for tmux when `$TERM` tells it runs inside tmux or screen, and return
+/// the number of bytes read from `source`.
+///
+/// The image is encoded as it is read, so `source` may be a file or a download in
+/// progress. The escape sequence is terminated even if reading fails.
+pub fn display(writer: &mut (impl Write + ?Sized), source: &mut (impl Read + ?Sized), options: &Options) -> Result<u64> {
+    // 终端会把超链接附加到图片所在的单元格上。
+    if let Some(uri) = options.link {
+        write!(writer, "\x1b]8;;{uri}\x1b\\")?;
+    }
+    write_osc(writer)?;
+    writer.write_all(b"1337;File=inline=1")?;
+    if let Some(size) = options.size {
+        write!(writer, ";size={size}")?;
+    }
+
+    if let Some(name) = options.filename {
+        write!(writer, ";{}", name_argument(name))?;
+    }
+
+    if let Some(w) = options.width {
+        write!(writer, ";width={w}")?;
+    }
+
+    if let Some(h) = options.height {
+        write!(writer, ";height={h}")?;
+    }
+
+    write!(writer, ";preserveAspectRatio={}", options.preserve_aspect_ratio as u8)?;
+
+    if let Some(ft) = options.file_type {
+        write!(writer, ";type={ft}")?;
+    }
+    let copied = write_payload(writer, source);
+    if options.link.is_some() {
+        writer.write_all(b"\x1b]8;;\x1b\\")?;
+    }
+    Ok(copied?)
+}
+
+/// Write the escape sequence drawing the image read from `source` the way `wezterm imgcat`
+/// does: the arguments in WezTerm's order, only those differing from its defaults, and no
+/// file type, which WezTerm ignores as it sniffs the format. Like `wezterm imgcat`, the
+/// whole image is sent in a single sequence.
+pub(crate) fn display_wezterm(writer: &mut (impl Write + ?Sized), source: &mut (impl Read + ?Sized), options: &Options) -> io::Result<u64> {
+    if let Some(uri) = options.link {
+        write!(writer, "\x1b]8;;{uri}\x1b\\")?;
+    }
+    write_osc(writer)?;
+    writer.write_all(b"1337;File=")?;
+    let mut arguments = Vec::new();
+    if let Some(size) = options.size {
+        arguments.push(format!("size={size}"));
+    }
+    if let Some(name) = options.filename {
+        arguments.push(name_argument(name));
+    }
+    if let Some(w) = options.width {
+        arguments.push(format!("width={w}"));
+    }
+    if let Some(h) = options.height {
+        arguments.push(format!("height={h}"));
+    }
+    if !options.preserve_aspect_ratio {
+        arguments.push("preserveAspectRatio=0".to_string());
+    }
+    arguments.push("inline=1".to_string());
+    writer.write_all(arguments.join(";").as_bytes())?;
+    let copied = write_payload(writer, source);
+    if options.link.is_some() {
+        writer.write_all(b"\x1b]8;;\x1b\\")?;
+    }
+    copied
+}
+
+/// Write the escape sequence sending the file read from `source` to the terminal as a
+/// download named `name`, which iTerm2 saves to the Downloads folder, and return the
+/// number of bytes read from `source`. `size` is only used to show the progress.
+pub fn download(writer: &mut (impl Write + ?Sized), source: &mut (impl Read + ?Sized), name: Option<&str>, size: Option<u64>) -> Result<u64> {
+    write_osc(writer)?;
+    writer.write_all(b"1337;File=inline=0")?;
+    if let Some(size) = size {
+        write!(writer, ";size={size}")?;
+    }
+    if let Some(name) = name {
+        write!(writer, ";{}", name_argument(name))?;
+    }
+    Ok(write_payload(writer, source)?)
+}
+
+/// The `name` argument of a File sequence: the file name in UTF-8 and standard base64,
+/// normalized to NFC, the form other programs expect, as macOS gives the names of files
+/// in decomposed form.
+fn name_argument(name: &str) -> String {
+    let name: String = name.nfc().collect();
+    format!("name={}", BASE64_STANDARD.encode(name))
+}
+
+/// Write the contents read from `source` in base64 after the arguments of a File
+/// sequence, and terminate it.
+fn write_payload(writer: &mut (impl Write + ?Sized), source: &mut (impl Read + ?Sized)) -> io::Result<u64> {
+    writer.write_all(b":")?;
+    // 逐块编码 base64，而不是先把整张图片编码成一个字符串。
+    let mut encoder = EncoderWriter::new(&mut *writer, &BASE64_STANDARD);
+    let copied = io::copy(source, &mut encoder);
+    encoder.finish()?;
+    drop(encoder);
+    // 即使读取中途出错也要结束转义序列，否则终端会一直等待它结束。
+    write_st(writer)?;
+    copied
+}
+
+/// Whether `$TERM` tells that the terminal is tmux or screen, which need escape
+/// sequences meant for the outer terminal to be wrapped.
+pub fn in_tmux() -> bool {
+    terminal::var("TERM").is_ok_and(|term| term.starts_with("screen") || term.starts_with("tmux"))
+}
+
+fn write_osc(out: &mut (impl Write + ?Sized)) -> io::Result<()> {
+    if in_tmux() {
+        return out.write_all(b"\x1bPtmux;\x1b\x1b]");
+    }
+    out.write_all(b"\x1b]")
+}
+
+fn write_st(out: &mut (impl Write + ?Sized)) -> io::Result<()> {
+    if in_tmux() {
+        return out.write_all(b"\x07\x1b\\");
+    }
+    out.write_all(b"\x07")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A source failing as a download cut short does.
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    #[test]
+    fn errors() {
+        terminal::set_environment(Some(Vec::new()));
+        let blocks = ImageDisplay::builder().protocol(Protocol::Blocks).width(Length::Cells(4)).build();
+        let error = blocks.display(&mut Vec::new(), &mut &b"\x89PNG\r\n\x1a\nbroken"[..]).unwrap_err();
+        assert!(matches!(error, ImgcatError::Decode(_)), "{error:?}");
+        assert_eq!(error.exit_code(), 6);
+        let error = blocks.display(&mut Vec::new(), &mut Failing).unwrap_err();
+        assert!(matches!(&error, ImgcatError::Io(e) if e.kind() == io::ErrorKind::ConnectionReset), "{error:?}");
+        // 读取失败时转义序列照样结束。
+        let mut out = Vec::new();
+        let error = display(&mut out, &mut Failing, &Options::default()).unwrap_err();
+        assert!(matches!(&error, ImgcatError::Io(e) if e.kind() == io::ErrorKind::ConnectionReset), "{error:?}");
+        assert!(out.ends_with(b":\x07"));
+        assert!(download(&mut Vec::new(), &mut Failing, Some("a.png"), None).is_err_and(|e| e.exit_code() == 4));
+        terminal::set_environment(None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Where the inputs given on the command line are read from.
+
+use anyhow::{bail, Context};
+use url::Url;
+use crate::i18n::tr;
+use crate::{InputKind, SUPPORTED_SCHEMES};
+
+/// Where an input is read from.
+pub enum Location<'a> {
+    Url(Url),
+    /// a git object, `REV:path`
+    Git(&'a str),
+    /// a `data:` URL embedding the image, as found in Markdown and HTML documents
+    Data(&'a str),
+    File(&'a str),
+}
+
+impl<'a> Location<'a> {
+    pub fn resolve(path: &'a str, kind: InputKind) -> anyhow::Result<Self> {
+        match kind {
+            InputKind::Auto => {}
+            InputKind::File => return Ok(Location::File(path)),
+            InputKind::Url => {
+                let u = Url::parse(path).with_context(|| tr!("invalid URL {}", path))?;
+                if !SUPPORTED_SCHEMES.contains(u.scheme()) {
+                    bail!(tr!("unsupported URL scheme {} in {}", u.scheme(), path));
+                }
+                return Ok(Location::Url(u));
+            }
+        }
+        if let Some(object) = path.strip_prefix("git:") {
+            return Ok(Location::Git(object));
+        }
+        if path.starts_with("data:") {
+            return Ok(Location::Data(path));
+        }
+        if windows_path(path) {
+            return Ok(Location::File(path));
+        }
+
+        // 由于在 Windows 中， 类似 C:/a/b/c 这样的绝对路径可以被 Url::parse 函数正确解析。
+        // 这里限定 scheme 为给定集合中的值时，才认为他是一个图片的 URL。
+        if let Ok(u) = Url::parse(path) {
+            if SUPPORTED_SCHEMES.contains(u.scheme()) {
+                return Ok(Location::Url(u));
+            }
+        }
+
+        // 其余情况，包括 Url 解析出错，或者解析得到的 scheme 不在给定的集合中，
+        // 则回退到认为给定的 path 是一个本地文件系统的路径。
+        Ok(Location::File(path))
+    }
+}
+
+/// The URLs of an input listing mirrors of an image, `URL1|URL2|...`, tried in turn until
+/// one answers. Only inputs of two or more URLs separated by `|` are split, so that local
+/// files whose names contain it are still found.
+pub fn mirrors(path: &str, kind: InputKind) -> Option<Vec<(&str, Url)>> {
+    if matches!(kind, InputKind::File) || !path.contains('|') {
+        return None;
+    }
+    path.split('|')
+        .map(|part| Url::parse(part).ok().filter(|u| SUPPORTED_SCHEMES.contains(u.scheme())).map(|u| (part, u)))
+        .collect()
+}
+
+/// Whether `path` is a Windows path starting with a drive letter, such as `C:\image.png`
+/// or the drive-relative `C:image.png`, or a UNC path such as `\\server\share\image.png`,
+/// also with the `\\?\` prefix of long paths. The drive letter reads like a URL scheme.
+pub fn windows_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with(r"\\") || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The kind and text of the location of `path`.
+    fn resolve(path: &str, kind: InputKind) -> Option<(&'static str, String)> {
+        Some(match Location::resolve(path, kind).ok()? {
+            Location::Url(u) => ("url", u.to_string()),
+            Location::Git(object) => ("git", object.to_string()),
+            Location::Data(url) => ("data", url.to_string()),
+            Location::File(f) => ("file", f.to_string()),
+        })
+    }
+
+    #[test]
+    fn locations() {
+        let location = |kind, text: &str| Some((kind, text.to_string()));
+        assert_eq!(resolve("https://example.com/a.png", InputKind::Auto), location("url", "https://example.com/a.png"));
+        assert_eq!(resolve("ftp://example.com/a.png", InputKind::Auto), location("url", "ftp://example.com/a.png"));
+        assert_eq!(resolve("git:HEAD~1:docs/a.png", InputKind::Auto), location("git", "HEAD~1:docs/a.png"));
+        assert_eq!(resolve("data:image/png;base64,iVBO", InputKind::Auto), location("data", "data:image/png;base64,iVBO"));
+        assert_eq!(resolve("photos/a.png", InputKind::Auto), location("file", "photos/a.png"));
+        // file:// 和其他不支持的 scheme 当作本地文件，由 local_path 解码。
+        assert_eq!(resolve("file:///tmp/a.png", InputKind::Auto), location("file", "file:///tmp/a.png"));
+        assert_eq!(resolve("mailto:someone@example.com", InputKind::Auto), location("file", "mailto:someone@example.com"));
+        // 盘符看起来像 URL 的 scheme。
+        assert_eq!(resolve(r"C:\photos\a.png", InputKind::Auto), location("file", r"C:\photos\a.png"));
+        assert_eq!(resolve("c:a.png", InputKind::Auto), location("file", "c:a.png"));
+        assert_eq!(resolve(r"\\server\share\a.png", InputKind::Auto), location("file", r"\\server\share\a.png"));
+    }
+
+    #[test]
+    fn locations_of_each_kind() {
+        assert_eq!(resolve("git:HEAD:a.png", InputKind::File), Some(("file", "git:HEAD:a.png".to_string())));
+        assert_eq!(resolve("https://example.com/a.png", InputKind::File), Some(("file", "https://example.com/a.png".to_string())));
+        assert_eq!(resolve("https://example.com/a.png", InputKind::Url), Some(("url", "https://example.com/a.png".to_string())));
+        assert_eq!(resolve("file:///tmp/a.png", InputKind::Url), None);
+        assert_eq!(resolve("a.png", InputKind::Url), None);
+    }
+
+    #[test]
+    fn mirror_lists() {
+        let urls = |path, kind| mirrors(path, kind).map(|urls| urls.into_iter().map(|(part, u)| (part, u.to_string())).collect::<Vec<_>>());
+        assert_eq!(
+            urls("https://a.example/x.png|http://b.example/x.png", InputKind::Auto),
+            Some(vec![
+                ("https://a.example/x.png", "https://a.example/x.png".to_string()),
+                ("http://b.example/x.png", "http://b.example/x.png".to_string()),
+            ]),
+        );
+        assert_eq!(urls("https://a.example/x.png|http://b.example/x.png", InputKind::Url).map(|urls| urls.len()), Some(2));
+        assert_eq!(urls("https://a.example/x.png|http://b.example/x.png", InputKind::File), None);
+        assert_eq!(urls("https://a.example/x.png", InputKind::Auto), None);
+        // 名字里带 | 的本地文件不拆开。
+        assert_eq!(urls("a|b.png", InputKind::Auto), None);
+        assert_eq!(urls("https://a.example/x.png|b.png", InputKind::Auto), None);
+        assert_eq!(urls("https://a.example/x.png|", InputKind::Auto), None);
+    }
+
+    #[test]
+    fn windows_paths() {
+        assert!(windows_path(r"C:\a.png"));
+        assert!(windows_path("C:/a.png"));
+        assert!(windows_path("C:a.png"));
+        assert!(windows_path(r"\\server\share\a.png"));
+        assert!(windows_path(r"\\?\C:\a.png"));
+        assert!(!windows_path("/tmp/a.png"));
+        assert!(!windows_path("https://example.com/a.png"));
+        assert!(!windows_path("1:a.png"));
+        assert!(!windows_path("C"));
+    }
+}
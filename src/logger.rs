@@ -0,0 +1,77 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use imgcat::terminal;
+
+/// The level of the process, for the threads that don't run a command line of their own.
+static LEVEL: Mutex<LevelFilter> = Mutex::new(LevelFilter::Warn);
+
+thread_local! {
+    /// The level of the command line running on this thread, which the daemon runs for
+    /// a client on a thread of its own.
+    static THREAD_LEVEL: Cell<Option<LevelFilter>> = const { Cell::new(None) };
+}
+
+/// Writes log messages to stderr. Warnings and errors are printed as they are,
+/// the other messages are prefixed with their level and the time since start.
+///
+/// Each thread has the level of the command line it runs, so that every command
+/// line run by the daemon can set its own.
+struct Logger {
+    start: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // 依赖库 (reqwest、hyper 等) 的日志太多，只在 -vvv 时输出。
+        let level = level();
+        metadata.level() <= level
+            && (level == LevelFilter::Trace || metadata.target().starts_with(env!("CARGO_CRATE_NAME")))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // 整行一次写出，不和其他线程的日志交错。
+        let line = match record.level() {
+            Level::Error | Level::Warn => format!("{}\n", record.args()),
+            level => format!("[{:8.3}s {level:<5}] {}\n", self.start.elapsed().as_secs_f64(), record.args()),
+        };
+        let _ = imgcat::terminal::stderr().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger: errors only with -q, warnings by default, and more
+/// details with each -v.
+pub fn init(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    // 守护进程中每个请求都会调用这里，日志器只安装一次。
+    let _ = log::set_boxed_logger(Box::new(Logger {start: Instant::now()}));
+    if terminal::session().is_none() {
+        *LEVEL.lock().unwrap_or_else(|e| e.into_inner()) = level;
+    }
+    set_level(level);
+}
+
+/// The level of the messages logged on this thread.
+pub fn level() -> LevelFilter {
+    THREAD_LEVEL.get().unwrap_or_else(|| *LEVEL.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Log the messages up to `level` on this thread, as the one that spawned it does.
+pub fn set_level(level: LevelFilter) {
+    THREAD_LEVEL.set(Some(level));
+    // 日志宏先和全局的最高级别比较，它要放过所有线程的消息。
+    log::set_max_level(log::max_level().max(level));
+}
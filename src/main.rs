@@ -1,20 +1,102 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::fs::{self, File};
-use std::{env, io};
-use std::io::Read;
-use anyhow;
-use anyhow::Context;
+use std::{env, fmt, io, thread};
+use std::ffi::OsString;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::cell::Cell;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{bail, Context};
 use base64::Engine;
-use base64::prelude::{BASE64_STANDARD, BASE64_URL_SAFE};
-use clap::{Parser, ArgAction, arg};
-use reqwest;
+use base64::prelude::BASE64_STANDARD_NO_PAD;
+use bytes::Bytes;
+use log::{debug, error, info, warn, LevelFilter};
+use clap::builder::{BoolishValueParser, PossibleValuesParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use reqwest::blocking::{Client, Response};
 use url::Url;
-use pathsep::path_separator;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use phf::{phf_set, Set};
+use bitmap::Bitmap;
+use frame::{BorderStyle, Frame};
+use imgcat::{bitmap, layout, probe, render, split, terminal, BitmapCache, ColorDepth, ImgcatError, Length, Protocol, Renderer, Symbols};
+use i18n::tr;
+use location::{mirrors, windows_path, Location};
+
+mod chart;
+mod check;
+mod clipboard;
+mod config;
+mod daemon;
+mod diff;
+mod digest;
+mod dryrun;
+mod exif;
+mod font;
+mod frame;
+mod highlight;
+mod histogram;
+mod html;
+mod i18n;
+mod location;
+mod logger;
+mod markdown;
+mod mmap;
+mod nowplaying;
+mod nvim;
+mod pace;
+mod palette;
+mod plot;
+mod popup;
+mod previewer;
+mod qr;
+mod screenshot;
+mod server;
+mod stream;
+mod swatch;
+mod testpattern;
+mod viewer;
+mod watch;
+mod window;
 
 const SUPPORTED_SCHEMES: Set<&'static str> = phf_set!{
      "http", "https", "ftp",
 };
 
+/// Size from which local files are mapped into memory rather than read.
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Size of the buffer the escape sequences of images are written through.
+const OUTPUT_BUFFER_LEN: usize = 64 * 1024;
+
+/// Width in cells of the columns of the grid command when --columns is not given.
+const GRID_COLUMN_WIDTH: u16 = 20;
+
+/// Largest width in pixels of the images of QR codes drawn by the qr command: wide enough
+/// to scan without the terminal scaling them up, and small enough to send quickly.
+const QR_MAX_PIXELS: usize = 200;
+
+/// Default of --alt, the name, format, dimensions and size of the image.
+const DEFAULT_ALT: &str = "{name} {format} {width}x{height} {size}";
+
+/// Extensions of the files picked up by --monitor.
+const IMAGE_EXTENSIONS: Set<&'static str> = phf_set!{
+    "png", "apng", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff", "svg", "heic", "avif", "ico",
+};
+
+/// Characters of the IDs escaped when they are substituted into a URL template.
+const URL_TEMPLATE_ESCAPES: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
 /// Display images inline in terminals support iTerm2's Inline Images Protocol
 ///
 /// If you don't specify width or height an appropriate value will be chosen automatically.
@@ -25,6 +107,8 @@ const SUPPORTED_SCHEMES: Set<&'static str> = phf_set!{
 ///     N%     percent of the session's width or height
 ///     auto   the image's inherent size will be used to determine an appropriate dimension
 ///
+/// Inputs of the form git:REV:path are read from the git repository of the current directory.
+///
 /// If a type is provided, it is used as a hint to disambiguate."
 /// The file type can be a mime type like text/markdown, a language name like Java, or a file extension like .c
 /// The file type can usually be inferred from the extension or its contents. -t is most useful when"
@@ -36,20 +120,179 @@ const SUPPORTED_SCHEMES: Set<&'static str> = phf_set!{
 ///     $ cat graph.png | imgcat -W 100%
 ///     $ imgcat -p -W 500px -u http://host.tld/path/to/image.jpg -W 80 -f image.png
 ///     $ cat url_list.txt | xargs imgcat -p -W 40 -u
+///     $ cut -f1 photos.tsv | imgcat -p --url-template 'https://cdn.example.com/{}.jpg'
 ///     $ imgcat -t application/json config.json
-#[derive(Parser, Debug)]
-#[command(version, about, long_about, verbatim_doc_comment)]
+///     $ plot.py | imgcat --stdin-name plot.png
+///     $ python train.py | imgcat --plot-mode
+///     $ imgcat --watch figure.png
+///     $ imgcat -p --monitor ~/Screenshots
+///     $ imgcat -p --columns 4 thumbnails/*.jpg
+///     $ imgcat --align center -W 50% slide.png
+///     $ imgcat --preset thumb photos/*.jpg
+///     $ imgcat --border rounded --padding 1 --margin 1 -W 40 dashboard.png
+///     $ imgcat --label '{name} {width}x{height} {size}' shots/*.png
+///     $ imgcat --dry-run -W 40 -u $(cat gallery_urls.txt)
+///     $ imgcat --pager photos/*.jpg
+///     $ imgcat --popup photo.png
+///     $ imgcat -i photos/*.png
+///     $ choice=$(imgcat -n --columns 4 --select *.png | cut -f2)
+///     $ imgcat -c
+///     $ imgcat --screenshot region
+///     $ imgcat --now-playing -W 20
+///     $ imgcat --fps 2 --duration 60 http://camera.local/video.mjpg
+///     $ imgcat git:HEAD:docs/logo.png git:feature:docs/logo.png
+///     $ imgcat diff --threshold 0.5 expected.png actual.png
+///     $ imgcat compare -i before.png after.png
+///     $ imgcat grid -p thumbnails/*.jpg
+///     $ imgcat info photos/*.jpg
+///     $ imgcat -W 60 README.md
+///     $ imgcat --extract-images --min-size 100 https://example.com/
+///     $ imgcat check
+///     $ imgcat --daemon &
+///     $ imgcat --serve /tmp/imgcat-nvim.sock
+///     $ imgcat --previewer 80 40 100 1 photo.jpg
+///     $ mkfifo /tmp/ueberzug && tail -f /tmp/ueberzug | imgcat layer
+///     $ fzf --preview 'imgcat --fzf-preview {}'
+///     $ imgcat --vifm-preview 40 20 80 1 photo.png
+///     $ imgcat --yazi-preview 40 20 80 1 photo.png
+///     $ imgcat qr https://example.com
+///     $ imgcat color '#ff8800' 'rgb(12, 34, 56)'
+///     $ imgcat testpattern -W 100%
+///     $ imgcat plot --x date --y price prices.csv
+///     $ imgcat code src/main.rs --lines 30
+///     $ imgcat --download report.pdf
+///     $ imgcat --copy chart.png
+///     $ imgcat --window *.png
+///     $ imgcat <(curl -s https://example.com/chart.png)
+///     $ imgcat '\\server\share\photo.png'
+///     $ jq -r .image response.json | imgcat --stdin-base64
+///     $ imgcat --lang zh photo.png
+///     $ imgcat --yes raw_scan.tiff
+///     $ imgcat --protocol regis photo.png
+///     $ imgcat --protocol fbdev photo.png
+///     $ imgcat --protocol blocks --symbols braille photo.png
+///     $ imgcat --protocol blocks --colors 16 photo.png
+///     $ TERM=dumb imgcat shots/*.png
+///     $ imgcat --max-rps 2 $(cat thumbnails.txt)
+///     $ imgcat 'https://artifacts.example.com/plot.png|https://mirror.example.org/plot.png'
+///     $ source <(imgcat completions bash)
+#[derive(Parser, Clone, Debug)]
+#[command(version, about, long_about, verbatim_doc_comment, args_override_self = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short='t', long)]
     file_type: Option<String>,
 
+    /// file name of the image read from stdin. Its extension is used as the file type if -t is not given
+    #[arg(long, value_name = "NAME")]
+    stdin_name: Option<String>,
+
+    /// decode stdin from base64, possibly wrapped or given as a data: URL. Base64 text of PNG, JPEG, GIF, BMP and WebP images is decoded without it
+    #[arg(long)]
+    stdin_base64: bool,
+
+    /// for figures piped from plotting programs: fit the images to the width of the terminal, and turn plots on light backgrounds dark in dark terminals
+    #[arg(long)]
+    plot_mode: bool,
+
+    /// width of a character cell divided by its height, e.g. 0.5, for drawing with half blocks. Measured from the terminal if not given
+    #[arg(long, value_name = "RATIO", value_parser = parse_cell_aspect)]
+    cell_aspect: Option<f64>,
+
+    /// characters --protocol blocks and the text previews draw with: blocks, ascii, braille, geometric or custom:CHARS, from the darkest to the brightest
+    #[arg(long, value_name = "SET", default_value = "blocks")]
+    symbols: Symbols,
+
+    /// colors of the terminal --protocol blocks reduces images to: truecolor, 256 or 16. Detected from $COLORTERM and $TERM if not given
+    #[arg(long, value_name = "DEPTH", value_parser = color_depth_parser())]
+    colors: Option<ColorDepth>,
+
     /// output width of the image
     #[arg(short='W', long)]
-    width: Option<String>,
+    width: Option<Length>,
 
     /// output height of the image
     #[arg(short='H', long)]
-    height: Option<String>,
+    height: Option<Length>,
+
+    /// size the images with a named preset instead of -W and -H, which take precedence when given after it
+    #[arg(long, value_enum, value_name = "NAME")]
+    preset: Option<Preset>,
+
+    /// horizontal alignment of the images
+    #[arg(long, value_enum, default_value_t = Align::Left)]
+    align: Align,
+
+    /// number of blank cells between the image and its border
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    padding: u16,
+
+    /// number of blank lines and columns around the image
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    margin: u16,
+
+    /// draw a border around the image
+    #[arg(long, value_enum, value_name = "STYLE", num_args = 0..=1, default_missing_value = "single")]
+    border: Option<BorderStyle>,
+
+    /// lay out the images side by side in N columns
+    #[arg(long, value_name = "N")]
+    columns: Option<u16>,
+
+    /// print a caption for each image. The placeholders {name}, {path}, {format}, {width}, {height} and {size} are expanded
+    #[arg(long, value_name = "TEMPLATE")]
+    label: Option<String>,
+
+    /// text describing each image, printed instead of it in terminals only showing text, and below it while asciinema records. The placeholders of --label are expanded
+    #[arg(long, value_name = "TEMPLATE", default_value = DEFAULT_ALT)]
+    alt: String,
+
+    /// where to print the caption given by --label
+    #[arg(long, value_enum, default_value_t = LabelPosition::Below)]
+    label_position: LabelPosition,
+
+    /// browse the images full screen: n/p for the next/previous image, +/- to zoom, r to rotate and q to quit
+    #[arg(short, long)]
+    interactive: bool,
+
+    /// clear the screen, or the lines used by the previous image, before each image
+    #[arg(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "screen")]
+    clear: Option<Clear>,
+
+    /// show one image, or one row of images with --columns, at a time and wait for a keypress
+    #[arg(long)]
+    pager: bool,
+
+    /// inside tmux, show the images in a popup sized to the image, which closes on a keypress
+    #[arg(long)]
+    popup: bool,
+
+    /// send the inputs, of any type, to iTerm2 as files to download instead of showing them, like it2dl
+    #[arg(long)]
+    download: bool,
+
+    /// also put the images on the clipboard: the system's when local, or the terminal's with kitty's clipboard protocol over SSH
+    #[arg(long)]
+    copy: bool,
+
+    /// show the images in windows of their own when the terminal shows none, e.g. in GNOME Terminal or Alacritty
+    #[arg(long)]
+    window: bool,
+
+    /// set when no image can be drawn in the terminal, see `describes_images`, and each
+    /// one is described by a line of text instead
+    #[arg(skip)]
+    plain: bool,
+
+    /// print the index of each image
+    #[arg(short = 'n', long)]
+    number: bool,
+
+    /// after showing the images, ask which one to pick and print its index and path
+    #[arg(long)]
+    select: bool,
 
     /// preserve aspect ratio when draw the image
     #[arg(short='s', long="stretch", action=ArgAction::SetFalse, default_value_t = true)]
@@ -59,62 +302,724 @@ struct Cli {
     #[arg(short, long)]
     print_path: bool,
 
-    /// input image files or URLs to show. Read from stdin if not given
+    /// print the camera, lens, exposure, ISO, date, orientation and GPS position recorded in the EXIF data below each image
+    #[arg(long)]
+    exif: bool,
+
+    /// draw a histogram of the RGB channels, or of the luminance, below each image
+    #[arg(long, value_enum, value_name = "CHANNELS", num_args = 0..=1, default_missing_value = "rgb")]
+    histogram: Option<histogram::Channels>,
+
+    /// show the N dominant colors of each image as swatches below it, with their hex codes
+    #[arg(long, value_name = "N")]
+    palette: Option<usize>,
+
+    /// print a digest of each image below it
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    checksum: Option<digest::Algorithm>,
+
+    /// check that the digest of every image, computed with --checksum (sha256 by default), is HASH and exit with status 1 otherwise
+    #[arg(long, value_name = "HASH")]
+    expect: Option<String>,
+
+    /// print the format, dimensions, color type, bit depth, frame count and file size of each image instead of showing it
+    #[arg(long)]
+    info: bool,
+
+    /// print a JSON object describing each image, or the error it caused, on its own line instead of showing it
+    #[arg(long)]
+    json: bool,
+
+    /// check that every input can be read and print its format and display size, without drawing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// make the image itself a clickable link to its file or URL, where the terminal supports it
+    #[arg(long)]
+    link: bool,
+
+    /// protocol the images are drawn with: kitty's in terminals known to implement it and iTerm2's otherwise by default
+    #[arg(long, value_name = "NAME", default_value = "auto", value_parser = protocol_parser())]
+    protocol: Protocol,
+
+    /// only report errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// report network activity, the protocol used and timings on stderr; -vv for more details
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// language of the errors, warnings and summaries: English, Chinese, or that of the locale by default
+    #[arg(long, value_name = "LANG", default_value = "auto")]
+    lang: i18n::Lang,
+
+    /// when an input can't be shown, go on with the others and list the failures at the end (the default)
+    #[arg(long, overrides_with = "fail_fast")]
+    keep_going: bool,
+
+    /// stop at the first input that can't be shown
+    #[arg(long, overrides_with = "keep_going")]
+    fail_fast: bool,
+
+    /// print on stderr how long fetching, decoding, converting and sending each image took, and how much was sent
+    #[arg(long)]
+    stats: bool,
+
+    /// number of threads opening, downloading and decoding inputs, and scaling them for --columns, ahead of the one shown. Defaults to the number of CPUs
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// keep running in the background and serve the invocations of imgcat given --use-daemon, which connect to it
+    #[arg(long)]
+    daemon: bool,
+
+    /// Unix socket of --daemon. Defaults to daemon.sock in a directory of the user in $XDG_RUNTIME_DIR or in the temporary directory
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// memory --daemon keeps the bitmaps it decoded and scaled in, e.g. 64M, 0 to keep none
+    #[arg(long, value_name = "SIZE", default_value = "256M", value_parser = parse_byte_size)]
+    cache_size: u64,
+
+    /// have the command line run by the daemon of --daemon listening on --socket, if there is one
+    #[arg(long)]
+    use_daemon: bool,
+
+    /// listen on a Unix socket for JSON commands placing images at positions of the terminal, for editor plugins
+    #[arg(long, value_name = "PATH")]
+    serve: Option<PathBuf>,
+
+    /// draw the image fitted into a rectangle of cells, as a previewer of lf or ranger. The clear command erases it
+    #[arg(long, num_args = 4, value_names = ["WIDTH", "HEIGHT", "X", "Y"])]
+    previewer: Option<Vec<u16>>,
+
+    /// fit the image into the preview pane of vifm, from %pw %ph %px %py. With %pd it is drawn on the terminal,
+    /// otherwise written as lines of half blocks
+    #[arg(long, num_args = 4, value_names = ["WIDTH", "HEIGHT", "X", "Y"])]
+    vifm_preview: Option<Vec<u16>>,
+
+    /// preview the image in the pane of Yazi given by the area of its previewer plugins, writing kitty's Unicode
+    /// placeholders for Yazi to show where the terminal implements kitty's protocol. The clear command removes it
+    #[arg(long, num_args = 4, value_names = ["WIDTH", "HEIGHT", "X", "Y"])]
+    yazi_preview: Option<Vec<u16>>,
+
+    /// write the image as lines of half blocks for the viewer of Midnight Commander, sized to -W and -H,
+    /// $COLUMNS and $LINES, or the terminal
+    #[arg(long)]
+    mc_preview: bool,
+
+    /// size the image to the preview window of fzf and draw it with the iTerm2 protocol, for fzf --preview
+    #[arg(long)]
+    fzf_preview: bool,
+
+    /// in a terminal of Neovim, write the images to the terminal itself instead of having Neovim draw them over its window
+    #[arg(long)]
+    no_nvim: bool,
+
+    /// seconds to wait for a connection to a server
+    #[arg(long, value_name = "SECONDS", default_value = "30", value_parser = parse_seconds)]
+    timeout: Duration,
+
+    /// seconds between the starts of requests to servers, lengthened at random by up to half, so that batches of URLs don't hammer them
+    #[arg(long, value_name = "SECONDS", value_parser = parse_delay)]
+    delay_between: Option<f64>,
+
+    /// most requests to servers started each second, with the same random lengthening as --delay-between
+    #[arg(long, value_name = "N", value_parser = parse_rate)]
+    max_rps: Option<f64>,
+
+    /// maximum frame rate when playing an MJPEG stream
+    #[arg(long, value_parser = parse_rate)]
+    fps: Option<f64>,
+
+    /// stop playing an MJPEG stream after the given number of seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    duration: Option<Duration>,
+
+    /// capture the screen and show the screenshot
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "full")]
+    screenshot: Option<screenshot::Mode>,
+
+    /// show the album art of the track a media player is currently playing
+    #[arg(long)]
+    now_playing: bool,
+
+    /// redraw the image in place every time the file changes
+    #[arg(long, value_name = "FILE")]
+    watch: Option<String>,
+
+    /// watch a directory and show every image file written into it
+    #[arg(long, value_name = "DIR")]
+    monitor: Option<String>,
+
+    /// read IDs from stdin, one per line, and show the images at the URLs built by substituting them for {} in the template
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+
+    /// show the images of HTML pages, given as files or URLs, with their alt texts as captions
+    #[arg(long)]
+    extract_images: bool,
+
+    /// skip the images smaller than WIDTHxHEIGHT pixels, or N by N pixels
+    #[arg(long, value_name = "SIZE", value_parser = parse_min_size)]
+    min_size: Option<(u32, u32)>,
+
+    /// ask before showing an input larger than SIZE bytes, or with a K, M or G suffix; 0 never asks
+    #[arg(long, value_name = "SIZE", default_value = "50M", value_parser = parse_byte_size)]
+    confirm_above: u64,
+
+    /// show inputs larger than --confirm-above without asking
+    #[arg(short, long)]
+    yes: bool,
+
+    /// show the image currently stored in the system clipboard
+    #[arg(short, long)]
+    clipboard: bool,
+
+    /// image URL to show, without checking whether it is a local file. Repeat -u for each URL
+    #[arg(short, long = "url", value_name = "URL", num_args = 1, action = ArgAction::Append)]
+    urls: Vec<String>,
+
+    /// image file to show, even if its name looks like a URL or a git object. Repeat -f for each file
+    #[arg(short, long = "file", value_name = "FILE", num_args = 1, action = ArgAction::Append)]
+    files: Vec<String>,
+
+    /// input image files or URLs to show, or Markdown documents whose images to show. Read from stdin if not given
     #[arg(num_args = 0..)]
     inputs: Vec<String>
 }
 
+/// How an input is opened.
+#[derive(Clone, Copy, Debug)]
+enum InputKind {
+    /// a URL, a git object or a file, depending on what it looks like
+    Auto,
+    /// given with -u
+    Url,
+    /// given with -f
+    File,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Show images, the same as giving them without a command.
+    Show {
+        /// input image files or URLs to show. Read from stdin if not given
+        #[arg(num_args = 0..)]
+        inputs: Vec<String>,
+    },
+    /// Print the format, dimensions, color type, bit depth, frame count and file size of each image.
+    ///
+    /// The same as --info, or --json when it is given.
+    Info {
+        /// input image files or URLs. Read from stdin if not given
+        #[arg(num_args = 0..)]
+        inputs: Vec<String>,
+    },
+    /// Lay out images side by side, in as many columns of 20 cells as fit unless --columns is given.
+    Grid {
+        /// input image files or URLs to show. Read from stdin if not given
+        #[arg(num_args = 0..)]
+        inputs: Vec<String>,
+    },
+    /// Compare two images of the same size pixel by pixel and show where they differ.
+    ///
+    /// The differing pixels are drawn in red over a faded copy of the second image.
+    /// Exits with status 1 when the share of differing pixels is above the threshold.
+    Diff {
+        /// maximum share of differing pixels, in percent, for the images to be considered the same
+        #[arg(long, default_value_t = 0.0)]
+        threshold: f64,
+
+        /// only print the statistics, don't show the difference image
+        #[arg(long)]
+        no_image: bool,
+
+        first: String,
+        second: String,
+    },
+    /// Show two images side by side, labelled with their paths, for before/after reviews.
+    Compare {
+        /// show the images full screen one at a time instead, switching between them with space
+        #[arg(short, long)]
+        interactive: bool,
+
+        first: String,
+        second: String,
+    },
+    /// Convert a PNG, BMP, JPEG, GIF or WebP image to PNG or BMP, chosen by the extension of the output file.
+    Convert {
+        input: String,
+        output: String,
+    },
+    /// Probe the terminal and report what it supports, with a test image for each supported protocol.
+    ///
+    /// Run this first when images don't show up.
+    Check,
+    /// Clear the screen and the scrollback, removing the images shown.
+    Clear,
+    /// Place images from ueberzug's JSON commands read from stdin, in place of `ueberzug layer`.
+    ///
+    /// Each line is an object such as {"action": "add", "identifier": "preview", "x": 0,
+    /// "y": 0, "max_width": 40, "max_height": 20, "path": "photo.jpg"}, or
+    /// {"action": "remove", "identifier": "preview"}. The images are erased at the end of the input.
+    Layer {
+        /// format of the commands; only ueberzug's json is supported
+        #[arg(long, value_name = "NAME", default_value = "json", value_parser = ["json"])]
+        parser: String,
+
+        /// don't report invalid commands on stderr
+        #[arg(long)]
+        silent: bool,
+
+        /// output of ueberzugpp, used as the protocol when it names one, e.g. kitty or sixel
+        #[arg(short, long, value_name = "NAME")]
+        output: Option<String>,
+    },
+    /// Show a QR code of a text or URL, to open it on a phone.
+    ///
+    /// The code is drawn as an image, or as half blocks in black and white with --protocol blocks
+    /// or --text, which scan as well. Wi-Fi credentials are given as WIFI:T:WPA;S:<name>;P:<password>;;
+    Qr {
+        /// error correction level: the share of the code that can be damaged, from 7% for l to 30% for h
+        #[arg(long, value_enum, default_value = "m", ignore_case = true)]
+        level: qr::Level,
+
+        /// write the code as text with half blocks even where images can be shown
+        #[arg(long)]
+        text: bool,
+
+        #[arg(value_name = "TEXT")]
+        data: String,
+    },
+    /// Show solid swatches of colors labelled with their hex, rgb() and hsl() notations.
+    Color {
+        /// write black and white text on the swatches and the contrast ratios of the colors with them
+        #[arg(long)]
+        contrast: bool,
+
+        /// colors as #rrggbb, #rgb, rgb(r, g, b) or hsl(h, s%, l%)
+        #[arg(required = true, value_parser = swatch::parse_color)]
+        colors: Vec<[u8; 3]>,
+    },
+    /// Show a test card for checking how the terminal reproduces colors, corrects the aspect
+    /// of its cells and scales images.
+    ///
+    /// It has color bars, gray and color ramps, resolution wedges of lines one to four pixels
+    /// wide and a circle that only stays round when the aspect ratio is kept.
+    Testpattern,
+    /// Plot columns of CSV or TSV data as a line, scatter or bar chart.
+    ///
+    /// The names of the columns and the ranges of the axes are printed below the chart.
+    Plot {
+        /// column of the x values, by name or number from 1. The row numbers are used if not given or not numeric
+        #[arg(long, value_name = "COLUMN")]
+        x: Option<String>,
+
+        /// columns of the y values, each a series. All the other numeric columns if not given
+        #[arg(long, value_name = "COLUMN")]
+        y: Vec<String>,
+
+        #[arg(long, value_enum, default_value = "line")]
+        kind: chart::Kind,
+
+        /// delimiter of the fields, a tab when the first line has one and a comma otherwise if not given
+        #[arg(long, value_name = "CHAR")]
+        delimiter: Option<char>,
+
+        /// CSV or TSV file. Read from stdin if not given
+        input: Option<PathBuf>,
+    },
+    /// Show text, Markdown or source code as a syntax highlighted image, for previews that look
+    /// the same with every protocol.
+    ///
+    /// The language is taken from the extension of the file or of --stdin-name. The text is drawn
+    /// with a monospace TrueType font found on the system unless --font is given.
+    Code {
+        /// language of the text, such as rust, python or markdown, or an extension of its files
+        #[arg(long)]
+        language: Option<String>,
+
+        /// width of the text in characters, at which longer lines are wrapped
+        #[arg(long, value_name = "COLUMNS", default_value_t = 80)]
+        wrap: usize,
+
+        /// number of lines shown from the start of the text
+        #[arg(long, default_value_t = 40)]
+        lines: usize,
+
+        /// TrueType or OpenType font file the text is drawn with
+        #[arg(long, value_name = "PATH")]
+        font: Option<PathBuf>,
+
+        /// size of the font in pixels
+        #[arg(long, value_name = "PIXELS", default_value_t = 16.0)]
+        font_size: f64,
+
+        #[arg(long, value_enum, default_value = "auto")]
+        theme: highlight::Theme,
+
+        /// file to show. Read from stdin if not given
+        input: Option<PathBuf>,
+    },
+    /// Print the completion script for a shell.
+    ///
+    /// For example, add `source <(imgcat completions bash)` to ~/.bashrc.
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Preset {
+    /// 16x8 cells
+    Thumb,
+    /// a quarter of the terminal
+    Small,
+    /// half of the terminal
+    Medium,
+    /// three quarters of the terminal
+    Large,
+    /// the whole terminal
+    Full,
+}
+
+impl Preset {
+    /// The width and height of the preset.
+    fn size(self) -> (Length, Length) {
+        match self {
+            Preset::Thumb => (Length::Cells(16), Length::Cells(8)),
+            Preset::Small => (Length::Percent(25), Length::Percent(25)),
+            Preset::Medium => (Length::Percent(50), Length::Percent(50)),
+            Preset::Large => (Length::Percent(75), Length::Percent(75)),
+            Preset::Full => (Length::Percent(100), Length::Percent(100)),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Clear {
+    /// the whole screen
+    Screen,
+    /// the lines used by the previous image
+    Previous,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LabelPosition {
+    Above,
+    Below,
+}
+
+/// The contents of an image, read into memory, mapped from a file or shared
+/// with the buffer it was downloaded or read into.
+enum Data {
+    Owned(Vec<u8>),
+    Mapped(mmap::Mapping),
+    Shared(Bytes),
+}
+
+impl std::ops::Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Owned(data) => data,
+            Data::Mapped(mapping) => mapping,
+            Data::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Data {
+    fn from(data: Vec<u8>) -> Self {
+        Data::Owned(data)
+    }
+}
+
+impl From<Bytes> for Data {
+    fn from(bytes: Bytes) -> Self {
+        Data::Shared(bytes)
+    }
+}
+
 struct Image<'a> {
-    data: Vec<u8>,
+    data: Data,
     filename: Option<String>,
     path: Option<&'a str>,
+    /// file type hint used when -t is not given
+    file_type: Option<String>,
+}
+
+/// What an input resolves to: a single image, or a stream of frames to play.
+enum Source<'a> {
+    Image(Image<'a>),
+    /// a remote image whose body hasn't been read yet
+    Download(Download<'a>),
+    /// a multipart/x-mixed-replace response delivering a sequence of JPEG frames
+    Stream(Response),
+    /// a file that isn't a regular file, whose images are read as they come
+    Pipe(Pipe<'a>),
+}
+
+struct Download<'a> {
+    path: &'a str,
+    filename: Option<String>,
+    response: Response,
+    start: Instant,
+}
+
+impl<'a> Download<'a> {
+    fn into_image(self) -> anyhow::Result<Image<'a>> {
+        let Download {path, filename, response, start} = self;
+        let data = response
+            .bytes()
+            .map_err(ImgcatError::Http)
+            .with_context(|| tr!("failed to fetch image data from {}", path))?;
+        info!("fetched {} from {path} in {:.0?}", format_size(data.len()), start.elapsed());
+        Ok(Image {data: data.into(), filename, path: Some(path), file_type: None})
+    }
+}
+
+/// A named pipe, a process substitution such as `<(curl -s URL)`, or a device such as
+/// `/dev/stdin`: files without a length known in advance, which deliver their data as
+/// another process writes it.
+struct Pipe<'a> {
+    path: &'a str,
+    filename: Option<String>,
+    file: File,
+}
+
+impl<'a> Pipe<'a> {
+    fn open(path: &'a str) -> anyhow::Result<Self> {
+        let f = local_path(path);
+        let filename = file_name(&f);
+        let file = File::open(f.as_ref())
+            .map_err(ImgcatError::Io)
+            .with_context(|| tr!("failed to open file {}", f))?;
+        Ok(Self {path, filename, file})
+    }
+
+    /// Read the images from the pipe, each as soon as it is complete, as from stdin.
+    fn images(self) -> impl Iterator<Item = anyhow::Result<Image<'a>>> {
+        let Pipe {path, filename, file} = self;
+        split::split_stream(file).map(move |data| {
+            let data = data.map_err(ImgcatError::Io).with_context(|| tr!("failed to read from {}", path))?;
+            info!("read {} from {path}", format_size(data.len()));
+            Ok(Image {data: data.into(), filename: filename.clone(), path: Some(path), file_type: None})
+        })
+    }
+
+    /// Read all the data of the pipe as a single image.
+    fn into_image(self) -> anyhow::Result<Image<'a>> {
+        let Pipe {path, filename, mut file} = self;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(ImgcatError::Io)
+            .with_context(|| tr!("failed to read from {}", path))?;
+        info!("read {} from {path}", format_size(data.len()));
+        Ok(Image {data: data.into(), filename, path: Some(path), file_type: None})
+    }
+}
+
+impl<'a> Source<'a> {
+    fn open_as(path: &'a str, kind: InputKind) -> anyhow::Result<Self> {
+        if let Some(urls) = mirrors(path, kind) {
+            return Self::from_mirrors(urls);
+        }
+        match Location::resolve(path, kind)? {
+            Location::Url(u) => Self::from_url(path, u),
+            Location::Git(object) => Image::from_git(path, object).map(Source::Image),
+            Location::Data(url) => Image::from_data_url(url).map(Source::Image),
+            Location::File(f) if is_pipe(f) => Pipe::open(f).map(Source::Pipe),
+            Location::File(f) => Image::from_file(f).map(Source::Image),
+        }
+    }
+
+    /// Open the first of the mirrors of an image that answers successfully, trying the next
+    /// one when a server can't be reached or answers with an error.
+    fn from_mirrors(urls: Vec<(&'a str, Url)>) -> anyhow::Result<Self> {
+        let count = urls.len();
+        let mut failure = None;
+        for (i, (path, u)) in urls.into_iter().enumerate() {
+            match Self::from_url(path, u) {
+                Ok(source) => return Ok(source),
+                Err(e) if i + 1 < count => warn!("{}", tr!("{}, trying the next mirror", format!("{e:#}"))),
+                Err(e) => failure = Some(e),
+            }
+        }
+        Err(failure.expect("at least two mirrors"))
+    }
+
+    fn from_url(path: &'a str, u: Url) -> anyhow::Result<Self> {
+        // URL 中的文件名是百分号编码的，例如 %E5%9B%BE.png。
+        let filename = u.path()
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|x| !x.is_empty())
+            .map(|x| percent_decode_str(x).decode_utf8_lossy().into_owned());
+        pace::wait();
+        info!("fetching {path}");
+        let start = Instant::now();
+        let response = http_client()?
+            .get(u)
+            .send()
+            .map_err(ImgcatError::Http)
+            .with_context(|| tr!("failed to connect to {}", path))?;
+        debug!("{path}: {} {:?}, after {:.0?}", response.status(), response.headers().get("content-type"), start.elapsed());
+        // 错误页面（404 的 HTML 等）不是图片，不能当作图片发送给终端。
+        let status = response.status();
+        let response = response.error_for_status()
+            .map_err(ImgcatError::Http)
+            .with_context(|| tr!("{} answered {}", path, status))?;
+        if stream::is_mjpeg(&response) {
+            info!("{path} is an MJPEG stream");
+            return Ok(Source::Stream(response));
+        }
+        Ok(Source::Download(Download {path, filename, response, start}))
+    }
 }
 
 impl<'a> Image<'a> {
-    fn try_new(path: &'a str) -> anyhow::Result<Self> {
-        // 由于在 Windows 中， 类似 C:/a/b/c 这样的绝对路径可以被 Url::parse 函数正确解析。
-        // 这里限定 scheme 为给定集合中的值时，才认为他是一个图片的 URL。
-        if let Ok(u) = Url::parse(path) {
-            if SUPPORTED_SCHEMES.contains(u.scheme()) {
-                let filename = u.path()
-                    .trim_end_matches('/')
-                    .rsplitn(2, '/')
-                    .next()
-                    .map(|x| x.to_string());
-                let data = reqwest::blocking::get(u)
-                    .with_context(|| format!("failed to connect to {path}"))?
-                    .bytes()
-                    .with_context(|| format!("failed to fetch image data from {path}"))?
-                    .iter()
-                    .cloned()
-                    .collect();
-                return Ok(Self {data, filename, path: Some(path)});
-            }
-        }
-
-        // 其余情况，包括 Url 解析出错，或者解析得到的 scheme 不在给定的集合中，
-        // 则回退到认为给定的 path 是一个本地文件系统的路径。
-        let f = path.trim_start_matches("file://");
-        let filename = f.rsplitn(2, path_separator!())
+    /// Open an input that has to be a single image.
+    fn open(path: &'a str) -> anyhow::Result<Self> {
+        Self::open_as(path, InputKind::Auto)
+    }
+
+    fn open_as(path: &'a str, kind: InputKind) -> anyhow::Result<Self> {
+        match Source::open_as(path, kind)? {
+            Source::Image(image) => Ok(image),
+            Source::Download(download) => download.into_image(),
+            Source::Stream(_) => bail!(tr!("{} is a video stream, not an image", path)),
+            Source::Pipe(pipe) => pipe.into_image(),
+        }
+    }
+
+    fn from_file(path: &'a str) -> anyhow::Result<Self> {
+        Self::load_file(path, true)
+    }
+
+    /// Read a file that may be rewritten while it is shown, as with --watch and --monitor,
+    /// without mapping it: reading a mapping of a file truncated meanwhile raises SIGBUS.
+    fn read_file(path: &'a str) -> anyhow::Result<Self> {
+        Self::load_file(path, false)
+    }
+
+    fn load_file(path: &'a str, map: bool) -> anyhow::Result<Self> {
+        let f = local_path(path);
+        let f = f.as_ref();
+        let filename = file_name(f);
+        let mut file = File::open(f)
+            .map_err(ImgcatError::Io)
+            .with_context(|| tr!("failed to open file {}", f))?;
+        // 大的普通文件直接映射到内存中，不必复制一份；管道等其他文件只能读取。
+        let len = file.metadata()
+            .ok()
+            .filter(|m| map && m.is_file() && m.len() >= MMAP_THRESHOLD)
+            .and_then(|m| usize::try_from(m.len()).ok());
+        if let Some(mapping) = len.and_then(|len| mmap::Mapping::new(&file, len).ok()) {
+            debug!("mapped {} of {f}", format_size(mapping.len()));
+            return Ok(Self {data: Data::Mapped(mapping), filename, path: Some(path), file_type: None});
+        }
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(ImgcatError::Io)
+            .with_context(|| tr!("failed to read from file {}", f))?;
+        debug!("read {} from {f}", format_size(buffer.len()));
+        Ok(Self {data: buffer.into(), filename, path: Some(path), file_type: None})
+    }
+
+    /// Read a blob from the git repository of the current directory.
+    /// `object` has the form `REV:path/to/image.png`, as accepted by `git cat-file`.
+    fn from_git(path: &'a str, object: &str) -> anyhow::Result<Self> {
+        let (rev, file) = object.split_once(':')
+            .with_context(|| tr!("invalid git input {}, expected git:REV:path", path))?;
+        let output = terminal::command("git")
+            .args(["cat-file", "blob", object])
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| tr!("failed to run git, is it installed?"))?;
+        if !output.status.success() {
+            bail!(tr!(
+                "failed to read {} at revision {}: {}",
+                file,
+                rev,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+        debug!("read {} from {file} at revision {rev}", format_size(output.stdout.len()));
+        let filename = file.rsplit('/')
             .next()
             .map(|x| x.to_string());
-        let mut file = File::open(path)
-            .with_context(|| format!("failed to open file {f}"))?;
-        let metadata = fs::metadata(&f);
-        let mut buffer = match metadata {
-            Ok(m) => {vec![0; m.len() as usize]}
-            Err(_) => {Vec::new()}
+        Ok(Self {data: output.stdout.into(), filename, path: Some(path), file_type: None})
+    }
+
+    /// Decode the image embedded in a `data:` URL, in base64 or percent-encoded.
+    fn from_data_url(url: &str) -> anyhow::Result<Self> {
+        let (header, payload) = url["data:".len()..].split_once(',')
+            .with_context(|| tr!("invalid data: URL, expected a comma"))?;
+        let data = match header.strip_suffix(";base64") {
+            Some(_) => {
+                // base64 也可能被百分号编码。
+                let payload: Vec<u8> = percent_decode_str(payload).collect();
+                decode_base64(&payload).with_context(|| tr!("invalid base64 in data: URL"))?
+            }
+            None => percent_decode_str(payload).collect(),
         };
-        file.read(&mut buffer)
-            .with_context(|| format!("failed to read from file {f}"))?;
-        Ok(Self {data: buffer, filename, path: Some(path)})
+        let file_type = header.split(';').next()
+            .and_then(|mime| mime.strip_prefix("image/"))
+            .map(|subtype| format!(".{}", subtype.split('+').next().unwrap_or(subtype)));
+        debug!("decoded {} from a data: URL", format_size(data.len()));
+        Ok(Self {data: data.into(), filename: None, path: None, file_type})
     }
 
-    fn from_stdin() -> anyhow::Result<Self> {
-        let mut data = Vec::new();
-        io::stdin().read_to_end(&mut data)
-            .with_context(|| "failed to read stdin")?;
-        Ok(Self {data, filename: None, path: None})
+    /// Read the images from stdin, each as soon as it is complete. Several PNG, JPEG or GIF
+    /// images may be concatenated in the stream, such as the figures of a plotting loop,
+    /// and each of them is returned separately. Images encoded in base64 are decoded, if
+    /// `base64` tells so or they are recognized as such.
+    ///
+    /// `name` is used as the file name of the images, and its extension as their file type.
+    fn from_stdin(name: Option<&str>, base64: bool) -> impl Iterator<Item = anyhow::Result<Self>> {
+        let filename = name.map(|x| x.to_string());
+        let file_type = name
+            .and_then(|x| x.rsplit_once('.'))
+            .map(|(_, ext)| format!(".{ext}"));
+        split::split_stream(terminal::stdin()).map(move |data| {
+            let mut data = data.with_context(|| tr!("failed to read stdin"))?;
+            info!("read {} from stdin", format_size(data.len()));
+            if base64 {
+                data = base64_image(&data).with_context(|| tr!("invalid base64 on stdin"))?;
+            } else if probe::probe(&data).is_none() {
+                // JSON 接口和 MIME 邮件给出的图片常是 base64 文本，解码后是图片才采用。
+                if let Some(decoded) = base64_image(&data).filter(|d| probe::probe(d).is_some()) {
+                    debug!("decoded {} of base64 from stdin", format_size(decoded.len()));
+                    data = decoded;
+                }
+            }
+            Ok(Self {data: data.into(), filename: filename.clone(), path: None, file_type: file_type.clone()})
+        })
+    }
+
+    fn from_clipboard() -> anyhow::Result<Self> {
+        let data = clipboard::read_image()
+            .with_context(|| tr!("failed to read image from clipboard"))?;
+        Ok(Self {data: data.into(), filename: Some("clipboard.png".to_string()), path: None, file_type: None})
+    }
+
+    fn from_screenshot(mode: screenshot::Mode) -> anyhow::Result<Self> {
+        let data = screenshot::capture(mode)
+            .with_context(|| tr!("failed to capture the screen"))?;
+        Ok(Self {data: data.into(), filename: Some("screenshot.png".to_string()), path: None, file_type: None})
     }
 
     fn len(&self) -> usize {
@@ -122,77 +1027,2427 @@ impl<'a> Image<'a> {
     }
 }
 
-fn print_osc() {
-    if let Ok(term) = env::var("TERM") {
-        if term.starts_with("screen") || term.starts_with("tmux") {
-            print!("\x1bPtmux;\x1b\x1b]");
-        } else {
-            print!("\x1b]");
+/// Whether the file input `path` isn't a regular file or a directory, e.g. a named pipe.
+fn is_pipe(path: &str) -> bool {
+    fs::metadata(local_path(path).as_ref()).is_ok_and(|m| !m.is_file() && !m.is_dir())
+}
+
+/// Decode base64 text, ignoring whitespace such as the line breaks of MIME parts, and
+/// accepting the URL-safe alphabet and missing padding.
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let text: Vec<u8> = text.iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .map(|&b| match b {
+            b'-' => b'+',
+            b'_' => b'/',
+            b => b,
+        })
+        .collect();
+    let end = text.iter().rposition(|&b| b != b'=').map_or(0, |i| i + 1);
+    BASE64_STANDARD_NO_PAD.decode(&text[..end]).ok()
+}
+
+/// The bytes encoded in base64 in `text` read from stdin, also when it is a `data:` URL.
+fn base64_image(text: &[u8]) -> Option<Vec<u8>> {
+    let text = text.trim_ascii();
+    let payload = match text.strip_prefix(b"data:") {
+        Some(url) => &url[url.iter().position(|&b| b == b',')? + 1..],
+        None => text,
+    };
+    decode_base64(payload).filter(|data| !data.is_empty())
+}
+
+/// The last component of the local path `path`, by the rules of the platform: on Windows,
+/// after either kind of slash or the drive of a drive-relative path.
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+/// The file system path of a file input.
+fn local_path(path: &str) -> Cow<'_, str> {
+    // file:// URL 中的路径是百分号编码的，例如 MPRIS 给出的专辑封面地址。
+    // to_file_path 还处理 Windows 的盘符和 UNC 路径：file:///C:/a.png、file://server/share/a.png。
+    if path.starts_with("file://") {
+        if let Some(file) = Url::parse(path).ok().and_then(|u| u.to_file_path().ok()) {
+            return Cow::Owned(file.to_string_lossy().into_owned());
         }
-    } else {
-        print!("\x1b]");
+    }
+    match path.strip_prefix("file://") {
+        Some(p) => percent_decode_str(p).decode_utf8_lossy(),
+        None => Cow::Borrowed(path),
     }
 }
 
-fn print_image(
-    image: Image,
-    args: &Cli,
-) {
-    print_osc();
-    print!("1337;File=inline=1;size={}", image.len());
+thread_local! {
+    /// Connection timeout given by --timeout to the command line running on this thread.
+    static CONNECT_TIMEOUT: Cell<Duration> = const { Cell::new(Duration::from_secs(30)) };
+}
 
-    if let Some(name) = &image.filename {
-        print!(";name={}", BASE64_URL_SAFE.encode(name));
-    }
+/// The HTTP client and the timeout it was built with, kept so that connections are
+/// reused, also across the command lines run by --daemon.
+static HTTP_CLIENT: Mutex<Option<(Duration, Client)>> = Mutex::new(None);
 
-    if let Some(w) = &args.width {
-        print!(";width={w}");
+/// The HTTP client used to fetch remote images.
+///
+/// Only the connection is subject to a timeout: reading the body of an MJPEG
+/// stream never finishes, so a total request timeout would cut it off.
+fn http_client() -> anyhow::Result<Client> {
+    let timeout = CONNECT_TIMEOUT.get();
+    let mut cached = HTTP_CLIENT.lock().unwrap();
+    if let Some((t, client)) = &*cached {
+        if *t == timeout {
+            return Ok(client.clone());
+        }
     }
+    let client = Client::builder()
+        .connect_timeout(timeout)
+        .timeout(None)
+        .build()
+        .with_context(|| tr!("failed to create HTTP client"))?;
+    *cached = Some((timeout, client.clone()));
+    Ok(client)
+}
 
-    if let Some(h) = &args.height {
-        print!(";height={h}");
-    }
+/// Prints images one after another, either stacked vertically or laid out in a grid.
+struct Printer<'a, W: Write> {
+    args: &'a Cli,
+    /// where the escape sequences and captions are written
+    out: W,
+    grid: Option<Grid>,
+    /// paths of the images printed so far, `-` for images without one
+    shown: Vec<String>,
+    /// with --pager, whether a page was completed and a keypress is due before the next image
+    page_full: bool,
+    /// set when the user quits the pager, no more images should be printed
+    quit: bool,
+    /// what to clear before each image
+    clear: Option<Clear>,
+    /// number of lines used by the last image and its captions, if known
+    drawn_rows: Option<u16>,
+    /// set when an image didn't match the digest given by --expect
+    mismatch: bool,
+    /// alt text of the next image, taken from the Markdown document referencing it and
+    /// printed in place of its --label
+    caption: Option<String>,
+    /// with --plot-mode, the dark background of the terminal, onto which plots are turned
+    plot_background: Option<[u8; 3]>,
+}
 
-    print!(";preserveAspectRatio={}", args.preserve_aspect_ratio as u8);
+/// State of the --columns layout.
+///
+/// The rows needed by a row of images are reserved up front, so that drawing
+/// never scrolls the screen and the saved cursor position stays valid. Every
+/// image is then drawn at the saved position shifted right by its column.
+struct Grid {
+    columns: u16,
+    /// width of a grid cell in character cells, including the gap between images
+    cell_width: u16,
+    /// height of the images in character cells
+    cell_height: u16,
+    /// column the next image is drawn in
+    column: u16,
+}
 
-    if let Some(ft) = &args.file_type {
-        print!(";type={ft}");
+impl Grid {
+    /// The width and height the images are drawn at, leaving a gap between them.
+    fn cell(&self) -> (Length, Length) {
+        (Length::Cells((self.cell_width - 1) as u32), Length::Cells(self.cell_height as u32))
     }
-    print!(":{}", BASE64_STANDARD.encode(&image.data));
-    print_st();
+}
 
-    println!();
-    if args.print_path {
-        if let Some(name) = &image.path {
-            println!("{name}");
+impl<'a, W: Write> Printer<'a, W> {
+    fn new(args: &'a Cli, out: W) -> Self {
+        let grid = args.columns.filter(|n| *n > 0).map(|columns| {
+            let size = terminal::size();
+            let cell_width = (size.columns / columns).max(2);
+            // 一行图片必须能完整显示在屏幕内，否则预留的行数会失效。
+            let caption_rows = caption_rows(args);
+            let max_height = size.rows.saturating_sub(1 + caption_rows).max(1);
+            let cell_height = match args.height {
+                Some(Length::Cells(n)) => n.min(u16::MAX as u32) as u16,
+                _ => cell_width / 2,
+            }
+                .clamp(1, max_height);
+            Grid {columns, cell_width, cell_height, column: 0}
+        });
+        Self {
+            args,
+            out,
+            grid,
+            shown: Vec::new(),
+            page_full: false,
+            quit: false,
+            clear: args.clear,
+            drawn_rows: None,
+            mismatch: false,
+            caption: None,
+            // 终端不回答背景色查询时，假定它是深色的，这在终端中最常见。
+            plot_background: args.plot_mode
+                .then(|| terminal::background().unwrap_or([0; 3]))
+                .filter(|color| plot::is_dark(*color)),
         }
     }
-}
 
-fn print_st() {
-    if let Ok(term) = env::var("TERM") {
-        if term.starts_with("screen") || term.starts_with("tmux") {
-            print!("\x07\x1b\\");
+    fn print(&mut self, image: Image) -> io::Result<()> {
+        self.print_decoded(image, None, None, Timings::default())
+    }
+
+    /// Print `image`, whose pixels may have been decoded already for --histogram, --palette
+    /// or to convert it to a format the terminal takes, or scaled to its cell of the grid,
+    /// as `timings` tells.
+    fn print_decoded(
+        &mut self,
+        image: Image,
+        bitmap: Option<imgcat::Result<Bitmap>>,
+        prepared: Option<Prepared>,
+        mut timings: Timings,
+    ) -> io::Result<()> {
+        let args = self.args;
+        if let (Some((min_width, min_height)), Some(info)) = (args.min_size, probe::probe(&image.data)) {
+            if info.width < min_width || info.height < min_height {
+                debug!("{}: skipped, {}x{} is smaller than --min-size", image.path.unwrap_or("-"), info.width, info.height);
+                return Ok(());
+            }
+        }
+        if let Some(expected) = &args.expect {
+            let algorithm = args.checksum.unwrap_or(digest::Algorithm::Sha256);
+            let actual = algorithm.hex_digest(&image.data);
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                warn!("{}", tr!("{}: {} mismatch, expected {}, got {}", image.path.unwrap_or("-"), algorithm.name(), expected, actual));
+                self.mismatch = true;
+            }
+        }
+        if args.info {
+            return print_info(&mut self.out, &image);
+        }
+        if args.json {
+            return print_json(&mut self.out, &image, args);
+        }
+        let Some(index) = self.next_index(image.path)? else {
+            return Ok(());
+        };
+        let first = index == 1;
+        if args.window {
+            return window::open(&image.data, image.path.unwrap_or("-")).map_err(|e| io::Error::other(format!("{e:#}")));
+        }
+        let undrawable = args.protocol == Protocol::Auto && !render::auto_draws(probe::probe(&image.data).map(|info| info.format));
+        if args.plain || undrawable {
+            writeln!(self.out, "[{}]", format_label(&args.alt, &image))?;
+            self.drawn_rows = Some(1);
+            return Ok(());
+        }
+        if args.copy {
+            // 复制失败不影响显示。
+            if let Err(e) = copy_image(&mut self.out, &image, args) {
+                warn!("{}", tr!("{}: failed to copy to the clipboard: {}", image.path.unwrap_or("-"), format!("{e:#}")));
+            }
+        }
+        let convert = needs_converting(&image.data, args);
+        let darken = self.plot_background.is_some();
+        let bitmap = bitmap.or_else(|| (needs_bitmap(args) && self.grid.is_none() || convert || darken).then(|| {
+            let start = Instant::now();
+            let bitmap = decode_cached(&image);
+            timings.decode = Some(start.elapsed());
+            bitmap
+        }));
+        let converted = match (&bitmap, self.plot_background) {
+            (Some(Ok(bitmap)), Some(background)) if plot::light_background(bitmap) => {
+                let start = Instant::now();
+                let converted = converted_image(&image, &plot::darken(bitmap, background));
+                timings.encode = Some(start.elapsed());
+                Some(converted)
+            }
+            (Some(Ok(bitmap)), _) if convert => {
+                let start = Instant::now();
+                let converted = converted_image(&image, bitmap);
+                timings.encode = Some(start.elapsed());
+                Some(converted)
+            }
+            // 终端不认识原来的格式，照原样发送只会什么也显示不出来。
+            (Some(Err(e)), _) if convert => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, tr!("failed to convert to PNG for this terminal: {}", format!("{e:#}"))));
+            }
+            _ => None,
+        };
+        let shown = converted.as_ref().unwrap_or(&image);
+        let label = self.caption.take().or_else(|| args.label.as_deref().map(|t| format_label(t, &image)));
+        let out = &mut self.out;
+        let Some(grid) = &mut self.grid else {
+            match (self.clear, self.drawn_rows) {
+                (Some(Clear::Previous), _) if first => {}
+                (Some(Clear::Previous), Some(rows)) => write!(out, "\x1b[{rows}A\x1b[J")?,
+                // 不知道上一张图片占用了多少行时，只能清除整个屏幕。
+                (Some(_), _) => renderer(args).clear(out)?,
+                (None, _) => {}
+            }
+            let mut captions = 0;
+            if args.number {
+                writeln!(out, "[{index}]")?;
+                captions += 1;
+            }
+            if let (Some(label), LabelPosition::Above) = (&label, args.label_position) {
+                writeln!(out, "{label}")?;
+                captions += 1;
+            }
+            let (rows, sent) = print_framed(out, shown, args)?;
+            // asciinema 的录像中没有图片，留下替代文本。
+            if terminal::recorded() {
+                writeln!(out, "[{}]", format_label(&args.alt, &image))?;
+                captions += 1;
+            }
+            if args.print_path {
+                if let Some(name) = &image.path {
+                    writeln!(out, "{}", linked(name, name))?;
+                    captions += 1;
+                }
+            }
+            if let (Some(label), LabelPosition::Below) = (&label, args.label_position) {
+                writeln!(out, "{label}")?;
+                captions += 1;
+            }
+            if args.exif {
+                for (name, value) in exif::fields(&image.data) {
+                    writeln!(out, "{name:>8}: {value}")?;
+                    captions += 1;
+                }
+            }
+            if let (Some(channels), Some(bitmap)) = (args.histogram, &bitmap) {
+                captions += print_histogram(out, bitmap, channels, args)?;
+            }
+            if let (Some(n), Some(bitmap)) = (args.palette, &bitmap) {
+                print_palette(out, bitmap, n)?;
+                captions += 1;
+            }
+            if let Some(algorithm) = args.checksum {
+                // 与 sha256sum 等工具的输出格式相同，方便直接比对。
+                writeln!(out, "{}  {}", algorithm.hex_digest(&image.data), image.path.unwrap_or("-"))?;
+                captions += 1;
+            }
+            self.drawn_rows = rows.map(|r| r + captions);
+            if args.stats {
+                print_stats(image.path, &timings, &sent);
+            }
+            return Ok(());
+        };
+
+        // 网格中每张图片只有一行说明文字：优先使用 --label，否则使用 -p 打印的路径。
+        let caption = label.or_else(|| args.print_path.then(|| image.path.map(str::to_string)).flatten());
+        let linked_path = args.label.is_none() && args.print_path;
+        let caption_rows = caption_rows(args);
+        // 没有为说明文字预留行时，Markdown 中的替代文本也不显示，以免超出网格。
+        let caption = match (args.number, caption.filter(|_| caption_rows > 0)) {
+            (true, Some(c)) => Some(format!("[{index}] {c}")),
+            (true, None) => Some(format!("[{index}]")),
+            (false, c) => c,
+        };
+        let (image_row, caption_row) = match args.label_position {
+            LabelPosition::Above => (caption_rows, 0),
+            LabelPosition::Below => (0, grid.cell_height),
+        };
+        if grid.column == 0 {
+            if self.clear.is_some() {
+                renderer(args).clear(out)?;
+            }
+            let rows = grid.cell_height + caption_rows;
+            write!(out, "{}\x1b[{rows}A\x1b7", "\n".repeat(rows as usize))?;
+        }
+        let offset = grid.column * grid.cell_width;
+        let goto = |out: &mut dyn Write, row: u16| -> io::Result<()> {
+            write!(out, "\x1b8")?;
+            if offset > 0 {
+                write!(out, "\x1b[{offset}C")?;
+            }
+            if row > 0 {
+                write!(out, "\x1b[{row}B")?;
+            }
+            Ok(())
+        };
+        goto(out, image_row)?;
+        let (width, height) = grid.cell();
+        let sent = print_prepared(out, shown, prepared.as_ref(), args, Some(width), Some(height))?;
+        if args.stats {
+            print_stats(image.path, &timings, &sent);
+        }
+        if let Some(caption) = caption {
+            goto(out, caption_row)?;
+            let caption: String = caption.chars().take(grid.cell_width as usize - 1).collect();
+            match image.path {
+                Some(path) if linked_path => write!(out, "{}", linked(path, &caption))?,
+                _ => write!(out, "{caption}")?,
+            }
+        }
+        grid.column += 1;
+        if grid.column == grid.columns {
+            self.finish()?;
         } else {
-            print!("\x07");
+            // 网格中一整行图片算作一页。
+            self.page_full = false;
         }
-    } else {
-        print!("\x07");
+        Ok(())
     }
-}
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
-    if args.inputs.is_empty() {
-        let image = Image::from_stdin()?;
-        print_image(image, &args);
-    } else {
-        args.inputs
-            .iter()
-            .try_for_each(|x| -> anyhow::Result<()> {
-                print_image(Image::try_new(x)?, &args);
-                Ok(())
-            })?;
+    /// Wait for the next page if the current one is full, and record that the
+    /// image at `path` is shown. Return its index, or `None` if the user quit.
+    fn next_index(&mut self, path: Option<&str>) -> io::Result<Option<usize>> {
+        if self.page_full {
+            self.wait_for_next_page()?;
+        }
+        if self.quit {
+            return Ok(None);
+        }
+        self.page_full = self.args.pager;
+        self.shown.push(path.unwrap_or("-").to_string());
+        Ok(Some(self.shown.len()))
+    }
+
+    /// Whether remote images can be sent to the terminal as they are downloaded:
+    /// nothing is requested that needs their contents or dimensions first.
+    fn streamable(&self) -> bool {
+        let args = self.args;
+        renderer(args).capabilities().streaming
+            && self.grid.is_none()
+            && self.clear != Some(Clear::Previous)
+            && args.expect.is_none()
+            && args.min_size.is_none()
+            && !args.info
+            && !args.json
+            && args.label.is_none()
+            && !args.exif
+            && args.histogram.is_none()
+            && args.palette.is_none()
+            && !args.plot_mode
+            && args.checksum.is_none()
+            && !args.copy
+            && !args.window
+            && !terminal::recorded()
+            && !args.plain
+            && args.border.is_none()
+            && args.padding == 0
+            && args.margin == 0
+            && args.align == Align::Left
+    }
+
+    /// Send a remote image to the terminal while it is downloaded, so that memory
+    /// use stays bounded and the terminal gets the first bytes early.
+    fn print_download(&mut self, download: Download) -> anyhow::Result<()> {
+        let args = self.args;
+        let Download {path, filename, mut response, start} = download;
+        let Some(index) = self.next_index(Some(path))? else {
+            return Ok(());
+        };
+        let out = &mut self.out;
+        if self.clear.is_some() {
+            renderer(args).clear(out)?;
+        }
+        if args.number {
+            writeln!(out, "[{index}]")?;
+        }
+        let header = Header {
+            path: Some(path),
+            filename: filename.as_deref(),
+            file_type: None,
+            size: response.content_length(),
+            format: None,
+            cache: None,
+        };
+        let sent = send_inline(out, &header, &mut response, args, args.width, args.height)
+            .with_context(|| tr!("failed to stream {}", path))?;
+        info!("streamed {path} in {:.0?}", start.elapsed());
+        if args.stats {
+            // 边下载边发送：fetch 只是开始发送之前的时间，其余的下载时间算在 send 中。
+            print_stats(Some(path), &Timings {fetch: Some(start.elapsed() - sent.elapsed), ..Timings::default()}, &sent);
+        }
+        writeln!(out)?;
+        if args.print_path {
+            writeln!(out, "{}", linked(path, path))?;
+        }
+        if let Some(caption) = self.caption.take() {
+            writeln!(out, "{caption}")?;
+        }
+        self.drawn_rows = None;
+        Ok(())
+    }
+
+    /// Wait for a keypress before the next page: q quits, any other key continues.
+    fn wait_for_next_page(&mut self) -> io::Result<()> {
+        const PROMPT: &str = "-- more -- (space: next, q: quit)";
+        self.page_full = false;
+        write!(self.out, "\x1b[7m{PROMPT}\x1b[0m")?;
+        self.out.flush()?;
+        let key = terminal::read_key();
+        // 清除提示行，使下一张图片从同一行开始绘制。
+        write!(self.out, "\r\x1b[2K")?;
+        self.quit = matches!(key, Ok(b'q' | b'Q') | Err(_));
+        Ok(())
+    }
+
+    /// Move the cursor below the last row of images.
+    fn finish(&mut self) -> io::Result<()> {
+        let caption_rows = caption_rows(self.args);
+        if let Some(grid) = &mut self.grid {
+            if grid.column > 0 {
+                writeln!(self.out, "\x1b8\x1b[{}B", grid.cell_height + caption_rows)?;
+                grid.column = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print `image` with the margin, frame and alignment requested on the command line,
+/// leaving the cursor at the start of the line below it.
+///
+/// Return the number of lines used, if the size of the image is known, and what was sent.
+fn print_framed(out: &mut impl Write, image: &Image, args: &Cli) -> io::Result<(Option<u16>, Sent)> {
+    let frame = Frame {border: args.border, padding: args.padding};
+    let size = terminal::size();
+    let (width, height) = (args.width, args.height);
+    let cells = layout::display_cells(&image.data, width, height, args.preserve_aspect_ratio, &size);
+    write!(out, "{}", "\n".repeat(args.margin as usize))?;
+    match cells {
+        Some((columns, rows)) if !frame.is_empty() => {
+            let outer = columns + 2 * frame.inset() + 2 * args.margin;
+            let indent = args.margin + alignment_padding(outer, args.align, &size);
+            frame.draw(out, indent, columns, rows)?;
+            // 先画好边框，再回到框内绘制图片，最后恢复到边框下方。
+            write!(out, "\x1b7\x1b[{}A\x1b[{}G", rows + frame.inset(), indent + frame.inset() + 1)?;
+            let sent = print_inline(out, image, args, Some(Length::Cells(columns as u32)), Some(Length::Cells(rows as u32)))?;
+            write!(out, "\x1b8")?;
+            write!(out, "{}", "\n".repeat(args.margin as usize))?;
+            Ok((Some(rows + 2 * frame.inset() + 2 * args.margin), sent))
+        }
+        _ => {
+            let indent = args.margin + cells.map_or(0, |(columns, _)| {
+                alignment_padding(columns + 2 * args.margin, args.align, &size)
+            });
+            if indent > 0 {
+                write!(out, "\x1b[{indent}C")?;
+            }
+            let sent = print_inline(out, image, args, width, height)?;
+            writeln!(out)?;
+            write!(out, "{}", "\n".repeat(args.margin as usize))?;
+            Ok((cells.map(|(_, rows)| rows + 2 * args.margin), sent))
+        }
+    }
+}
+
+/// Draw the histogram of `image` and return the number of lines used.
+fn print_histogram(out: &mut impl Write, bitmap: &imgcat::Result<Bitmap>, channels: histogram::Channels, args: &Cli) -> io::Result<u16> {
+    const ROWS: u16 = 6;
+    match bitmap {
+        Ok(bitmap) => {
+            let histogram = Image {
+                data: histogram::render(bitmap, channels).to_png().into(),
+                filename: Some("histogram.png".to_string()),
+                path: None,
+                file_type: None,
+            };
+            print_inline(out, &histogram, args, Some(Length::Cells(ROWS as u32 * 4)), Some(Length::Cells(ROWS as u32)))?;
+            writeln!(out)?;
+            Ok(ROWS)
+        }
+        Err(e) => {
+            writeln!(out, "histogram: {e}")?;
+            Ok(1)
+        }
+    }
+}
+
+/// Print the `n` dominant colors of `image` on a line, as swatches followed by their hex codes.
+fn print_palette(out: &mut impl Write, bitmap: &imgcat::Result<Bitmap>, n: usize) -> io::Result<()> {
+    match bitmap {
+        Ok(bitmap) => {
+            let swatches = palette::dominant_colors(bitmap, n)
+                .iter()
+                .map(|s| {
+                    let [r, g, b] = s.rgb;
+                    format!("\x1b[48;2;{r};{g};{b}m   \x1b[0m {} {:.0}%", s.hex(), s.share * 100.0)
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", swatches.join("  "))
+        }
+        Err(e) => writeln!(out, "palette: {e}"),
+    }
+}
+
+/// Number of columns to move right so that something `columns` wide is aligned as requested by --align.
+fn alignment_padding(columns: u16, align: Align, size: &terminal::Size) -> u16 {
+    let free = size.columns.saturating_sub(columns);
+    match align {
+        Align::Left => 0,
+        Align::Center => free / 2,
+        Align::Right => free,
+    }
+}
+
+/// The URI printed paths and images link to: the URL itself, or a `file://` URI for local files.
+fn link_target(path: &str) -> Option<String> {
+    if path.starts_with("git:") {
+        return None;
+    }
+    if let Ok(u) = Url::parse(path) {
+        if SUPPORTED_SCHEMES.contains(u.scheme()) || u.scheme() == "file" {
+            return Some(path.to_string());
+        }
+    }
+    let absolute = fs::canonicalize(path).ok()?;
+    let mut uri = Url::from_file_path(absolute).ok()?;
+    // 带上主机名，终端才能区分通过 SSH 访问的远程文件；UNC 路径的主机是文件所在的服务器。
+    if let Some(host) = terminal::hostname().filter(|_| uri.host_str().is_none_or(str::is_empty)) {
+        let _ = uri.set_host(Some(&host));
+    }
+    Some(uri.to_string())
+}
+
+/// `text`, wrapped in a hyperlink to `path` when it can be linked to.
+fn linked(path: &str, text: &str) -> String {
+    match link_target(path) {
+        Some(uri) => terminal::hyperlink(&uri, text),
+        None => text.to_string(),
+    }
+}
+
+/// Number of caption lines drawn along with each image of a grid.
+fn caption_rows(args: &Cli) -> u16 {
+    (args.print_path || args.label.is_some() || args.number) as u16
+}
+
+/// Compare two images for the diff subcommand, returning whether they are
+/// within the threshold.
+fn run_diff(first: &str, second: &str, threshold: f64, no_image: bool, printer: &mut Printer<impl Write>) -> anyhow::Result<bool> {
+    let decode = |path| -> anyhow::Result<Bitmap> {
+        Bitmap::decode(&Image::open(path)?.data)
+            .with_context(|| tr!("failed to decode {}", path))
+    };
+    let (comparison, highlighted) = diff::compare(&decode(first)?, &decode(second)?)?;
+    if !no_image && comparison.differing > 0 {
+        printer.print(Image {
+            data: highlighted.to_png().into(),
+            filename: Some("diff.png".to_string()),
+            path: None,
+            file_type: None,
+        })?;
+        printer.finish()?;
+    }
+    writeln!(
+        printer.out,
+        "{} of {} pixels differ ({:.3}%), mean difference {:.2}, hash distance {}",
+        comparison.differing, comparison.total, comparison.percent(), comparison.mean_delta, comparison.hash_distance,
+    )?;
+    Ok(comparison.percent() <= threshold)
+}
+
+/// Show two images side by side for the compare subcommand, or toggle between them full screen.
+fn run_compare(first: &str, second: &str, interactive: bool, args: &Cli) -> anyhow::Result<()> {
+    let inputs = [first.to_string(), second.to_string()];
+    if interactive {
+        return viewer::toggle(&mut io::stdout(), &inputs, args);
+    }
+    let args = Cli {
+        columns: Some(2),
+        label: args.label.clone().or(Some("{path}".to_string())),
+        ..args.clone()
+    };
+    let mut printer = Printer::new(&args, terminal::stdout());
+    for path in &inputs {
+        printer.print(Image::open(path)?)?;
+    }
+    printer.finish()?;
+    Ok(())
+}
+
+/// Decode the image at `input` and write it to `output` as PNG or BMP, chosen by the
+/// extension of `output`.
+fn run_convert(input: &str, output: &str) -> anyhow::Result<()> {
+    let image = Image::open(input)?;
+    let bitmap = Bitmap::decode(&image.data).with_context(|| tr!("failed to decode {}", input))?;
+    let extension = Path::new(output).extension().and_then(|x| x.to_str()).unwrap_or_default();
+    let data = match extension.to_ascii_lowercase().as_str() {
+        "png" => bitmap.to_png(),
+        "bmp" => bitmap.to_bmp(),
+        _ => bail!(tr!("unsupported output format of {}, expected a .png or .bmp file", output)),
+    };
+    fs::write(output, data).with_context(|| tr!("failed to write {}", output))
+}
+
+/// Ask on the terminal whether to show the input at `path` when it takes more than
+/// --confirm-above, unless --yes is given. Encoding and sending a huge image can keep
+/// the terminal, or a whole tmux session, busy for minutes. Without a terminal to ask,
+/// as in previewers and scripts, the input is refused.
+fn confirm_size(out: &mut impl Write, path: &str, size: Option<u64>, args: &Cli) -> anyhow::Result<()> {
+    let Some(size) = size.filter(|&n| needs_confirming(n, args)) else {
+        return Ok(());
+    };
+    let human = format_size(size as usize);
+    if terminal::stderr().is_terminal() {
+        // 先让前面的图片显示出来，再提问。
+        out.flush()?;
+        if let Ok(answer) = terminal::prompt_line(&tr!("{} takes {}, show it anyway? [y/N] ", path, human)) {
+            if matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes") {
+                return Ok(());
+            }
+        }
+    }
+    Err(ImgcatError::PayloadTooLarge {size, limit: args.confirm_above})
+        .context(tr!("{} is larger than --confirm-above, give --yes to show it", path))
+}
+
+/// Whether an input of `size` bytes has to be confirmed before it is shown.
+fn needs_confirming(size: u64, args: &Cli) -> bool {
+    // --info 和 --json 不编码图片，不用询问。
+    args.confirm_above > 0 && size > args.confirm_above && !args.yes && !args.info && !args.json
+}
+
+/// Ask which of the printed images to pick and write its index and path to `output`.
+fn select_image(shown: &[String], output: &mut impl Write) -> anyhow::Result<()> {
+    if shown.is_empty() {
+        bail!(tr!("no image to select"));
+    }
+    let answer = terminal::prompt_line(&tr!("select an image [1-{}]: ", shown.len()))
+        .with_context(|| tr!("failed to read the selection from the terminal"))?;
+    let index: usize = answer.parse()
+        .ok()
+        .filter(|i| (1..=shown.len()).contains(i))
+        .with_context(|| tr!("invalid selection: {}", answer))?;
+    writeln!(output, "{index}\t{}", shown[index - 1])?;
+    Ok(())
+}
+
+/// Expand the placeholders of a --label template for `image`.
+fn format_label(template: &str, image: &Image) -> String {
+    let info = probe::probe(&image.data);
+    let dimension = |f: fn(&probe::Info) -> u32| info.as_ref()
+        .map_or("?".to_string(), |i| f(i).to_string());
+    // 一次展开，文件名里的 {…} 不会被再次替换。
+    let mut label = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        label.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else { break };
+        let value = match &rest[1..end] {
+            "name" => image.filename.as_deref().or(image.path).unwrap_or("-").to_string(),
+            "path" => image.path.unwrap_or("-").to_string(),
+            "format" => info.map_or("?".to_string(), |i| i.format.to_string()),
+            "width" => dimension(|i| i.width),
+            "height" => dimension(|i| i.height),
+            "size" => format_size(image.len()),
+            _ => {
+                label.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        label.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    label.push_str(rest);
+    label
+}
+
+/// Print a line describing `image`, for --info.
+fn print_info(out: &mut impl Write, image: &Image) -> io::Result<()> {
+    let name = image.path.or(image.filename.as_deref()).unwrap_or("-");
+    let size = format_size(image.len());
+    match probe::probe(&image.data) {
+        Some(info) => {
+            let frames = probe::frame_count(&image.data, info.format)
+                .map_or("? frames".to_string(), |n| format!("{n} frame{}", if n == 1 { "" } else { "s" }));
+            writeln!(
+                out,
+                "{name}: {} {}x{}, {}, {}-bit, {frames}, {size}",
+                info.format, info.width, info.height, info.color, info.bit_depth,
+            )
+        }
+        None => writeln!(out, "{name}: unknown format, {size}"),
+    }
+}
+
+/// Print a JSON object describing `image`, for --json.
+fn print_json(out: &mut impl Write, image: &Image, args: &Cli) -> io::Result<()> {
+    let info = probe::probe(&image.data);
+    let frames = info.and_then(|i| probe::frame_count(&image.data, i.format));
+    writeln!(out, "{}", serde_json::json!({
+        "path": image.path,
+        "name": image.filename,
+        "url": image.path.and_then(link_target),
+        "format": info.map(|i| i.format.to_string()),
+        "width": info.map(|i| i.width),
+        "height": info.map(|i| i.height),
+        "color": info.map(|i| i.color),
+        "bit_depth": info.map(|i| i.bit_depth),
+        "frames": frames,
+        "size": image.len(),
+        "protocol": protocol(renderer_for(args, &image.data).as_ref()),
+        "status": "ok",
+    }))
+}
+
+/// Print a JSON object reporting that `path` couldn't be shown, for --json.
+fn print_json_error(out: &mut impl Write, path: &str, error: &anyhow::Error) -> io::Result<()> {
+    writeln!(out, "{}", serde_json::json!({
+        "path": path,
+        "status": "error",
+        "error": format!("{error:#}"),
+    }))
+}
+
+/// The renderer drawing the images with the protocol given by --protocol.
+fn renderer(args: &Cli) -> Box<dyn Renderer> {
+    render::renderer(args.protocol)
+}
+
+/// The renderer drawing `data` with the protocol given by --protocol, which also depends on
+/// its format in some terminals.
+fn renderer_for(args: &Cli, data: &[u8]) -> Box<dyn Renderer> {
+    render::renderer_for(args.protocol, probe::probe(data).map(|info| info.format))
+}
+
+/// Name of the protocol `renderer` sends images with, and whether they pass through tmux.
+fn protocol(renderer: &dyn Renderer) -> String {
+    match renderer.capabilities().passthrough && imgcat::in_tmux() {
+        true => format!("{}+tmux", renderer.name()),
+        false => renderer.name().to_string(),
+    }
+}
+
+/// Whether imgcat draws in the terminal of VS Code with its images off, which it only
+/// draws, with the iTerm2 protocol and sixel, when asked to in its settings. Images and
+/// sixel come together there, so its answer to the DA1 query tells whether they are on.
+fn vscode_images_off() -> bool {
+    terminal::host() == Some(terminal::Host::VsCode)
+        && io::stdout().is_terminal()
+        && terminal::sixel(Duration::from_millis(300)) == Some(false)
+}
+
+/// Whether imgcat draws in a version of Windows Terminal older than 1.22, the first one
+/// drawing sixel. Windows Terminal has no variable telling its version, but it lists
+/// sixel in its answer to the DA1 query since then.
+fn windows_terminal_without_sixel() -> bool {
+    terminal::windows_terminal()
+        && io::stdout().is_terminal()
+        && terminal::sixel(Duration::from_millis(300)) == Some(false)
+}
+
+/// Whether the terminal shows none of the images imgcat draws, for --window: it implements
+/// no protocol of images, only shows text, or is VS Code or Windows Terminal without them.
+fn shows_no_images() -> bool {
+    terminal::without_images() || terminal::text_only() || vscode_images_off() || windows_terminal_without_sixel()
+}
+
+/// Whether images are described by a line of text rather than drawn, when --protocol isn't
+/// given: in terminals only showing text, or where they would be drawn with half blocks,
+/// which show nothing without colors, and `$NO_COLOR` asks for none.
+fn describes_images(args: &Cli) -> bool {
+    let no_color = terminal::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    terminal::text_only() || no_color && renderer(args).name() == "blocks"
+}
+
+/// Parses the names of the protocols of --protocol, which are offered for completion.
+fn protocol_parser() -> impl TypedValueParser<Value = Protocol> {
+    let names = Protocol::ALL.map(Protocol::name);
+    PossibleValuesParser::new(names).map(|name| name.parse::<Protocol>().expect("a known protocol"))
+}
+
+/// Parses the color depths of --colors, which are offered for completion.
+fn color_depth_parser() -> impl TypedValueParser<Value = ColorDepth> {
+    let names = ColorDepth::ALL.map(ColorDepth::name);
+    PossibleValuesParser::new(names).map(|name| name.parse::<ColorDepth>().expect("a known color depth"))
+}
+
+/// Format a byte count for humans, e.g. `532 B` or `1.2 MiB`.
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Write the escape sequence displaying `image` at the cursor position to `out`.
+fn print_inline(out: &mut impl Write, image: &Image, args: &Cli, width: Option<Length>, height: Option<Length>) -> io::Result<Sent> {
+    print_prepared(out, image, None, args, width, height)
+}
+
+/// [`print_inline`] with the bitmaps of the image decoded and scaled ahead, if any.
+fn print_prepared(
+    out: &mut impl Write,
+    image: &Image,
+    prepared: Option<&Prepared>,
+    args: &Cli,
+    width: Option<Length>,
+    height: Option<Length>,
+) -> io::Result<Sent> {
+    let header = Header {
+        path: image.path,
+        filename: image.filename.as_deref(),
+        file_type: image.file_type.as_deref(),
+        size: Some(image.len() as u64),
+        format: probe::probe(&image.data).map(|info| info.format),
+        cache: prepared.map(|prepared| prepared as &dyn BitmapCache),
+    };
+    send_inline(out, &header, &mut &image.data[..], args, width, height)
+}
+
+/// What the escape sequence of an image tells about it besides its contents.
+struct Header<'a> {
+    path: Option<&'a str>,
+    filename: Option<&'a str>,
+    /// file type hint used when -t is not given
+    file_type: Option<&'a str>,
+    /// size in bytes, only used by the terminal to show the progress
+    size: Option<u64>,
+    /// format, which the protocol depends on in some terminals, `None` when it isn't known
+    format: Option<probe::Format>,
+    /// bitmaps of the image decoded and scaled ahead, besides those the daemon keeps
+    cache: Option<&'a dyn BitmapCache>,
+}
+
+/// What was sent to the terminal for an image.
+struct Sent {
+    /// size of the image
+    size: u64,
+    /// time taken to encode and write it
+    elapsed: Duration,
+    /// protocol it was sent with
+    protocol: String,
+}
+
+/// Send the escape sequence displaying the image read from `body` to `out`.
+fn send_inline(
+    out: &mut impl Write,
+    header: &Header,
+    body: &mut impl Read,
+    args: &Cli,
+    width: Option<Length>,
+    height: Option<Length>,
+) -> io::Result<Sent> {
+    let start = Instant::now();
+    // 整个转义序列通过一个缓冲区写出，避免大量细碎的写操作。
+    let mut out = io::BufWriter::with_capacity(OUTPUT_BUFFER_LEN, out);
+    let renderer = render::renderer_for(args.protocol, header.format);
+    let sent = write_inline(&mut out, renderer.as_ref(), header, body, args, width, height)?;
+    out.flush()?;
+    let sent = Sent {size: sent, elapsed: start.elapsed(), protocol: protocol(renderer.as_ref())};
+    debug!(
+        "sent {} ({}) in {:.0?}, width={}, height={}",
+        header.path.unwrap_or("-"),
+        format_size(sent.size as usize),
+        sent.elapsed,
+        width.unwrap_or(Length::Auto),
+        height.unwrap_or(Length::Auto),
+    );
+    Ok(sent)
+}
+
+/// Time spent on an image before it is sent, for --stats.
+#[derive(Clone, Copy, Default)]
+struct Timings {
+    /// reading or downloading it
+    fetch: Option<Duration>,
+    /// decoding its pixels
+    decode: Option<Duration>,
+    /// converting it to PNG for the terminal
+    encode: Option<Duration>,
+}
+
+/// Print on stderr where the time went for an image, and how much was sent, for --stats.
+fn print_stats(path: Option<&str>, timings: &Timings, sent: &Sent) {
+    let steps = [("fetch", timings.fetch), ("decode", timings.decode), ("encode", timings.encode)];
+    let mut stats: Vec<String> = steps
+        .into_iter()
+        .filter_map(|(step, time)| Some(format!("{step} {:.1?}", time?)))
+        .collect();
+    // 图片由终端缩放，imgcat 自己从不缩放。
+    stats.push(format!("send {:.1?}", sent.elapsed));
+    stats.push(format!("{} image", format_size(sent.size as usize)));
+    stats.push(format!("{} payload", format_size(sent.size.div_ceil(3) as usize * 4)));
+    stats.push(sent.protocol.clone());
+    let _ = writeln!(terminal::stderr(), "{}: {}", path.unwrap_or("-"), stats.join(", "));
+}
+
+/// Write the escape sequence and return the size of the image.
+fn write_inline(
+    out: &mut impl Write,
+    renderer: &dyn Renderer,
+    header: &Header,
+    body: &mut impl Read,
+    args: &Cli,
+    width: Option<Length>,
+    height: Option<Length>,
+) -> io::Result<u64> {
+    let link = header.path
+        .filter(|_| args.link)
+        .and_then(link_target);
+    let kept = daemon::cache(header.path);
+    let options = imgcat::Options {
+        width,
+        height,
+        preserve_aspect_ratio: args.preserve_aspect_ratio,
+        filename: header.filename,
+        file_type: args.file_type.as_deref().or(header.file_type),
+        size: header.size,
+        link: link.as_deref(),
+        cell_aspect: args.cell_aspect,
+        symbols: args.symbols.clone(),
+        colors: args.colors,
+        cache: header.cache.or(kept.as_ref().map(|kept| kept as &dyn BitmapCache)),
+    };
+    if !args.no_nvim && terminal::host() == Some(terminal::Host::Neovim) && nvim::connect() {
+        return nvim::emit(out, renderer, body, &options).map_err(io::Error::other);
+    }
+    renderer.emit(out, body, &options)
+}
+
+/// Put `image` on the clipboard of the system, or of the terminal when running on a remote
+/// host. Terminals only taking text with OSC 52 get the URL of remote images instead.
+fn copy_image(out: &mut impl Write, image: &Image, args: &Cli) -> anyhow::Result<()> {
+    let mime = probe::probe(&image.data).context(tr!("not an image"))?.format.mime_type();
+    if !clipboard::remote() {
+        return clipboard::write_image(&image.data, mime);
+    }
+    if renderer(args).name() == "kitty" {
+        return Ok(clipboard::write_kitty(out, &image.data, mime)?);
+    }
+    match image.path.filter(|path| path.starts_with("http://") || path.starts_with("https://")) {
+        Some(url) => {
+            clipboard::write_osc52(out, url)?;
+            info!("{url}: copied the URL, the terminal only takes text on its clipboard");
+            Ok(())
+        }
+        None => bail!(tr!("the terminal only takes text on its clipboard")),
+    }
+}
+
+/// Send each input to the terminal as a file to download, or all of stdin if there are none.
+fn download_files(inputs: &[(String, InputKind)], args: &Cli) -> anyhow::Result<()> {
+    let mut out = io::BufWriter::with_capacity(OUTPUT_BUFFER_LEN, terminal::stdout());
+    if inputs.is_empty() {
+        // 不按图片拆分 stdin，任何文件都原样发送。
+        let mut data = Vec::new();
+        terminal::stdin().read_to_end(&mut data).with_context(|| tr!("failed to read stdin"))?;
+        if args.stdin_base64 {
+            data = base64_image(&data).with_context(|| tr!("invalid base64 on stdin"))?;
+        }
+        info!("sending {} from stdin", format_size(data.len()));
+        imgcat::download(&mut out, &mut &data[..], args.stdin_name.as_deref(), Some(data.len() as u64))?;
+    }
+    for (path, kind) in inputs {
+        let file = Image::open_as(path, *kind)?;
+        info!("{path}: sending {}", format_size(file.len()));
+        imgcat::download(&mut out, &mut &file.data[..], file.filename.as_deref(), Some(file.len() as u64))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Play an MJPEG stream, redrawing every frame at the same position.
+fn play_stream(path: &str, response: Response, printer: &mut Printer<impl Write>) -> anyhow::Result<()> {
+    let args = printer.args;
+    let options = stream::Playback {
+        fps: args.fps,
+        duration: args.duration,
+    };
+    // 先保存光标位置，之后每一帧都从同一位置开始绘制，从而覆盖上一帧。
+    write!(printer.out, "\x1b7")?;
+    stream::play(response, &options, |frame| {
+        write!(printer.out, "\x1b8")?;
+        printer.print(Image {data: frame.to_vec().into(), filename: None, path: Some(path), file_type: None})?;
+        Ok(())
+    }).with_context(|| tr!("failed to play stream {}", path))
+}
+
+/// Display `path` and redraw it every time the file changes, until interrupted.
+fn watch_file(path: &str, printer: &mut Printer<impl Write>) -> anyhow::Result<()> {
+    let file = Path::new(path);
+    let dir = match file.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let mut watcher = watch::DirWatcher::new(dir)?;
+    printer.clear.get_or_insert(Clear::Screen);
+    let mut redraw = || {
+        // 文件可能正被删除或重写，出错时只报告错误并继续监视。
+        match Image::read_file(path) {
+            Ok(image) => printer.print(image),
+            Err(e) => {
+                error!("{e:#}");
+                Ok(())
+            }
+        }
+    };
+    redraw()?;
+    loop {
+        if watcher.wait()?.iter().any(|p| p.file_name() == file.file_name()) {
+            redraw()?;
+        }
+    }
+}
+
+/// Show every image file written or moved into `dir`, until interrupted.
+fn monitor_dir(dir: &str, printer: &mut Printer<impl Write>) -> anyhow::Result<()> {
+    let mut watcher = watch::DirWatcher::new(Path::new(dir))?;
+    loop {
+        for path in watcher.wait()? {
+            let is_image = path.extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| IMAGE_EXTENSIONS.contains(x.to_ascii_lowercase().as_str()));
+            if !is_image {
+                continue;
+            }
+            let path = path.to_string_lossy();
+            match Image::read_file(&path) {
+                Ok(image) => printer.print(image)?,
+                Err(e) => error!("{e:#}"),
+            }
+        }
+    }
+}
+
+/// Show the album art of the currently playing track, captioned with its title and artist.
+fn show_now_playing(printer: &mut Printer<impl Write>) -> anyhow::Result<()> {
+    let track = nowplaying::current()
+        .with_context(|| tr!("failed to query the currently playing track"))?;
+    let caption = track.caption();
+    match track.art {
+        nowplaying::Art::Url(url) => printer.print(Image::open(&url)?)?,
+        nowplaying::Art::Data(data) => {
+            printer.print(Image {data: data.into(), filename: None, path: None, file_type: None})?;
+        }
+    }
+    if let Some(caption) = caption {
+        printer.finish()?;
+        writeln!(printer.out, "{caption}")?;
+    }
+    Ok(())
+}
+
+/// Read IDs from stdin, one per line, and substitute each of them for `{}` in `template`.
+fn urls_from_template(template: &str) -> anyhow::Result<Vec<String>> {
+    if !template.contains("{}") {
+        bail!(tr!("the URL template {} does not contain {{}}", template));
+    }
+    let mut text = String::new();
+    terminal::stdin().read_to_string(&mut text)
+        .with_context(|| tr!("failed to read stdin"))?;
+    Ok(text.lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|id| template.replace("{}", &utf8_percent_encode(id, URL_TEMPLATE_ESCAPES).to_string()))
+        .collect())
+}
+
+/// Whether the pixels of the images are needed.
+fn needs_bitmap(args: &Cli) -> bool {
+    args.histogram.is_some() || args.palette.is_some() || args.plot_mode
+}
+
+/// Whether `data` is in a format the protocol doesn't take, so that it is converted
+/// to PNG, unless -t tells which type it is.
+fn needs_converting(data: &[u8], args: &Cli) -> bool {
+    args.file_type.is_none() && probe::probe(data).is_some_and(|info| {
+        !renderer_for(args, data).capabilities().formats.contains(&info.format)
+    })
+}
+
+/// Decode `image`, or take its bitmap from those the daemon keeps.
+fn decode_cached(image: &Image) -> imgcat::Result<Bitmap> {
+    let cache = daemon::cache(image.path);
+    if let Some(bitmap) = cache.as_ref().and_then(|cache| cache.get(&image.data, None)) {
+        return Ok(bitmap);
+    }
+    let bitmap = Bitmap::decode(&image.data)?;
+    if let Some(cache) = &cache {
+        cache.put(&image.data, None, &bitmap);
+    }
+    Ok(bitmap)
+}
+
+/// `image` re-encoded as PNG from its decoded `bitmap`.
+fn converted_image<'a>(image: &Image<'a>, bitmap: &Bitmap) -> Image<'a> {
+    debug!("{}: converting to PNG for this terminal", image.path.unwrap_or("-"));
+    Image {
+        data: bitmap.to_png().into(),
+        filename: image.filename.clone(),
+        path: image.path,
+        file_type: Some(".png".to_string()),
+    }
+}
+
+/// An input opened, and decoded if needed, on a background thread.
+struct Opened<'a> {
+    source: Source<'a>,
+    bitmap: Option<imgcat::Result<Bitmap>>,
+    /// with --columns, the image decoded and scaled to its cell of the grid
+    prepared: Option<Prepared>,
+    timings: Timings,
+}
+
+/// An input opened on a background thread, or put off because it is larger than --confirm-above.
+enum Prefetched<'a> {
+    Opened(Opened<'a>),
+    /// an input of the given size, opened but neither read nor decoded, or not opened at
+    /// all if it is a local file, until it is confirmed
+    Unconfirmed(u64, Option<Source<'a>>),
+}
+
+/// Open an input, also downloading remote images unless they are `streamable`, i.e. sent
+/// to the terminal while they are downloaded, and decoding images if `decode` is set or
+/// they are converted for the terminal.
+/// Called on background threads, so that downloads and decoding happen there.
+fn open_input<'a>(path: &'a str, kind: InputKind, streamable: bool, decode: bool, args: &Cli) -> anyhow::Result<Opened<'a>> {
+    let start = Instant::now();
+    let source = Source::open_as(path, kind)?;
+    finish_opening(source, start, streamable, decode, args)
+}
+
+/// Open an input like [`open_input`], but stop before reading or decoding it as soon as
+/// it turns out to be larger than --confirm-above: the size of local files is known
+/// before they are opened, that of downloads from their Content-Length.
+fn prefetch_input<'a>(path: &'a str, kind: InputKind, streamable: bool, decode: bool, args: &Cli) -> anyhow::Result<Prefetched<'a>> {
+    if let Some(size) = file_size(path, kind).filter(|&n| needs_confirming(n, args)) {
+        return Ok(Prefetched::Unconfirmed(size, None));
+    }
+    let start = Instant::now();
+    let source = match Source::open_as(path, kind)? {
+        Source::Download(download) => match download.response.content_length() {
+            Some(n) if needs_confirming(n, args) => return Ok(Prefetched::Unconfirmed(n, Some(Source::Download(download)))),
+            _ if streamable => Source::Download(download),
+            _ => Source::Image(download.into_image()?),
+        },
+        source => source,
+    };
+    // 大小事先不知道的输入，例如 git 对象和没有 Content-Length 的下载，至少在解码之前询问。
+    match source {
+        Source::Image(image) if needs_confirming(image.len() as u64, args) => {
+            Ok(Prefetched::Unconfirmed(image.len() as u64, Some(Source::Image(image))))
+        }
+        source => finish_opening(source, start, streamable, decode, args).map(Prefetched::Opened),
+    }
+}
+
+/// Download and decode an opened input as [`open_input`] does.
+fn finish_opening<'a>(source: Source<'a>, start: Instant, streamable: bool, decode: bool, args: &Cli) -> anyhow::Result<Opened<'a>> {
+    let source = match source {
+        Source::Download(download) if !streamable => Source::Image(download.into_image()?),
+        source => source,
+    };
+    let mut timings = Timings {fetch: Some(start.elapsed()), ..Timings::default()};
+    let bitmap = match &source {
+        Source::Image(image) if decode || needs_converting(&image.data, args) => {
+            let start = Instant::now();
+            let bitmap = decode_cached(image);
+            timings.decode = Some(start.elapsed());
+            Some(bitmap)
+        }
+        _ => None,
+    };
+    Ok(Opened {source, bitmap, prepared: None, timings})
+}
+
+/// Decode and scale the image of `opened` to a `cell` of the grid of --columns, as the renderer
+/// drawing it would, so that the thread showing it only encodes it.
+fn prepare_cell(opened: &mut Opened, (width, height): (Length, Length), args: &Cli) {
+    let Source::Image(image) = &opened.source else {
+        return;
+    };
+    // 转换格式或调暗背景之后画出的是另一张图片。
+    if args.plot_mode || needs_converting(&image.data, args) {
+        return;
+    }
+    let start = Instant::now();
+    let prepared = Prepared::new(&image.data, daemon::cache(image.path));
+    if let Some(Ok(bitmap)) = &opened.bitmap {
+        prepared.put(&image.data, None, bitmap);
+    }
+    let options = imgcat::Options {
+        width: Some(width),
+        height: Some(height),
+        preserve_aspect_ratio: args.preserve_aspect_ratio,
+        cell_aspect: args.cell_aspect,
+        symbols: args.symbols.clone(),
+        colors: args.colors,
+        cache: Some(&prepared),
+        ..imgcat::Options::default()
+    };
+    // 出错的图片在显示时再报告。
+    if let Err(e) = renderer_for(args, &image.data).prepare(&image.data, &options) {
+        debug!("{}: failed to prepare: {e}", image.path.unwrap_or("-"));
+        return;
+    }
+    opened.timings.decode = Some(opened.timings.decode.unwrap_or_default() + start.elapsed());
+    opened.prepared = Some(prepared);
+}
+
+/// The bitmaps of an image decoded and scaled ahead of drawing it, handed to the renderer as
+/// [`imgcat::Options::cache`] along with those the daemon keeps of its file.
+struct Prepared {
+    /// hash of the data decoded, to tell it from the images converted from it
+    data: u64,
+    /// by the size they were scaled to, `None` at their own size
+    bitmaps: Mutex<HashMap<Option<(u32, u32)>, Bitmap>>,
+    kept: Option<daemon::Cached<'static>>,
+}
+
+impl Prepared {
+    fn new(data: &[u8], kept: Option<daemon::Cached<'static>>) -> Self {
+        Prepared {data: hash(data), bitmaps: Mutex::new(HashMap::new()), kept}
+    }
+}
+
+impl fmt::Debug for Prepared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prepared").field("kept", &self.kept).finish_non_exhaustive()
+    }
+}
+
+impl BitmapCache for Prepared {
+    fn get(&self, data: &[u8], size: Option<(u32, u32)>) -> Option<Bitmap> {
+        let bitmaps = self.bitmaps.lock().unwrap_or_else(|e| e.into_inner());
+        let found = bitmaps.get(&size).filter(|_| hash(data) == self.data);
+        found.cloned().or_else(|| self.kept.as_ref()?.get(data, size))
+    }
+
+    fn put(&self, data: &[u8], size: Option<(u32, u32)>, bitmap: &Bitmap) {
+        if hash(data) == self.data {
+            self.bitmaps.lock().unwrap_or_else(|e| e.into_inner()).insert(size, bitmap.clone());
+        }
+        if let Some(kept) = &self.kept {
+            kept.put(data, size, bitmap);
+        }
+    }
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The size of a regular local file, found without reading it.
+fn file_size(path: &str, kind: InputKind) -> Option<u64> {
+    if mirrors(path, kind).is_some() {
+        return None;
+    }
+    let Ok(Location::File(f)) = Location::resolve(path, kind) else {
+        return None;
+    };
+    fs::metadata(local_path(f).as_ref()).ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/// The positional inputs and those given with -u and -f, in the order they appear on the command line.
+fn command_line_inputs(args: &Cli, matches: &ArgMatches) -> Vec<(String, InputKind)> {
+    let mut inputs: Vec<(usize, String, InputKind)> = Vec::new();
+    for (id, values, kind) in [
+        ("inputs", &args.inputs, InputKind::Auto),
+        ("urls", &args.urls, InputKind::Url),
+        ("files", &args.files, InputKind::File),
+    ] {
+        let indices = matches.indices_of(id).into_iter().flatten();
+        inputs.extend(indices.zip(values).map(|(i, path)| (i, path.clone(), kind)));
+    }
+    inputs.sort_by_key(|(i, _, _)| *i);
+    inputs.into_iter().map(|(_, path, kind)| (path, kind)).collect()
+}
+
+/// Replace the Markdown documents among `inputs`, and all of them if they are `html`
+/// pages, by the images they reference, in document order, and record the alt texts
+/// of the images in `captions` by their indices.
+fn expand_documents(inputs: Vec<(String, InputKind)>, html: bool, captions: &mut HashMap<usize, String>) -> anyhow::Result<Vec<(String, InputKind)>> {
+    let mut expanded = Vec::new();
+    for (path, kind) in inputs {
+        let markdown = markdown::is_markdown(&path);
+        if !html && !markdown {
+            expanded.push((path, kind));
+            continue;
+        }
+        let document = Image::open_as(&path, kind)?;
+        let text = String::from_utf8_lossy(&document.data);
+        let (references, base) = match markdown {
+            true => (markdown::images(&text), None),
+            false => {
+                let page = html::page(&text);
+                (page.images, page.base)
+            }
+        };
+        info!("{path}: {} image(s)", references.len());
+        // 相对地址按 <base> 给出的地址解析，它本身也可能是相对于页面的。
+        let (base, base_kind) = match base {
+            Some(base) => document_target(&path, kind, &base),
+            None => (path.clone(), kind),
+        };
+        for reference in references {
+            let (target, kind) = document_target(&base, base_kind, &reference.target);
+            if !reference.alt.is_empty() {
+                captions.insert(expanded.len(), reference.alt);
+            }
+            expanded.push((target, kind));
+        }
+    }
+    Ok(expanded)
+}
+
+/// The input of the image `target` referenced by the Markdown document or HTML page at
+/// `document`, resolved against the URL, git revision or directory of the document when
+/// it is relative.
+fn document_target(document: &str, kind: InputKind, target: &str) -> (String, InputKind) {
+    if target.starts_with("data:") {
+        return (target.to_string(), InputKind::Auto);
+    }
+    if let Some(u) = Url::parse(target).ok().filter(|_| !windows_path(target)) {
+        let kind = if u.scheme() == "file" { InputKind::File } else { InputKind::Url };
+        return (target.to_string(), kind);
+    }
+    // 链接中的路径是百分号编码的，查询参数和锚点对本地文件没有意义。
+    let relative = target.split(['?', '#']).next().unwrap_or(target);
+    let relative = percent_decode_str(relative).decode_utf8_lossy();
+    let relative = relative.trim_start_matches("./");
+    match Location::resolve(document, kind) {
+        Ok(Location::Url(base)) => match base.join(target) {
+            Ok(u) => (u.to_string(), InputKind::Url),
+            Err(_) => (target.to_string(), InputKind::Url),
+        },
+        Ok(Location::Git(object)) => {
+            let (rev, file) = object.split_once(':').unwrap_or(("HEAD", object));
+            let dir = file.rsplit_once('/').map_or("", |(dir, _)| dir);
+            let path = match relative.strip_prefix('/') {
+                Some(root) => root.to_string(),
+                None if dir.is_empty() => relative.to_string(),
+                None => format!("{dir}/{relative}"),
+            };
+            (format!("git:{rev}:{path}"), InputKind::Auto)
+        }
+        _ => {
+            let document = local_path(document);
+            // <base> 给出的目录以 / 结尾，它本身就是相对路径的起点。
+            let dir = match document.ends_with(['/', '\\']) {
+                true => PathBuf::from(document.as_ref()),
+                false => Path::new(document.as_ref()).parent().map(Path::to_path_buf).unwrap_or_default(),
+            };
+            // 带 \\?\ 前缀的长路径中 / 不是分隔符。
+            let relative = match cfg!(windows) {
+                true => Cow::Owned(relative.replace('/', "\\")),
+                false => Cow::Borrowed(relative),
+            };
+            (dir.join(relative.as_ref()).to_string_lossy().into_owned(), InputKind::File)
+        }
+    }
+}
+
+/// Parses the ratio of --cell-aspect, a positive number.
+fn parse_cell_aspect(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(aspect) if aspect.is_finite() && aspect > 0.0 => Ok(aspect),
+        _ => Err(format!("invalid cell aspect {value:?}, expected a positive number such as 0.5")),
+    }
+}
+
+/// Parses a positive number of seconds, as of --timeout and --duration, refusing those too large for a
+/// [`Duration`] and those rounding to nothing.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(duration)) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!("invalid number of seconds {value:?}, expected a positive number such as 2.5")),
+    }
+}
+
+/// Parses the seconds of --delay-between, 0 or more, refusing those too large for a
+/// [`Duration`].
+fn parse_delay(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(n) if Duration::try_from_secs_f64(n).is_ok() => Ok(n),
+        _ => Err(format!("invalid number of seconds {value:?}, expected 0 or a positive number such as 0.5")),
+    }
+}
+
+/// Parses the positive number of events a second of --max-rps and --fps, refusing those so small
+/// that the time between two events is too long for a [`Duration`].
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() && n > 0.0 && Duration::try_from_secs_f64(1.0 / n).is_ok() => Ok(n),
+        _ => Err(format!("invalid rate {value:?}, expected a positive number such as 0.5")),
+    }
+}
+
+/// Parses the size of --min-size, `WIDTHxHEIGHT` or a single number for both.
+fn parse_min_size(value: &str) -> Result<(u32, u32), String> {
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|_| format!("invalid size '{value}', expected WIDTHxHEIGHT or N"));
+    match value.split_once(['x', 'X']) {
+        Some((width, height)) => Ok((parse(width)?, parse(height)?)),
+        None => parse(value).map(|n| (n, n)),
+    }
+}
+
+/// Parses the size of --confirm-above and --cache-size, a number of bytes with an optional K, M or G suffix
+/// for powers of 1024, e.g. `50M` or `1.5GiB`.
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let number = lower.strip_suffix("ib").or_else(|| lower.strip_suffix('b')).unwrap_or(&lower);
+    let (number, shift) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 10),
+        Some('m') => (&number[..number.len() - 1], 20),
+        Some('g') => (&number[..number.len() - 1], 30),
+        _ => (number, 0),
+    };
+    // 超出 u64 的大小是错误，不悄悄截断成最大值。
+    match number.trim().parse::<f64>().map(|n| n * (1u64 << shift) as f64) {
+        Ok(bytes) if (0.0..u64::MAX as f64).contains(&bytes) => Ok(bytes as u64),
+        _ => Err(format!("invalid size '{value}', expected a number of bytes such as 50M")),
+    }
+}
+
+/// Set the width and height of the preset, or those the configuration file
+/// gives it. Of --preset and -W or -H, the one given last wins.
+fn apply_preset(args: &mut Cli, preset: Preset, matches: &ArgMatches) -> anyhow::Result<()> {
+    let name = preset.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+    let (width, height) = config::preset(&name)?;
+    let parse = |value: Option<String>, default| match value {
+        Some(value) => value.parse().map_err(|e| anyhow::anyhow!(tr!("invalid size '{}' in [preset.{}] of the configuration file: {}", value, name, e))),
+        None => Ok(default),
+    };
+    let (default_width, default_height) = preset.size();
+    let (width, height) = (parse(width, default_width)?, parse(height, default_height)?);
+    // 环境变量中的值没有位置，它们早于命令行上的任何选项。
+    let overrides = |id| match (matches.value_source(id), matches.index_of(id)) {
+        (None | Some(ValueSource::DefaultValue), _) => true,
+        (_, None) => matches.index_of("preset").is_some(),
+        (_, Some(index)) => matches.index_of("preset").is_some_and(|preset| preset > index),
+    };
+    if overrides("width") {
+        args.width = Some(width);
+    }
+    if overrides("height") {
+        args.height = Some(height);
+    }
+    Ok(())
+}
+
+/// Size the image to the preview window of fzf, from the variables fzf sets for the
+/// --preview command, unless -W or -H are given. fzf passes the escape sequences of
+/// the iTerm2 protocol through, which also need no decoding; those of kitty's only
+/// with Unicode placeholders.
+fn apply_fzf_preview(args: &mut Cli, matches: &ArgMatches) {
+    let given = |id| matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable));
+    let var = |name| terminal::var(name).ok().and_then(|v| v.parse::<u16>().ok()).filter(|n| *n > 0);
+    if let Some(columns) = var("FZF_PREVIEW_COLUMNS").filter(|_| !given("width")) {
+        args.width = Some(Length::Cells(columns.into()));
+    }
+    if let Some(lines) = var("FZF_PREVIEW_LINES").filter(|_| !given("height")) {
+        // 图片碰到屏幕底部时终端会滚动，fzf 的界面随之错位，所以少用一行。
+        let bottom = var("FZF_PREVIEW_TOP").unwrap_or(0).saturating_add(lines) >= terminal::size().rows;
+        args.height = Some(Length::Cells((lines - bottom as u16).max(1).into()));
+    }
+    if !given("protocol") {
+        args.protocol = Protocol::Iterm2;
+    }
+    // 只有一张图片，不必启动后台线程。
+    args.jobs = NonZeroUsize::new(1);
+}
+
+/// Exit status 3 when all of the `total` inputs failed, 1 when some of them did and 0 otherwise.
+fn exit_status(failed: usize, total: usize) -> i32 {
+    // 2 已被 clap 用于命令行错误。
+    match failed {
+        0 => 0,
+        n if n == total => 3,
+        _ => 1,
+    }
+}
+
+/// Exit status when imgcat stops because of `error`: the one of the [`ImgcatError`] that
+/// caused it, telling I/O, network, format and terminal errors apart, and 1 for others.
+fn error_status(error: &anyhow::Error) -> i32 {
+    error.chain()
+        .find_map(|cause| cause.downcast_ref::<ImgcatError>())
+        .map_or(1, ImgcatError::exit_code)
+}
+
+/// Report `error` on stderr and return the exit status imgcat ends with.
+///
+/// Rust ignores SIGPIPE, so writing to a pipe whose reader has exited, such as `head`
+/// or a pager, fails with a broken pipe instead of killing imgcat. It ends imgcat
+/// quietly and successfully, as SIGPIPE would have, but without an unfinished image.
+fn report_error(error: &anyhow::Error) -> i32 {
+    if broken_pipe(error) {
+        return 0;
+    }
+    let mut stderr = terminal::stderr();
+    match i18n::lang() {
+        i18n::Lang::En => {
+            let _ = writeln!(stderr, "Error: {error:?}");
+        }
+        // anyhow 的格式中 "Caused by" 是英文，这里自己列出原因。
+        _ => {
+            let _ = writeln!(stderr, "{}: {error}", tr!("Error"));
+            let causes: Vec<_> = error.chain().skip(1).collect();
+            if !causes.is_empty() {
+                let _ = writeln!(stderr, "\n{}:", tr!("Caused by"));
+            }
+            for cause in causes {
+                let _ = writeln!(stderr, "    {cause}");
+            }
+        }
+    }
+    error_status(error)
+}
+
+/// Whether `error` comes from writing to a pipe whose reader has exited.
+fn broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let io = cause.downcast_ref::<io::Error>().or(match cause.downcast_ref::<ImgcatError>() {
+            Some(ImgcatError::Io(e)) => Some(e),
+            _ => None,
+        });
+        io.is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Whether reading the image from stdin would wait for someone to type it: stdin is a
+/// terminal and wasn't asked for with --stdin-name or --stdin-base64.
+fn waiting_on_terminal(args: &Cli) -> bool {
+    terminal::stdin().is_terminal() && args.stdin_name.is_none() && !args.stdin_base64
+}
+
+/// Print the usage and how to give imgcat an image on stderr, in place of waiting for
+/// one on the terminal. Returns the exit status of command line errors.
+fn usage_hint() -> i32 {
+    let _ = write!(
+        terminal::stderr(),
+        "{}\n\n{}\n\n{}\n",
+        tr!("imgcat needs an image: give files or URLs, or pipe one into it."),
+        command().render_usage(),
+        tr!("For example `imgcat photo.png` or `curl -s URL | imgcat`. See `imgcat --help` for more."),
+    );
+    2
+}
+
+/// Name of the environment variable giving the default of a long option, e.g. `IMGCAT_WIDTH` for `--width`.
+fn env_name(option: &str) -> String {
+    format!("IMGCAT_{}", option.to_uppercase().replace('-', "_"))
+}
+
+/// The command line interface, with every option also read from an `IMGCAT_*` environment
+/// variable, and accepted after the commands too.
+fn command() -> clap::Command {
+    Cli::command().mut_args(|arg| match arg.get_long().map(env_name) {
+        Some(_) if matches!(arg.get_long(), Some("help" | "version")) => arg,
+        Some(_) if matches!(arg.get_long(), Some("url" | "file")) => arg.global(true),
+        None => arg,
+        Some(name) => {
+            let arg = arg.env(name).global(true);
+            // 开关选项的环境变量接受 1/0、yes/no、on/off 等写法。
+            // 环境变量的值按是否给出了开关来解释，因此 SetFalse 的开关需要把值取反。
+            match arg.get_action() {
+                ArgAction::SetTrue => arg.value_parser(BoolishValueParser::new()),
+                ArgAction::SetFalse => arg
+                    .value_parser(BoolishValueParser::new().map(|b| !b))
+                    .default_value("false")
+                    .default_missing_value("true"),
+                _ => arg,
+            }
+        }
+    })
+}
+
+/// The arguments clap parses for `command_line`: the options of the configuration file
+/// that no environment variable sets, then, for the command lines run by the daemon
+/// (`remote`), those of the `IMGCAT_*` variables of the client, then the command line.
+fn arguments(cli: &clap::Command, command_line: Vec<OsString>, remote: bool) -> anyhow::Result<Vec<OsString>> {
+    let options: Vec<&str> = cli.get_arguments().filter_map(|a| a.get_long()).collect();
+    let mut argv: Vec<OsString> = command_line.iter().take(1).cloned().collect();
+    argv.extend(config::load_args(&options)?
+        .into_iter()
+        .filter(|arg| {
+            let option = arg.trim_start_matches('-').split('=').next().unwrap_or_default();
+            terminal::var_os(env_name(option)).is_none()
+        })
+        .map(Into::into));
+    if remote {
+        argv.extend(env_args(cli));
+    }
+    argv.extend(command_line.into_iter().skip(1));
+    Ok(argv)
+}
+
+/// The options given by the `IMGCAT_*` variables of the environment read by
+/// [`terminal::var_os`], as arguments, for the command lines run by --daemon.
+fn env_args(cli: &clap::Command) -> Vec<OsString> {
+    let mut args = Vec::new();
+    for arg in cli.get_arguments() {
+        let (Some(name), Some(long)) = (arg.get_env(), arg.get_long()) else {
+            continue;
+        };
+        let Some(value) = terminal::var_os(name) else {
+            continue;
+        };
+        match arg.get_action() {
+            // 与 clap 一样，开关的环境变量除了 0、no、off 等假值以外都表示给出了开关。
+            ArgAction::SetTrue | ArgAction::SetFalse | ArgAction::Count => {
+                let value = value.to_string_lossy().to_ascii_lowercase();
+                if !["", "n", "no", "f", "false", "off", "0"].contains(&value.as_str()) {
+                    args.push(format!("--{long}").into());
+                }
+            }
+            _ => {
+                let mut option = OsString::from(format!("--{long}="));
+                option.push(value);
+                args.push(option);
+            }
+        }
+    }
+    args
+}
+
+/// What the command line running on a thread set up for it, handed to the threads it
+/// spawns: the session of the client the daemon runs it for, the verbosity, the language,
+/// the connection timeout and the pace of requests.
+struct CommandContext {
+    session: Option<Arc<terminal::Session>>,
+    level: LevelFilter,
+    lang: i18n::Lang,
+    timeout: Duration,
+    pace: pace::Shared,
+}
+
+impl CommandContext {
+    fn current() -> Self {
+        CommandContext {
+            session: terminal::session(),
+            level: logger::level(),
+            lang: i18n::lang(),
+            timeout: CONNECT_TIMEOUT.get(),
+            pace: pace::current(),
+        }
+    }
+
+    /// Run the rest of this thread in this context.
+    fn enter(self) {
+        terminal::enter(self.session);
+        logger::set_level(self.level);
+        i18n::set_lang(self.lang);
+        CONNECT_TIMEOUT.set(self.timeout);
+        pace::enter(self.pace);
+    }
+}
+
+fn main() {
+    // 在启动任何线程之前屏蔽信号，之后的线程都继承这个信号掩码。失败时保留默认的处理方式。
+    let _ = terminal::restore_on_interrupt();
+    let argv: Vec<OsString> = env::args_os().collect();
+    let status = daemon::forward(&argv).unwrap_or_else(|| run(argv, false).unwrap_or_else(|e| report_error(&e)));
+    // 弹出窗口在 imgcat 退出时关闭，先等待按键。
+    if popup::inside() {
+        popup::leave();
+    }
+    process::exit(status);
+}
+
+/// Run the command line `argv` and return the exit status. `remote` is set when the
+/// daemon runs it for a client, in which case it returns [`daemon::RUN_LOCALLY`] for
+/// the command lines the client has to run itself.
+fn run(command_line: Vec<OsString>, remote: bool) -> anyhow::Result<i32> {
+    // 配置文件中的选项插在命令行参数之前，命令行中再次给出的选项会覆盖它们。
+    // 环境变量优先于配置文件，所以设置了环境变量的选项不从配置文件中读取。
+    let cli = command();
+    let original = command_line.clone();
+    let argv = arguments(&cli, command_line, remote)?;
+    // clap 读取的是守护进程自己的环境变量，客户端的环境变量已经作为选项插在配置文件之后。
+    let cli = match remote {
+        true => cli.mut_args(|arg| arg.env(None)),
+        false => cli,
+    };
+    let parsed = cli.clone().try_get_matches_from(argv)
+        .and_then(|matches| Cli::from_arg_matches(&matches).map(|args| (matches, args)));
+    let (matches, mut args) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            // 守护进程运行的命令行也要把帮助和错误写到客户端，而不是 clap 使用的进程自己的流。
+            let mut stream = if e.use_stderr() { terminal::stderr() } else { terminal::stdout() };
+            match stream.is_terminal() {
+                true => write!(stream, "{}", e.render().ansi())?,
+                false => write!(stream, "{}", e.render())?,
+            }
+            return Ok(e.exit_code());
+        }
+    };
+    if remote && !daemon::serves(&args) {
+        return Ok(daemon::RUN_LOCALLY);
+    }
+    if let Some(preset) = args.preset {
+        apply_preset(&mut args, preset, &matches)?;
+    }
+    if args.fzf_preview {
+        apply_fzf_preview(&mut args, &matches);
+    }
+    if args.plot_mode && !matches!(matches.value_source("width"), Some(ValueSource::CommandLine | ValueSource::EnvVariable)) {
+        args.width = Some(Length::Percent(100));
+    }
+    // show、info 和 grid 命令是默认行为的另一种写法，这里把它们转换成对应的选项。
+    let command_inputs = match &args.command {
+        Some(Command::Show {inputs} | Command::Info {inputs} | Command::Grid {inputs}) => Some(inputs.clone()),
+        _ => None,
+    };
+    let input_matches = match command_inputs {
+        Some(inputs) => {
+            args.inputs = inputs;
+            matches.subcommand().map_or(&matches, |(_, m)| m)
+        }
+        None => &matches,
+    };
+    match args.command {
+        Some(Command::Info {..}) => args.info = !args.json,
+        Some(Command::Grid {..}) if args.columns.is_none() => {
+            args.columns = Some((terminal::size().columns / GRID_COLUMN_WIDTH).max(1));
+        }
+        _ => {}
+    }
+    CONNECT_TIMEOUT.set(args.timeout);
+    pace::init(args.delay_between, args.max_rps)?;
+    logger::init(args.quiet, args.verbose);
+    i18n::init(args.lang);
+    debug!(
+        "TERM={}, TERM_PROGRAM={}",
+        terminal::var("TERM").unwrap_or_default(),
+        terminal::var("TERM_PROGRAM").unwrap_or_default(),
+    );
+    let automatic = args.protocol == Protocol::Auto;
+    if args.window {
+        args.window = !remote && args.protocol == Protocol::Auto && window::display_server() && shows_no_images();
+        debug!("window: {}", args.window);
+    }
+    if !remote && !args.window && args.protocol == Protocol::Auto && windows_terminal_without_sixel() {
+        info!("Windows Terminal before 1.22 draws no sixel, using half blocks");
+        args.protocol = Protocol::Blocks;
+    }
+    if !remote && !args.window && args.protocol == Protocol::Auto && vscode_images_off() {
+        warn!("{}", tr!("the terminal of VS Code shows no images: turn on terminal.integrated.enableImages in its settings, with terminal.integrated.gpuAcceleration not off; drawing with half blocks meanwhile"));
+        args.protocol = Protocol::Blocks;
+    }
+    args.plain = automatic && describes_images(&args);
+    match args.window {
+        true => info!("opening the images in windows"),
+        false if args.plain => info!("describing the images with text"),
+        false => info!("using the {} protocol", protocol(renderer(&args).as_ref())),
+    }
+    if args.popup && !remote {
+        if !popup::inside() {
+            return popup::open(&original, &command_line_inputs(&args, input_matches), &args);
+        }
+        popup::enter(&mut args)?;
+    }
+    if args.daemon {
+        daemon::serve(args.socket.as_deref(), args.cache_size as usize, run)?;
+        return Ok(0);
+    }
+    if let Some(socket) = &args.serve {
+        server::run(socket, &args)?;
+        return Ok(0);
+    }
+    if let Some(geometry) = &args.previewer {
+        // lf 和 ranger 读取预览脚本的 stdout，图片要直接输出到终端。
+        let _stdout = terminal::redirect_stdout_to_tty().ok();
+        let geometry = previewer::Geometry::new(geometry);
+        if matches!(args.command, Some(Command::Clear)) {
+            previewer::clear(&mut io::stdout(), geometry, &args)?;
+            return Ok(0);
+        }
+        let inputs = command_line_inputs(&args, input_matches);
+        return previewer::show(&mut io::stdout(), inputs.first(), geometry, &args);
+    }
+    if let Some(geometry) = &args.vifm_preview {
+        let geometry = previewer::Geometry::new(geometry);
+        // 带 %pd 时 vifm 让出终端，直接画图；否则它把输出当作文本显示在预览窗格中。
+        let direct = io::stdout().is_terminal();
+        if matches!(args.command, Some(Command::Clear)) {
+            if direct {
+                previewer::clear(&mut io::stdout(), geometry, &args)?;
+            }
+            return Ok(0);
+        }
+        let inputs = command_line_inputs(&args, input_matches);
+        if direct {
+            previewer::show(&mut io::stdout(), inputs.first(), geometry, &args)?;
+        } else {
+            previewer::show_text(&mut io::stdout(), inputs.first(), geometry.width, geometry.height, &args)?;
+        }
+        return Ok(0);
+    }
+    if let Some(geometry) = &args.yazi_preview {
+        let geometry = previewer::Geometry::new(geometry);
+        if matches!(args.command, Some(Command::Clear)) {
+            previewer::clear_yazi(geometry, &args)?;
+            return Ok(0);
+        }
+        let inputs = command_line_inputs(&args, input_matches);
+        previewer::show_yazi(&mut io::stdout(), inputs.first(), geometry, &args)?;
+        return Ok(0);
+    }
+    if args.mc_preview {
+        let size = terminal::size();
+        let cells = |length: Option<Length>, var, default| match length {
+            Some(Length::Cells(n)) => n as u16,
+            _ => terminal::var(var).ok().and_then(|n| n.parse().ok()).unwrap_or(default),
+        };
+        let (width, height) = (cells(args.width, "COLUMNS", size.columns), cells(args.height, "LINES", size.rows));
+        let inputs = command_line_inputs(&args, input_matches);
+        previewer::show_text(&mut io::stdout(), inputs.first(), width, height, &args)?;
+        return Ok(0);
+    }
+    if args.download {
+        let inputs = command_line_inputs(&args, input_matches);
+        if inputs.is_empty() && waiting_on_terminal(&args) {
+            return Ok(usage_hint());
+        }
+        download_files(&inputs, &args)?;
+        return Ok(0);
+    }
+    // 选择结果写到原来的 stdout 上，图片则直接输出到终端，这样在 $(...) 中使用时图片依然可见。
+    let selection_output = args.select
+        .then(|| terminal::redirect_stdout_to_tty().ok())
+        .flatten();
+    let mut printer = Printer::new(&args, terminal::stdout());
+    match &args.command {
+        Some(Command::Diff {first, second, threshold, no_image}) => {
+            let similar = run_diff(first, second, *threshold, *no_image, &mut printer)?;
+            return Ok(if similar { 0 } else { 1 });
+        }
+        Some(Command::Compare {first, second, interactive}) => {
+            run_compare(first, second, *interactive, &args)?;
+            return Ok(0);
+        }
+        Some(Command::Qr {level, text, data}) => {
+            let code = qr::QrCode::encode(data.as_bytes(), *level)?;
+            if *text || renderer(&args).name() == "blocks" {
+                code.write_text(&mut printer.out)?;
+            } else {
+                printer.print(Image {
+                    data: code.to_bitmap(QR_MAX_PIXELS).to_png().into(),
+                    filename: Some("qr.png".to_string()),
+                    path: None,
+                    file_type: None,
+                })?;
+                printer.finish()?;
+            }
+            return Ok(0);
+        }
+        Some(Command::Color {contrast, colors}) => {
+            swatch::write(&mut printer.out, colors, *contrast)?;
+            return Ok(0);
+        }
+        Some(Command::Plot {x, y, kind, delimiter, input}) => {
+            let text = match input {
+                Some(path) => fs::read_to_string(path).with_context(|| tr!("failed to read {}", path.display()))?,
+                None => io::read_to_string(terminal::stdin())?,
+            };
+            let chart = chart::draw(&chart::Table::parse(&text, *delimiter), x.as_deref(), y, *kind)?;
+            printer.print(Image {
+                data: chart.bitmap.to_png().into(),
+                filename: Some("plot.png".to_string()),
+                path: None,
+                file_type: None,
+            })?;
+            printer.finish()?;
+            writeln!(printer.out, "{}", chart.legend)?;
+            return Ok(0);
+        }
+        Some(Command::Code {language, wrap, lines, font, font_size, theme, input}) => {
+            let text = match input {
+                Some(path) => fs::read(path).with_context(|| tr!("failed to read {}", path.display()))?,
+                None => {
+                    let mut data = Vec::new();
+                    terminal::stdin().read_to_end(&mut data)?;
+                    data
+                }
+            };
+            let name = input.as_ref().map(|p| p.to_string_lossy().into_owned()).or_else(|| args.stdin_name.clone());
+            let language = language.as_deref().or_else(|| highlight::language(name.as_deref()?));
+            let font_path = font.clone().or_else(font::find_default)
+                .context(tr!("no monospace font found, give a TrueType font with --font"))?;
+            let mut font = font::Font::open(&font_path, *font_size)?;
+            let theme = match theme {
+                highlight::Theme::Auto => match terminal::background().is_none_or(plot::is_dark) {
+                    true => highlight::Theme::Dark,
+                    false => highlight::Theme::Light,
+                },
+                theme => *theme,
+            };
+            let highlighted = highlight::highlight(&String::from_utf8_lossy(&text), language, theme);
+            printer.print(Image {
+                data: highlight::draw(&highlighted, &mut font, theme, *wrap, *lines).to_png().into(),
+                filename: Some("code.png".to_string()),
+                path: None,
+                file_type: None,
+            })?;
+            printer.finish()?;
+            return Ok(0);
+        }
+        Some(Command::Testpattern) => {
+            printer.print(Image {
+                data: testpattern::generate().to_png().into(),
+                filename: Some("testpattern.png".to_string()),
+                path: None,
+                file_type: None,
+            })?;
+            printer.finish()?;
+            return Ok(0);
+        }
+        Some(Command::Convert {input, output}) => {
+            run_convert(input, output)?;
+            return Ok(0);
+        }
+        Some(Command::Check) => {
+            check::run(&mut printer.out, &args)?;
+            return Ok(0);
+        }
+        Some(Command::Clear) => {
+            renderer(&args).clear(&mut printer.out)?;
+            write!(printer.out, "\x1b[3J")?;
+            printer.out.flush()?;
+            return Ok(0);
+        }
+        Some(Command::Completions {shell}) => {
+            // clap_complete 写入失败时会 panic，先生成到内存中，管道关闭时和其他输出一样安静地退出。
+            let mut command = command();
+            let name = command.get_name().to_string();
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut command, name, &mut script);
+            terminal::stdout().write_all(&script)?;
+            return Ok(0);
+        }
+        Some(Command::Layer {silent, output, ..}) => {
+            let mut args = args.clone();
+            // ueberzugpp 的 -o 还可以是 x11、wayland 等窗口输出，这些不支持，仍按 --protocol 输出。
+            if let Some(output) = output {
+                match output.parse() {
+                    Ok(protocol) => args.protocol = protocol,
+                    Err(_) => warn!("{}", tr!("unsupported output {}, using the {} protocol", output, protocol(renderer(&args).as_ref()))),
+                }
+            }
+            server::layer(io::stdin().lock(), &args, *silent)?;
+            return Ok(0);
+        }
+        Some(Command::Show {..} | Command::Info {..} | Command::Grid {..}) | None => {}
+    }
+    if let Some(path) = &args.watch {
+        watch_file(path, &mut printer)?;
+        return Ok(0);
+    }
+    if let Some(dir) = &args.monitor {
+        monitor_dir(dir, &mut printer)?;
+        return Ok(0);
+    }
+    if args.clipboard {
+        printer.print(Image::from_clipboard()?)?;
+    }
+    if let Some(mode) = args.screenshot {
+        printer.print(Image::from_screenshot(mode)?)?;
+    }
+    if args.now_playing {
+        show_now_playing(&mut printer)?;
+    }
+    // stdin 被 --url-template 用来读取 ID；否则只有在没有任何其他输入时才从 stdin 读取图片。
+    let mut inputs = command_line_inputs(&args, input_matches);
+    if let Some(template) = &args.url_template {
+        inputs.extend(urls_from_template(template)?.into_iter().map(|url| (url, InputKind::Url)));
+    }
+    let mut captions = HashMap::new();
+    if args.extract_images || inputs.iter().any(|(path, _)| markdown::is_markdown(path)) {
+        inputs = expand_documents(inputs, args.extract_images, &mut captions)?;
+    }
+    info!("got {} input image(s)", inputs.len());
+    if args.dry_run {
+        let failures = dryrun::run(&inputs, &args)?;
+        return Ok(exit_status(failures.len(), inputs.len()));
+    }
+    if args.interactive {
+        let paths: Vec<String> = inputs.into_iter().map(|(path, _)| path).collect();
+        viewer::run(&mut printer.out, &paths, &args)?;
+        return Ok(0);
+    }
+    let other_sources = args.clipboard || args.screenshot.is_some() || args.now_playing;
+    if args.url_template.is_none() && inputs.is_empty() && !other_sources {
+        if waiting_on_terminal(&args) {
+            return Ok(usage_hint());
+        }
+        for image in Image::from_stdin(args.stdin_name.as_deref(), args.stdin_base64) {
+            let image = image?;
+            confirm_size(&mut printer.out, "-", Some(image.len() as u64), &args)?;
+            printer.print(image)?;
+            // 绘图程序可能隔一段时间才输出下一张图片，先让这一张显示出来。
+            printer.out.flush()?;
+        }
+    }
+    let fail_fast = args.fail_fast && !args.keep_going;
+    let jobs = match args.jobs {
+        Some(jobs) => jobs.get(),
+        None => thread::available_parallelism().map_or(4, |n| n.get()),
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .thread_name(|i| format!("imgcat-{i}"))
+        .build()
+        .context(tr!("failed to start the threads"))?;
+    let streamable = printer.streamable();
+    let decode = needs_bitmap(&args);
+    let cell = printer.grid.as_ref().map(Grid::cell);
+    let cli = &args;
+    let mut failures = Vec::new();
+    pool.in_place_scope(|scope| -> anyhow::Result<()> {
+        let mut queue = inputs.iter().enumerate();
+        let mut pending = VecDeque::new();
+        loop {
+            // 由线程池打开、解码和缩放接下来的几个输入，与当前图片的输出同时进行。
+            while pending.len() < jobs {
+                let Some((i, (x, kind))) = queue.next() else {
+                    break;
+                };
+                let context = CommandContext::current();
+                let (sender, receiver) = mpsc::sync_channel(1);
+                scope.spawn(move |_| {
+                    context.enter();
+                    let prefetched = prefetch_input(x, *kind, streamable, decode, cli).map(|mut prefetched| {
+                        if let (Prefetched::Opened(opened), Some(cell)) = (&mut prefetched, cell) {
+                            prepare_cell(opened, cell, cli);
+                        }
+                        prefetched
+                    });
+                    let _ = sender.send(prefetched);
+                });
+                pending.push_back((i, x, kind, receiver));
+            }
+            let Some((i, x, kind, receiver)) = pending.pop_front() else {
+                break;
+            };
+            if printer.quit {
+                break;
+            }
+            // 任务崩溃时发送端被丢弃，线程池在作用域结束时重新抛出它的 panic。
+            let Ok(opened) = receiver.recv() else {
+                break;
+            };
+            printer.caption = captions.get(&i).cloned();
+            let result = opened.and_then(|prefetched| {
+                let Opened {source, bitmap, prepared, timings} = match prefetched {
+                    Prefetched::Opened(opened) => opened,
+                    Prefetched::Unconfirmed(size, source) => {
+                        // 确认之后才读取和解码。
+                        confirm_size(&mut printer.out, x, Some(size), cli)?;
+                        let start = Instant::now();
+                        let source = match source {
+                            Some(source) => source,
+                            None => Source::open_as(x, *kind)?,
+                        };
+                        finish_opening(source, start, streamable, decode, cli)?
+                    }
+                };
+                match source {
+                    Source::Image(image) => printer.print_decoded(image, bitmap, prepared, timings)?,
+                    Source::Download(download) => printer.print_download(download)?,
+                    Source::Stream(_) if args.json => writeln!(printer.out, "{}", serde_json::json!({
+                        "path": x,
+                        "format": "MJPEG stream",
+                        "status": "ok",
+                    }))?,
+                    Source::Stream(_) if args.info => writeln!(printer.out, "{x}: MJPEG stream")?,
+                    Source::Stream(response) => play_stream(x, response, &mut printer)?,
+                    Source::Pipe(pipe) => {
+                        for image in pipe.images() {
+                            let image = image?;
+                            confirm_size(&mut printer.out, x, Some(image.len() as u64), cli)?;
+                            printer.print(image)?;
+                            printer.out.flush()?;
+                        }
+                    }
+                }
+                Ok(())
+            });
+            let Err(e) = result else {
+                continue;
+            };
+            // 读取输出的程序已经退出，后面的图片也无处可写。
+            if broken_pipe(&e) {
+                return Err(e);
+            }
+            // 输出 JSON 时每个输入的错误单独报告。
+            if args.json {
+                print_json_error(&mut printer.out, x, &e)?;
+            } else if fail_fast {
+                return Err(e);
+            }
+            failures.push((x, e));
+            if fail_fast {
+                break;
+            }
+        }
+        Ok(())
+    })?;
+    printer.finish()?;
+    if args.select {
+        match selection_output {
+            Some(mut output) => select_image(&printer.shown, &mut output)?,
+            None => select_image(&printer.shown, &mut printer.out)?,
+        }
+    }
+    if !failures.is_empty() && !args.json {
+        error!("{}", tr!("{} of {} inputs failed:", failures.len(), inputs.len()));
+        for (path, e) in &failures {
+            error!("  {path}: {e:#}");
+        }
+    }
+    Ok(match exit_status(failures.len(), inputs.len() + other_sources as usize) {
+        0 if printer.mismatch => 1,
+        status => status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_text() {
+        assert_eq!(decode_base64(b"aGVsbG8="), Some(b"hello".to_vec()));
+        // 缺少的填充、多余的填充和 MIME 的换行都接受。
+        assert_eq!(decode_base64(b"aGVsbG8"), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"aGVsbG8=="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64(b" aGVs\r\nbG8=\n"), Some(b"hello".to_vec()));
+        // URL 安全的字母表用 - 和 _ 代替 + 和 /。
+        assert_eq!(decode_base64(b"-_8"), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode_base64(b"+/8="), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode_base64(b""), Some(Vec::new()));
+        assert_eq!(decode_base64(b"aGVs$G8="), None);
+        assert_eq!(decode_base64(b"aGVsb"), None);
+        assert_eq!(decode_base64(b"aG=VsbG8"), None);
+    }
+
+    #[test]
+    fn base64_images() {
+        assert_eq!(base64_image(b"aGVsbG8=\n"), Some(b"hello".to_vec()));
+        assert_eq!(base64_image(b"data:image/png;base64,aGVsbG8=\n"), Some(b"hello".to_vec()));
+        assert_eq!(base64_image(b"data:image/png;base64,-_8"), Some(vec![0xfb, 0xff]));
+        assert_eq!(base64_image(b"data:image/png;base64"), None);
+        assert_eq!(base64_image(b"data:,"), None);
+        assert_eq!(base64_image(b" \n"), None);
+        assert_eq!(base64_image(b"not base64!"), None);
+    }
+
+    #[test]
+    fn byte_sizes() {
+        for (value, bytes) in [
+            ("0", 0), ("512", 512), (" 512 ", 512), ("512b", 512), ("512B", 512),
+            ("4k", 4096), ("4K", 4096), ("4KB", 4096), ("4kib", 4096), ("50M", 50 << 20), ("50MiB", 50 << 20),
+            ("1.5G", 3 << 29), ("1.5GiB", 3 << 29), ("2 G", 2 << 30), ("0.5k", 512), ("1e3", 1000),
+            ("16000000000G", 16_000_000_000 << 30),
+        ] {
+            assert_eq!(parse_byte_size(value), Ok(bytes), "{value}");
+        }
+        for invalid in [
+            "", "b", "k", "KiB", "-1", "-1k", "1.5.0M", "50T", "50MM", "M50", "50 MB B", "NaN", "inf", "infk",
+            "17179869184G", "1e20", "18446744073709551616000",
+        ] {
+            assert!(parse_byte_size(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn exit_statuses() {
+        assert_eq!(exit_status(0, 3), 0);
+        assert_eq!(exit_status(1, 3), 1);
+        assert_eq!(exit_status(3, 3), 3);
+        let http = Client::new().get("not a URL").build().unwrap_err();
+        let errors = [
+            (ImgcatError::Io(io::ErrorKind::NotFound.into()), 4),
+            (ImgcatError::Http(http), 5),
+            (ImgcatError::Decode("truncated".into()), 6),
+            (ImgcatError::UnsupportedTerminal("no sixel".into()), 7),
+            (ImgcatError::PayloadTooLarge {size: 2, limit: 1}, 8),
+        ];
+        for (error, status) in errors {
+            let message = error.to_string();
+            let error = anyhow::Error::new(error);
+            assert_eq!(error_status(&error), status, "{message}");
+            // 加上说明之后，原因仍然决定退出状态。
+            let error = error.context("failed to decode a.png").context("while showing a.png");
+            assert_eq!(error_status(&error), status, "{message}");
+        }
+        assert_eq!(error_status(&anyhow::anyhow!("invalid selection")), 1);
+        let io = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(error_status(&io), 1);
+    }
+
+    /// The width and height of `argv` when the configuration file holds `config` and the
+    /// environment `vars`, parsed as the daemon parses the command lines of its clients.
+    fn sizes(config: &str, vars: &[(&str, &str)], argv: &[&str]) -> anyhow::Result<(Option<Length>, Option<Length>)> {
+        let path = env::temp_dir().join(format!("imgcat-preset-test-{}.toml", process::id()));
+        fs::write(&path, config).unwrap();
+        let mut environment: Vec<(OsString, OsString)> = vars.iter().map(|(name, value)| (name.into(), value.into())).collect();
+        environment.push(("IMGCAT_CONFIG".into(), path.clone().into()));
+        terminal::set_environment(Some(environment));
+        let cli = command();
+        let argv = arguments(&cli, ["imgcat"].iter().chain(argv).map(OsString::from).collect(), true);
+        let parsed = argv.and_then(|argv| {
+            let matches = cli.mut_args(|arg| arg.env(None)).try_get_matches_from(argv)?;
+            let mut args = Cli::from_arg_matches(&matches)?;
+            if let Some(preset) = args.preset {
+                apply_preset(&mut args, preset, &matches)?;
+            }
+            Ok((args.width, args.height))
+        });
+        terminal::set_environment(None);
+        fs::remove_file(&path).unwrap();
+        parsed
+    }
+
+    #[test]
+    fn presets() {
+        let size = |config, vars, argv| sizes(config, vars, argv).unwrap();
+        let thumb = (Some(Length::Cells(16)), Some(Length::Cells(8)));
+        assert_eq!(size("", &[], &["--preset", "thumb"]), thumb);
+        // 命令行上 --preset 和 -W、-H 中后给出的优先。
+        assert_eq!(size("", &[], &["-W", "10", "--preset", "thumb"]), thumb);
+        assert_eq!(size("", &[], &["--preset", "thumb", "-W", "10"]), (Some(Length::Cells(10)), Some(Length::Cells(8))));
+        assert_eq!(
+            size("", &[], &["--preset", "large", "-H", "5", "-W", "10"]),
+            (Some(Length::Cells(10)), Some(Length::Cells(5))),
+        );
+        // 配置文件和环境变量早于命令行上的任何选项。
+        assert_eq!(size("width = 30\nheight = 20", &[], &["--preset", "thumb"]), thumb);
+        assert_eq!(size("", &[("IMGCAT_WIDTH", "30")], &["--preset", "thumb"]), thumb);
+        assert_eq!(
+            size("", &[("IMGCAT_PRESET", "thumb")], &["-W", "10"]),
+            (Some(Length::Cells(10)), Some(Length::Cells(8))),
+        );
+        assert_eq!(
+            size("", &[("IMGCAT_PRESET", "large")], &["-H", "5"]),
+            (Some(Length::Percent(75)), Some(Length::Cells(5))),
+        );
+        // 环境变量优先于配置文件：设置了 IMGCAT_WIDTH 时配置文件中的 width 不起作用。
+        assert_eq!(
+            size("width = 30", &[("IMGCAT_WIDTH", "40"), ("IMGCAT_PRESET", "thumb")], &["-H", "5"]),
+            (Some(Length::Cells(16)), Some(Length::Cells(5))),
+        );
+        assert_eq!(size("width = 30", &[("IMGCAT_WIDTH", "40")], &[]), (Some(Length::Cells(40)), None));
+        // 配置文件可以修改预设的尺寸。
+        let config = "[preset.thumb]\nwidth = \"20\"\n";
+        assert_eq!(size(config, &[], &["--preset", "thumb"]), (Some(Length::Cells(20)), Some(Length::Cells(8))));
+        assert_eq!(size(config, &[], &["--preset", "thumb", "-W", "10"]), (Some(Length::Cells(10)), Some(Length::Cells(8))));
+        assert!(sizes("[preset.thumb]\nwidth = \"20em\"\n", &[], &["--preset", "thumb"]).is_err());
+    }
+
+    #[test]
+    fn labels() {
+        let png = imgcat::bitmap::Bitmap::new(3, 5).to_png();
+        let size = format_size(png.len());
+        let image = Image {data: png.into(), filename: Some("cat.png".to_string()), path: Some("pics/cat.png"), file_type: None};
+        assert_eq!(format_label("{name} {width}x{height} {size}", &image), format!("cat.png 3x5 {size}"));
+        assert_eq!(format_label("{path}: {format}", &image), "pics/cat.png: PNG");
+        // 未知的占位符和不成对的括号原样保留。
+        assert_eq!(format_label("{nope} {width", &image), "{nope} {width");
+        assert_eq!(format_label("{{name}}", &image), "{cat.png}");
+        // 只有路径时 {name} 用路径；文件名里的占位符不再展开。
+        let image = Image {data: imgcat::bitmap::Bitmap::new(1, 1).to_png().into(), filename: None, path: Some("{size}.png"), file_type: None};
+        assert_eq!(format_label("{name}", &image), "{size}.png");
+        let unknown = Image {data: b"hello".to_vec().into(), filename: None, path: None, file_type: None};
+        assert_eq!(format_label("{name} {path} {format} {width}x{height} {size}", &unknown), "- - ? ?x? 5 B");
+    }
+
+    #[test]
+    fn alignment() {
+        let size = |columns| terminal::Size {columns, rows: 24, width: 0, height: 0};
+        for (columns, width, center, right) in [(80, 20, 30, 60), (81, 20, 30, 61), (80, 21, 29, 59), (79, 79, 0, 0), (10, 30, 0, 0)] {
+            assert_eq!(alignment_padding(width, Align::Left, &size(columns)), 0);
+            assert_eq!(alignment_padding(width, Align::Center, &size(columns)), center, "{width} in {columns}");
+            assert_eq!(alignment_padding(width, Align::Right, &size(columns)), right, "{width} in {columns}");
+        }
+    }
+
+    #[test]
+    fn document_targets() {
+        let target = |document, kind, target| {
+            let (target, kind) = document_target(document, kind, target);
+            (target, format!("{kind:?}"))
+        };
+        let file = |path: &str| (path.replace('/', std::path::MAIN_SEPARATOR_STR), "File".to_string());
+        // 文档在网上时相对于它的 URL 解析。
+        let page = "https://example.com/docs/README.md";
+        assert_eq!(target(page, InputKind::Auto, "img/a%20b.png?raw=1"), ("https://example.com/docs/img/a%20b.png?raw=1".to_string(), "Url".to_string()));
+        assert_eq!(target(page, InputKind::Auto, "/logo.png"), ("https://example.com/logo.png".to_string(), "Url".to_string()));
+        // git 对象相对于它所在的目录，/ 开头的相对于仓库的根。
+        assert_eq!(target("git:v1.0:docs/README.md", InputKind::Auto, "./img/a%20b.png#top"), ("git:v1.0:docs/img/a b.png".to_string(), "Auto".to_string()));
+        assert_eq!(target("git:README.md", InputKind::Auto, "/img/a.png"), ("git:HEAD:img/a.png".to_string(), "Auto".to_string()));
+        assert_eq!(target("git:README.md", InputKind::Auto, "a.png"), ("git:HEAD:a.png".to_string(), "Auto".to_string()));
+        // 其余的是本地文件，相对于文档所在的目录或 <base> 给出的目录。
+        assert_eq!(target("docs/README.md", InputKind::Auto, "img/a%20b.png?raw=1"), file("docs/img/a b.png"));
+        assert_eq!(target("docs/", InputKind::File, "./a.png"), file("docs/a.png"));
+        assert_eq!(target("README.md", InputKind::File, "a.png"), file("a.png"));
+        // 绝对的地址和 data: URL 不受文档影响。
+        assert_eq!(target("docs/README.md", InputKind::Auto, "https://example.com/a.png"), ("https://example.com/a.png".to_string(), "Url".to_string()));
+        assert_eq!(target("docs/README.md", InputKind::Auto, "file:///tmp/a.png"), ("file:///tmp/a.png".to_string(), "File".to_string()));
+        assert_eq!(target(page, InputKind::Auto, "data:image/png;base64,AA=="), ("data:image/png;base64,AA==".to_string(), "Auto".to_string()));
+    }
+
+    #[test]
+    fn markdown_documents() {
+        for path in ["README.md", "notes.MARKDOWN", "a.mdown", "b.mkd", "https://example.com/README.md?plain=1#top"] {
+            assert!(markdown::is_markdown(path), "{path}");
+        }
+        for path in ["README", "image.png", "md", "page.html", "https://example.com/a.png?x=.md"] {
+            assert!(!markdown::is_markdown(path), "{path}");
+        }
+        let dir = env::temp_dir().join(format!("imgcat-markdown-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let document = dir.join("README.md").to_string_lossy().into_owned();
+        fs::write(&document, "![Logo](img/logo.png) and ![](https://example.com/a.png)\n").unwrap();
+        // 只有 Markdown 文档被换成它的图片，其余输入原样保留。
+        let mut captions = HashMap::new();
+        let inputs = vec![("photo.jpg".to_string(), InputKind::Auto), (document, InputKind::Auto)];
+        let expanded = expand_documents(inputs, false, &mut captions);
+        fs::remove_dir_all(&dir).unwrap();
+        let expanded: Vec<_> = expanded.unwrap().into_iter().map(|(target, kind)| (target, format!("{kind:?}"))).collect();
+        assert_eq!(expanded, [
+            ("photo.jpg".to_string(), "Auto".to_string()),
+            (dir.join("img").join("logo.png").to_string_lossy().into_owned(), "File".to_string()),
+            ("https://example.com/a.png".to_string(), "Url".to_string()),
+        ]);
+        assert_eq!(captions, HashMap::from([(1, "Logo".to_string())]));
+    }
+
+    #[test]
+    fn html_pages() {
+        let dir = env::temp_dir().join(format!("imgcat-html-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let page = dir.join("page.html").to_string_lossy().into_owned();
+        fs::write(&page, r#"<base href="assets/"><img src="a.png" alt="A"><picture><source srcset="b.webp 2x"></picture>"#).unwrap();
+        let inputs = || vec![(page.clone(), InputKind::Auto)];
+        // 没有 --extract-images 时 HTML 页面是普通的输入。
+        let mut captions = HashMap::new();
+        assert_eq!(expand_documents(inputs(), false, &mut captions).unwrap().len(), 1);
+        // 有时所有输入都当作页面，图片相对于 <base> 解析。
+        let expanded = expand_documents(inputs(), true, &mut captions);
+        fs::remove_dir_all(&dir).unwrap();
+        let expanded: Vec<_> = expanded.unwrap().into_iter().map(|(target, _)| target).collect();
+        let assets = dir.join("assets");
+        assert_eq!(expanded, [assets.join("a.png"), assets.join("b.webp")].map(|p| p.to_string_lossy().into_owned()));
+        assert_eq!(captions, HashMap::from([(0, "A".to_string())]));
+    }
+
+    #[test]
+    fn broken_pipes() {
+        let epipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(broken_pipe(&epipe().into()));
+        assert!(broken_pipe(&anyhow::Error::from(epipe()).context("writing the image")));
+        assert!(broken_pipe(&ImgcatError::Io(epipe()).into()));
+        assert!(broken_pipe(&anyhow::Error::from(ImgcatError::Io(epipe())).context("writing the image").context("showing a.png")));
+        // 经过 Write 传回的库错误还是原来的 io::Error。
+        assert!(broken_pipe(&io::Error::from(ImgcatError::Io(epipe())).into()));
+        assert!(!broken_pipe(&io::Error::from(io::ErrorKind::NotFound).into()));
+        assert!(!broken_pipe(&ImgcatError::Io(io::ErrorKind::WriteZero.into()).into()));
+        assert!(!broken_pipe(&anyhow::anyhow!("broken pipe")));
     }
-    Ok(())
 }
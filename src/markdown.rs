@@ -0,0 +1,101 @@
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use crate::html::{self, collapse_whitespace, Reference};
+
+/// Whether `path` names a Markdown document, by its extension.
+pub fn is_markdown(path: &str) -> bool {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    path.rsplit_once('.').is_some_and(|(_, ext)| {
+        ["md", "markdown", "mdown", "mkd"].iter().any(|e| ext.eq_ignore_ascii_case(e))
+    })
+}
+
+/// The images of a Markdown document in document order: inline images `![alt](target)`,
+/// reference images `![alt][label]` and `![alt]` with their definitions, and HTML
+/// `<img>` tags. Images in code blocks and code spans are left out.
+pub fn images(text: &str) -> Vec<Reference> {
+    let mut images = Vec::new();
+    // 正在读取替代文本的图片和它们在结果中的位置，图片可以嵌套在替代文本中。
+    let mut open: Vec<(usize, String, String)> = Vec::new();
+    let mut block = String::new();
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::Image {dest_url, ..}) => open.push((images.len(), dest_url.into_string(), String::new())),
+            Event::End(TagEnd::Image) => {
+                let (index, target, alt) = open.pop().expect("unbalanced image events");
+                images.insert(index, Reference {alt: collapse_whitespace(&alt), target});
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, alt)) = open.last_mut() {
+                    alt.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, alt)) = open.last_mut() {
+                    alt.push(' ');
+                }
+            }
+            // HTML 块逐行给出，整块解析，一个标签可能跨越几行。
+            Event::Html(html) => block.push_str(&html),
+            Event::End(TagEnd::HtmlBlock) => images.extend(html::img_tags(&std::mem::take(&mut block))),
+            Event::InlineHtml(html) => images.extend(html::img_tags(&html)),
+            _ => {}
+        }
+    }
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    fn images(text: &str) -> Vec<(String, String)> {
+        super::images(text).into_iter().map(|r| (r.alt, r.target)).collect()
+    }
+
+    #[test]
+    fn images_of_each_kind() {
+        let text = r#"# Title
+
+![A *logo*](logo.png "Title") and ![`code` alt
+on two lines](<path with spaces.png>) and ![escaped \]](a\(1\).png)
+
+![Reference][ref] ![ref] ![Collapsed][] ![Missing][none]
+
+<p align="center">
+  <img src="banner.svg"
+       alt="Banner">
+</p>
+
+Inline <img src='inline.gif' alt="x &amp; y"> HTML.
+
+[![Badge](badge.svg)](https://example.com)
+
+[ref]: <images/ref.png> "Title"
+[collapsed]: collapsed.png
+"#;
+        let expected = [
+            ("A logo", "logo.png"),
+            ("code alt on two lines", "path with spaces.png"),
+            ("escaped ]", "a(1).png"),
+            ("Reference", "images/ref.png"),
+            ("ref", "images/ref.png"),
+            ("Collapsed", "collapsed.png"),
+            ("Banner", "banner.svg"),
+            ("x & y", "inline.gif"),
+            ("Badge", "badge.svg"),
+        ];
+        let expected: Vec<_> = expected.iter().map(|(alt, target)| (alt.to_string(), target.to_string())).collect();
+        assert_eq!(images(text), expected);
+    }
+
+    #[test]
+    fn code_is_skipped() {
+        let text = "```md\n![fenced](a.png)\n```\n\n    ![indented](b.png)\n\n`![span](c.png)` ~~~\n\n~~~~\n<img src=d.png>\n~~~~\n";
+        assert!(images(text).is_empty());
+    }
+
+    #[test]
+    fn nested_images_in_order() {
+        let expected = [("outer", "outer.png"), ("inner", "inner.png")];
+        let expected: Vec<_> = expected.iter().map(|(alt, target)| (alt.to_string(), target.to_string())).collect();
+        assert_eq!(images("![outer ![inner](inner.png)](outer.png)"), expected);
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+/// A read-only memory mapping of a whole file.
+///
+/// The contents change if another process writes to the file while it is
+/// mapped, and reading past the end of a file truncated in the meantime
+/// raises SIGBUS; both are accepted for the short time an image is shown.
+/// Files expected to change, those of --watch and --monitor, are read instead.
+#[cfg(unix)]
+pub struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// 映射是只读的，可以安全地交给其他线程。
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+
+#[cfg(unix)]
+impl Mapping {
+    /// Map the first `len` bytes of `file`, which must not be empty.
+    pub fn new(file: &File, len: usize) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+        let ptr = unsafe {
+            libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {ptr, len})
+    }
+}
+
+#[cfg(unix)]
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+/// Memory mappings are only used on unix, other systems read files instead.
+#[cfg(not(unix))]
+pub struct Mapping(Vec<u8>);
+
+#[cfg(not(unix))]
+impl Mapping {
+    pub fn new(_file: &File, _len: usize) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(not(unix))]
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
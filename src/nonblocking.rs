@@ -0,0 +1,95 @@
+use std::io;
+use std::panic;
+use std::sync::OnceLock;
+use bytes::Bytes;
+use reqwest::Client;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use crate::{terminal, ImageDisplay};
+
+/// Write what draws the image `data` to `writer`, without blocking the runtime. The
+/// escape sequence is built in memory on a thread of the blocking pool of tokio, as
+/// decoding and scaling the image for the sixel, kitty and blocks renderers takes long.
+pub async fn display<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8], display: &ImageDisplay) -> crate::Result<()> {
+    write(writer, Bytes::copy_from_slice(data), display.clone()).await
+}
+
+async fn write<W: AsyncWrite + Unpin>(writer: &mut W, data: Bytes, display: ImageDisplay) -> crate::Result<()> {
+    // 终端的环境属于这个线程，转换图片的线程也要用它。
+    let session = terminal::session();
+    let task = tokio::task::spawn_blocking(move || {
+        terminal::enter(session);
+        let mut sequence = Vec::new();
+        let result = display.display_sized(&mut sequence, &mut &data[..], Some(data.len() as u64));
+        terminal::enter(None);
+        result.map(|_| sequence)
+    });
+    let sequence = match task.await {
+        Ok(sequence) => sequence?,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => return Err(io::Error::other(e).into()),
+    };
+    writer.write_all(&sequence).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Download the image at `url` and write what draws it to `writer`, named after the
+/// last segment of the URL unless `display` was given a file name. Return the size
+/// of the image.
+pub async fn display_url<W: AsyncWrite + Unpin>(writer: &mut W, url: &str, display: &ImageDisplay) -> crate::Result<u64> {
+    let response = client().get(url).send().await?.error_for_status()?;
+    let name = response.url()
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .map(str::to_string);
+    let data = response.bytes().await?;
+    let len = data.len() as u64;
+    let mut display = display.clone();
+    if display.filename.is_none() {
+        display.filename = name;
+    }
+    write(writer, data, display).await?;
+    Ok(len)
+}
+
+/// The HTTP client shared by the downloads, so that connections are reused.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::Bitmap;
+    use crate::{ColorDepth, Length, Protocol};
+
+    #[test]
+    fn display_on_the_blocking_pool() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut png = Bitmap::new(4, 4);
+        png.pixels.fill(255);
+        let png = png.to_png();
+        let blocks = ImageDisplay::builder()
+            .width(Length::Cells(2))
+            .height(Length::Cells(1))
+            .preserve_aspect(false)
+            .colors(ColorDepth::Truecolor)
+            .protocol(Protocol::Blocks)
+            .build();
+        let iterm2 = ImageDisplay::builder().protocol(Protocol::Iterm2).build();
+        // 转换图片的线程用这个线程的环境：在 tmux 中要包装转义序列。
+        terminal::set_environment(Some(vec![("TERM".into(), "screen".into())]));
+        let mut expected = Vec::new();
+        blocks.display_sized(&mut expected, &mut png.as_slice(), Some(png.len() as u64)).unwrap();
+        let mut out = Vec::new();
+        runtime.block_on(display(&mut out, &png, &blocks)).unwrap();
+        assert_eq!(out, expected);
+        let mut out = Vec::new();
+        runtime.block_on(display(&mut out, &png, &iterm2)).unwrap();
+        assert!(out.starts_with(b"\x1bPtmux;\x1b\x1b]1337;File=inline=1;"));
+        let error = runtime.block_on(display(&mut Vec::new(), b"abc", &blocks)).unwrap_err();
+        terminal::set_environment(None);
+        assert!(matches!(error, crate::ImgcatError::Decode(_)), "{error:?}");
+    }
+}
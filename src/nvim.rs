@@ -0,0 +1,191 @@
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use anyhow::{bail, Context};
+use log::debug;
+use imgcat::Options;
+use rmpv::Value;
+use crate::i18n::tr;
+use crate::{layout, terminal, Length, Renderer};
+
+/// Lua code run by Neovim with the escape sequence of the image and the number of its
+/// rows above the cursor. It draws the image on the terminal Neovim runs in, where the
+/// current window, which has to be a terminal, shows the cursor, and returns an error
+/// message otherwise.
+const PLACE_LUA: &str = r#"
+local sequence, above = ...
+local win = vim.api.nvim_get_current_win()
+local buf = vim.api.nvim_win_get_buf(win)
+if vim.bo[buf].buftype ~= "terminal" then
+    return "the current window of Neovim is not a terminal"
+end
+vim.cmd.redraw()
+local top, left = unpack(vim.fn.win_screenpos(win))
+local line, column = unpack(vim.api.nvim_win_get_cursor(win))
+local row = top + line - vim.fn.line("w0", win) - above
+local data = string.format("\0277\27[%d;%dH%s\0278", row, left + column, sequence)
+if vim.api.nvim_ui_send then
+    vim.api.nvim_ui_send(data)
+else
+    vim.api.nvim_chan_send(vim.v.stderr, data)
+end
+"#;
+
+/// The connection to the Neovim instance whose terminal imgcat runs in, and the
+/// address of its server, kept across the command lines run by --daemon.
+static CLIENT: Mutex<Option<(String, Client)>> = Mutex::new(None);
+
+/// A msgpack-RPC connection to Neovim.
+struct Client {
+    stream: Stream,
+    next_id: u32,
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+type Stream = std::fs::File;
+
+impl Client {
+    fn connect(address: &str) -> io::Result<Self> {
+        #[cfg(unix)]
+        let stream = Stream::connect(address)?;
+        // Windows 上 Neovim 监听的是命名管道，可以像文件一样打开。
+        #[cfg(not(unix))]
+        let stream = std::fs::OpenOptions::new().read(true).write(true).open(address)?;
+        Ok(Client {stream, next_id: 0})
+    }
+
+    /// Call `method` with `params` and return its result.
+    fn call(&mut self, method: &str, params: Vec<Value>) -> anyhow::Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Value::Array(vec![0.into(), id.into(), method.into(), Value::Array(params)]);
+        let mut message = Vec::new();
+        rmpv::encode::write_value(&mut message, &request)?;
+        self.stream.write_all(&message)?;
+        loop {
+            // 通知和其他请求的回复都跳过，只等待这个请求的回复。
+            let Value::Array(reply) = rmpv::decode::read_value(&mut self.stream)? else {
+                bail!(tr!("invalid message from Neovim"));
+            };
+            match &reply[..] {
+                [Value::Integer(kind), Value::Integer(reply_id), error, result]
+                    if kind.as_u64() == Some(1) && reply_id.as_u64() == Some(id.into()) =>
+                {
+                    return match error {
+                        Value::Nil => Ok(result.clone()),
+                        Value::Array(error) => bail!(tr!("Neovim: {}", error.get(1).map_or(tr!("unknown error").into(), lua_string))),
+                        _ => bail!(tr!("Neovim: {}", tr!("unknown error"))),
+                    };
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+fn lua_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        Value::Binary(s) => String::from_utf8_lossy(s).into_owned(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Whether imgcat runs in a terminal of Neovim whose server it can connect to,
+/// connecting unless it already is. `$NVIM` may also be inherited by programs
+/// started from such a terminal, which then run in other terminals.
+pub fn connect() -> bool {
+    let Ok(address) = terminal::var("NVIM") else {
+        return false;
+    };
+    let mut cached = CLIENT.lock().unwrap();
+    if cached.as_ref().is_some_and(|(a, _)| *a == address) {
+        return true;
+    }
+    match Client::connect(&address) {
+        Ok(client) => {
+            debug!("connected to Neovim at {address}");
+            *cached = Some((address, client));
+            true
+        }
+        Err(e) => {
+            debug!("failed to connect to Neovim at {address}: {e}");
+            false
+        }
+    }
+}
+
+/// Draw the image read from `body` at the cursor through the Neovim [`connect`]ed to,
+/// whose terminals don't show images themselves, and return the number of bytes read.
+/// The rows it takes are reserved in the terminal first, so that Neovim scrolls the
+/// text above it.
+pub fn emit(out: &mut impl Write, renderer: &dyn Renderer, body: &mut impl Read, options: &Options) -> anyhow::Result<u64> {
+    let mut data = Vec::new();
+    let read = body.read_to_end(&mut data)? as u64;
+    let size = terminal::size();
+    let (columns, rows) = layout::display_cells(&data, options.width, options.height, options.preserve_aspect_ratio, &size)
+        .unwrap_or((size.columns, 1));
+    let options = Options {
+        width: Some(Length::Cells(columns.into())),
+        height: Some(Length::Cells(rows.into())),
+        ..options.clone()
+    };
+    let mut sequence = Vec::new();
+    renderer.emit(&mut sequence, &mut data.as_slice(), &options)?;
+    // 用 IND 逐行下移，在屏幕底部时滚动，然后停在图片最后一行，和终端画完图片时一样。
+    write!(out, "{}", "\x1bD".repeat(rows as usize - 1))?;
+    out.flush()?;
+    let mut cached = CLIENT.lock().unwrap();
+    let (_, client) = cached.as_mut().context(tr!("not connected to Neovim"))?;
+    let params = vec![PLACE_LUA.into(), Value::Array(vec![sequence[..].into(), u32::from(rows - 1).into()])];
+    match client.call("nvim_exec_lua", params) {
+        Ok(Value::Nil) => Ok(read),
+        Ok(message) => bail!("{}", lua_string(&message)),
+        Err(e) => {
+            // 连接可能已经断开，下次重新连接。
+            *cached = None;
+            Err(e)
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::thread;
+    use super::*;
+
+    /// Answer the requests of `client` as Neovim would, notifying it of an event and
+    /// replying to another request first.
+    fn serve(mut stream: Stream) {
+        while let Ok(Value::Array(request)) = rmpv::decode::read_value(&mut stream) {
+            let [_, id, method, params] = &request[..] else { panic!("{request:?}") };
+            let (error, result) = match method.as_str() {
+                Some("echo") => (Value::Nil, params.clone()),
+                _ => (Value::Array(vec![0.into(), "Invalid method".into()]), Value::Nil),
+            };
+            for message in [
+                Value::Array(vec![2.into(), "redraw".into(), Value::Array(Vec::new())]),
+                Value::Array(vec![1.into(), 1000.into(), Value::Nil, Value::Nil]),
+                Value::Array(vec![1.into(), id.clone(), error, result]),
+            ] {
+                rmpv::encode::write_value(&mut stream, &message).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let (stream, neovim) = Stream::pair().unwrap();
+        let server = thread::spawn(move || serve(neovim));
+        let mut client = Client {stream, next_id: 0};
+        let params = vec!["é".into(), b"\x1b_G\x1b\\"[..].into(), Value::Map(vec![(1.into(), (-2).into())])];
+        assert_eq!(client.call("echo", params.clone()).unwrap(), Value::Array(params));
+        let error = client.call("nvim_unknown", Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "Neovim: Invalid method");
+        assert_eq!(client.call("echo", vec![u32::MAX.into()]).unwrap(), Value::Array(vec![u32::MAX.into()]));
+        assert_eq!(client.next_id, 3);
+        drop(client);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::bail;
+use crate::i18n::tr;
+
+/// How far apart requests to servers start, from --delay-between and --max-rps, and when
+/// the next one may start.
+struct Pace {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+/// The pace of a command line, shared by the threads running it.
+#[derive(Clone)]
+pub struct Shared(Arc<Mutex<Pace>>);
+
+thread_local! {
+    /// The pace of the command line running on this thread: those run by the daemon at
+    /// the same time each keep their own.
+    static PACE: RefCell<Shared> = RefCell::new(Shared(Arc::new(Mutex::new(Pace {interval: Duration::ZERO, next: None}))));
+}
+
+/// The pace of the command line running on this thread, for the threads it spawns to [`enter`].
+pub fn current() -> Shared {
+    PACE.with(|pace| pace.borrow().clone())
+}
+
+/// Pace the requests of this thread with those of the thread `pace` was taken from.
+pub fn enter(pace: Shared) {
+    PACE.with(|current| *current.borrow_mut() = pace);
+}
+
+/// Set the time between the starts of requests: `delay` seconds, or more if `max_rps`
+/// requests a second would be exceeded. Both unset leave requests unpaced. Fails when the
+/// time is negative, not a number or too long to wait.
+pub fn init(delay: Option<f64>, max_rps: Option<f64>) -> anyhow::Result<()> {
+    let seconds = delay.unwrap_or(0.0).max(max_rps.map_or(0.0, |rps| 1.0 / rps));
+    // 随机延长一半后的间隔也要能加到时间点上，否则 wait 会溢出。
+    let interval = Duration::try_from_secs_f64(seconds).ok().filter(|interval| {
+        interval.checked_add(*interval / 2).and_then(|longest| Instant::now().checked_add(longest)).is_some()
+    });
+    let Some(interval) = interval else {
+        bail!(tr!("invalid time between requests of {} seconds", seconds));
+    };
+    enter(Shared(Arc::new(Mutex::new(Pace {interval, next: None}))));
+    Ok(())
+}
+
+/// Wait until the next request may start, and book the following turn. The background
+/// threads of --jobs share the turns, so the pace holds whatever their number.
+pub fn wait() {
+    let wait = current().0.lock().unwrap().turn(Instant::now(), jitter());
+    thread::sleep(wait);
+}
+
+impl Pace {
+    /// Book the turn of a request made at `now`, and return how long it must wait for it.
+    /// The interval after it is made longer by `jitter` halves of itself.
+    fn turn(&mut self, now: Instant, jitter: f64) -> Duration {
+        if self.interval.is_zero() {
+            return Duration::ZERO;
+        }
+        let start = self.next.map_or(now, |next| next.max(now));
+        // 间隔随机延长至多一半，同时启动的多个 imgcat 不会一直同时发出请求。
+        self.next = Some(start + self.interval.mul_f64(1.0 + jitter / 2.0));
+        start - now
+    }
+}
+
+/// A random number from 0 to 1, from the random keys of the hasher of the standard library.
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut pace = Pace {interval: Duration::from_secs(1), next: None};
+        assert_eq!(pace.turn(at(0), 0.0), Duration::ZERO);
+        // 同时到达的请求依次排队，每个等上一个的间隔。
+        assert_eq!(pace.turn(at(0), 0.0), Duration::from_secs(1));
+        assert_eq!(pace.turn(at(0), 1.0), Duration::from_secs(2));
+        assert_eq!(pace.turn(at(500), 0.0), Duration::from_millis(3000));
+        // 间隔过后到达的请求不用等。
+        assert_eq!(pace.turn(at(10_000), 0.5), Duration::ZERO);
+        assert_eq!(pace.turn(at(10_000), 0.0), Duration::from_millis(1250));
+        assert_eq!(pace.next, Some(at(12_250)));
+
+        let mut unpaced = Pace {interval: Duration::ZERO, next: None};
+        assert_eq!(unpaced.turn(at(0), 1.0), Duration::ZERO);
+        assert_eq!(unpaced.next, None);
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = || current().0.lock().unwrap().interval;
+        init(Some(0.5), None).unwrap();
+        assert_eq!(interval(), Duration::from_millis(500));
+        init(Some(0.1), Some(4.0)).unwrap();
+        assert_eq!(interval(), Duration::from_millis(250));
+        init(None, None).unwrap();
+        assert_eq!(interval(), Duration::ZERO);
+        // 随机延长后放不进 Instant 的间隔等不到。
+        assert!(init(Some(f64::MAX), None).is_err());
+        assert!(init(None, Some(0.0)).is_err());
+    }
+
+    #[test]
+    fn jitters() {
+        assert!((0..100).map(|_| jitter()).all(|j| (0.0..1.0).contains(&j)));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::bitmap::Bitmap;
+
+/// Number of pixels looked at, larger images are sampled.
+const MAX_SAMPLES: usize = 65536;
+
+/// A dominant color and the share of the pixels it stands for.
+pub struct Swatch {
+    pub rgb: [u8; 3],
+    pub share: f64,
+}
+
+impl Swatch {
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.rgb[0], self.rgb[1], self.rgb[2])
+    }
+}
+
+/// Extract up to `n` dominant colors of the opaque pixels of `bitmap` with the
+/// median cut algorithm, most common first.
+pub fn dominant_colors(bitmap: &Bitmap, n: usize) -> Vec<Swatch> {
+    let opaque = bitmap.pixels.chunks(4).filter(|p| p[3] >= 128);
+    let step = (bitmap.pixels.len() / 4 / MAX_SAMPLES).max(1);
+    let pixels: Vec<[u8; 3]> = opaque.step_by(step).map(|p| [p[0], p[1], p[2]]).collect();
+    let total = pixels.len();
+    if total == 0 || n == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < n {
+        // 每次切分颜色范围最大的盒子，沿范围最大的通道在中位数处一分为二。
+        let Some((i, channel, _)) = boxes.iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .filter(|(_, _, range)| *range > 0)
+            .max_by_key(|(_, _, range)| *range)
+        else {
+            break;
+        };
+        let mut pixels = boxes.swap_remove(i);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut swatches: Vec<Swatch> = boxes.iter()
+        .map(|b| {
+            let mut sum = [0u64; 3];
+            for p in b {
+                for c in 0..3 {
+                    sum[c] += p[c] as u64;
+                }
+            }
+            Swatch {
+                rgb: sum.map(|s| (s / b.len() as u64) as u8),
+                share: b.len() as f64 / total as f64,
+            }
+        })
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    swatches
+}
+
+/// The channel whose values spread the most in `pixels`, and that spread.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|c| {
+            let (min, max) = pixels.iter()
+                .fold((255, 0), |(min, max), p| (p[c].min(min), p[c].max(max)));
+            (c, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
@@ -0,0 +1,49 @@
+use imgcat::bitmap::Bitmap;
+
+/// Share of the pixels along the edges of an image that have to be light or transparent
+/// for it to be taken as a plot on a light background.
+const LIGHT_EDGES: usize = 80;
+
+/// Whether `color` is dark enough for the light backgrounds of plots to stand out on it.
+pub fn is_dark(color: [u8; 3]) -> bool {
+    luminance(color) < 128
+}
+
+/// Whether `bitmap` looks like a plot drawn on a light or transparent background, as
+/// matplotlib draws them by default: most pixels along its edges are.
+pub fn light_background(bitmap: &Bitmap) -> bool {
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    if width == 0 || height == 0 {
+        return false;
+    }
+    let edges = (0..width).flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]));
+    let (mut light, mut total) = (0, 0);
+    for (x, y) in edges {
+        let [r, g, b, a] = bitmap.get(x, y);
+        light += (a < 128 || luminance([r, g, b]) >= 200) as usize;
+        total += 1;
+    }
+    light * 100 >= total * LIGHT_EDGES
+}
+
+/// `bitmap` with its lightness inverted and its hues kept, and black turned into
+/// `background`, so that a plot drawn on white shows on the dark background of the
+/// terminal, with light axes and text and the same colors for its lines.
+pub fn darken(bitmap: &Bitmap, background: [u8; 3]) -> Bitmap {
+    let mut darkened = bitmap.clone();
+    for pixel in darkened.pixels.chunks_exact_mut(4) {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(u16::from);
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        for (c, bg) in pixel[..3].iter_mut().zip(background) {
+            // 在 HSL 中反转亮度：各分量加上 255 - max - min，色相和饱和度不变。
+            let inverted = 255 + *c as u16 - max - min;
+            *c = (bg as u16 + inverted * (255 - bg as u16) / 255) as u8;
+        }
+    }
+    darkened
+}
+
+fn luminance([r, g, b]: [u8; 3]) -> u32 {
+    (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000
+}
@@ -0,0 +1,122 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::Mutex;
+use anyhow::{bail, Context};
+use crate::i18n::tr;
+use crate::{layout, renderer, terminal, Cli, InputKind, Length};
+
+/// Variable telling the imgcat started in the popup the terminal of the tmux client and
+/// the column and row of the popup's top left corner on it, counted from 0.
+const INSIDE: &str = "IMGCAT_IN_POPUP";
+
+/// Escape sequence erasing the image drawn in the popup from the client's terminal.
+static ERASE: Mutex<Option<(String, Vec<u8>)>> = Mutex::new(None);
+
+/// Whether this imgcat is the one started in a popup by [`open`].
+pub fn inside() -> bool {
+    terminal::var_os(INSIDE).is_some()
+}
+
+/// Run `command_line` again in a tmux popup sized to the image of the only input, which
+/// closes after a keypress and leaves nothing in the scrollback of the pane. Returns the
+/// exit status of tmux.
+pub fn open(command_line: &[OsString], inputs: &[(String, InputKind)], args: &Cli) -> anyhow::Result<i32> {
+    if terminal::var_os("TMUX").is_none() {
+        bail!(tr!("--popup only works inside tmux"));
+    }
+    let [(path, kind)] = inputs else {
+        bail!(tr!("--popup shows a single image"));
+    };
+    if path == "-" {
+        bail!(tr!("--popup can't show images read from stdin"));
+    }
+    let output = terminal::command("tmux")
+        .args(["display-message", "-p", "#{client_tty} #{client_width} #{client_height} #{client_termname}"])
+        .stdin(Stdio::null())
+        .output()
+        .context(tr!("failed to run {}, is it installed?", "tmux"))?;
+    let client = String::from_utf8_lossy(&output.stdout);
+    let [tty, client_width, client_height, term] = client.split_whitespace().collect::<Vec<_>>()[..] else {
+        bail!(tr!("tmux has no client to show the popup on"));
+    };
+    let (client_width, client_height): (u16, u16) = (client_width.parse()?, client_height.parse()?);
+    let (width, height) = popup_size(path, *kind, args)
+        .unwrap_or((client_width * 4 / 5, client_height * 4 / 5));
+    let (width, height) = (width.min(client_width), height.min(client_height));
+    let (x, y) = ((client_width - width) / 2, (client_height - height) / 2);
+    // tmux 的弹出窗口不转发 passthrough 序列，图片直接写到客户端的终端上，
+    // TERM 也换成客户端终端的，这样图片不会再被包装给 tmux。
+    let mut command = format!("{INSIDE}={} TERM={}", shell_quote(&format!("{tty} {x} {y}")), shell_quote(term));
+    // 弹出窗口中的 shell 不一定有同样的 PATH，用当前程序的完整路径。
+    let program = env::current_exe().map(OsString::from).ok().or_else(|| command_line.first().cloned());
+    for arg in program.iter().chain(command_line.iter().skip(1)) {
+        command.push(' ');
+        command.push_str(&shell_quote(&arg.to_string_lossy()));
+    }
+    let mut tmux = terminal::command("tmux");
+    // -y 给出的是弹出窗口下边缘的位置。
+    tmux.args(["display-popup", "-E", "-T", path])
+        .args(["-x", &x.to_string(), "-y", &(y + height).to_string()])
+        .args(["-w", &width.to_string(), "-h", &height.to_string()]);
+    if let Ok(dir) = env::current_dir() {
+        tmux.arg("-d").arg(dir);
+    }
+    let status = tmux.arg(command)
+        .stdin(Stdio::null())
+        .status()
+        .context(tr!("failed to run {}, is it installed?", "tmux"))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Point stdout at the terminal of the tmux client, with the cursor inside the popup's
+/// border, and fit the image into the popup unless its size is given.
+pub fn enter(args: &mut Cli) -> anyhow::Result<()> {
+    let target = terminal::var(INSIDE)?;
+    let mut fields = target.rsplitn(3, ' ');
+    let (Some(y), Some(x), Some(tty)) = (fields.next(), fields.next(), fields.next()) else {
+        bail!(tr!("invalid {}: {}", INSIDE, target));
+    };
+    let (x, y): (u16, u16) = (x.parse()?, y.parse()?);
+    // 重定向之前 stdout 还是弹出窗口，之后的终端大小是整个客户端的。
+    let size = terminal::size();
+    args.width = args.width.or(Some(Length::Cells(size.columns.into())));
+    args.height = args.height.or(Some(Length::Cells(size.rows.saturating_sub(1).max(1).into())));
+    let mut erase = Vec::new();
+    renderer(args).erase(&mut erase, x + 1, y + 1, size.columns, size.rows)?;
+    *ERASE.lock().unwrap() = Some((tty.to_string(), erase));
+    // 重定向的文件描述符一直用到进程退出。
+    std::mem::forget(terminal::redirect_stdout(tty)?);
+    let mut out = terminal::stdout();
+    write!(out, "\x1b[{};{}H", y + 2, x + 2)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Wait for a key before the popup closes, and erase the image from the client's terminal.
+pub fn leave() {
+    let _ = terminal::read_key();
+    if let Some((tty, erase)) = ERASE.lock().unwrap().take() {
+        if let Ok(mut tty) = OpenOptions::new().write(true).open(tty) {
+            let _ = tty.write_all(&erase);
+        }
+    }
+}
+
+/// The size of the popup showing the local file `path`, with its border and the line
+/// after the image. `None` for remote images, which are only downloaded in the popup.
+fn popup_size(path: &str, kind: InputKind, args: &Cli) -> Option<(u16, u16)> {
+    if matches!(kind, InputKind::Url) {
+        return None;
+    }
+    let data = fs::read(path).ok()?;
+    let (columns, rows) = layout::display_cells(&data, args.width, args.height, true, &terminal::size())?;
+    Some((columns.saturating_add(2), rows.saturating_add(3)))
+}
+
+/// `arg` quoted for the shell tmux runs the popup's command with.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
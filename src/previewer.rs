@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+use anyhow::bail;
+use log::debug;
+use imgcat::Options;
+use imgcat::bitmap::Bitmap;
+use crate::{converted_image, format_label, layout, needs_converting, open_input, print_inline, render, renderer, terminal, Cli, Image, InputKind, Length, Source};
+use crate::i18n::tr;
+
+/// Exit status after drawing a preview. lf caches the previews of scripts exiting
+/// with 0, and would not run imgcat to draw the image again when it is overwritten.
+pub const DRAWN: i32 = 1;
+
+/// Number of the image previewed in Yazi with kitty's Unicode placeholders. Each preview
+/// replaces the previous one.
+const YAZI_IMAGE_ID: u32 = 0x1ea71;
+
+/// The rectangle of cells given to --previewer, in the order of the arguments of lf's previewer scripts.
+#[derive(Clone, Copy, Debug)]
+pub struct Geometry {
+    pub width: u16,
+    pub height: u16,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Geometry {
+    pub fn new(values: &[u16]) -> Self {
+        let [width, height, x, y] = values.try_into().expect("four values");
+        Geometry {width, height, x, y}
+    }
+}
+
+/// Draw the first input fitted into `geometry`, erasing what was drawn there before,
+/// and return the exit status the file manager expects.
+pub fn show(out: &mut impl Write, input: Option<&(String, InputKind)>, geometry: Geometry, args: &Cli) -> anyhow::Result<i32> {
+    let image = open(input, args)?;
+    let Geometry {width, height, x, y} = geometry;
+    let (box_width, box_height) = (Length::Cells(width.into()), Length::Cells(height.into()));
+    let (columns, rows) = layout::display_cells(&image.data, Some(box_width), Some(box_height), true, &terminal::size())
+        .unwrap_or((width, height));
+    renderer(args).erase(out, x, y, width, height)?;
+    write!(out, "\x1b7\x1b[{};{}H", y + 1, x + 1)?;
+    print_inline(out, &image, args, Some(Length::Cells(columns.into())), Some(Length::Cells(rows.into())))?;
+    out.write_all(b"\x1b8")?;
+    out.flush()?;
+    Ok(DRAWN)
+}
+
+/// Write the first input fitted into `width` by `height` cells with half blocks, as lines
+/// of text, for file managers showing the output of their previewers as text. Images that
+/// can't be decoded are described by a line of text instead, as with --alt.
+pub fn show_text(out: &mut impl Write, input: Option<&(String, InputKind)>, width: u16, height: u16, args: &Cli) -> anyhow::Result<()> {
+    let image = open(input, args)?;
+    let options = Options {
+        width: Some(Length::Cells(width.into())),
+        height: Some(Length::Cells(height.into())),
+        preserve_aspect_ratio: true,
+        cell_aspect: args.cell_aspect,
+        symbols: args.symbols.clone(),
+        colors: args.colors,
+        ..Options::default()
+    };
+    match render::blocks_text(out, &mut &image.data[..], &options) {
+        // 解码在输出任何内容之前进行，失败时预览窗格还是空的。
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            debug!("{}: described, failed to decode it: {e}", image.path.unwrap_or("-"));
+            writeln!(out, "[{}]", format_label(&args.alt, &image))?;
+        }
+        result => {
+            result?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Preview the first input in the rectangle of Yazi's preview pane given by `geometry`.
+/// In terminals with kitty's protocol, the image is sent to the terminal and the lines of
+/// Unicode placeholders showing it are written to `out`, for Yazi to draw as the text of
+/// the pane, so that it stays in place as Yazi redraws; otherwise, and in Konsole, which
+/// doesn't implement the placeholders, it is drawn on the terminal.
+pub fn show_yazi(out: &mut impl Write, input: Option<&(String, InputKind)>, geometry: Geometry, args: &Cli) -> anyhow::Result<()> {
+    let mut tty = terminal::open_tty()?;
+    // Konsole 不支持 Unicode 占位字符，直接绘制。
+    if renderer(args).name() != "kitty" || terminal::konsole() {
+        show(&mut tty, input, geometry, args)?;
+        return Ok(());
+    }
+    let image = open(input, args)?;
+    let options = Options {
+        width: Some(Length::Cells(geometry.width.into())),
+        height: Some(Length::Cells(geometry.height.into())),
+        preserve_aspect_ratio: true,
+        ..Options::default()
+    };
+    render::kitty_placeholders(&mut tty, out, &mut &image.data[..], &options, YAZI_IMAGE_ID)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Remove the preview of [`show_yazi`] from `geometry`.
+pub fn clear_yazi(geometry: Geometry, args: &Cli) -> io::Result<()> {
+    let mut tty = terminal::open_tty()?;
+    match renderer(args).name() {
+        "kitty" if !terminal::konsole() => render::delete_kitty_image(&mut tty, YAZI_IMAGE_ID),
+        _ => clear(&mut tty, geometry, args),
+    }
+}
+
+/// The image of the first input, converted for the terminal if needed.
+fn open<'a>(input: Option<&'a (String, InputKind)>, args: &Cli) -> anyhow::Result<Image<'a>> {
+    let Some((path, kind)) = input else {
+        bail!(tr!("the file to preview is missing"));
+    };
+    let opened = open_input(path, *kind, false, false, args)?;
+    match opened.source {
+        Source::Image(image) => Ok(match opened.bitmap {
+            Some(bitmap) => converted_image(&image, &bitmap?),
+            None => image,
+        }),
+        Source::Stream(_) => bail!(tr!("{} is a video stream, not an image", path)),
+        Source::Download(_) => unreachable!("downloads are opened as images"),
+        // 预览只显示一张图片，读取管道中的全部数据。
+        Source::Pipe(pipe) => {
+            let image = pipe.into_image()?;
+            if !needs_converting(&image.data, args) {
+                return Ok(image);
+            }
+            Ok(converted_image(&image, &Bitmap::decode(&image.data)?))
+        }
+    }
+}
+
+/// Erase the preview drawn in `geometry`, for the cleaner scripts of lf and ranger.
+pub fn clear(out: &mut impl Write, geometry: Geometry, args: &Cli) -> io::Result<()> {
+    let Geometry {width, height, x, y} = geometry;
+    renderer(args).erase(out, x, y, width, height)?;
+    out.flush()
+}
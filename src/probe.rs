@@ -0,0 +1,358 @@
+use std::fmt;
+use crate::split;
+
+/// Image formats recognized by their signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Webp,
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [Format::Png, Format::Jpeg, Format::Gif, Format::Bmp, Format::Webp];
+
+    /// The media type of images in this format, e.g. `image/png`.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::Gif => "image/gif",
+            Format::Bmp => "image/bmp",
+            Format::Webp => "image/webp",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+            Format::Gif => "GIF",
+            Format::Bmp => "BMP",
+            Format::Webp => "WebP",
+        })
+    }
+}
+
+/// Format and dimensions of an image, read from its header.
+#[derive(Clone, Copy, Debug)]
+pub struct Info {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// color model, e.g. `RGBA` or `indexed`
+    pub color: &'static str,
+    /// bits per sample, or per pixel for indexed and BMP images
+    pub bit_depth: u8,
+}
+
+/// Read the format and dimensions of an image from its header, without decoding it.
+pub fn probe(data: &[u8]) -> Option<Info> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // IHDR 总是第一个数据块。
+        if data.get(12..16)? != b"IHDR" {
+            return None;
+        }
+        let color = match *data.get(25)? {
+            0 => "grayscale",
+            2 => "RGB",
+            3 => "indexed",
+            4 => "grayscale+alpha",
+            6 => "RGBA",
+            _ => return None,
+        };
+        return Some(Info {
+            format: Format::Png,
+            width: be32(data, 16)?,
+            height: be32(data, 20)?,
+            color,
+            bit_depth: *data.get(24)?,
+        });
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(Info {
+            format: Format::Gif,
+            width: le16(data, 6)?,
+            height: le16(data, 8)?,
+            color: "indexed",
+            bit_depth: (data.get(10)? & 0x07) + 1,
+        });
+    }
+    if data.starts_with(b"BM") {
+        return probe_bmp(data);
+    }
+    if data.starts_with(b"\xff\xd8") {
+        return probe_jpeg(data);
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12)? == b"WEBP" {
+        return probe_webp(data);
+    }
+    None
+}
+
+/// BMP images have a 14-byte file header, then an information header whose size tells
+/// its version: the 12-byte OS/2 header stores the dimensions in 16 bits, the later ones in 32.
+fn probe_bmp(data: &[u8]) -> Option<Info> {
+    let size = le32(data, 14)?;
+    let (width, height, bit_depth) = match size {
+        12 => (le16(data, 18)?, le16(data, 20)?, le16(data, 24)?),
+        40 | 52 | 56 | 64 | 108 | 124 => (
+            (le32(data, 18)? as i32).unsigned_abs(),
+            // 高度为负表示自上而下存储的图像。
+            (le32(data, 22)? as i32).unsigned_abs(),
+            le16(data, 28)?,
+        ),
+        _ => return None,
+    };
+    // 像素数据不能从文件头和信息头之中开始。
+    if data.len() < 14 + size as usize || (le32(data, 10)? as usize) < 14 + size as usize {
+        return None;
+    }
+    let color = match bit_depth {
+        1 | 2 | 4 | 8 => "indexed",
+        16 | 24 => "RGB",
+        32 => "RGBA",
+        _ => return None,
+    };
+    Some(Info {format: Format::Bmp, width, height, color, bit_depth: bit_depth as u8})
+}
+
+/// WebP images start with one of three chunks, each storing the dimensions differently.
+fn probe_webp(data: &[u8]) -> Option<Info> {
+    let info = |width, height, color| Some(Info {format: Format::Webp, width, height, color, bit_depth: 8});
+    match data.get(12..16)? {
+        b"VP8 " => {
+            // 有损格式：关键帧头部的起始码之后是 14 位的宽度和高度。
+            if data.get(23..26)? != b"\x9d\x01\x2a" {
+                return None;
+            }
+            info(le16(data, 26)? & 0x3fff, le16(data, 28)? & 0x3fff, "YUV")
+        }
+        b"VP8L" => {
+            if *data.get(20)? != 0x2f {
+                return None;
+            }
+            let bits = le32(data, 21)?;
+            let color = if bits >> 28 & 1 == 1 { "RGBA" } else { "RGB" };
+            info((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1, color)
+        }
+        b"VP8X" => {
+            let color = if data.get(20)? & 0x10 != 0 { "RGBA" } else { "RGB" };
+            info((le32(data, 24)? & 0xffffff) + 1, (le32(data, 27)? & 0xffffff) + 1, color)
+        }
+        _ => None,
+    }
+}
+
+fn probe_jpeg(data: &[u8]) -> Option<Info> {
+    let mut pos = 2;
+    loop {
+        while *data.get(pos)? == 0xff {
+            pos += 1;
+        }
+        let marker = *data.get(pos)?;
+        pos += 1;
+        if marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            continue;
+        }
+        let len = be16(data, pos)? as usize;
+        // SOF0 到 SOF15 中除了 DHT (C4)、JPG (C8) 和 DAC (CC) 以外的标记都带有图像尺寸。
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            return Some(Info {
+                format: Format::Jpeg,
+                width: be16(data, pos + 5)?,
+                height: be16(data, pos + 3)?,
+                color: match *data.get(pos + 7)? {
+                    1 => "grayscale",
+                    4 => "CMYK",
+                    _ => "YCbCr",
+                },
+                bit_depth: *data.get(pos + 2)?,
+            });
+        }
+        if marker == 0xda || marker == 0xd9 {
+            return None;
+        }
+        pos += len;
+    }
+}
+
+/// Number of frames of an image: the frames of an animated GIF or APNG, 1 for still images.
+pub fn frame_count(data: &[u8], format: Format) -> Option<u32> {
+    match format {
+        Format::Gif => split::gif_frame_count(data),
+        Format::Png => match animation_control(data)? {
+            Some(pos) => be32(data, pos),
+            None => Some(1),
+        },
+        // 动画 WebP 的每一帧是一个 ANMF 数据块。
+        Format::Webp => {
+            let mut frames = 0;
+            let mut pos = 12;
+            while let Some(len) = le32(data, pos + 4) {
+                if data.get(pos..pos + 4)? == b"ANMF" {
+                    frames += 1;
+                }
+                // RIFF 数据块按偶数字节对齐。
+                pos += 8 + len as usize + (len as usize & 1);
+            }
+            Some(frames.max(1))
+        }
+        Format::Jpeg | Format::Bmp => Some(1),
+    }
+}
+
+/// Number of times an APNG image is played, 0 for forever, `None` for still PNG images.
+pub fn apng_plays(data: &[u8]) -> Option<u32> {
+    be32(data, animation_control(data)?? + 4)
+}
+
+/// Position of the body of the `acTL` chunk of a PNG image, `Some(None)` for still images
+/// and `None` if the image ends before its first `IDAT`.
+fn animation_control(data: &[u8]) -> Option<Option<usize>> {
+    // APNG 在 acTL 数据块中记录帧数和播放次数，这个块必须出现在第一个 IDAT 之前。
+    let mut pos = 8;
+    loop {
+        let len = be32(data, pos)? as usize;
+        match data.get(pos + 4..pos + 8)? {
+            b"acTL" => return Some(Some(pos + 8)),
+            b"IDAT" | b"IEND" => return Some(None),
+            _ => pos = pos.checked_add(12 + len)?,
+        }
+    }
+}
+
+fn be16(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as u32)
+}
+
+fn be32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn le16(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as u32)
+}
+
+fn le32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(color: u8) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        png.extend_from_slice(&[8, color, 0, 0, 0]);
+        png
+    }
+
+    /// A BMP with an information header of `size` bytes and the pixels right after it.
+    fn bmp(size: u32, width: i32, height: i32, bit_depth: u16) -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&(14 + size).to_le_bytes());
+        bmp.extend_from_slice(&size.to_le_bytes());
+        if size == 12 {
+            bmp.extend_from_slice(&(width as u16).to_le_bytes());
+            bmp.extend_from_slice(&(height as u16).to_le_bytes());
+        } else {
+            bmp.extend_from_slice(&width.to_le_bytes());
+            bmp.extend_from_slice(&height.to_le_bytes());
+        }
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&bit_depth.to_le_bytes());
+        bmp.resize(14 + size as usize, 0);
+        bmp
+    }
+
+    /// A WebP of one chunk, padded for the fields read after its start.
+    fn webp(chunk: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        webp.extend_from_slice(chunk);
+        webp.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        webp.extend_from_slice(body);
+        webp.resize(webp.len().max(32), 0);
+        webp
+    }
+
+    fn summary(data: &[u8]) -> Option<(Format, u32, u32, &'static str, u8)> {
+        probe(data).map(|info| (info.format, info.width, info.height, info.color, info.bit_depth))
+    }
+
+    #[test]
+    fn png_headers() {
+        assert_eq!(summary(&png(0)), Some((Format::Png, 640, 480, "grayscale", 8)));
+        assert_eq!(summary(&png(2)), Some((Format::Png, 640, 480, "RGB", 8)));
+        assert_eq!(summary(&png(3)), Some((Format::Png, 640, 480, "indexed", 8)));
+        assert_eq!(summary(&png(4)), Some((Format::Png, 640, 480, "grayscale+alpha", 8)));
+        assert_eq!(summary(&png(6)), Some((Format::Png, 640, 480, "RGBA", 8)));
+        assert_eq!(summary(&png(5)), None);
+        assert_eq!(summary(&png(6)[..20]), None);
+    }
+
+    #[test]
+    fn gif_headers() {
+        assert_eq!(summary(b"GIF89a\x40\x01\xf0\x00\xf7\0\0"), Some((Format::Gif, 320, 240, "indexed", 8)));
+        assert_eq!(summary(b"GIF87a\x0a\x00\x0a\x00\x80\0\0"), Some((Format::Gif, 10, 10, "indexed", 1)));
+        assert_eq!(summary(b"GIF89a\x0a\x00"), None);
+    }
+
+    #[test]
+    fn bmp_headers() {
+        assert_eq!(summary(&bmp(40, 100, -50, 24)), Some((Format::Bmp, 100, 50, "RGB", 24)));
+        assert_eq!(summary(&bmp(124, 100, 50, 32)), Some((Format::Bmp, 100, 50, "RGBA", 32)));
+        assert_eq!(summary(&bmp(108, 100, 50, 8)), Some((Format::Bmp, 100, 50, "indexed", 8)));
+        // OS/2 的信息头用 16 位存储尺寸。
+        assert_eq!(summary(&bmp(12, 300, 200, 4)), Some((Format::Bmp, 300, 200, "indexed", 4)));
+        assert_eq!(summary(&bmp(20, 100, 50, 24)), None);
+        assert_eq!(summary(&bmp(40, 100, 50, 7)), None);
+        assert_eq!(summary(&bmp(40, 100, 50, 24)[..40]), None);
+        let mut overlapping = bmp(40, 100, 50, 24);
+        overlapping[10] = 40;
+        assert_eq!(summary(&overlapping), None);
+        assert_eq!(summary(b"BM text"), None);
+    }
+
+    #[test]
+    fn jpeg_headers() {
+        // APP0 之后是 SOF2：精度 8 位，高 480，宽 640，3 个分量。
+        let mut jpeg = b"\xff\xd8\xff\xe0\x00\x10JFIF\0\x01\x01\0\0\x01\0\x01\0\0".to_vec();
+        jpeg.extend_from_slice(b"\xff\xc2\x00\x11\x08\x01\xe0\x02\x80\x03");
+        assert_eq!(summary(&jpeg), Some((Format::Jpeg, 640, 480, "YCbCr", 8)));
+        *jpeg.last_mut().unwrap() = 1;
+        assert_eq!(summary(&jpeg), Some((Format::Jpeg, 640, 480, "grayscale", 8)));
+        // DHT 不是帧头，扫描开始之前没有帧头的图像无法识别。
+        assert_eq!(summary(b"\xff\xd8\xff\xc4\x00\x04\x00\x00\xff\xda\x00\x02"), None);
+        assert_eq!(summary(b"\xff\xd8\xff\xe0\x00\x10JFIF"), None);
+    }
+
+    #[test]
+    fn webp_headers() {
+        let mut vp8 = b"\x30\x01\x00\x9d\x01\x2a".to_vec();
+        // 宽度和高度的高 2 位是缩放比例。
+        vp8.extend_from_slice(&(0x4000u16 | 400).to_le_bytes());
+        vp8.extend_from_slice(&300u16.to_le_bytes());
+        assert_eq!(summary(&webp(b"VP8 ", &vp8)), Some((Format::Webp, 400, 300, "YUV", 8)));
+        vp8[3] = 0;
+        assert_eq!(summary(&webp(b"VP8 ", &vp8)), None);
+
+        let bits = |alpha: u32| [&[0x2f][..], &(399 | 299 << 14 | alpha << 28u32).to_le_bytes()].concat();
+        assert_eq!(summary(&webp(b"VP8L", &bits(0))), Some((Format::Webp, 400, 300, "RGB", 8)));
+        assert_eq!(summary(&webp(b"VP8L", &bits(1))), Some((Format::Webp, 400, 300, "RGBA", 8)));
+        assert_eq!(summary(&webp(b"VP8L", &[0x2e, 0, 0, 0, 0])), None);
+
+        let extended = |flags: u8| [&[flags, 0, 0, 0][..], &[0x3f, 0x42, 0x0f], &[0x7f, 0x84, 0x1e]].concat();
+        assert_eq!(summary(&webp(b"VP8X", &extended(0x10))), Some((Format::Webp, 1_000_000, 2_000_000, "RGBA", 8)));
+        assert_eq!(summary(&webp(b"VP8X", &extended(0))), Some((Format::Webp, 1_000_000, 2_000_000, "RGB", 8)));
+        assert_eq!(summary(&webp(b"ALPH", &[0; 10])), None);
+    }
+}
@@ -0,0 +1,122 @@
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyByteArray, PyBytes};
+use crate::bitmap::Bitmap;
+use crate::{probe, render, ImageDisplay, Length, Protocol};
+
+create_exception!(imgcat, ImgcatError, PyException, "An image that couldn't be displayed.");
+
+/// Display an image inline in the terminal by writing to `sys.stdout`.
+///
+/// `image` is the encoded image as `bytes`, a PIL image, a matplotlib figure, or a
+/// numpy array of shape (height, width), (height, width, 3) or (height, width, 4)
+/// with integers from 0 to 255 or floats from 0 to 1. `width` and `height` take the
+/// forms of -W and -H, e.g. `40`, `"250px"` or `"50%"`.
+#[pyfunction]
+#[pyo3(signature = (image, width=None, height=None, stretch=false, protocol="auto", filename=None))]
+fn display(
+    py: Python<'_>,
+    image: &Bound<'_, PyAny>,
+    width: Option<&Bound<'_, PyAny>>,
+    height: Option<&Bound<'_, PyAny>>,
+    stretch: bool,
+    protocol: &str,
+    filename: Option<String>,
+) -> PyResult<()> {
+    let protocol: Protocol = protocol.parse().map_err(PyValueError::new_err)?;
+    let mut builder = ImageDisplay::builder().protocol(protocol).preserve_aspect(!stretch);
+    if let Some(width) = width {
+        builder = builder.width(length(width)?);
+    }
+    if let Some(height) = height {
+        builder = builder.height(length(height)?);
+    }
+    if let Some(filename) = filename {
+        builder = builder.filename(filename);
+    }
+    let mut data = encoded(py, image)?;
+    // 与命令行一样，终端不能解码的格式先转换成 PNG。
+    let formats = render::renderer(protocol).capabilities().formats;
+    if probe::probe(&data).is_some_and(|info| !formats.contains(&info.format)) {
+        data = Bitmap::decode(&data).map_err(|e| ImgcatError::new_err(e.to_string()))?.to_png();
+    }
+    let mut out = Vec::new();
+    builder.build()
+        .display_sized(&mut out, &mut data.as_slice(), Some(data.len() as u64))
+        .map_err(|e| ImgcatError::new_err(e.to_string()))?;
+    // 转义序列只含 ASCII 和半块字符，作为 str 写入，这样 IPython 替换的 sys.stdout 也能收到。
+    let stdout = py.import("sys")?.getattr("stdout")?;
+    stdout.call_method1("write", (String::from_utf8_lossy(&out),))?;
+    stdout.call_method0("flush")?;
+    Ok(())
+}
+
+/// A width or height given as a number of cells or a string in the form of -W.
+fn length(value: &Bound<'_, PyAny>) -> PyResult<Length> {
+    match value.extract::<u32>() {
+        Ok(cells) => format!("{cells}").parse(),
+        Err(_) => value.extract::<String>()?.parse(),
+    }
+    .map_err(PyValueError::new_err)
+}
+
+/// The bytes of the encoded image, converting PIL images, matplotlib figures and numpy arrays to PNG.
+fn encoded(py: Python<'_>, image: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    if let Ok(bytes) = image.downcast::<PyBytes>() {
+        return Ok(bytes.as_bytes().to_vec());
+    }
+    if let Ok(bytes) = image.downcast::<PyByteArray>() {
+        return Ok(bytes.to_vec());
+    }
+    if image.hasattr("dtype")? && image.hasattr("shape")? {
+        return Ok(array_bitmap(image)?.to_png());
+    }
+    // PIL 的 Image 有 save，matplotlib 的 Figure 有 savefig，都能写到 BytesIO 中。
+    for method in ["savefig", "save"] {
+        if image.hasattr(method)? {
+            let buffer = py.import("io")?.call_method0("BytesIO")?;
+            let kwargs = [("format", "png")].into_py_dict(py)?;
+            image.call_method(method, (&buffer,), Some(&kwargs))?;
+            let png = buffer.call_method0("getvalue")?;
+            return Ok(png.downcast::<PyBytes>()?.as_bytes().to_vec());
+        }
+    }
+    Err(PyTypeError::new_err("expected bytes, a PIL image, a matplotlib figure or a numpy array"))
+}
+
+/// The pixels of a numpy array of shape (height, width) or (height, width, channels).
+fn array_bitmap(array: &Bound<'_, PyAny>) -> PyResult<Bitmap> {
+    let array = match array.getattr("dtype")?.getattr("kind")?.extract::<String>()?.as_str() {
+        "f" => array.call_method1("clip", (0.0, 1.0))?.call_method1("__mul__", (255.0,))?,
+        _ => array.call_method1("clip", (0, 255))?,
+    };
+    let shape: Vec<usize> = array.getattr("shape")?.extract()?;
+    let (height, width, channels) = match shape[..] {
+        [height, width] => (height, width, 1),
+        [height, width, channels @ 1..=4] => (height, width, channels),
+        _ => return Err(PyValueError::new_err(format!("unsupported array shape {shape:?}"))),
+    };
+    let pixels = array.call_method1("astype", ("uint8",))?.call_method0("tobytes")?;
+    let pixels = pixels.downcast::<PyBytes>()?.as_bytes();
+    let mut bitmap = Bitmap::new(width as u32, height as u32);
+    for (i, pixel) in pixels.chunks(channels).enumerate() {
+        let rgba = match *pixel {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        };
+        bitmap.set(i % width, i / width, rgba);
+    }
+    Ok(bitmap)
+}
+
+/// The `imgcat` Python module.
+#[pymodule]
+fn imgcat(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(display, m)?)?;
+    m.add("ImgcatError", m.py().get_type::<ImgcatError>())?;
+    Ok(())
+}
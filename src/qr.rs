@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+use anyhow::bail;
+use clap::ValueEnum;
+use qrcode::{Color, EcLevel};
+use crate::bitmap::Bitmap;
+use crate::i18n::tr;
+
+/// Width of the light border around the code, in modules, as the standard requires.
+const QUIET_ZONE: usize = 4;
+
+/// Error correction level of a QR code: the share of the code that can be damaged.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// 7%
+    L,
+    /// 15%
+    M,
+    /// 25%
+    Q,
+    /// 30%
+    H,
+}
+
+impl From<Level> for EcLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::L => EcLevel::L,
+            Level::M => EcLevel::M,
+            Level::Q => EcLevel::Q,
+            Level::H => EcLevel::H,
+        }
+    }
+}
+
+/// A QR code, as its square of dark and light modules.
+pub struct QrCode {
+    pub size: usize,
+    code: qrcode::QrCode,
+}
+
+impl QrCode {
+    /// Encode `data` in the smallest version that holds it at `level`.
+    pub fn encode(data: &[u8], level: Level) -> anyhow::Result<Self> {
+        let Ok(code) = qrcode::QrCode::with_error_correction_level(data, level.into()) else {
+            bail!(tr!("{} bytes are too many for a QR code at level {}", data.len(), format!("{level:?}")));
+        };
+        Ok(QrCode {size: code.width(), code})
+    }
+
+    /// Whether the module at column `x` and row `y` is dark.
+    pub fn dark(&self, x: usize, y: usize) -> bool {
+        self.code[(x, y)] == Color::Dark
+    }
+
+    /// The code as a black and white image with its quiet zone, at most `max_side` pixels
+    /// wide unless that's less than a pixel per module, with as many whole pixels per module
+    /// as fit, so that the modules stay sharp.
+    pub fn to_bitmap(&self, max_side: usize) -> Bitmap {
+        let scale = (max_side / (self.size + QUIET_ZONE * 2)).max(1);
+        let side = (self.size + QUIET_ZONE * 2) * scale;
+        let mut bitmap = Bitmap::new(side as u32, side as u32);
+        for y in 0..side {
+            for x in 0..side {
+                let dark = self.module_with_quiet_zone(x / scale, y / scale);
+                bitmap.set(x, y, if dark { [0, 0, 0, 255] } else { [255, 255, 255, 255] });
+            }
+        }
+        bitmap
+    }
+
+    /// Write the code with its quiet zone as lines of half blocks, two rows of modules a
+    /// line, in black and white whatever the colors of the terminal, so that it scans
+    /// where images can't be shown.
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        let side = self.size + QUIET_ZONE * 2;
+        for y in (0..side).step_by(2) {
+            for x in 0..side {
+                let color = |dark| if dark { "0;0;0" } else { "255;255;255" };
+                let top = color(self.module_with_quiet_zone(x, y));
+                let bottom = color(y + 1 < side && self.module_with_quiet_zone(x, y + 1));
+                write!(out, "\x1b[38;2;{top};48;2;{bottom}m▀")?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+
+    fn module_with_quiet_zone(&self, x: usize, y: usize) -> bool {
+        let inside = |n: usize| (QUIET_ZONE..QUIET_ZONE + self.size).contains(&n);
+        inside(x) && inside(y) && self.dark(x - QUIET_ZONE, y - QUIET_ZONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The level and mask of the code, from the format information next to the top left
+    /// finder, checked against its copy next to the other two.
+    fn format(code: &QrCode) -> (u32, u32) {
+        let size = code.size;
+        let mut first = [(8, 0), (8, 1), (8, 2), (8, 3), (8, 4), (8, 5), (8, 7), (8, 8), (7, 8)].to_vec();
+        first.extend((9..15).map(|i| (14 - i, 8)));
+        let mut second: Vec<(usize, usize)> = (0..8).map(|i| (size - 1 - i, 8)).collect();
+        second.extend((8..15).map(|i| (8, size - 15 + i)));
+        let read = |positions: &[(usize, usize)]| {
+            positions.iter().enumerate().fold(0, |bits, (i, &(x, y))| bits | (code.dark(x, y) as u32) << i) ^ 0x5412
+        };
+        let bits = read(&first);
+        assert_eq!(bits, read(&second));
+        (bits >> 13, bits >> 10 & 7)
+    }
+
+    #[test]
+    fn levels() {
+        // 格式信息中 L、M、Q、H 分别记为 1、0、3、2。
+        for (level, bits) in [(Level::L, 1), (Level::M, 0), (Level::Q, 3), (Level::H, 2)] {
+            assert_eq!(format(&QrCode::encode(b"https://imgcat", level).unwrap()).0, bits, "{level:?}");
+        }
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(QrCode::encode(b"", Level::H).unwrap().size, 21);
+        // 版本 1 的 L 级最多 17 字节。
+        assert_eq!(QrCode::encode(&[0; 17], Level::L).unwrap().size, 21);
+        assert_eq!(QrCode::encode(&[0; 18], Level::L).unwrap().size, 25);
+        // 版本 40 的 L 级最多 2953 字节。
+        let code = QrCode::encode(&[7; 2953], Level::L).unwrap();
+        assert_eq!(code.size, 177);
+        assert_eq!(format(&code).0, 1);
+        assert!(QrCode::encode(&[7; 2954], Level::L).is_err());
+    }
+
+    #[test]
+    fn images_and_text() {
+        let code = QrCode::encode(b"imgcat", Level::Q).unwrap();
+        // 21 个模块加两边各 4 个的静区，每个模块 3 个像素。
+        let bitmap = code.to_bitmap(100);
+        assert_eq!((bitmap.width, bitmap.height), (87, 87));
+        assert_eq!(bitmap.get(11, 11), [255, 255, 255, 255]);
+        assert_eq!(bitmap.get(12, 12), [0, 0, 0, 255]);
+        assert_eq!(code.to_bitmap(10).width, 29);
+        let mut text = Vec::new();
+        code.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(text.lines().count(), 15);
+        assert!(text.lines().all(|line| line.matches('▀').count() == 29));
+        // 第 3 行的上半是静区，下半是定位图案的上边。
+        let line = text.lines().nth(2).unwrap();
+        assert!(line.contains("\x1b[38;2;255;255;255;48;2;0;0;0m▀"));
+    }
+}
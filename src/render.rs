@@ -0,0 +1,1111 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use crate::bitmap::{Animation, Bitmap, Frame};
+use crate::probe::{self, Format};
+use crate::fbdev::Framebuffer;
+use crate::terminal::ColorDepth;
+use crate::{ansi, layout, terminal, Options};
+
+/// Number of base64 characters sent in each escape sequence of the kitty protocol.
+const KITTY_CHUNK_LEN: usize = 4096;
+
+/// Clears the screen and moves the cursor to its top left corner.
+const CLEAR_SCREEN: &[u8] = b"\x1b[H\x1b[2J";
+
+/// What a [`Renderer`] can draw, so that callers prepare images accordingly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// formats of the images drawn as they are; images in other formats have to be converted first
+    pub formats: Vec<Format>,
+    /// whether an image can be sent while it is read, before it is complete
+    pub streaming: bool,
+    /// whether the escape sequences are wrapped to pass through tmux when running inside it
+    pub passthrough: bool,
+}
+
+/// A protocol images are drawn with.
+///
+/// The renderers of imgcat are selected with [`renderer`]; other crates may implement
+/// this trait to draw images on terminals with protocols of their own.
+pub trait Renderer {
+    /// Name of the protocol, e.g. `iterm2`.
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Write what draws the image read from `source` at the cursor position to `out`,
+    /// and return the number of bytes read from `source`.
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64>;
+
+    /// Decode and scale the image `data` as [`emit`](Self::emit) would, keeping the bitmaps
+    /// in the cache of the options, so that drawing it later only encodes them. Does nothing
+    /// for the protocols whose terminals scale the images themselves.
+    fn prepare(&self, _data: &[u8], _options: &Options) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Clear the screen, along with the images drawn on it.
+    fn clear(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(CLEAR_SCREEN)
+    }
+
+    /// Erase the image drawn over `columns` by `rows` cells from column `x` and row `y`,
+    /// counted from 0, and leave the cursor where it was.
+    fn erase(&self, out: &mut dyn Write, x: u16, y: u16, columns: u16, rows: u16) -> io::Result<()> {
+        erase_cells(out, x, y, columns, rows)
+    }
+}
+
+/// Overwrite a rectangle of cells with spaces, saving and restoring the cursor.
+fn erase_cells(out: &mut dyn Write, x: u16, y: u16, columns: u16, rows: u16) -> io::Result<()> {
+    out.write_all(b"\x1b7\x1b[0m")?;
+    for row in y..y.saturating_add(rows) {
+        write!(out, "\x1b[{};{}H{:columns$}", row + 1, x + 1, "", columns = columns as usize)?;
+    }
+    out.write_all(b"\x1b8")
+}
+
+/// The protocols of the renderers of imgcat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// kitty's protocol in terminals known to implement it, iTerm2's otherwise
+    Auto,
+    /// iTerm2's Inline Images Protocol
+    Iterm2,
+    /// kitty's graphics protocol
+    Kitty,
+    /// DEC sixel graphics
+    Sixel,
+    /// DEC ReGIS graphics, for the VT340 and terminals emulating it
+    Regis,
+    /// the Linux framebuffer, for consoles without a display server
+    Fbdev,
+    /// half blocks colored with truecolor escape sequences, for terminals without graphics
+    Blocks,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 7] = [
+        Protocol::Auto,
+        Protocol::Iterm2,
+        Protocol::Kitty,
+        Protocol::Sixel,
+        Protocol::Regis,
+        Protocol::Fbdev,
+        Protocol::Blocks,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Auto => "auto",
+            Protocol::Iterm2 => "iterm2",
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+            Protocol::Regis => "regis",
+            Protocol::Fbdev => "fbdev",
+            Protocol::Blocks => "blocks",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Protocol::ALL
+            .into_iter()
+            .find(|p| p.name() == value)
+            .ok_or_else(|| format!("unknown protocol {value}, expected auto, iterm2, kitty, sixel, regis, fbdev or blocks"))
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Characters the renderers drawing with text, [`Blocks`] and [`blocks_text`], draw images with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Symbols {
+    /// upper and lower half blocks, two pixels a cell in their own colors
+    #[default]
+    Blocks,
+    /// ASCII characters by the brightness of the cell, which copy and paste anywhere
+    Ascii,
+    /// braille patterns, eight dots a cell in one color over the background of the terminal
+    Braille,
+    /// quadrant blocks, four pixels a cell in two colors
+    Geometric,
+    /// the given characters by the brightness of the cell, from the darkest to the brightest
+    Custom(Vec<char>),
+}
+
+impl Symbols {
+    /// Width and height of a cell in the pixels the characters draw.
+    fn cell(&self) -> (usize, usize) {
+        match self {
+            Symbols::Blocks => (1, 2),
+            Symbols::Ascii | Symbols::Custom(_) => (1, 1),
+            Symbols::Braille => (2, 4),
+            Symbols::Geometric => (2, 2),
+        }
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "blocks" => Ok(Symbols::Blocks),
+            "ascii" => Ok(Symbols::Ascii),
+            "braille" => Ok(Symbols::Braille),
+            "geometric" => Ok(Symbols::Geometric),
+            _ => match value.strip_prefix("custom:") {
+                Some(chars) if !chars.is_empty() => Ok(Symbols::Custom(chars.chars().collect())),
+                Some(_) => Err("custom: needs the characters to draw with, e.g. custom:' .oO@'".to_string()),
+                None => Err(format!("unknown symbols {value}, expected blocks, ascii, braille, geometric or custom:CHARS")),
+            },
+        }
+    }
+}
+
+/// The renderer drawing images with `protocol`, for images in a format [`Bitmap::decode`] decodes.
+pub fn renderer(protocol: Protocol) -> Box<dyn Renderer> {
+    renderer_for(protocol, Some(Format::Png))
+}
+
+/// The renderer drawing an image in `format`, `None` when it isn't recognized, with `protocol`.
+/// In the terminals where [`Protocol::Auto`] picks kitty's protocol only for the images it
+/// can convert, the others are sent with iTerm2's protocol, which the terminal may take.
+pub fn renderer_for(protocol: Protocol, format: Option<Format>) -> Box<dyn Renderer> {
+    // Bitmap::decode 能解码 probe 识别的所有格式。
+    let decodable = format.is_some();
+    match protocol {
+        Protocol::Auto if terminal::host() == Some(terminal::Host::MidnightCommander) && decodable => Box::new(Blocks),
+        // WezTerm 默认不启用 kitty 的协议，启用后也不播放动画，而 iTerm2 的协议可以播放 GIF、APNG 和 WebP 动画。
+        Protocol::Auto if terminal::wezterm() => Box::new(ITerm2),
+        Protocol::Auto if kitty_terminal() => Box::new(Kitty),
+        // Konsole 也实现了 iTerm2 的协议，无法转换为 PNG 的图片交给它自己解码。
+        Protocol::Auto if terminal::konsole() && decodable => Box::new(Kitty),
+        // Windows Terminal 和 ConEmu 不显示 iTerm2 协议的图片，只能画出解码后的像素。
+        Protocol::Auto if terminal::windows_terminal() && decodable => Box::new(Sixel),
+        Protocol::Auto if terminal::conemu() && decodable => Box::new(Blocks),
+        Protocol::Auto | Protocol::Iterm2 => Box::new(ITerm2),
+        Protocol::Kitty => Box::new(Kitty),
+        Protocol::Sixel => Box::new(Sixel),
+        Protocol::Regis => Box::new(Regis),
+        Protocol::Fbdev => Box::new(Fbdev),
+        Protocol::Blocks => Box::new(Blocks),
+    }
+}
+
+/// Whether [`Protocol::Auto`] draws images in `format`, `None` when it isn't recognized: it doesn't
+/// where it only draws decoded pixels, as in the subshell of Midnight Commander, which escape
+/// sequences of images don't pass through, in Windows Terminal and in ConEmu, and the image
+/// can't be decoded.
+pub fn auto_draws(format: Option<Format>) -> bool {
+    format.is_some()
+        || terminal::host() != Some(terminal::Host::MidnightCommander) && !terminal::windows_terminal() && !terminal::conemu()
+}
+
+/// Whether the environment tells that the terminal implements kitty's graphics protocol.
+fn kitty_terminal() -> bool {
+    // 从 kitty 中启动的 VS Code 也继承了 KITTY_WINDOW_ID，但它的终端不支持 kitty 的协议。
+    if terminal::host() == Some(terminal::Host::VsCode) {
+        return false;
+    }
+    // tmux 中 TERM 被改写，但 KITTY_WINDOW_ID 会被继承下来。
+    terminal::var("TERM").is_ok_and(|t| t == "xterm-kitty" || t == "xterm-ghostty")
+        || terminal::var("TERM_PROGRAM").is_ok_and(|p| p == "ghostty")
+        || terminal::var_os("KITTY_WINDOW_ID").is_some()
+}
+
+/// iTerm2's Inline Images Protocol, written by [`crate::display`], or in WezTerm as
+/// `wezterm imgcat` writes it.
+pub struct ITerm2;
+
+impl Renderer for ITerm2 {
+    fn name(&self) -> &'static str {
+        "iterm2"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: Format::ALL.into_iter().filter(|f| terminal::accepts(*f)).collect(),
+            streaming: true,
+            passthrough: true,
+        }
+    }
+
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        if terminal::wezterm() {
+            return crate::display_wezterm(out, source, options);
+        }
+        Ok(crate::display(out, source, options)?)
+    }
+}
+
+/// kitty's graphics protocol. Only PNG images are sent, images in other formats being
+/// decoded and converted first, in chunks, and placed over the number of cells they
+/// take. The frames of APNG images are sent one by one and played by the terminal.
+/// Konsole, which implements part of the protocol, gets the whole image in one
+/// sequence, and only the first frame of APNG images.
+pub struct Kitty;
+
+impl Renderer for Kitty {
+    fn name(&self) -> &'static str {
+        "kitty"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {formats: vec![Format::Png], streaming: false, passthrough: true}
+    }
+
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        let mut data = Vec::new();
+        let read = source.read_to_end(&mut data)? as u64;
+        let data = kitty_png(data)?;
+        // q=2 让终端不回复，回复会被当作键盘输入。
+        let mut control = "a=T,f=100,q=2".to_string();
+        let size = terminal::size();
+        let cells = layout::display_cells(&data, options.width, options.height, options.preserve_aspect_ratio, &size);
+        if let Some((columns, rows)) = cells {
+            control += &format!(",c={columns},r={rows}");
+        }
+        // Konsole 只实现了 kitty 协议的一部分：动画的帧会被当作新的图片显示，分块传输也与 kitty 不同，
+        // 所以只发送第一帧，并且整张图片放在一个序列中。
+        if terminal::konsole() {
+            write_apc(out, &format!("{control},m=0"), BASE64_STANDARD.encode(&data).as_bytes())?;
+            return Ok(read);
+        }
+        match animation(&data) {
+            Some(animation) => transmit_animation(out, &animation, &control, animation_id())?,
+            None => transmit(out, &data, &control)?,
+        }
+        Ok(read)
+    }
+
+    fn clear(&self, out: &mut dyn Write) -> io::Result<()> {
+        // 图片不随文字一起清除，需要单独删除。
+        write_apc(out, "a=d,q=2", b"")?;
+        out.write_all(CLEAR_SCREEN)
+    }
+
+    fn erase(&self, out: &mut dyn Write, x: u16, y: u16, columns: u16, rows: u16) -> io::Result<()> {
+        // d=p 删除覆盖左上角那一格的图片，坐标从 1 开始。
+        write_apc(out, &format!("a=d,d=p,x={},y={},q=2", x + 1, y + 1), b"")?;
+        erase_cells(out, x, y, columns, rows)
+    }
+}
+
+/// `data` as a PNG image, the only format sent with kitty's protocol: other images are
+/// decoded and encoded as PNG, and those that can't be decoded are an error, since the
+/// terminal would draw nothing, and, asked not to answer, wouldn't tell.
+fn kitty_png(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    if probe::probe(&data).is_some_and(|info| info.format == Format::Png) {
+        return Ok(data);
+    }
+    let bitmap = Bitmap::decode(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(bitmap.to_png())
+}
+
+/// Send the PNG image `data` to the terminal with kitty's protocol, in chunks, the first
+/// one carrying `control`.
+fn transmit(out: &mut dyn Write, data: &[u8], control: &str) -> io::Result<()> {
+    let payload = BASE64_STANDARD.encode(data);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK_LEN).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            write_apc(out, &format!("{control},m={more}"), chunk)?;
+        } else {
+            write_apc(out, &format!("m={more}"), chunk)?;
+        }
+    }
+    Ok(())
+}
+
+/// The frames of `data` if it is an APNG image, which kitty would only show the first
+/// frame of, and that can be decoded.
+fn animation(data: &[u8]) -> Option<Animation> {
+    let info = probe::probe(data).filter(|info| info.format == Format::Png)?;
+    if probe::frame_count(data, info.format)? < 2 {
+        return None;
+    }
+    Animation::decode(data).ok()
+}
+
+/// Send the frames of `animation` as image `id` with kitty's animation protocol, the
+/// first one carrying `control`, and start playing them.
+fn transmit_animation(out: &mut dyn Write, animation: &Animation, control: &str, id: u32) -> io::Result<()> {
+    // 时长为 0 会被忽略，至少取 1 毫秒。
+    let gap = |frame: &Frame| frame.delay.as_millis().max(1);
+    let (first, rest) = animation.frames.split_first().expect("an animation has frames");
+    transmit(out, &first.bitmap.to_png(), &format!("{control},i={id}"))?;
+    // 第一帧随图片一起创建，它的时长要单独设置。
+    write_apc(out, &format!("a=a,i={id},r=1,z={},q=2", gap(first)), b"")?;
+    for frame in rest {
+        // 每一帧都已合成为完整的画面，X=1 直接替换画布。
+        transmit(out, &frame.bitmap.to_png(), &format!("a=f,i={id},f=100,X=1,z={},q=2", gap(frame)))?;
+    }
+    // v=1 无限循环，v=n 播放 n-1 次。
+    let loops = if animation.plays == 0 { 1 } else { animation.plays + 1 };
+    write_apc(out, &format!("a=a,i={id},s=3,v={loops},q=2"), b"")
+}
+
+/// An id for an animated image, which frames are added to by id, unlikely to replace the
+/// images of other programs or of other runs of imgcat.
+fn animation_id() -> u32 {
+    static COUNT: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    nanos.wrapping_add(COUNT.fetch_add(1, Ordering::Relaxed).wrapping_mul(0x9e37_79b9)).max(1)
+}
+
+/// Send the image read from `source`, converted to PNG, to the terminal on `image_out` with
+/// kitty's protocol as image `id`, without placing it, and write to `text_out` the lines of
+/// Unicode placeholders showing it, for programs that only pass text through, such as
+/// Yazi and tmux. Images are placed over at most 297 by 297 cells.
+pub fn kitty_placeholders(image_out: &mut dyn Write, text_out: &mut dyn Write, source: &mut dyn Read, options: &Options, id: u32) -> io::Result<u64> {
+    let mut data = Vec::new();
+    let read = source.read_to_end(&mut data)? as u64;
+    let data = kitty_png(data)?;
+    let size = terminal::size();
+    let (columns, rows) = layout::display_cells(&data, options.width, options.height, options.preserve_aspect_ratio, &size)
+        .unwrap_or((1, 1));
+    let (columns, rows) = (columns.min(DIACRITICS.len() as u16), rows.min(DIACRITICS.len() as u16));
+    // U=1 创建虚拟位置，图片只显示在占位字符所在的格子上。
+    let control = format!("a=T,f=100,U=1,c={columns},r={rows},q=2");
+    match animation(&data) {
+        Some(animation) => transmit_animation(image_out, &animation, &control, id)?,
+        None => transmit(image_out, &data, &format!("{control},i={id}"))?,
+    }
+    image_out.flush()?;
+    // 图片编号写在占位字符的前景色里。
+    let color = format!("\x1b[38;2;{};{};{}m", id >> 16 & 0xff, id >> 8 & 0xff, id & 0xff);
+    for &row in &DIACRITICS[..rows as usize] {
+        let mut line = color.clone();
+        for &column in &DIACRITICS[..columns as usize] {
+            line.extend([PLACEHOLDER, row, column]);
+        }
+        line += "\x1b[39m\n";
+        text_out.write_all(line.as_bytes())?;
+    }
+    Ok(read)
+}
+
+/// Delete the image sent by [`kitty_placeholders`] as `id`, which frees the terminal's
+/// memory and blanks its placeholders.
+pub fn delete_kitty_image(out: &mut dyn Write, id: u32) -> io::Result<()> {
+    write_apc(out, &format!("a=d,d=I,i={id},q=2"), b"")?;
+    out.flush()
+}
+
+/// The character kitty draws the image given by its foreground color on.
+const PLACEHOLDER: char = '\u{10eeee}';
+
+/// The combining characters following [`PLACEHOLDER`] that give the row and column of
+/// the image it shows, in the order kitty numbers them.
+const DIACRITICS: [char; 297] = [
+    '\u{0305}', '\u{030d}', '\u{030e}', '\u{0310}', '\u{0312}', '\u{033d}', '\u{033e}', '\u{033f}', '\u{0346}', '\u{034a}',
+    '\u{034b}', '\u{034c}', '\u{0350}', '\u{0351}', '\u{0352}', '\u{0357}', '\u{035b}', '\u{0363}', '\u{0364}', '\u{0365}',
+    '\u{0366}', '\u{0367}', '\u{0368}', '\u{0369}', '\u{036a}', '\u{036b}', '\u{036c}', '\u{036d}', '\u{036e}', '\u{036f}',
+    '\u{0483}', '\u{0484}', '\u{0485}', '\u{0486}', '\u{0487}', '\u{0592}', '\u{0593}', '\u{0594}', '\u{0595}', '\u{0597}',
+    '\u{0598}', '\u{0599}', '\u{059c}', '\u{059d}', '\u{059e}', '\u{059f}', '\u{05a0}', '\u{05a1}', '\u{05a8}', '\u{05a9}',
+    '\u{05ab}', '\u{05ac}', '\u{05af}', '\u{05c4}', '\u{0610}', '\u{0611}', '\u{0612}', '\u{0613}', '\u{0614}', '\u{0615}',
+    '\u{0616}', '\u{0617}', '\u{0657}', '\u{0658}', '\u{0659}', '\u{065a}', '\u{065b}', '\u{065d}', '\u{065e}', '\u{06d6}',
+    '\u{06d7}', '\u{06d8}', '\u{06d9}', '\u{06da}', '\u{06db}', '\u{06dc}', '\u{06df}', '\u{06e0}', '\u{06e1}', '\u{06e2}',
+    '\u{06e4}', '\u{06e7}', '\u{06e8}', '\u{06eb}', '\u{06ec}', '\u{0730}', '\u{0732}', '\u{0733}', '\u{0735}', '\u{0736}',
+    '\u{073a}', '\u{073d}', '\u{073f}', '\u{0740}', '\u{0741}', '\u{0743}', '\u{0745}', '\u{0747}', '\u{0749}', '\u{074a}',
+    '\u{07eb}', '\u{07ec}', '\u{07ed}', '\u{07ee}', '\u{07ef}', '\u{07f0}', '\u{07f1}', '\u{07f3}', '\u{0816}', '\u{0817}',
+    '\u{0818}', '\u{0819}', '\u{081b}', '\u{081c}', '\u{081d}', '\u{081e}', '\u{081f}', '\u{0820}', '\u{0821}', '\u{0822}',
+    '\u{0823}', '\u{0825}', '\u{0826}', '\u{0827}', '\u{0829}', '\u{082a}', '\u{082b}', '\u{082c}', '\u{082d}', '\u{0951}',
+    '\u{0953}', '\u{0954}', '\u{0f82}', '\u{0f83}', '\u{0f86}', '\u{0f87}', '\u{135d}', '\u{135e}', '\u{135f}', '\u{17dd}',
+    '\u{193a}', '\u{1a17}', '\u{1a75}', '\u{1a76}', '\u{1a77}', '\u{1a78}', '\u{1a79}', '\u{1a7a}', '\u{1a7b}', '\u{1a7c}',
+    '\u{1b6b}', '\u{1b6d}', '\u{1b6e}', '\u{1b6f}', '\u{1b70}', '\u{1b71}', '\u{1b72}', '\u{1b73}', '\u{1cd0}', '\u{1cd1}',
+    '\u{1cd2}', '\u{1cda}', '\u{1cdb}', '\u{1ce0}', '\u{1dc0}', '\u{1dc1}', '\u{1dc3}', '\u{1dc4}', '\u{1dc5}', '\u{1dc6}',
+    '\u{1dc7}', '\u{1dc8}', '\u{1dc9}', '\u{1dcb}', '\u{1dcc}', '\u{1dd1}', '\u{1dd2}', '\u{1dd3}', '\u{1dd4}', '\u{1dd5}',
+    '\u{1dd6}', '\u{1dd7}', '\u{1dd8}', '\u{1dd9}', '\u{1dda}', '\u{1ddb}', '\u{1ddc}', '\u{1ddd}', '\u{1dde}', '\u{1ddf}',
+    '\u{1de0}', '\u{1de1}', '\u{1de2}', '\u{1de3}', '\u{1de4}', '\u{1de5}', '\u{1de6}', '\u{1dfe}', '\u{20d0}', '\u{20d1}',
+    '\u{20d4}', '\u{20d5}', '\u{20d6}', '\u{20d7}', '\u{20db}', '\u{20dc}', '\u{20e1}', '\u{20e7}', '\u{20e9}', '\u{20f0}',
+    '\u{2cef}', '\u{2cf0}', '\u{2cf1}', '\u{2de0}', '\u{2de1}', '\u{2de2}', '\u{2de3}', '\u{2de4}', '\u{2de5}', '\u{2de6}',
+    '\u{2de7}', '\u{2de8}', '\u{2de9}', '\u{2dea}', '\u{2deb}', '\u{2dec}', '\u{2ded}', '\u{2dee}', '\u{2def}', '\u{2df0}',
+    '\u{2df1}', '\u{2df2}', '\u{2df3}', '\u{2df4}', '\u{2df5}', '\u{2df6}', '\u{2df7}', '\u{2df8}', '\u{2df9}', '\u{2dfa}',
+    '\u{2dfb}', '\u{2dfc}', '\u{2dfd}', '\u{2dfe}', '\u{2dff}', '\u{a66f}', '\u{a67c}', '\u{a67d}', '\u{a6f0}', '\u{a6f1}',
+    '\u{a8e0}', '\u{a8e1}', '\u{a8e2}', '\u{a8e3}', '\u{a8e4}', '\u{a8e5}', '\u{a8e6}', '\u{a8e7}', '\u{a8e8}', '\u{a8e9}',
+    '\u{a8ea}', '\u{a8eb}', '\u{a8ec}', '\u{a8ed}', '\u{a8ee}', '\u{a8ef}', '\u{a8f0}', '\u{a8f1}', '\u{aab0}', '\u{aab2}',
+    '\u{aab3}', '\u{aab7}', '\u{aab8}', '\u{aabe}', '\u{aabf}', '\u{aac1}', '\u{fe20}', '\u{fe21}', '\u{fe22}', '\u{fe23}',
+    '\u{fe24}', '\u{fe25}', '\u{fe26}', '\u{10a0f}', '\u{10a38}', '\u{1d185}', '\u{1d186}', '\u{1d187}', '\u{1d188}', '\u{1d189}',
+    '\u{1d1aa}', '\u{1d1ab}', '\u{1d1ac}', '\u{1d1ad}', '\u{1d242}', '\u{1d243}', '\u{1d244}',
+];
+
+/// Write a kitty graphics command, wrapped for tmux when running inside it.
+fn write_apc(out: &mut dyn Write, control: &str, payload: &[u8]) -> io::Result<()> {
+    if crate::in_tmux() {
+        write!(out, "\x1bPtmux;\x1b\x1b_G{control};")?;
+        out.write_all(payload)?;
+        return out.write_all(b"\x1b\x1b\\\x1b\\");
+    }
+    write!(out, "\x1b_G{control};")?;
+    out.write_all(payload)?;
+    out.write_all(b"\x1b\\")
+}
+
+/// Size of a character cell in sixel pixels in Windows Terminal, which scales sixel
+/// images as a VT340 would draw them, whatever the size of its font.
+const WINDOWS_TERMINAL_SIXEL_CELL: (f64, f64) = (10.0, 20.0);
+
+/// DEC sixel graphics, with the colors reduced to a 6x6x6 cube, which takes 216 of the
+/// 256 color registers of xterm and Windows Terminal.
+pub struct Sixel;
+
+impl Renderer for Sixel {
+    fn name(&self) -> &'static str {
+        "sixel"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {formats: Format::ALL.to_vec(), streaming: false, passthrough: false}
+    }
+
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        let (size, cell) = sixel_cell();
+        let (bitmap, read) = scaled(source, options, &size, cell)?;
+        let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+        let colors: Vec<Option<u8>> = bitmap.pixels.chunks(4).map(cube_index).collect();
+        // P2=1：没有画的像素保持透明。
+        write!(out, "\x1bP0;1;0q\"1;1;{width};{height}")?;
+        let mut used = [false; 216];
+        colors.iter().flatten().for_each(|c| used[*c as usize] = true);
+        for c in (0..216).filter(|c| used[*c]) {
+            let [r, g, b] = [c / 36, c / 6 % 6, c % 6].map(|level| level * 20);
+            write!(out, "#{c};2;{r};{g};{b}")?;
+        }
+        for top in (0..height).step_by(6) {
+            let band = top..(top + 6).min(height);
+            let mut band_used = [false; 216];
+            for y in band.clone() {
+                colors[y * width..(y + 1) * width].iter().flatten().for_each(|c| band_used[*c as usize] = true);
+            }
+            for c in (0..216).filter(|c| band_used[*c]) {
+                let sixels: Vec<u8> = (0..width)
+                    .map(|x| {
+                        let bits = band.clone()
+                            .filter(|y| colors[y * width + x] == Some(c as u8))
+                            .fold(0, |bits, y| bits | 1 << (y - top));
+                        b'?' + bits
+                    })
+                    .collect();
+                write!(out, "#{c}")?;
+                write_runs(out, &sixels)?;
+                // $ 回到本条带的开头，用下一种颜色继续画。
+                out.write_all(b"$")?;
+            }
+            out.write_all(b"-")?;
+        }
+        out.write_all(b"\x1b\\")?;
+        Ok(read)
+    }
+
+    fn prepare(&self, data: &[u8], options: &Options) -> io::Result<()> {
+        let (size, cell) = sixel_cell();
+        scaled(&mut &data[..], options, &size, cell).map(drop)
+    }
+}
+
+/// The size of the terminal, and that of a character cell in sixel pixels.
+fn sixel_cell() -> (terminal::Size, (f64, f64)) {
+    let size = terminal::size();
+    let cell = match terminal::windows_terminal() {
+        true => WINDOWS_TERMINAL_SIXEL_CELL,
+        false => size.cell_size(),
+    };
+    (size, cell)
+}
+
+/// The register of the color of an RGBA pixel in the 6x6x6 cube, `None` if it is transparent.
+fn cube_index(pixel: &[u8]) -> Option<u8> {
+    if pixel[3] < 128 {
+        return None;
+    }
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    Some((level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])) as u8)
+}
+
+/// Write sixel characters, with runs of the same one compressed.
+fn write_runs(out: &mut dyn Write, sixels: &[u8]) -> io::Result<()> {
+    for run in sixels.chunk_by(|a, b| a == b) {
+        if run.len() > 3 {
+            write!(out, "!{}{}", run.len(), run[0] as char)?;
+        } else {
+            out.write_all(run)?;
+        }
+    }
+    Ok(())
+}
+
+/// Size of a character cell of the VT340 in the pixels ReGIS addresses, on its screen of
+/// 800x480 pixels showing 80x24 cells.
+const REGIS_CELL: (f64, f64) = (10.0, 20.0);
+
+/// Number of color registers of the VT340, the first of which holds the background.
+const REGIS_REGISTERS: usize = 16;
+
+/// Timeout of the query of the cursor position, which ReGIS needs to draw at it.
+const REGIS_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// DEC ReGIS graphics, drawing the pixels as horizontal vectors in the 15 colors of the
+/// VT340 the image uses most. ReGIS addresses the screen rather than the cursor, so the
+/// terminal is asked where the cursor is.
+pub struct Regis;
+
+impl Renderer for Regis {
+    fn name(&self) -> &'static str {
+        "regis"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {formats: Format::ALL.to_vec(), streaming: false, passthrough: false}
+    }
+
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        let (bitmap, read) = scaled(source, options, &terminal::size(), REGIS_CELL)?;
+        let columns = (bitmap.width as f64 / REGIS_CELL.0).ceil() as u16;
+        let rows = (bitmap.height as f64 / REGIS_CELL.1).ceil() as u16;
+        // 先换行腾出图片的位置，在屏幕底部时滚动屏幕，再回到第一行询问光标的位置。
+        for _ in 1..rows {
+            out.write_all(b"\x1bD")?;
+        }
+        if rows > 1 {
+            write!(out, "\x1b[{}A", rows - 1)?;
+        }
+        out.flush()?;
+        let (row, column) = terminal::cursor_position(REGIS_QUERY_TIMEOUT).unwrap_or((1, 1));
+        let left = (column - 1) as usize * REGIS_CELL.0 as usize;
+        let top = (row - 1) as usize * REGIS_CELL.1 as usize;
+        write_regis(out, &bitmap, left, top)?;
+        // 和半块字符一样，把光标留在图片最后一行的右边。
+        if rows > 1 {
+            write!(out, "\x1b[{}B", rows - 1)?;
+        }
+        write!(out, "\x1b[{columns}C")?;
+        Ok(read)
+    }
+
+    fn prepare(&self, data: &[u8], options: &Options) -> io::Result<()> {
+        scaled(&mut &data[..], options, &terminal::size(), REGIS_CELL).map(drop)
+    }
+
+    fn clear(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(CLEAR_SCREEN)?;
+        out.write_all(b"\x1bPpS(E)\x1b\\")
+    }
+
+    fn erase(&self, out: &mut dyn Write, x: u16, y: u16, columns: u16, rows: u16) -> io::Result<()> {
+        let (left, top) = (x as usize * REGIS_CELL.0 as usize, y as usize * REGIS_CELL.1 as usize);
+        let right = left + (columns as usize * REGIS_CELL.0 as usize).max(1) - 1;
+        let bottom = top + (rows as usize * REGIS_CELL.1 as usize).max(1) - 1;
+        // 用背景色（0 号寄存器）填满图片所在的矩形。
+        write!(out, "\x1bPpW(I0)P[{left},{top}]F(V[{right},{top}][{right},{bottom}][{left},{bottom}][{left},{top}])\x1b\\")?;
+        erase_cells(out, x, y, columns, rows)
+    }
+}
+
+/// Write the ReGIS commands drawing `bitmap` with its top left corner at the pixel `left`,
+/// `top` of the screen.
+fn write_regis(out: &mut dyn Write, bitmap: &Bitmap, left: usize, top: usize) -> io::Result<()> {
+    let palette = regis_palette(&bitmap.pixels);
+    out.write_all(b"\x1bPp")?;
+    for (i, color) in palette.iter().enumerate() {
+        let (h, l, s) = dec_hls(*color);
+        write!(out, "S(M{}(AH{h}L{l}S{s}))", i + 1)?;
+    }
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    let registers: Vec<Option<usize>> = bitmap.pixels.chunks(4)
+        .map(|p| (p[3] >= 128).then(|| nearest(&palette, [p[0], p[1], p[2]]) + 1))
+        .collect();
+    for register in 1..=palette.len() {
+        write!(out, "W(I{register})")?;
+        for y in 0..height {
+            let line = &registers[y * width..(y + 1) * width];
+            let mut x = 0;
+            for run in line.chunk_by(|a, b| a == b) {
+                if run[0] == Some(register) {
+                    let (x1, x2, y) = (left + x, left + x + run.len() - 1, top + y);
+                    write!(out, "P[{x1},{y}]V[{x2},{y}]")?;
+                }
+                x += run.len();
+            }
+        }
+    }
+    out.write_all(b"\x1b\\")
+}
+
+/// The colors of the registers of the VT340 other than the background: the average colors
+/// of the most frequent cells of a 4x4x4 division of the RGB cube among the opaque pixels.
+fn regis_palette(pixels: &[u8]) -> Vec<[u8; 3]> {
+    let mut bins = [(0u64, [0u64; 3]); 64];
+    for p in pixels.chunks(4).filter(|p| p[3] >= 128) {
+        let bin = &mut bins[(p[0] as usize >> 6) * 16 + (p[1] as usize >> 6) * 4 + (p[2] as usize >> 6)];
+        bin.0 += 1;
+        (0..3).for_each(|i| bin.1[i] += p[i] as u64);
+    }
+    let mut bins: Vec<_> = bins.into_iter().filter(|(n, _)| *n > 0).collect();
+    bins.sort_by_key(|(n, _)| std::cmp::Reverse(*n));
+    bins.into_iter()
+        .take(REGIS_REGISTERS - 1)
+        .map(|(n, sum)| sum.map(|c| (c / n) as u8))
+        .collect()
+}
+
+/// Index of the color of `palette` closest to `color`.
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> usize {
+    let distance = |c: &[u8; 3]| (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2)).sum::<i32>();
+    (0..palette.len()).min_by_key(|i| distance(&palette[*i])).unwrap_or(0)
+}
+
+/// Hue, lightness and saturation of an RGB color as ReGIS takes them: the hue in degrees
+/// from blue, with red at 120 and green at 240, and the others in percent.
+fn dec_hls([r, g, b]: [u8; 3]) -> (u32, u32, u32) {
+    let [r, g, b] = [r, g, b].map(|c| c as f64 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0, (lightness * 100.0).round() as u32, 0);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    // 通常的色相从红色算起，DEC 的从蓝色算起。
+    let hue = (hue.round() as u32 + 120) % 360;
+    (hue, (lightness * 100.0).round() as u32, (saturation * 100.0).round().min(100.0) as u32)
+}
+
+/// The Linux framebuffer: the image is converted to the pixel format of `/dev/fb0`, or of
+/// the device named by `$FRAMEBUFFER`, and drawn centered on the screen, with no escape
+/// sequences. The console redraws the text over it as it changes.
+pub struct Fbdev;
+
+impl Renderer for Fbdev {
+    fn name(&self) -> &'static str {
+        "fbdev"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {formats: Format::ALL.to_vec(), streaming: false, passthrough: false}
+    }
+
+    fn emit(&self, _out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        let framebuffer = Framebuffer::open()?;
+        let (width, height) = framebuffer.size();
+        // 控制台的行列数加上帧缓冲的分辨率，-W 和 -H 的格子数按控制台的字体换算成像素。
+        let size = terminal::Size {
+            width: width.min(u16::MAX as u32) as u16,
+            height: height.min(u16::MAX as u32) as u16,
+            ..terminal::size()
+        };
+        let (mut bitmap, read) = scaled(source, options, &size, size.cell_size())?;
+        if bitmap.height > height {
+            let fitted = match options.preserve_aspect_ratio {
+                true => (bitmap.width as u64 * height as u64 / bitmap.height as u64).max(1) as u32,
+                false => bitmap.width,
+            };
+            bitmap = bitmap.resize(fitted, height);
+        }
+        framebuffer.draw_centered(&bitmap)?;
+        Ok(read)
+    }
+}
+
+/// Upper and lower half blocks, each cell showing two pixels with truecolor escape
+/// sequences. Works in any terminal with truecolor, without graphics.
+pub struct Blocks;
+
+impl Renderer for Blocks {
+    fn name(&self) -> &'static str {
+        "blocks"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {formats: Format::ALL.to_vec(), streaming: false, passthrough: false}
+    }
+
+    fn emit(&self, out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+        write_blocks(out, source, options, false)
+    }
+
+    fn prepare(&self, data: &[u8], options: &Options) -> io::Result<()> {
+        let (size, cell) = blocks_cell(options);
+        scaled(&mut &data[..], options, &size, cell).map(drop)
+    }
+}
+
+/// Draw the image with the symbols of [`Blocks`], as lines of text ending in newlines,
+/// for programs showing the output of a command as text, such as the previews of file managers.
+pub fn blocks_text(out: &mut dyn Write, source: &mut dyn Read, options: &Options) -> io::Result<u64> {
+    write_blocks(out, source, options, true)
+}
+
+/// The size of the terminal, and that of a character cell in the pixels of the symbols.
+fn blocks_cell(options: &Options) -> (terminal::Size, (f64, f64)) {
+    // 终端的格子不一定正好是 1:2，按格子的实际宽高比取样，图片才不会被压扁或拉长。
+    let size = match options.cell_aspect.or_else(terminal::cell_aspect) {
+        Some(aspect) => terminal::size().with_cell_aspect(aspect),
+        None => terminal::size(),
+    };
+    let (cell_width, cell_height) = options.symbols.cell();
+    (size, (cell_width as f64, cell_height as f64))
+}
+
+fn write_blocks(out: &mut dyn Write, source: &mut dyn Read, options: &Options, text: bool) -> io::Result<u64> {
+    let (size, cell) = blocks_cell(options);
+    let (mut bitmap, read) = scaled(source, options, &size, cell)?;
+    let (cell_width, cell_height) = options.symbols.cell();
+    let depth = options.colors.unwrap_or_else(terminal::color_depth);
+    ansi::dither(&mut bitmap, depth);
+    let pixel = |x, y| match x < bitmap.width as usize && y < bitmap.height as usize {
+        true => Some(bitmap.get(x, y)).filter(|p| p[3] >= 128).map(|[r, g, b, _]| [r, g, b]),
+        false => None,
+    };
+    let columns = bitmap.width.div_ceil(cell_width as u32) as usize;
+    let mut cell = Vec::with_capacity(cell_width * cell_height);
+    for row in 0..bitmap.height.div_ceil(cell_height as u32) as usize {
+        if row > 0 && !text {
+            // 用 IND 换行：保持所在的列，在屏幕底部时滚动屏幕。
+            write!(out, "\x1b[{columns}D\x1bD")?;
+        }
+        for column in 0..columns {
+            cell.clear();
+            for y in row * cell_height..(row + 1) * cell_height {
+                cell.extend((column * cell_width..(column + 1) * cell_width).map(|x| pixel(x, y)));
+            }
+            write_symbol(out, &options.symbols, depth, &cell)?;
+        }
+        out.write_all(if text { b"\x1b[0m\n" } else { b"\x1b[0m" })?;
+    }
+    Ok(read)
+}
+
+/// Write the character drawing the pixels of a cell, given row by row, `None` where they
+/// are transparent, with the escape sequences coloring it in the colors of `depth`.
+fn write_symbol(out: &mut dyn Write, symbols: &Symbols, depth: ColorDepth, cell: &[Option<[u8; 3]>]) -> io::Result<()> {
+    let opaque: Vec<[u8; 3]> = cell.iter().flatten().copied().collect();
+    if opaque.is_empty() {
+        return out.write_all(b"\x1b[0m ");
+    }
+    let fg = |rgb| ansi::sgr(depth, rgb, false);
+    let bg = |rgb| ansi::sgr(depth, rgb, true);
+    match symbols {
+        Symbols::Blocks => match (cell[0], cell[1]) {
+            (Some(top), Some(bottom)) => write!(out, "\x1b[{};{}m▀", fg(top), bg(bottom)),
+            (Some(top), None) => write!(out, "\x1b[0;{}m▀", fg(top)),
+            (None, Some(bottom)) => write!(out, "\x1b[0;{}m▄", fg(bottom)),
+            (None, None) => out.write_all(b"\x1b[0m "),
+        },
+        Symbols::Ascii | Symbols::Custom(_) => {
+            let ramp = match symbols {
+                Symbols::Custom(chars) => chars.as_slice(),
+                _ => ASCII_RAMP,
+            };
+            let color = average(&opaque);
+            let index = (luminance(color) as usize * (ramp.len() - 1) + 127) / 255;
+            write!(out, "\x1b[0;{}m{}", fg(color), ramp[index])
+        }
+        Symbols::Braille => {
+            // 亮于平均值的像素画成点，点的颜色是它们的平均色，其余的露出终端的背景。
+            let threshold = opaque.iter().map(|p| luminance(*p) as u32).sum::<u32>() / opaque.len() as u32;
+            let lit = |p: &Option<[u8; 3]>| p.is_some_and(|p| luminance(p) as u32 >= threshold);
+            let mask = BRAILLE_DOTS.iter()
+                .zip(cell)
+                .filter(|(_, p)| lit(p))
+                .fold(0, |mask, (dot, _)| mask | dot);
+            let color = average(&cell.iter().filter(|p| lit(p)).flatten().copied().collect::<Vec<_>>());
+            let symbol = char::from_u32(0x2800 + mask).unwrap_or(' ');
+            write!(out, "\x1b[0;{}m{symbol}", fg(color))
+        }
+        Symbols::Geometric => {
+            // 四个象限按亮度分成前景和背景两组，各取平均色。
+            let threshold = opaque.iter().map(|p| luminance(*p) as u32).sum::<u32>() / opaque.len() as u32;
+            let lit = |p: &Option<[u8; 3]>| p.is_some_and(|p| luminance(p) as u32 >= threshold);
+            let mask = (0..4).filter(|i| lit(&cell[*i])).fold(0, |mask, i| mask | 1 << i);
+            let color = average(&cell.iter().filter(|p| lit(p)).flatten().copied().collect::<Vec<_>>());
+            let dark: Vec<[u8; 3]> = cell.iter().filter(|p| !lit(p)).flatten().copied().collect();
+            let symbol = QUADRANTS[mask];
+            match dark.is_empty() {
+                true => write!(out, "\x1b[0;{}m{symbol}", fg(color)),
+                false => write!(out, "\x1b[{};{}m{symbol}", fg(color), bg(average(&dark))),
+            }
+        }
+    }
+}
+
+/// Characters of [`Symbols::Ascii`], from the darkest pixels to the brightest.
+const ASCII_RAMP: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// Bits of the dots of braille patterns, for the pixels of a 2x4 cell row by row.
+const BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+/// Quadrant characters, indexed by the quadrants they fill: 1 for the top left one, 2 for
+/// the top right, 4 for the bottom left and 8 for the bottom right.
+const QUADRANTS: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+
+/// Perceived brightness of a color, from 0 to 255.
+fn luminance([r, g, b]: [u8; 3]) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+/// Average of colors, black if there are none.
+fn average(colors: &[[u8; 3]]) -> [u8; 3] {
+    let n = colors.len().max(1) as u32;
+    let sum = colors.iter().fold([0u32; 3], |sum, c| [0, 1, 2].map(|i| sum[i] + c[i] as u32));
+    sum.map(|c| (c / n) as u8)
+}
+
+/// Read and decode the image from `source`, and scale it to the size it is drawn at in a
+/// terminal of `size`, in pixels of which a character cell holds `cell`. Return it with the
+/// number of bytes read. The bitmaps are taken from, and kept in, the cache of the options.
+fn scaled(source: &mut dyn Read, options: &Options, size: &terminal::Size, cell: (f64, f64)) -> io::Result<(Bitmap, u64)> {
+    let mut data = Vec::new();
+    let read = source.read_to_end(&mut data)? as u64;
+    let bitmap = match options.cache.and_then(|cache| cache.get(&data, None)) {
+        Some(bitmap) => bitmap,
+        None => {
+            let bitmap = Bitmap::decode(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if let Some(cache) = options.cache {
+                cache.put(&data, None, &bitmap);
+            }
+            bitmap
+        }
+    };
+    let (columns, rows) = layout::display_cells(&data, options.width, options.height, options.preserve_aspect_ratio, size)
+        .unwrap_or((1, 1));
+    let (mut width, mut height) = (columns as f64 * cell.0, rows as f64 * cell.1);
+    // 行列数是向上取整的，按比例缩放时图片只占满其中一边。
+    // 取样的像素不一定是正方形，比较的是它们在屏幕上的大小。
+    if options.preserve_aspect_ratio && bitmap.width > 0 && bitmap.height > 0 {
+        let (cell_width, cell_height) = size.cell_size();
+        let aspect = (cell_width / cell.0) / (cell_height / cell.1);
+        let scale = (width * aspect / bitmap.width as f64).min(height / bitmap.height as f64);
+        (width, height) = (bitmap.width as f64 * scale / aspect, bitmap.height as f64 * scale);
+    }
+    let (width, height) = (width.round().max(1.0) as u32, height.round().max(1.0) as u32);
+    let Some(cache) = options.cache else {
+        return Ok((bitmap.resize(width, height), read));
+    };
+    let scaled = cache.get(&data, Some((width, height))).unwrap_or_else(|| {
+        let scaled = bitmap.resize(width, height);
+        cache.put(&data, Some((width, height)), &scaled);
+        scaled
+    });
+    Ok((scaled, read))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Length;
+
+    /// What `renderer` writes for `data` when the environment holds only `vars`.
+    fn emit(renderer: &dyn Renderer, data: &[u8], options: &Options, vars: &[(&str, &str)]) -> io::Result<Vec<u8>> {
+        terminal::set_environment(Some(vars.iter().map(|(name, value)| (name.into(), value.into())).collect()));
+        let mut out = Vec::new();
+        let read = renderer.emit(&mut out, &mut &data[..], options);
+        terminal::set_environment(None);
+        assert_eq!(read?, data.len() as u64);
+        Ok(out)
+    }
+
+    /// A bitmap of `width` x `height` pixels of the colors `pixel` gives them.
+    fn bitmap(width: u32, height: u32, pixel: impl Fn(usize, usize) -> [u8; 4]) -> Bitmap {
+        let mut bitmap = Bitmap::new(width, height);
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                bitmap.set(x, y, pixel(x, y));
+            }
+        }
+        bitmap
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    /// Options drawing the image over exactly `columns` by `rows` cells.
+    fn cells(columns: u32, rows: u32) -> Options<'static> {
+        Options {
+            width: Some(Length::Cells(columns)),
+            height: Some(Length::Cells(rows)),
+            preserve_aspect_ratio: false,
+            cell_aspect: Some(0.5),
+            colors: Some(ColorDepth::Truecolor),
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn iterm2() {
+        let options = Options {
+            width: Some(Length::Cells(10)),
+            filename: Some("a.png"),
+            file_type: Some("image/png"),
+            size: Some(3),
+            ..Options::default()
+        };
+        let out = emit(&ITerm2, b"abc", &options, &[]).unwrap();
+        assert_eq!(out, b"\x1b]1337;File=inline=1;size=3;name=YS5wbmc=;width=10;preserveAspectRatio=1;type=image/png:YWJj\x07");
+        let out = emit(&ITerm2, b"abc", &options, &[("TERM", "tmux-256color")]).unwrap();
+        assert!(out.starts_with(b"\x1bPtmux;\x1b\x1b]1337;File=inline=1;") && out.ends_with(b":YWJj\x07\x1b\\"));
+        // WezTerm 的参数顺序和 wezterm imgcat 一样，不写默认值和文件类型。
+        let out = emit(&ITerm2, b"abc", &options, &[("TERM_PROGRAM", "WezTerm")]).unwrap();
+        assert_eq!(out, b"\x1b]1337;File=size=3;name=YS5wbmc=;width=10;inline=1:YWJj\x07");
+        let options = Options {link: Some("https://example.com/"), ..Options::default()};
+        let out = emit(&ITerm2, b"", &options, &[]).unwrap();
+        assert_eq!(out, b"\x1b]8;;https://example.com/\x1b\\\x1b]1337;File=inline=1;preserveAspectRatio=1:\x07\x1b]8;;\x1b\\");
+    }
+
+    #[test]
+    fn kitty() {
+        let png = bitmap(2, 2, |_, _| RED).to_png();
+        let payload = BASE64_STANDARD.encode(&png);
+        let out = emit(&Kitty, &png, &Options::default(), &[]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("\x1b_Ga=T,f=100,q=2,c=1,r=1,m=0;{payload}\x1b\\"));
+        let out = emit(&Kitty, &png, &Options::default(), &[("TERM", "screen")]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("\x1bPtmux;\x1b\x1b_Ga=T,f=100,q=2,c=1,r=1,m=0;{payload}\x1b\x1b\\\x1b\\"));
+        // 其他格式先转换为 PNG，不能解码的图片是错误。
+        let bmp = bitmap(2, 2, |_, _| RED).to_bmp();
+        let out = emit(&Kitty, &bmp, &Options::default(), &[]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("\x1b_Ga=T,f=100,q=2,c=1,r=1,m=0;{payload}\x1b\\"));
+        let mut gif = io::Cursor::new(Vec::new());
+        let red = bitmap(2, 2, |_, _| RED);
+        image::write_buffer_with_format(&mut gif, &red.pixels, 2, 2, image::ColorType::Rgba8, image::ImageFormat::Gif).unwrap();
+        let out = emit(&Kitty, gif.get_ref(), &Options::default(), &[]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("\x1b_Ga=T,f=100,q=2,c=1,r=1,m=0;{payload}\x1b\\"));
+        assert_eq!(emit(&Kitty, b"abc", &Options::default(), &[]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        // 随机像素几乎无法压缩，6912 字节的像素编码成 PNG 后，base64 编码分成 3 块。
+        let png = bitmap(48, 36, |x, y| {
+            let mut h = (y * 48 + x) as u32 ^ 0x9e37_79b9;
+            h = (h ^ h >> 16).wrapping_mul(0x045d_9f3b);
+            h = (h ^ h >> 16).wrapping_mul(0x045d_9f3b);
+            (h ^ h >> 16).to_le_bytes()
+        }).to_png();
+        assert!((6145..9216).contains(&png.len()));
+        let payload = BASE64_STANDARD.encode(&png);
+        let options = cells(1, 1);
+        let out = String::from_utf8(emit(&Kitty, &png, &options, &[]).unwrap()).unwrap();
+        let expected = format!(
+            "\x1b_Ga=T,f=100,q=2,c=1,r=1,m=1;{}\x1b\\\x1b_Gm=1;{}\x1b\\\x1b_Gm=0;{}\x1b\\",
+            &payload[..4096], &payload[4096..8192], &payload[8192..],
+        );
+        assert_eq!(out, expected);
+        // Konsole 只接受一个序列。
+        let out = String::from_utf8(emit(&Kitty, &png, &options, &[("KONSOLE_VERSION", "240202")]).unwrap()).unwrap();
+        assert_eq!(out, format!("\x1b_Ga=T,f=100,q=2,c=1,r=1,m=0;{payload}\x1b\\"));
+    }
+
+    #[test]
+    fn kitty_clear_and_erase() {
+        let mut out = Vec::new();
+        terminal::set_environment(Some(Vec::new()));
+        Kitty.clear(&mut out).unwrap();
+        Kitty.erase(&mut out, 1, 2, 3, 2).unwrap();
+        terminal::set_environment(None);
+        assert_eq!(out, b"\x1b_Ga=d,q=2;\x1b\\\x1b[H\x1b[2J\x1b_Ga=d,d=p,x=2,y=3,q=2;\x1b\\\x1b7\x1b[0m\x1b[3;2H   \x1b[4;2H   \x1b8");
+    }
+
+    #[test]
+    fn sixel() {
+        // Windows Terminal 的格子固定为 10x20 个 sixel 像素，与运行测试的终端无关。
+        let png = bitmap(10, 20, |x, _| if x < 5 { RED } else { CLEAR }).to_png();
+        let out = emit(&Sixel, &png, &cells(1, 1), &[("WT_SESSION", "1")]).unwrap();
+        let band = "#180!5~!5?$-";
+        let expected = format!("\x1bP0;1;0q\"1;1;10;20#180;2;100;0;0{band}{band}{band}#180!5B!5?$-\x1b\\");
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn sixel_colors() {
+        assert_eq!(cube_index(&[255, 0, 0, 255]), Some(180));
+        assert_eq!(cube_index(&[0, 0, 255, 128]), Some(5));
+        assert_eq!(cube_index(&[255, 255, 255, 127]), None);
+        let mut out = Vec::new();
+        write_runs(&mut out, b"~~~~~??~~~").unwrap();
+        assert_eq!(out, b"!5~??~~~");
+    }
+
+    #[test]
+    fn regis() {
+        let image = bitmap(3, 2, |x, y| [[RED, RED, BLUE], [CLEAR, RED, RED]][y][x]);
+        let mut out = Vec::new();
+        write_regis(&mut out, &image, 100, 40).unwrap();
+        let expected = "\x1bPpS(M1(AH120L50S100))S(M2(AH0L50S100))\
+            W(I1)P[100,40]V[101,40]P[101,41]V[102,41]W(I2)P[102,40]V[102,40]\x1b\\";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(dec_hls([0, 255, 0]), (240, 50, 100));
+        assert_eq!(dec_hls([128, 128, 128]), (0, 50, 0));
+        let mut out = Vec::new();
+        Regis.erase(&mut out, 1, 2, 3, 1).unwrap();
+        assert_eq!(out, b"\x1bPpW(I0)P[10,40]F(V[39,40][39,59][10,59][10,40])\x1b\\\x1b7\x1b[0m\x1b[3;2H   \x1b8");
+    }
+
+    #[test]
+    fn blocks() {
+        let png = bitmap(2, 4, |x, y| [[RED, CLEAR], [BLUE, GREEN], [GREEN, GREEN], [CLEAR, CLEAR]][y][x]).to_png();
+        let out = emit(&Blocks, &png, &cells(2, 2), &[]).unwrap();
+        let expected = "\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[0;38;2;0;255;0m▄\x1b[0m\
+            \x1b[2D\x1bD\x1b[0;38;2;0;255;0m▀\x1b[0;38;2;0;255;0m▀\x1b[0m";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        let mut out = Vec::new();
+        terminal::set_environment(Some(Vec::new()));
+        let read = blocks_text(&mut out, &mut png.as_slice(), &cells(2, 2));
+        terminal::set_environment(None);
+        assert_eq!(read.unwrap(), png.len() as u64);
+        let expected = "\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[0;38;2;0;255;0m▄\x1b[0m\n\
+            \x1b[0;38;2;0;255;0m▀\x1b[0;38;2;0;255;0m▀\x1b[0m\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn symbols() {
+        let write = |symbols: Symbols, cell: &[Option<[u8; 3]>]| {
+            let mut out = Vec::new();
+            write_symbol(&mut out, &symbols, ColorDepth::Truecolor, cell).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let (white, black) = (Some([255, 255, 255]), Some([0, 0, 0]));
+        assert_eq!(write(Symbols::Ascii, &[white]), "\x1b[0;38;2;255;255;255m@");
+        assert_eq!(write(Symbols::Ascii, &[None]), "\x1b[0m ");
+        assert_eq!(write(Symbols::Custom(vec!['a', 'b']), &[black]), "\x1b[0;38;2;0;0;0ma");
+        // 左边一列亮：点 1、2、3、7。
+        let braille = [white, black, white, black, white, black, white, black];
+        assert_eq!(write(Symbols::Braille, &braille), "\x1b[0;38;2;255;255;255m⡇");
+        assert_eq!(write(Symbols::Geometric, &[white, black, None, white]), "\x1b[38;2;255;255;255;48;2;0;0;0m▚");
+        assert_eq!(write(Symbols::Geometric, &[white, white, white, white]), "\x1b[0;38;2;255;255;255m█");
+    }
+
+    #[test]
+    fn fbdev_without_a_framebuffer() {
+        let png = bitmap(1, 1, |_, _| RED).to_png();
+        terminal::set_environment(Some(vec![("FRAMEBUFFER".into(), "/dev/null".into())]));
+        let mut out = Vec::new();
+        let result = Fbdev.emit(&mut out, &mut png.as_slice(), &Options::default());
+        terminal::set_environment(None);
+        assert!(result.is_err() && out.is_empty());
+    }
+}
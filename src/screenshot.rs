@@ -0,0 +1,106 @@
+use std::process::Stdio;
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use crate::i18n::tr;
+use crate::terminal;
+
+/// Which part of the screen to capture.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// a region selected interactively
+    Region,
+    /// the active window
+    Window,
+    /// the whole screen
+    Full,
+}
+
+/// Capture the screen and return the screenshot as PNG bytes.
+///
+/// `screencapture` is used on macOS, `grim`/`slurp` on Wayland, `maim`/`xdotool`
+/// on X11 and PowerShell on Windows, which only supports full screen captures.
+pub fn capture(mode: Mode) -> anyhow::Result<Vec<u8>> {
+    let data = capture_platform(mode)?;
+    if data.is_empty() {
+        bail!(tr!("the screenshot was cancelled"));
+    }
+    Ok(data)
+}
+
+#[cfg(target_os = "macos")]
+fn capture_platform(mode: Mode) -> anyhow::Result<Vec<u8>> {
+    // screencapture 不支持输出到 stdout，只能先写入临时文件。
+    let file = std::env::temp_dir().join(format!("imgcat-screenshot-{}.png", std::process::id()));
+    let file_arg = file.to_string_lossy();
+    let mut args = vec!["-x", "-t", "png"];
+    match mode {
+        Mode::Region => args.push("-i"),
+        Mode::Window => args.extend(["-i", "-w"]),
+        Mode::Full => {}
+    }
+    args.push(file_arg.as_ref());
+    run("screencapture", &args)?;
+    let data = std::fs::read(&file).unwrap_or_default();
+    let _ = std::fs::remove_file(&file);
+    Ok(data)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn capture_platform(mode: Mode) -> anyhow::Result<Vec<u8>> {
+    if terminal::var_os("WAYLAND_DISPLAY").is_some() {
+        match mode {
+            Mode::Full => run("grim", &["-"]),
+            Mode::Region | Mode::Window => {
+                // Wayland 下没有通用的获取活动窗口的方法，窗口模式同样交由 slurp 选择区域。
+                let geometry = run("slurp", &[])?;
+                let geometry = String::from_utf8_lossy(&geometry);
+                run("grim", &["-g", geometry.trim(), "-"])
+            }
+        }
+    } else {
+        match mode {
+            Mode::Full => run("maim", &[]),
+            Mode::Region => run("maim", &["--select"]),
+            Mode::Window => {
+                let window = run("xdotool", &["getactivewindow"])?;
+                let window = String::from_utf8_lossy(&window);
+                run("maim", &["--window", window.trim()])
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn capture_platform(mode: Mode) -> anyhow::Result<Vec<u8>> {
+    use base64::Engine;
+    use base64::prelude::BASE64_STANDARD;
+
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Windows.Forms,System.Drawing;\
+        $b = [System.Windows.Forms.SystemInformation]::VirtualScreen;\
+        $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height;\
+        $g = [System.Drawing.Graphics]::FromImage($bmp);\
+        $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size);\
+        $ms = New-Object System.IO.MemoryStream;\
+        $bmp.Save($ms, [System.Drawing.Imaging.ImageFormat]::Png);\
+        [Convert]::ToBase64String($ms.ToArray())";
+    if mode != Mode::Full {
+        bail!(tr!("only full screen captures are supported on Windows"));
+    }
+    let out = run("powershell", &["-NoProfile", "-Command", SCRIPT])?;
+    let text = String::from_utf8_lossy(&out);
+    BASE64_STANDARD.decode(text.trim())
+        .context(tr!("failed to decode the screenshot"))
+}
+
+fn run(program: &str, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let out = terminal::command(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| tr!("failed to run {}, is it installed?", program))?;
+    if !out.status.success() {
+        bail!(tr!("{} exited with {}", program, out.status));
+    }
+    Ok(out.stdout)
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::Context;
+use log::{debug, error};
+use imgcat::Options;
+use crate::bitmap::Bitmap;
+use crate::i18n::tr;
+use serde_json::Value;
+use crate::{layout, terminal, Cli, Length};
+
+#[cfg(unix)]
+pub use unix::run;
+
+/// A command of a client, sent as a JSON object on a line of its own.
+#[derive(Debug)]
+enum Action {
+    /// draw the image at `path` from column `x` and row `y`, fitted into `width`
+    /// by `height` cells, in place of the image of the same identifier
+    Add {identifier: String, path: PathBuf, x: u16, y: u16, width: Option<u32>, height: Option<u32>},
+    /// erase the image of the identifier
+    Remove {identifier: String},
+    /// erase every image
+    Clear,
+}
+
+/// The cells an image was drawn over, to erase it.
+struct Placement {
+    x: u16,
+    y: u16,
+    columns: u16,
+    rows: u16,
+}
+
+/// Read ueberzug's commands from `input`, one JSON object per line, and place the
+/// images they add until the end of the input, where the images are erased as
+/// ueberzug does. Invalid commands are reported on stderr unless `silent` is set.
+pub fn layer(input: impl BufRead, args: &Cli, silent: bool) -> anyhow::Result<()> {
+    redirect_to_terminal()?;
+    let placements = Mutex::new(HashMap::new());
+    for line in input.lines() {
+        let line = line.context(tr!("failed to read a command"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        debug!("running {line}");
+        let result = parse(&line)
+            .map_err(anyhow::Error::msg)
+            .and_then(|action| execute(action, args, &placements));
+        if let Err(e) = result.with_context(|| tr!("invalid command {}", line)) {
+            if !silent {
+                error!("{e:#}");
+            }
+        }
+    }
+    execute(Action::Clear, args, &placements)
+}
+
+/// Point stdout at the terminal when it isn't one already: editor plugins and the
+/// scripts driving ueberzug usually read the output of the processes they start.
+fn redirect_to_terminal() -> anyhow::Result<()> {
+    if !io::stdout().is_terminal() {
+        terminal::redirect_stdout_to_tty().context(tr!("failed to open the terminal"))?;
+    }
+    Ok(())
+}
+
+/// Parse a command of --serve or `imgcat layer`, which also takes ueberzug's names
+/// `max_width` and `max_height` for `width` and `height`. Unknown fields, such as
+/// ueberzug's `scaler` or the nested objects of other clients, are ignored.
+fn parse(line: &str) -> Result<Action, String> {
+    let fields: serde_json::Map<String, Value> = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let get = |name: &str| fields.get(name);
+    let string = |name: &str| match get(name) {
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(Value::Null) | None => Ok(None),
+        Some(_) => Err(format!("{name} must be a string")),
+    };
+    // ueberzug 的客户端常常传入小数的坐标和大小，和 ueberzug 一样取整数部分。
+    let number = |name: &str| match get(name) {
+        Some(Value::Number(n)) if n.is_u64() => Ok(n.as_u64()),
+        Some(Value::Number(n)) if n.as_f64() >= Some(0.0) => Ok(n.as_f64().map(|x| x as u64)),
+        Some(Value::Null) | None => Ok(None),
+        Some(_) => Err(format!("{name} must be a non-negative number")),
+    };
+    let coordinate = |name: &str| {
+        number(name)?.unwrap_or(0).try_into().map_err(|_| format!("{name} is out of range"))
+    };
+    let cells = |name: &str, alias: &str| match number(name)?.or(number(alias)?) {
+        Some(0) => Err(format!("{name} must be greater than zero")),
+        Some(n) => u32::try_from(n).map(Some).map_err(|_| format!("{name} is out of range")),
+        None => Ok(None),
+    };
+    // 和 ueberzug 一样，没有给出 identifier 时用路径来标识图片。
+    let identifier = || {
+        string("identifier")?.or(string("path")?).ok_or_else(|| "identifier or path is missing".to_string())
+    };
+    match string("action")?.as_deref() {
+        Some("add") => Ok(Action::Add {
+            identifier: identifier()?,
+            path: string("path")?.ok_or("path is missing")?.into(),
+            x: coordinate("x")?,
+            y: coordinate("y")?,
+            width: cells("width", "max_width")?,
+            height: cells("height", "max_height")?,
+        }),
+        Some("remove") => Ok(Action::Remove {identifier: identifier()?}),
+        Some("clear") => Ok(Action::Clear),
+        Some(action) => Err(format!("unknown action {action}, expected add, remove or clear")),
+        None => Err("action is missing".to_string()),
+    }
+}
+
+fn execute(action: Action, args: &Cli, placements: &Mutex<HashMap<String, Placement>>) -> anyhow::Result<()> {
+    let renderer = crate::renderer(args);
+    let mut placements = placements.lock().unwrap();
+    let mut out = io::stdout().lock();
+    let erase = |out: &mut dyn Write, p: Placement| renderer.erase(out, p.x, p.y, p.columns, p.rows);
+    match action {
+        Action::Add {identifier, path, x, y, width, height} => {
+            let mut data = fs::read(&path).with_context(|| tr!("failed to read {}", path.display()))?;
+            if crate::needs_converting(&data, args) {
+                data = Bitmap::decode(&data)?.to_png();
+            }
+            let (width, height) = (width.map(Length::Cells), height.map(Length::Cells));
+            let (columns, rows) = layout::display_cells(&data, width, height, true, &terminal::size())
+                .with_context(|| tr!("{}: unknown image format", path.display()))?;
+            if let Some(old) = placements.remove(&identifier) {
+                erase(&mut out, old)?;
+            }
+            let filename = path.file_name().map(|name| name.to_string_lossy());
+            let options = Options {
+                width: Some(Length::Cells(columns.into())),
+                height: Some(Length::Cells(rows.into())),
+                preserve_aspect_ratio: true,
+                filename: filename.as_deref(),
+                size: Some(data.len() as u64),
+                cell_aspect: args.cell_aspect,
+                symbols: args.symbols.clone(),
+                colors: args.colors,
+                ..Options::default()
+            };
+            write!(out, "\x1b7\x1b[{};{}H", y + 1, x + 1)?;
+            renderer.emit(&mut out, &mut data.as_slice(), &options)?;
+            out.write_all(b"\x1b8")?;
+            placements.insert(identifier, Placement {x, y, columns, rows});
+        }
+        Action::Remove {identifier} => {
+            let placement = placements.remove(&identifier).with_context(|| tr!("no image {}", identifier))?;
+            erase(&mut out, placement)?;
+        }
+        Action::Clear => {
+            for (_, placement) in placements.drain() {
+                erase(&mut out, placement)?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::thread;
+    use anyhow::{bail, Context};
+    use log::{debug, error, info};
+    use crate::Cli;
+    use crate::i18n::tr;
+    use super::{execute, parse, redirect_to_terminal, Placement};
+
+    /// Listen on `socket` and draw the images the clients place, until imgcat is killed.
+    pub fn run(socket: &Path, args: &Cli) -> anyhow::Result<()> {
+        if UnixStream::connect(socket).is_ok() {
+            bail!(tr!("a server is already listening on {}", socket.display()));
+        }
+        if fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+            fs::remove_file(socket).with_context(|| tr!("failed to remove {}", socket.display()))?;
+        }
+        let listener = UnixListener::bind(socket)
+            .with_context(|| tr!("failed to listen on {}", socket.display()))?;
+        redirect_to_terminal()?;
+        info!("listening on {}", socket.display());
+        let placements = Mutex::new(HashMap::new());
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let placements = &placements;
+                        scope.spawn(move || serve(stream, args, placements));
+                    }
+                    Err(e) => error!("failed to accept a connection: {e}"),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Run the commands of a client and answer each with `{"status":"ok"}`, or
+    /// `{"status":"error","message":...}`, until it hangs up.
+    fn serve(stream: UnixStream, args: &Cli, placements: &Mutex<HashMap<String, Placement>>) {
+        for line in BufReader::new(&stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error!("failed to read a command: {e}");
+                    return;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            debug!("running {line}");
+            let result = parse(&line)
+                .map_err(anyhow::Error::msg)
+                .and_then(|action| execute(action, args, placements));
+            let reply = match result {
+                Ok(()) => serde_json::json!({"status": "ok"}),
+                Err(e) => serde_json::json!({"status": "error", "message": format!("{e:#}")}),
+            };
+            if writeln!(&stream, "{reply}").is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Placing images from editors only works on unix, where they connect to a Unix socket.
+#[cfg(not(unix))]
+pub fn run(_socket: &std::path::Path, _args: &crate::Cli) -> anyhow::Result<()> {
+    anyhow::bail!(tr!("--serve is only supported on unix systems"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let line = r#"{"action": "add", "identifier": "a", "path": "/tmp/a.png", "x": 2, "y": 3.7,
+            "max_width": 40, "height": null, "scaler": "fit_contain", "meta": {"tags": ["x", 1], "z": {}}}"#;
+        let Ok(Action::Add {identifier, path, x, y, width, height}) = parse(line) else { panic!("{line}") };
+        assert_eq!((identifier.as_str(), path.to_str()), ("a", Some("/tmp/a.png")));
+        assert_eq!((x, y, width, height), (2, 3, Some(40), None));
+        // 没有 identifier 时用路径标识图片。
+        let line = r#"{"action": "remove", "path": "/tmp/a.png"}"#;
+        assert!(matches!(parse(line), Ok(Action::Remove {identifier}) if identifier == "/tmp/a.png"));
+        assert!(matches!(parse(r#" {"action":"clear"} "#), Ok(Action::Clear)));
+        for invalid in [
+            "", "[]", r#"{"action":"add""#, r#"{"action":"clear"} x"#, r#"{"action":"add"}"#,
+            r#"{"action":"add","path":"a","x":-1}"#, r#"{"action":"add","path":"a","width":0}"#,
+            r#"{"action":"add","path":"a","x":"1"}"#, r#"{"action":"add","path":{"a":1}}"#,
+            r#"{"action":"add","path":"a","x":70000}"#, r#"{"action":"move"}"#, r#"{"path":"a"}"#,
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::{self, Read};
+use std::ops::Range;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SOI: &[u8] = b"\xff\xd8\xff";
+
+/// Split a stream of concatenated images into the byte ranges of each image.
+///
+/// PNG and JPEG images are delimited by walking their chunk/segment structure,
+/// GIF images by walking their blocks up to the trailer. As soon as the data
+/// stops looking like a known format the rest of the stream is returned as a
+/// single range, so a single image of any format is returned unchanged.
+pub fn split_images(data: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let rest = &data[start..];
+        let end = if rest.starts_with(PNG_SIGNATURE) {
+            png_end(rest)
+        } else if rest.starts_with(JPEG_SOI) {
+            jpeg_end(rest)
+        } else if rest.starts_with(b"GIF87a") || rest.starts_with(b"GIF89a") {
+            gif_end(rest)
+        } else {
+            None
+        };
+        match end {
+            Some(n) => {
+                ranges.push(start..start + n);
+                start += n;
+            }
+            None => {
+                ranges.push(start..data.len());
+                break;
+            }
+        }
+    }
+    ranges
+}
+
+/// Split a stream of concatenated images as it is read, yielding each image as soon as
+/// it is complete, e.g. the figures a plotting loop writes one after another.
+pub fn split_stream<R: Read>(reader: R) -> SplitStream<R> {
+    SplitStream {reader, buffer: Vec::new(), eof: false, yielded: false}
+}
+
+/// The iterator returned by [`split_stream`].
+///
+/// PNG, JPEG and GIF images are delimited as by [`split_images`], and the lines of text
+/// a program may print before or between them are skipped. As soon as the data stops
+/// looking like one of these formats, the rest of the stream is yielded as a single
+/// image, so a single image of any format is yielded unchanged at the end of the stream.
+pub struct SplitStream<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    eof: bool,
+    /// whether an image was yielded, after which trailing text is dropped
+    yielded: bool,
+}
+
+impl<R: Read> SplitStream<R> {
+    /// Read more data into the buffer, or the rest of the stream if `all` is set.
+    fn fill(&mut self, all: bool) -> io::Result<()> {
+        if all {
+            self.reader.read_to_end(&mut self.buffer)?;
+            self.eof = true;
+            return Ok(());
+        }
+        // 每次读取的量随缓冲区增长，这样不完整的图片被反复检查的总开销仍是线性的。
+        let len = self.buffer.len();
+        self.buffer.resize(len + len.max(64 * 1024), 0);
+        let read = loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        self.buffer.truncate(len + read.as_ref().map_or(0, |n| *n));
+        self.eof = read? == 0;
+        Ok(())
+    }
+
+    /// Take the first `len` bytes of the buffer as an image.
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        self.yielded = true;
+        let rest = self.buffer.split_off(len);
+        std::mem::replace(&mut self.buffer, rest)
+    }
+}
+
+impl<R: Read> Iterator for SplitStream<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        const SIGNATURES: [&[u8]; 4] = [PNG_SIGNATURE, JPEG_SOI, b"GIF87a", b"GIF89a"];
+        loop {
+            let text = text_len(&self.buffer);
+            let rest = &self.buffer[text..];
+            let end = if rest.starts_with(PNG_SIGNATURE) {
+                png_end(rest)
+            } else if rest.starts_with(JPEG_SOI) {
+                jpeg_end(rest)
+            } else if rest.starts_with(b"GIF87a") || rest.starts_with(b"GIF89a") {
+                gif_end(rest)
+            } else {
+                None
+            };
+            if let Some(len) = end {
+                // 程序在图片之前打印的文字不是图片的一部分。
+                self.buffer.drain(..text);
+                return Some(Ok(self.take(len)));
+            }
+            if !self.eof {
+                // 图片还不完整，或者目前只读到了文字：继续读取。其他格式的图片不能分割，读取剩下的全部数据。
+                let partial = SIGNATURES.iter().any(|s| rest.starts_with(s) || s.starts_with(rest));
+                if let Err(e) = self.fill(!partial) {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            if self.yielded {
+                self.buffer.drain(..text);
+            }
+            if self.buffer.is_empty() {
+                return None;
+            }
+            let len = self.buffer.len();
+            return Some(Ok(self.take(len)));
+        }
+    }
+}
+
+/// Whether `byte` may be part of the text printed between images.
+fn is_text(byte: u8) -> bool {
+    byte.is_ascii_graphic() || byte.is_ascii_whitespace()
+}
+
+/// Length of the text at the start of `data`, which ends at the first byte that can't be
+/// text or at the first GIF signature, whose bytes are all text.
+fn text_len(data: &[u8]) -> usize {
+    (0..data.len())
+        .find(|&i| !is_text(data[i]) || data[i..].starts_with(b"GIF87a") || data[i..].starts_with(b"GIF89a"))
+        .unwrap_or(data.len())
+}
+
+fn png_end(data: &[u8]) -> Option<usize> {
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        let header = data.get(pos..pos + 8)?;
+        let len = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+        // 数据块由 4 字节长度、4 字节类型、数据和 4 字节 CRC 组成。
+        pos = pos.checked_add(12 + len)?;
+        if pos > data.len() {
+            return None;
+        }
+        if &header[4..8] == b"IEND" {
+            return Some(pos);
+        }
+    }
+}
+
+/// Length of the JPEG image at the start of `data`, `None` if it is truncated or malformed.
+pub fn jpeg_end(data: &[u8]) -> Option<usize> {
+    match scan_jpeg(data) {
+        JpegScan::Complete(len) => Some(len),
+        JpegScan::Truncated | JpegScan::Malformed => None,
+    }
+}
+
+/// What walking the segments of the JPEG image at the start of a buffer found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JpegScan {
+    /// the image ends after this many bytes
+    Complete(usize),
+    /// the buffer ends before the image, which may go on in data yet to be read
+    Truncated,
+    /// a marker is missing where one should be, or another image starts inside this one,
+    /// so it never ends however much more data is read
+    Malformed,
+}
+
+/// Walk the segments of the JPEG image at the start of `data`, telling apart images
+/// that may still be completed by more data from those that never will.
+pub fn scan_jpeg(data: &[u8]) -> JpegScan {
+    walk_jpeg(data).unwrap_or(JpegScan::Truncated)
+}
+
+/// Walk the segments of a JPEG image, `None` when the data ends before it does.
+fn walk_jpeg(data: &[u8]) -> Option<JpegScan> {
+    let mut pos = 2;
+    loop {
+        // 标记之间允许出现任意数量的 0xFF 填充字节。
+        while *data.get(pos)? == 0xff && *data.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+        if *data.get(pos)? != 0xff {
+            return Some(JpegScan::Malformed);
+        }
+        let marker = *data.get(pos + 1)?;
+        pos += 2;
+        match marker {
+            0xd9 => return Some(JpegScan::Complete(pos)),
+            // 图像中间出现 SOI，说明这张图片被截断，后面接着的是下一张。
+            0xd8 => return Some(JpegScan::Malformed),
+            0x01 | 0xd0..=0xd7 => continue,
+            _ => {}
+        }
+        let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        pos += len;
+        if marker == 0xda {
+            // SOS 之后是熵编码数据，直到遇到一个既不是 0xFF00 填充也不是 RST 的标记为止。
+            loop {
+                if *data.get(pos)? == 0xff {
+                    let next = *data.get(pos + 1)?;
+                    if next != 0x00 && !(0xd0..=0xd7).contains(&next) {
+                        break;
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+            }
+        }
+    }
+}
+
+fn gif_end(data: &[u8]) -> Option<usize> {
+    walk_gif(data).map(|(end, _)| end)
+}
+
+/// Number of frames of the GIF image at the start of `data`, `None` if it is truncated or malformed.
+pub fn gif_frame_count(data: &[u8]) -> Option<u32> {
+    walk_gif(data).map(|(_, frames)| frames)
+}
+
+/// Walk the blocks of a GIF image, returning its length and number of frames.
+fn walk_gif(data: &[u8]) -> Option<(usize, u32)> {
+    let flags = *data.get(10)?;
+    let mut frames = 0;
+    let mut pos = 13;
+    if flags & 0x80 != 0 {
+        pos += 3 << ((flags & 0x07) + 1);
+    }
+    loop {
+        match *data.get(pos)? {
+            0x3b => return Some((pos + 1, frames)),
+            0x21 => {
+                pos = skip_sub_blocks(data, pos + 2)?;
+            }
+            0x2c => {
+                let flags = *data.get(pos + 9)?;
+                pos += 10;
+                if flags & 0x80 != 0 {
+                    pos += 3 << ((flags & 0x07) + 1);
+                }
+                // 跳过 LZW 最小码长字节。
+                pos = skip_sub_blocks(data, pos + 1)?;
+                frames += 1;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG of an IHDR and an IEND chunk, enough for its end to be found.
+    fn png() -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&[0, 0, 0, 13]);
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&[0, 0, 0, 10, 0, 0, 0, 10, 8, 6, 0, 0, 0]);
+        png.extend_from_slice(&[0; 4]);
+        png.extend_from_slice(&[0, 0, 0, 0]);
+        png.extend_from_slice(b"IEND");
+        png.extend_from_slice(&[0; 4]);
+        png
+    }
+
+    /// A 10x10 GIF of one frame, 29 bytes long. Its header starts with text: the signature
+    /// and the line feed of its width.
+    const GIF: &[u8] = b"GIF89a\n\0\n\0\0\0\0\x2c\0\0\0\0\n\0\n\0\0\x02\x02\x4c\x01\0\x3b";
+
+    const JPEG: &[u8] = b"\xff\xd8\xff\xd9";
+
+    /// Reads one byte at a time, like a pipe a program writes to slowly.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn split(data: &[u8]) -> Vec<Vec<u8>> {
+        let parts: Vec<_> = split_stream(data).collect::<io::Result<_>>().unwrap();
+        let trickled: Vec<_> = split_stream(Trickle(data)).collect::<io::Result<_>>().unwrap();
+        assert_eq!(parts, trickled);
+        parts
+    }
+
+    #[test]
+    fn png_then_gif() {
+        assert_eq!(GIF.len(), 29);
+        let data = [png(), GIF.to_vec()].concat();
+        assert_eq!(split(&data), [png(), GIF.to_vec()]);
+        assert_eq!(split_images(&data), [0..png().len(), png().len()..data.len()]);
+    }
+
+    #[test]
+    fn gif_then_gif() {
+        assert_eq!(split(&[GIF, GIF].concat()), [GIF, GIF]);
+        assert_eq!(gif_frame_count(GIF), Some(1));
+    }
+
+    #[test]
+    fn text_then_jpeg() {
+        let data = [b"Saving figure 1\n".as_slice(), JPEG, b"done\n", JPEG, b"\n"].concat();
+        assert_eq!(split(&data), [JPEG, JPEG]);
+        // 文字里的 GIF 签名也当作图片的开头。
+        assert_eq!(split(&[b"plot.gif: ".as_slice(), GIF].concat()), [GIF]);
+    }
+
+    #[test]
+    fn truncated_tail() {
+        let png = png();
+        let data = [png.as_slice(), &png[..20]].concat();
+        assert_eq!(split(&data), [&png, &png[..20]]);
+        assert_eq!(split(&[GIF, &GIF[..20]].concat()), [GIF, &GIF[..20]]);
+        assert_eq!(scan_jpeg(&JPEG[..3]), JpegScan::Truncated);
+        assert_eq!(scan_jpeg(b"\xff\xd8\xff\xd8"), JpegScan::Malformed);
+    }
+
+    #[test]
+    fn other_formats_are_left_whole() {
+        let data = b"RIFF\0\0\0\0WEBPVP8 ";
+        assert_eq!(split(data), [data]);
+        assert_eq!(split(b""), Vec::<Vec<u8>>::new());
+    }
+}
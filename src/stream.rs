@@ -0,0 +1,125 @@
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use reqwest::blocking::Response;
+use reqwest::header::CONTENT_TYPE;
+use crate::split::{scan_jpeg, JpegScan};
+
+const JPEG_SOI: &[u8] = b"\xff\xd8\xff";
+
+/// Number of bytes read at once from the stream.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Most bytes kept of a frame still being received, beyond which it is given up as
+/// broken, so that a stream gone wrong can't fill the memory.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+pub struct Playback {
+    /// maximum number of frames shown per second, frames arriving faster are dropped
+    pub fps: Option<f64>,
+    /// stop after this much time has passed
+    pub duration: Option<Duration>,
+}
+
+/// Whether the response is an MJPEG stream, i.e. served as `multipart/x-mixed-replace`.
+pub fn is_mjpeg(response: &Response) -> bool {
+    response.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim_start().to_ascii_lowercase().starts_with("multipart/x-mixed-replace"))
+}
+
+/// Read JPEG frames from `reader` and pass each complete frame to `show_frame`.
+///
+/// The multipart boundaries and part headers are not parsed: frames are found
+/// by their SOI marker and delimited by walking the JPEG segments, which works
+/// for the many cameras that get the multipart framing slightly wrong.
+pub fn play<R: Read + Send + 'static>(
+    reader: R,
+    options: &Playback,
+    mut show_frame: impl FnMut(&[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let interval = options.fps.and_then(|fps| Duration::try_from_secs_f64(1.0 / fps).ok());
+    let mut last_shown: Option<Instant> = None;
+    let mut buffer = Vec::new();
+    let chunks = read_in_background(reader);
+
+    loop {
+        // 停止发送数据的摄像头会让读取一直阻塞，等待数据时也要检查 --duration。
+        let received = match options.duration {
+            Some(d) => match chunks.recv_timeout(d.saturating_sub(started.elapsed())) {
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                received => received.ok(),
+            },
+            None => chunks.recv().ok(),
+        };
+        let chunk = match received {
+            Some(chunk) => chunk.with_context(|| "failed to read from stream")?,
+            None => return Ok(()),
+        };
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk);
+
+        loop {
+            let Some(start) = buffer.windows(JPEG_SOI.len()).position(|w| w == JPEG_SOI) else {
+                // 没有帧的开头，只保留结尾可能是半个 SOI 的字节。
+                buffer.drain(..buffer.len().saturating_sub(JPEG_SOI.len() - 1));
+                break;
+            };
+            let len = match scan_jpeg(&buffer[start..]) {
+                JpegScan::Complete(len) => len,
+                // 损坏或过长的帧永远不会完整，跳过它的 SOI，从后面的帧重新同步。
+                JpegScan::Malformed => {
+                    buffer.drain(..start + 2);
+                    continue;
+                }
+                JpegScan::Truncated if buffer.len() - start > MAX_FRAME_LEN => {
+                    buffer.drain(..start + 2);
+                    continue;
+                }
+                JpegScan::Truncated => {
+                    // 帧还没有接收完整，丢弃帧之前的数据（分隔符和头部），等待更多数据。
+                    buffer.drain(..start);
+                    break;
+                }
+            };
+            let frame = &buffer[start..start + len];
+            let due = match (interval, last_shown) {
+                (Some(interval), Some(last)) => last.elapsed() >= interval,
+                _ => true,
+            };
+            if due {
+                show_frame(frame)?;
+                last_shown = Some(Instant::now());
+            }
+            buffer.drain(..start + len);
+        }
+    }
+}
+
+/// Read `reader` on a thread of its own, passing on each chunk read, an empty one at the end
+/// of the stream, so that waiting for the next one can time out.
+fn read_in_background<R: Read + Send + 'static>(mut reader: R) -> Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::sync_channel(4);
+    thread::spawn(move || {
+        let mut chunk = vec![0; CHUNK_LEN];
+        loop {
+            let result = match reader.read(&mut chunk) {
+                // 被信号打断的读取什么也没读到，重新读取即可。
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result.map(|n| chunk[..n].to_vec()),
+            };
+            let last = !matches!(&result, Ok(data) if !data.is_empty());
+            // 播放结束后接收端被丢弃，读取线程也随之退出。
+            if sender.send(result).is_err() || last {
+                return;
+            }
+        }
+    });
+    receiver
+}
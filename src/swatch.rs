@@ -0,0 +1,170 @@
+use std::io::{self, Write};
+
+/// Width of a swatch in cells.
+const WIDTH: usize = 12;
+
+/// Parse a CSS color: `#rgb`, `#rrggbb` (the `#` may be left out), `rgb(r, g, b)` or
+/// `hsl(h, s%, l%)`.
+pub fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let invalid = || format!("invalid color {value:?}, expected #rrggbb, rgb(r, g, b) or hsl(h, s%, l%)");
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+    if let Some(arguments) = function_arguments(&lower, "rgb") {
+        let [r, g, b] = arguments.map(|a| match a.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0 * 255.0),
+            None => a.parse::<f64>().ok(),
+        });
+        return match (r, g, b) {
+            (Some(r), Some(g), Some(b)) => Ok([r, g, b].map(|c| c.round().clamp(0.0, 255.0) as u8)),
+            _ => Err(invalid()),
+        };
+    }
+    if let Some([h, s, l]) = function_arguments(&lower, "hsl") {
+        let h = h.trim_end_matches("deg").parse::<f64>().map_err(|_| invalid())?;
+        let percent = |a: &str| a.strip_suffix('%').unwrap_or(a).parse::<f64>().map(|p| p.clamp(0.0, 100.0) / 100.0);
+        return Ok(from_hsl(h, percent(s).map_err(|_| invalid())?, percent(l).map_err(|_| invalid())?));
+    }
+    let hex = lower.strip_prefix('#').unwrap_or(&lower);
+    let digits: Option<Vec<u8>> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect();
+    match digits.as_deref() {
+        Some(&[r, g, b]) => Ok([r * 17, g * 17, b * 17]),
+        Some(&[r1, r2, g1, g2, b1, b2]) => Ok([r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2]),
+        _ => Err(invalid()),
+    }
+}
+
+/// The three arguments of `name(a, b, c)`, also accepting the space separated form of
+/// CSS Color 4 and an alpha after them, which is ignored.
+fn function_arguments<'a>(value: &'a str, name: &str) -> Option<[&'a str; 3]> {
+    let inner = value.strip_prefix(name)?.trim_start_matches('a').trim_start().strip_prefix('(')?.strip_suffix(')')?;
+    let inner = inner.split('/').next()?;
+    let mut arguments = inner.split([',', ' ']).map(str::trim).filter(|a| !a.is_empty());
+    Some([arguments.next()?, arguments.next()?, arguments.next()?])
+}
+
+/// Write a solid swatch of each color, followed by its hex, rgb() and hsl() notations.
+/// With `contrast`, black and white text samples are written on the swatch, and the
+/// WCAG contrast ratios of the color with black and white are added to its notations.
+pub fn write(out: &mut impl Write, colors: &[[u8; 3]], contrast: bool) -> io::Result<()> {
+    for (i, &color) in colors.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        let [r, g, b] = color;
+        let background = format!("\x1b[48;2;{r};{g};{b}m");
+        let (h, s, l) = to_hsl(color);
+        let mut label = format!("#{r:02x}{g:02x}{b:02x}  rgb({r}, {g}, {b})  hsl({h:.0}, {:.0}%, {:.0}%)", s * 100.0, l * 100.0);
+        if contrast {
+            let ratio = |other| contrast_ratio(color, other);
+            label += &format!(
+                "  on black {:.1}:1 {}, on white {:.1}:1 {}",
+                ratio([0; 3]), grade(ratio([0; 3])), ratio([255; 3]), grade(ratio([255; 3])),
+            );
+        }
+        writeln!(out, "{background}{:WIDTH$}\x1b[0m", "")?;
+        if contrast {
+            // 左半边黑字，右半边白字，不用终端主题中可能被改过的调色板颜色。
+            let half = WIDTH / 2;
+            writeln!(out, "{background}\x1b[38;2;0;0;0m{:^half$}\x1b[38;2;255;255;255m{:^half$}\x1b[0m  {label}", "Aa", "Aa")?;
+        } else {
+            writeln!(out, "{background}{:WIDTH$}\x1b[0m  {label}", "")?;
+        }
+        writeln!(out, "{background}{:WIDTH$}\x1b[0m", "")?;
+    }
+    Ok(())
+}
+
+/// The WCAG level text of normal size reaches with a contrast `ratio`.
+fn grade(ratio: f64) -> &'static str {
+    match ratio {
+        r if r >= 7.0 => "AAA",
+        r if r >= 4.5 => "AA",
+        r if r >= 3.0 => "AA large",
+        _ => "fail",
+    }
+}
+
+/// The contrast ratio of two colors as defined by WCAG 2, from 1 to 21.
+fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn relative_luminance(color: [u8; 3]) -> f64 {
+    let [r, g, b] = color.map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Hue in degrees, saturation and lightness from 0 to 1.
+fn to_hsl(color: [u8; 3]) -> (f64, f64, f64) {
+    let [r, g, b] = color.map(|c| c as f64 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        (g - b) / d % 6.0
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    ((h * 60.0).rem_euclid(360.0), s, l)
+}
+
+fn from_hsl(h: f64, s: f64, l: f64) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        for (value, color) in [
+            ("#ff8800", [255, 136, 0]), ("F80", [255, 136, 0]), ("rgb(12, 34, 56)", [12, 34, 56]),
+            ("rgba(12 34 56 / 50%)", [12, 34, 56]), ("rgb(100%, 0%, 50%)", [255, 0, 128]),
+            ("hsl(120, 100%, 25%)", [0, 128, 0]), ("HSL(-120deg 100% 50%)", [0, 0, 255]),
+        ] {
+            assert_eq!(parse_color(value), Ok(color), "{value}");
+        }
+        for value in ["#ff88", "red", "rgb(1, 2)", "hsl(x, 1%, 1%)", "#gg0000"] {
+            assert!(parse_color(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn swatches() {
+        let mut out = Vec::new();
+        write(&mut out, &[[255, 136, 0]], false).unwrap();
+        let bar = "\x1b[48;2;255;136;0m            \x1b[0m";
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{bar}\n{bar}  #ff8800  rgb(255, 136, 0)  hsl(32, 100%, 50%)\n{bar}\n"));
+        let mut out = Vec::new();
+        write(&mut out, &[[0, 0, 0], [255, 255, 255]], true).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b[48;2;0;0;0m\x1b[38;2;0;0;0m  Aa  \x1b[38;2;255;255;255m  Aa  \x1b[0m  #000000"));
+        assert!(out.contains("on black 1.0:1 fail, on white 21.0:1 AAA"));
+        assert!(out.contains("on black 21.0:1 AAA, on white 1.0:1 fail"));
+        // 两个色块之间空一行。
+        assert_eq!(out.lines().filter(|l| l.is_empty()).count(), 1);
+    }
+}
@@ -0,0 +1,724 @@
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::Command;
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "tty")]
+use std::fs::OpenOptions;
+#[cfg(feature = "tty")]
+use std::io::{BufRead, BufReader};
+#[cfg(feature = "tty")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(all(feature = "tty", unix))]
+use std::sync::Mutex;
+use crate::probe::Format;
+
+/// The environment and standard streams of the process a command line runs for, used by
+/// [`var_os`], [`stdout`] and the other functions of this module in place of those of this
+/// process. `imgcat --daemon` runs the command lines of its clients on threads of their
+/// own, each in the session of its client: changing the environment or the standard
+/// streams of the process would race with the other threads.
+pub struct Session {
+    pub vars: Vec<(OsString, OsString)>,
+    /// stdin, stdout and stderr, `None` to keep those of the process
+    pub streams: Option<[File; 3]>,
+}
+
+thread_local! {
+    static SESSION: RefCell<Option<Arc<Session>>> = const { RefCell::new(None) };
+}
+
+/// Run the rest of this thread in `session`, or in this process again with `None`.
+/// The threads spawned for the same command line are handed [`session`] to enter.
+pub fn enter(session: Option<Arc<Session>>) {
+    SESSION.with(|current| *current.borrow_mut() = session);
+}
+
+/// The session this thread runs in, if [`enter`] gave one.
+pub fn session() -> Option<Arc<Session>> {
+    SESSION.with(|current| current.borrow().clone())
+}
+
+/// Read the variables of the terminal from `vars` instead of the environment of the process,
+/// or from it again with `None`, on this thread: [`enter`] a session keeping the standard
+/// streams of the process.
+pub fn set_environment(vars: Option<Vec<(OsString, OsString)>>) {
+    enter(vars.map(|vars| Arc::new(Session {vars, streams: None})));
+}
+
+/// The environment variable `name`, as [`env::var_os`] gives it unless this thread runs
+/// in a [`Session`].
+pub fn var_os(name: impl AsRef<OsStr>) -> Option<OsString> {
+    match session() {
+        Some(session) => session.vars.iter().find(|(n, _)| n == name.as_ref()).map(|(_, value)| value.clone()),
+        None => env::var_os(name),
+    }
+}
+
+/// The environment variable `name` as a string, like [`env::var`], read as [`var_os`] does.
+pub fn var(name: impl AsRef<OsStr>) -> Result<String, env::VarError> {
+    match var_os(name) {
+        Some(value) => value.into_string().map_err(env::VarError::NotUnicode),
+        None => Err(env::VarError::NotPresent),
+    }
+}
+
+/// A standard stream of the command line running on this thread: that of the [`Session`]
+/// it runs in, or of the process.
+pub struct Stream {
+    /// 0 for stdin, 1 for stdout and 2 for stderr
+    fd: usize,
+    session: Option<Arc<Session>>,
+}
+
+/// The standard input of the command line running on this thread.
+pub fn stdin() -> Stream {
+    Stream {fd: 0, session: session().filter(|s| s.streams.is_some())}
+}
+
+/// The standard output of the command line running on this thread.
+pub fn stdout() -> Stream {
+    Stream {fd: 1, session: session().filter(|s| s.streams.is_some())}
+}
+
+/// The standard error of the command line running on this thread.
+pub fn stderr() -> Stream {
+    Stream {fd: 2, session: session().filter(|s| s.streams.is_some())}
+}
+
+impl Stream {
+    /// The file of the session, `None` for the streams of the process.
+    fn file(&self) -> Option<&File> {
+        Some(&self.session.as_ref()?.streams.as_ref()?[self.fd])
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        match (self.file(), self.fd) {
+            (Some(file), _) => file.is_terminal(),
+            (None, 0) => io::stdin().is_terminal(),
+            (None, 1) => io::stdout().is_terminal(),
+            (None, _) => io::stderr().is_terminal(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn raw_fd(&self) -> std::os::fd::RawFd {
+        use std::os::fd::AsRawFd;
+        self.file().map_or(self.fd as _, |file| file.as_raw_fd())
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match (self.file(), self.fd) {
+            (Some(mut file), _) => file.read(buf),
+            (None, 0) => io::stdin().read(buf),
+            (None, _) => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match (self.file(), self.fd) {
+            (Some(mut file), _) => file.write(buf),
+            (None, 1) => io::stdout().write(buf),
+            (None, 2) => io::stderr().write(buf),
+            (None, _) => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        // 进程自己的 stdout 要在一次加锁中写完，不和其他线程的输出交错。
+        match (self.file(), self.fd) {
+            (Some(mut file), _) => file.write_all(buf),
+            (None, 1) => io::stdout().write_all(buf),
+            (None, 2) => io::stderr().write_all(buf),
+            (None, _) => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match (self.file(), self.fd) {
+            (Some(mut file), _) => file.flush(),
+            (None, 1) => io::stdout().flush(),
+            (None, 2) => io::stderr().flush(),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// A command running `program` with the environment of [`var_os`].
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    if let Some(session) = session() {
+        command.env_clear().envs(session.vars.iter().map(|(name, value)| (name, value)));
+    }
+    command
+}
+
+/// Size of the terminal window.
+#[derive(Clone, Copy, Debug)]
+pub struct Size {
+    pub columns: u16,
+    pub rows: u16,
+    /// width in pixels, 0 if unknown
+    pub width: u16,
+    /// height in pixels, 0 if unknown
+    pub height: u16,
+}
+
+impl Size {
+    /// Size of a character cell in pixels, assuming 8x16 when the terminal doesn't report its pixel size.
+    pub fn cell_size(&self) -> (f64, f64) {
+        if self.width == 0 || self.height == 0 || self.columns == 0 || self.rows == 0 {
+            return (8.0, 16.0);
+        }
+        (self.width as f64 / self.columns as f64, self.height as f64 / self.rows as f64)
+    }
+
+    /// The same size with cells `aspect` times as wide as they are high, keeping their height.
+    pub fn with_cell_aspect(self, aspect: f64) -> Size {
+        let (_, cell_height) = self.cell_size();
+        let pixels = |cells: u16, cell: f64| (cells as f64 * cell).round().min(u16::MAX as f64) as u16;
+        Size {
+            width: pixels(self.columns, cell_height * aspect),
+            height: pixels(self.rows, cell_height),
+            ..self
+        }
+    }
+}
+
+/// The width of a character cell divided by its height, from the size of the window in
+/// pixels, or as the terminal answers the CSI 16 t query. `None` if unknown.
+pub fn cell_aspect() -> Option<f64> {
+    static ANSWERED: OnceLock<Option<f64>> = OnceLock::new();
+    let size = size();
+    if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 {
+        let (width, height) = size.cell_size();
+        return Some(width / height);
+    }
+    // 只在 stdout 是终端时查询，预览脚本等程序中终端的输入属于别的程序。
+    if !stdout().is_terminal() || host().is_some() {
+        return None;
+    }
+    *ANSWERED.get_or_init(|| {
+        // 回复的形式是 ESC [ 6 ; 高度 ; 宽度 t。
+        let reply = query("\x1b[16t", b't', std::time::Duration::from_millis(300))?;
+        let reply = String::from_utf8(reply).ok()?;
+        let mut fields = reply.trim_start_matches("\x1b[").trim_end_matches('t').split(';');
+        if fields.next()? != "6" {
+            return None;
+        }
+        let height: f64 = fields.next()?.parse().ok()?;
+        let width: f64 = fields.next()?.parse().ok()?;
+        (width > 0.0 && height > 0.0).then(|| width / height)
+    })
+}
+
+/// Query the size of the terminal, falling back to `$COLUMNS` and `$LINES`
+/// (and eventually 80x24) when none of the standard streams is a terminal.
+pub fn size() -> Size {
+    query_size()
+        .unwrap_or_else(|| {
+            let var = |name, default| var(name).ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(default);
+            Size {columns: var("COLUMNS", 80), rows: var("LINES", 24), width: 0, height: 0}
+        })
+}
+
+#[cfg(unix)]
+fn query_size() -> Option<Size> {
+    [stdout().raw_fd(), stderr().raw_fd(), stdin().raw_fd()]
+        .into_iter()
+        .find_map(|fd| {
+            let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+            let ok = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) } == 0;
+            (ok && ws.ws_col > 0).then_some(Size {
+                columns: ws.ws_col,
+                rows: ws.ws_row,
+                width: ws.ws_xpixel,
+                height: ws.ws_ypixel,
+            })
+        })
+}
+
+#[cfg(not(unix))]
+fn query_size() -> Option<Size> {
+    None
+}
+
+/// Whether the terminal decodes images in `format` itself: iTerm2 through macOS and
+/// WezTerm through the image crate decode every format imgcat recognizes. Other terminals
+/// implementing the iTerm2 protocol, such as VS Code and mintty, only take PNG, JPEG and GIF.
+pub fn accepts(format: Format) -> bool {
+    // 通过 ssh 登录时 TERM_PROGRAM 不会被传递，但 iTerm2 设置的 LC_TERMINAL 通常会。
+    matches!(format, Format::Png | Format::Jpeg | Format::Gif)
+        || var("TERM_PROGRAM").is_ok_and(|p| p == "iTerm.app")
+        || var("LC_TERMINAL").is_ok_and(|t| t == "iTerm2")
+        || wezterm()
+}
+
+/// Whether imgcat runs in WezTerm, from `$TERM_PROGRAM`, or inside tmux, which replaces
+/// `$TERM_PROGRAM`, from `$WEZTERM_PANE`, which WezTerm sets in its panes.
+pub fn wezterm() -> bool {
+    match var("TERM_PROGRAM") {
+        Ok(program) if program == "WezTerm" => true,
+        Ok(program) if program != "tmux" => false,
+        _ => var_os("WEZTERM_PANE").is_some(),
+    }
+}
+
+/// A program showing the terminal imgcat runs in in a window or panel of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Host {
+    /// Neovim, whose terminals don't show images, but which can send them to the terminal it runs in
+    Neovim,
+    /// Visual Studio Code, whose terminal implements the iTerm2 protocol and sixel
+    VsCode,
+    /// the subshell of Midnight Commander, which only passes text through to the terminal
+    MidnightCommander,
+}
+
+/// The program hosting the terminal, from `$NVIM`, which Neovim sets to the address
+/// of its server in its terminals, `$TERM_PROGRAM`, and `$MC_SID`, which Midnight
+/// Commander sets in its subshell.
+pub fn host() -> Option<Host> {
+    if var_os("NVIM").is_some() {
+        Some(Host::Neovim)
+    } else if var("TERM_PROGRAM").is_ok_and(|p| p == "vscode") {
+        Some(Host::VsCode)
+    } else if var_os("MC_SID").is_some() {
+        Some(Host::MidnightCommander)
+    } else {
+        None
+    }
+}
+
+/// Whether imgcat runs in Windows Terminal, which sets `$WT_SESSION` and draws sixel
+/// graphics but not the images of the iTerm2 protocol. Terminals started from it inherit
+/// the variable, so it only counts when `$TERM_PROGRAM` doesn't name another terminal.
+pub fn windows_terminal() -> bool {
+    var_os("WT_SESSION").is_some() && var_os("TERM_PROGRAM").is_none()
+}
+
+/// Whether imgcat runs in ConEmu or Cmder, which set `$ConEmuANSI` and draw no images.
+pub fn conemu() -> bool {
+    var_os("ConEmuANSI").is_some() && var_os("TERM_PROGRAM").is_none()
+}
+
+/// Whether imgcat runs in Konsole, which sets `$KONSOLE_VERSION` and implements part of
+/// kitty's graphics protocol but not iTerm2's. Terminals started from it inherit the
+/// variable, so it doesn't count when `$TERM_PROGRAM` names another terminal, tmux aside,
+/// or when kitty's `$KITTY_WINDOW_ID` is set.
+pub fn konsole() -> bool {
+    var_os("KONSOLE_VERSION").is_some()
+        && var("TERM_PROGRAM").map_or(true, |p| p == "tmux")
+        && var_os("KITTY_WINDOW_ID").is_none()
+}
+
+/// Whether imgcat runs in a terminal known to implement none of the protocols of images:
+/// Alacritty, which sets `$ALACRITTY_WINDOW_ID`, or GNOME Terminal and the other terminals
+/// built on VTE, which set `$VTE_VERSION`. Terminals started from them inherit the
+/// variables, so they only count when `$TERM_PROGRAM` doesn't name another terminal than tmux.
+pub fn without_images() -> bool {
+    (var_os("ALACRITTY_WINDOW_ID").is_some() || var_os("VTE_VERSION").is_some())
+        && var("TERM_PROGRAM").map_or(true, |p| p == "tmux")
+}
+
+/// Whether `$TERM` tells that the terminal only shows text: a dumb terminal, or the
+/// console of Linux. On Unix, it is also the case when `$TERM` isn't set, as when
+/// running from cron, whose output is mailed.
+pub fn text_only() -> bool {
+    match var("TERM") {
+        Ok(term) => term == "dumb" || term == "linux",
+        // Windows 的终端通常不设置 TERM。
+        Err(_) => cfg!(unix),
+    }
+}
+
+/// Number of colors the terminal shows in its escape sequences of colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// any RGB color, with `38;2;R;G;B`
+    Truecolor,
+    /// the 256 colors of xterm, with `38;5;N`
+    Colors256,
+    /// the 16 colors of ANSI, with `30` to `37` and `90` to `97`
+    Colors16,
+}
+
+impl ColorDepth {
+    pub const ALL: [ColorDepth; 3] = [ColorDepth::Truecolor, ColorDepth::Colors256, ColorDepth::Colors16];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorDepth::Truecolor => "truecolor",
+            ColorDepth::Colors256 => "256",
+            ColorDepth::Colors16 => "16",
+        }
+    }
+}
+
+impl std::str::FromStr for ColorDepth {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        ColorDepth::ALL
+            .into_iter()
+            .find(|d| d.name() == value)
+            .ok_or_else(|| format!("unknown color depth {value}, expected truecolor, 256 or 16"))
+    }
+}
+
+/// Number of colors of the terminal, from `$COLORTERM`, which truecolor terminals set to
+/// `truecolor` or `24bit`, then the variables of terminals known to show truecolor
+/// without setting it, and the name of the terminfo entry in `$TERM`: `-direct` entries
+/// show truecolor and `-256color` ones 256 colors. Other terminals get 16 colors.
+pub fn color_depth() -> ColorDepth {
+    if var("COLORTERM").is_ok_and(|c| c == "truecolor" || c == "24bit") {
+        return ColorDepth::Truecolor;
+    }
+    let term = var("TERM").unwrap_or_default();
+    // 经过 ssh 时 COLORTERM 不会被传递，但这些终端的变量通常会，或者 TERM 本身就说明了终端。
+    let known = var("TERM_PROGRAM").is_ok_and(|p| TRUECOLOR_TERMINALS.contains(&p.as_str()))
+        || var_os("LC_TERMINAL").is_some()
+        || var_os("KITTY_WINDOW_ID").is_some()
+        || windows_terminal()
+        || konsole()
+        || term.ends_with("-direct")
+        || term == "xterm-kitty"
+        || term == "xterm-ghostty";
+    if known {
+        ColorDepth::Truecolor
+    } else if term.contains("256color") {
+        ColorDepth::Colors256
+    } else {
+        ColorDepth::Colors16
+    }
+}
+
+/// Values of `$TERM_PROGRAM` of terminals showing truecolor.
+const TRUECOLOR_TERMINALS: [&str; 8] = ["iTerm.app", "WezTerm", "vscode", "ghostty", "mintty", "Tabby", "rio", "WarpTerminal"];
+
+/// Whether the output is recorded by asciinema, which sets `$ASCIINEMA_REC` in the
+/// shell it records and doesn't keep images in the recordings.
+pub fn recorded() -> bool {
+    var_os("ASCIINEMA_REC").is_some()
+}
+
+#[cfg(all(feature = "tty", unix))]
+const TTY: &str = "/dev/tty";
+#[cfg(all(feature = "tty", windows))]
+const TTY: &str = "CONIN$";
+
+/// Wrap `text` in an OSC 8 hyperlink to `uri`.
+pub fn hyperlink(uri: &str, text: &str) -> String {
+    // OSC 8 不需要 tmux 的 passthrough，tmux 3.4 起会自行转发超链接。
+    format!("\x1b]8;;{uri}\x1b\\{text}\x1b]8;;\x1b\\")
+}
+
+/// Name of this host, used in `file://` URIs so that terminals can tell remote files from local ones.
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return None;
+    }
+    let len = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8(buffer[..len].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> Option<String> {
+    var("COMPUTERNAME").ok()
+}
+
+/// Print `prompt` on stderr and read a line typed on the controlling terminal,
+/// even when stdin is redirected.
+#[cfg(feature = "tty")]
+pub fn prompt_line(prompt: &str) -> io::Result<String> {
+    let tty = OpenOptions::new().read(true).open(TTY)?;
+    let mut stderr = stderr();
+    write!(stderr, "{prompt}")?;
+    stderr.flush()?;
+    let mut line = String::new();
+    BufReader::new(tty).read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Point stdout at the controlling terminal and return the original stdout,
+/// so that images are still displayed when the output of imgcat is captured.
+#[cfg(feature = "tty")]
+pub fn redirect_stdout_to_tty() -> io::Result<File> {
+    redirect_stdout(TTY)
+}
+
+/// Point stdout at the terminal device `path` and return the original stdout.
+#[cfg(all(feature = "tty", unix))]
+pub fn redirect_stdout(path: &str) -> io::Result<File> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let tty = OpenOptions::new().write(true).open(path)?;
+    io::stdout().flush()?;
+    let saved = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let saved = unsafe { File::from_raw_fd(saved) };
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(saved)
+}
+
+#[cfg(all(feature = "tty", not(unix)))]
+pub fn redirect_stdout(_path: &str) -> io::Result<File> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Puts a terminal into non-canonical mode without echo for as long as it is alive,
+/// so single keypresses can be read. Signals such as Ctrl-C are still delivered.
+#[cfg(all(feature = "tty", unix))]
+pub struct RawMode<'a> {
+    tty: &'a File,
+    original: libc::termios,
+}
+
+#[cfg(all(feature = "tty", unix))]
+impl<'a> RawMode<'a> {
+    pub fn enable(tty: &'a File) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = termios;
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        RAW_MODES.lock().unwrap_or_else(|e| e.into_inner()).push((tty.as_raw_fd(), original));
+        Ok(Self {tty, original})
+    }
+}
+
+#[cfg(all(feature = "tty", unix))]
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original) };
+        let mut modes = RAW_MODES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = modes.iter().rposition(|(fd, _)| *fd == self.tty.as_raw_fd()) {
+            modes.remove(i);
+        }
+    }
+}
+
+/// The terminals in raw mode and their original settings, in the order they were
+/// changed, for [`restore`].
+#[cfg(all(feature = "tty", unix))]
+static RAW_MODES: Mutex<Vec<(libc::c_int, libc::termios)>> = Mutex::new(Vec::new());
+
+/// Whether an [`AlternateScreen`] is alive, for [`restore`].
+#[cfg(feature = "tty")]
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Put the terminal back in the state imgcat found it in, for when it is interrupted
+/// and the destructors of [`RawMode`] and [`AlternateScreen`] won't run: restore the
+/// settings of terminals in raw mode, end the escape sequence of an image cut off in
+/// the middle, reset the colors, show the cursor and leave the alternate screen.
+#[cfg(feature = "tty")]
+pub fn restore() {
+    #[cfg(unix)]
+    for (fd, original) in RAW_MODES.lock().unwrap_or_else(|e| e.into_inner()).drain(..).rev() {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    }
+    // 输出被重定向到文件时不写入，文件中已经是不完整的图片了。
+    if !io::stdout().is_terminal() {
+        return;
+    }
+    // ST 结束 OSC、APC 和 DCS（包括 tmux 的 passthrough），终端不再把后面的输入当作图片数据。
+    let mut reset = "\x1b\\\x1b[0m\x1b[?25h".to_string();
+    if ALTERNATE_SCREEN.swap(false, Ordering::Relaxed) {
+        reset += "\x1b[?1049l";
+    }
+    // stdout 的锁可能正被主线程持有，直接写文件描述符。
+    #[cfg(unix)]
+    unsafe { libc::write(libc::STDOUT_FILENO, reset.as_ptr() as *const libc::c_void, reset.len()) };
+    #[cfg(not(unix))]
+    let _ = io::stdout().write_all(reset.as_bytes());
+}
+
+/// Restore the terminal with [`restore`] and exit when imgcat is interrupted with Ctrl-C
+/// or terminated, with the status of a process killed by the signal, 128 plus its number.
+///
+/// The signals are blocked and waited for on a thread of their own, so this has to be
+/// called before any other thread is started, which then inherit the blocked signals.
+/// Child processes get the default signal mask back.
+#[cfg(all(feature = "tty", unix))]
+pub fn restore_on_interrupt() -> io::Result<()> {
+    let mut signals: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigaddset(&mut signals, libc::SIGHUP);
+    }
+    let error = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) };
+    if error != 0 {
+        return Err(io::Error::from_raw_os_error(error));
+    }
+    std::thread::Builder::new().name("signals".to_string()).spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            restore();
+            std::process::exit(128 + signal);
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(all(feature = "tty", not(unix)))]
+pub fn restore_on_interrupt() -> io::Result<()> {
+    Ok(())
+}
+
+/// Switches to the alternate screen and hides the cursor for as long as it is alive.
+#[cfg(feature = "tty")]
+pub struct AlternateScreen;
+
+#[cfg(feature = "tty")]
+impl AlternateScreen {
+    pub fn enter() -> Self {
+        print!("\x1b[?1049h\x1b[?25l");
+        let _ = io::stdout().flush();
+        ALTERNATE_SCREEN.store(true, Ordering::Relaxed);
+        AlternateScreen
+    }
+}
+
+#[cfg(feature = "tty")]
+impl Drop for AlternateScreen {
+    fn drop(&mut self) {
+        ALTERNATE_SCREEN.store(false, Ordering::Relaxed);
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Open the controlling terminal for reading keys.
+#[cfg(feature = "tty")]
+pub fn open_tty() -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open(TTY)
+}
+
+/// Send a query to the terminal and read its reply up to and including
+/// `terminator`. `None` if the terminal doesn't answer within `timeout`.
+#[cfg(all(feature = "tty", unix))]
+pub fn query(request: &str, terminator: u8, timeout: std::time::Duration) -> Option<Vec<u8>> {
+    use std::io::Read;
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let tty = open_tty().ok()?;
+    let _raw = RawMode::enable(&tty).ok()?;
+    (&tty).write_all(request.as_bytes()).ok()?;
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    loop {
+        let left = deadline.checked_duration_since(Instant::now())?;
+        let mut fd = libc::pollfd {fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0};
+        if unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            return None;
+        }
+        let mut byte = [0u8; 1];
+        if (&tty).read(&mut byte).ok()? == 0 {
+            return None;
+        }
+        reply.push(byte[0]);
+        if byte[0] == terminator {
+            return Some(reply);
+        }
+    }
+}
+
+#[cfg(not(all(feature = "tty", unix)))]
+pub fn query(_request: &str, _terminator: u8, _timeout: std::time::Duration) -> Option<Vec<u8>> {
+    None
+}
+
+/// The parameters of the terminal's answer to the primary device attributes (DA1) query,
+/// which list the features it implements, such as 3 for ReGIS and 4 for sixel graphics.
+/// `None` if it doesn't answer within `timeout`.
+pub fn device_attributes(timeout: std::time::Duration) -> Option<Vec<String>> {
+    let reply = query("\x1b[c", b'c', timeout)?;
+    let attributes = std::str::from_utf8(&reply)
+        .ok()
+        .and_then(|reply| reply.strip_prefix("\x1b[?"))
+        .map(|reply| reply.trim_end_matches('c').split(';').map(str::to_string).collect());
+    Some(attributes.unwrap_or_default())
+}
+
+/// Whether the terminal draws sixel graphics, as its [`device_attributes`] tell. `None`
+/// if it doesn't answer within `timeout`.
+pub fn sixel(timeout: std::time::Duration) -> Option<bool> {
+    Some(device_attributes(timeout)?.iter().any(|p| p == "4"))
+}
+
+/// The row and column of the cursor, counted from 1, as the terminal answers the DSR
+/// query. `None` if it doesn't answer within `timeout`.
+pub fn cursor_position(timeout: std::time::Duration) -> Option<(u16, u16)> {
+    // 回复的形式是 ESC [ 行 ; 列 R。
+    let reply = String::from_utf8(query("\x1b[6n", b'R', timeout)?).ok()?;
+    let (row, column) = reply.strip_prefix("\x1b[")?.strip_suffix('R')?.split_once(';')?;
+    Some((row.parse().ok()?, column.parse().ok()?))
+}
+
+/// The background color of the terminal, as it answers the OSC 11 query, or as the
+/// palette index of `$COLORFGBG` set by rxvt and Konsole tells. `None` if unknown.
+pub fn background() -> Option<[u8; 3]> {
+    let reply = query("\x1b]11;?\x07", b'\x07', std::time::Duration::from_millis(300));
+    // 回复的形式是 ESC ] 11 ; rgb:RRRR/GGGG/BBBB BEL，每个分量有 1 到 4 位十六进制数字。
+    let color = reply.as_deref()
+        .and_then(|r| std::str::from_utf8(r).ok())
+        .and_then(|r| r.split_once("rgb:"))
+        .and_then(|(_, rgb)| {
+            let mut components = rgb.trim_end_matches(['\x07', '\x1b', '\\']).split('/').map(|c| {
+                let max = 16u32.checked_pow(c.len() as u32)? - 1;
+                u32::from_str_radix(c, 16).ok().map(|v| (v * 255 / max.max(1)) as u8)
+            });
+            Some([components.next()??, components.next()??, components.next()??])
+        });
+    color.or_else(|| {
+        let index: u8 = var("COLORFGBG").ok()?.rsplit(';').next()?.parse().ok()?;
+        Some(if matches!(index, 7 | 15) { [255; 3] } else { [0; 3] })
+    })
+}
+
+/// Wait for a single keypress on the controlling terminal.
+#[cfg(all(feature = "tty", unix))]
+pub fn read_key() -> io::Result<u8> {
+    use std::io::Read;
+
+    let tty = open_tty()?;
+    let _raw = RawMode::enable(&tty)?;
+    let mut key = [0u8; 1];
+    (&tty).read_exact(&mut key)?;
+    Ok(key[0])
+}
+
+/// Wait for a single keypress on the controlling terminal.
+///
+/// The console is left in line mode, so the key has to be followed by Enter.
+#[cfg(all(feature = "tty", not(unix)))]
+pub fn read_key() -> io::Result<u8> {
+    Ok(prompt_line("")?.bytes().next().unwrap_or(b'\n'))
+}
@@ -0,0 +1,57 @@
+use crate::bitmap::Bitmap;
+
+/// Size of the test pattern in pixels, 4:3 like the classic test cards.
+pub const WIDTH: usize = 480;
+pub const HEIGHT: usize = 360;
+
+/// The colors of the bars, at full intensity, in the order of the SMPTE bars.
+const BARS: [[u8; 3]; 8] = [
+    [255, 255, 255], [255, 255, 0], [0, 255, 255], [0, 255, 0],
+    [255, 0, 255], [255, 0, 0], [0, 0, 255], [0, 0, 0],
+];
+
+/// Periods in pixels of the line pairs of the resolution wedges.
+const WEDGE_PERIODS: [usize; 4] = [1, 2, 3, 4];
+
+/// A test card: color bars at the top, a gray ramp and red, green and blue ramps below
+/// them, resolution wedges of alternating lines one to four pixels wide at the bottom
+/// left, vertical above horizontal, and a circle in a square at the bottom right, which
+/// only stays round when the image is shown with its aspect ratio.
+pub fn generate() -> Bitmap {
+    let mut bitmap = Bitmap::new(WIDTH as u32, HEIGHT as u32);
+    let mut fill = |x0: usize, y0: usize, x1: usize, y1: usize, color: &dyn Fn(usize, usize) -> [u8; 3]| {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let [r, g, b] = color(x, y);
+                bitmap.set(x, y, [r, g, b, 255]);
+            }
+        }
+    };
+    let bar_width = WIDTH / BARS.len();
+    fill(0, 0, WIDTH, 100, &|x, _| BARS[(x / bar_width).min(BARS.len() - 1)]);
+    let ramp = |x: usize| (x * 255 / (WIDTH - 1)) as u8;
+    fill(0, 100, WIDTH, 140, &|x, _| [ramp(x); 3]);
+    fill(0, 140, WIDTH, 160, &|x, _| [ramp(x), 0, 0]);
+    fill(0, 160, WIDTH, 180, &|x, _| [0, ramp(x), 0]);
+    fill(0, 180, WIDTH, 200, &|x, _| [0, 0, ramp(x)]);
+
+    let wedge_width = 320 / WEDGE_PERIODS.len();
+    let line = |n: usize, period: usize| if (n / period).is_multiple_of(2) { [255; 3] } else { [0; 3] };
+    fill(0, 200, 320, 280, &|x, _| line(x, WEDGE_PERIODS[x / wedge_width]));
+    fill(0, 280, 320, HEIGHT, &|x, y| line(y, WEDGE_PERIODS[x / wedge_width]));
+
+    // 右下角 160x160 的正方形中画一个圆和十字线，画面被拉伸时圆会变成椭圆。
+    let (cx, cy, side) = (400.0, 280.0, 160.0);
+    fill(320, 200, WIDTH, HEIGHT, &|x, y| {
+        let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+        let radius = (dx * dx + dy * dy).sqrt();
+        let edge = dx.abs().max(dy.abs()) >= side / 2.0 - 1.0;
+        let cross = dx.abs() < 1.0 || dy.abs() < 1.0;
+        match () {
+            _ if (radius - 70.0).abs() < 1.5 => [255; 3],
+            _ if edge || cross => [128; 3],
+            _ => [0; 3],
+        }
+    });
+    bitmap
+}
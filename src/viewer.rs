@@ -0,0 +1,143 @@
+use std::io::{self, Read, Write};
+use anyhow::bail;
+use crate::bitmap::Bitmap;
+use crate::layout::Length;
+use crate::{print_inline, renderer, terminal, Cli, Image, ImgcatError};
+use crate::i18n::tr;
+
+const HELP: &str = "n/p: next/previous  +/-: zoom  r: rotate  q: quit";
+const TOGGLE_HELP: &str = "space: switch image  +/-: zoom  r: rotate  q: quit";
+
+/// State of the interactive viewer.
+struct View<'a> {
+    inputs: &'a [String],
+    index: usize,
+    /// size of the image in percent of the screen
+    zoom: u16,
+    image: Option<Image<'a>>,
+    /// whether moving past the last or first image wraps around, to flip between images
+    wrap: bool,
+    /// the current image rotated by the r key, decoded
+    rotated: Option<Bitmap>,
+    message: Option<String>,
+}
+
+impl<'a> View<'a> {
+    fn load(&mut self) {
+        self.rotated = None;
+        self.message = None;
+        self.image = match Image::open(&self.inputs[self.index]) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                self.message = Some(format!("{e:#}"));
+                None
+            }
+        };
+    }
+
+    fn rotate(&mut self) {
+        let Some(image) = &self.image else {
+            return;
+        };
+        let bitmap = match self.rotated.take() {
+            Some(b) => Ok(b),
+            None => Bitmap::decode(&image.data),
+        };
+        match bitmap {
+            Ok(b) => self.rotated = Some(b.rotate90()),
+            Err(e) => self.message = Some(format!("cannot rotate: {e:#}")),
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write, args: &Cli) -> io::Result<()> {
+        let size = terminal::size();
+        renderer(args).clear(out)?;
+        if let Some(image) = &self.image {
+            let width = Length::Cells((size.columns as u32 * self.zoom as u32 / 100).max(1));
+            let height = Length::Cells((size.rows.saturating_sub(1) as u32 * self.zoom as u32 / 100).max(1));
+            match &self.rotated {
+                Some(bitmap) => {
+                    let rotated = Image {
+                        data: bitmap.to_png().into(),
+                        filename: image.filename.clone(),
+                        path: image.path,
+                        file_type: Some(".png".to_string()),
+                    };
+                    print_inline(out, &rotated, args, Some(width), Some(height))?;
+                }
+                None => {
+                    print_inline(out, image, args, Some(width), Some(height))?;
+                }
+            }
+        }
+        let mut status = format!(
+            "[{}/{}] {}  {}%  {}",
+            self.index + 1,
+            self.inputs.len(),
+            self.inputs[self.index],
+            self.zoom,
+            if self.wrap { TOGGLE_HELP } else { HELP },
+        );
+        if let Some(message) = &self.message {
+            status = format!("{status}  ({message})");
+        }
+        let status: String = status.chars().take(size.columns as usize).collect();
+        write!(out, "\x1b[{};1H\x1b[7m{status}\x1b[0m", size.rows)?;
+        out.flush()
+    }
+}
+
+/// Browse `inputs` full screen on `out`, one image at a time, until q is pressed.
+pub fn run(out: &mut impl Write, inputs: &[String], args: &Cli) -> anyhow::Result<()> {
+    browse(out, inputs, args, false)
+}
+
+/// Flip between `inputs` full screen, going back to the first image after the
+/// last one, so that differences stand out as the images replace each other in place.
+pub fn toggle(out: &mut impl Write, inputs: &[String], args: &Cli) -> anyhow::Result<()> {
+    browse(out, inputs, args, true)
+}
+
+fn browse(out: &mut impl Write, inputs: &[String], args: &Cli, wrap: bool) -> anyhow::Result<()> {
+    if inputs.is_empty() {
+        bail!(tr!("the interactive viewer needs input files or URLs"));
+    }
+    let tty = terminal::open_tty()
+        .map_err(|e| ImgcatError::UnsupportedTerminal(format!("the interactive viewer needs a terminal: {e}")))?;
+    #[cfg(unix)]
+    let _raw = terminal::RawMode::enable(&tty)?;
+    let _screen = terminal::AlternateScreen::enter();
+
+    let mut view = View {inputs, index: 0, zoom: 100, image: None, wrap, rotated: None, message: None};
+    view.load();
+    loop {
+        view.draw(out, args)?;
+        let mut key = [0u8; 1];
+        if (&tty).read(&mut key)? == 0 {
+            return Ok(());
+        }
+        match key[0] {
+            b'q' | b'Q' => return Ok(()),
+            b'n' | b' ' if view.index + 1 < inputs.len() => {
+                view.index += 1;
+                view.load();
+            }
+            b'p' | b'\x7f' if view.index > 0 => {
+                view.index -= 1;
+                view.load();
+            }
+            b'n' | b' ' if wrap => {
+                view.index = 0;
+                view.load();
+            }
+            b'p' | b'\x7f' if wrap => {
+                view.index = inputs.len() - 1;
+                view.load();
+            }
+            b'+' | b'=' => view.zoom = (view.zoom * 5 / 4).min(400),
+            b'-' => view.zoom = (view.zoom * 4 / 5).max(10),
+            b'r' => view.rotate(),
+            _ => {}
+        }
+    }
+}
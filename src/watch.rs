@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::Context;
+use crate::i18n::tr;
+
+/// Time to wait for further events after a change is noticed, so that a file
+/// written in several steps is only reported once it is complete.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Watches a directory for files that are written or moved into it.
+///
+/// On Linux inotify is used. Elsewhere the directory is polled, comparing the
+/// modification time and size of its entries.
+pub struct DirWatcher {
+    dir: PathBuf,
+    #[cfg(target_os = "linux")]
+    inotify: std::fs::File,
+    #[cfg(not(target_os = "linux"))]
+    snapshot: std::collections::HashMap<PathBuf, (std::time::SystemTime, u64)>,
+}
+
+impl DirWatcher {
+    /// Block until some files in the directory change and return their paths.
+    pub fn wait(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut changed = self.next_changes()?;
+        while self.poll_readable(SETTLE_TIME) {
+            for path in self.next_changes()? {
+                if !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DirWatcher {
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        use std::ffi::CString;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::os::unix::ffi::OsStrExt;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| tr!("failed to initialize inotify"));
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let c_dir = CString::new(dir.as_os_str().as_bytes())?;
+        // 监视整个目录而不是文件本身：很多程序通过写临时文件再重命名的方式更新文件，
+        // 这会使针对原文件的监视失效。
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| tr!("failed to watch {}", dir.display()));
+        }
+        Ok(Self {dir: dir.to_path_buf(), inotify: fd.into()})
+    }
+
+    fn next_changes(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        use std::ffi::OsStr;
+        use std::io::Read;
+        use std::os::unix::ffi::OsStrExt;
+
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut buffer = [0u8; 4096];
+        let n = self.inotify.read(&mut buffer)
+            .with_context(|| tr!("failed to read inotify events"))?;
+        let mut changed = Vec::new();
+        let mut pos = 0;
+        while pos + HEADER <= n {
+            let event = unsafe {
+                std::ptr::read_unaligned(buffer[pos..].as_ptr() as *const libc::inotify_event)
+            };
+            let name = &buffer[pos + HEADER..pos + HEADER + event.len as usize];
+            // 文件名以 NUL 结尾，并可能带有额外的 NUL 填充。
+            let name = name.split(|b| *b == 0).next().unwrap_or_default();
+            if !name.is_empty() {
+                changed.push(self.dir.join(OsStr::from_bytes(name)));
+            }
+            pos += HEADER + event.len as usize;
+        }
+        Ok(changed)
+    }
+
+    fn poll_readable(&self, timeout: Duration) -> bool {
+        use std::os::fd::AsRawFd;
+
+        let mut pfd = libc::pollfd {fd: self.inotify.as_raw_fd(), events: libc::POLLIN, revents: 0};
+        unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) > 0 }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl DirWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        let mut watcher = Self {dir: dir.to_path_buf(), snapshot: Default::default()};
+        watcher.snapshot = watcher.scan()?;
+        Ok(watcher)
+    }
+
+    fn next_changes(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        loop {
+            let snapshot = self.scan()?;
+            let changed: Vec<_> = snapshot.iter()
+                .filter(|(path, stat)| self.snapshot.get(*path) != Some(*stat))
+                .map(|(path, _)| path.clone())
+                .collect();
+            self.snapshot = snapshot;
+            if !changed.is_empty() {
+                return Ok(changed);
+            }
+            std::thread::sleep(Self::POLL_INTERVAL);
+        }
+    }
+
+    fn poll_readable(&self, timeout: Duration) -> bool {
+        // 轮询模式下无法得知是否还有后续修改，等待一段时间后直接认为文件已经写完。
+        std::thread::sleep(timeout);
+        false
+    }
+
+    fn scan(&self) -> anyhow::Result<std::collections::HashMap<PathBuf, (std::time::SystemTime, u64)>> {
+        let mut snapshot = std::collections::HashMap::new();
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| tr!("failed to read directory {}", self.dir.display()))?;
+        for entry in entries.flatten() {
+            if let Ok(m) = entry.metadata() {
+                if m.is_file() {
+                    snapshot.insert(entry.path(), (m.modified()?, m.len()));
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+}
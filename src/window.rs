@@ -0,0 +1,185 @@
+use anyhow::Context;
+use imgcat::bitmap::Bitmap;
+use crate::i18n::tr;
+use crate::terminal;
+
+/// Whether a display server windows can be opened on is reachable: `$WAYLAND_DISPLAY` or
+/// `$DISPLAY` on Linux and the BSDs, also when forwarded over SSH, and the desktop on
+/// macOS and Windows unless logged in with SSH. Never without the `window` feature.
+pub fn display_server() -> bool {
+    cfg!(feature = "window") && match cfg!(any(target_os = "macos", windows)) {
+        true => !crate::clipboard::remote(),
+        false => terminal::var_os("WAYLAND_DISPLAY").is_some() || terminal::var_os("DISPLAY").is_some(),
+    }
+}
+
+/// Show an image in a window of its own, fitted into it, and wait until the window is
+/// closed, with Escape, `q` or its close button.
+pub fn open(data: &[u8], name: &str) -> anyhow::Result<()> {
+    let bitmap = Bitmap::decode(data).with_context(|| tr!("failed to decode {}", name))?;
+    show(bitmap, format!("{name} - imgcat")).with_context(|| tr!("failed to open a window for {}", name))
+}
+
+#[cfg(not(feature = "window"))]
+fn show(_bitmap: Bitmap, _title: String) -> anyhow::Result<()> {
+    anyhow::bail!(tr!("imgcat was built without the window feature"))
+}
+
+#[cfg(feature = "window")]
+use native::show;
+
+#[cfg(feature = "window")]
+mod native {
+    use std::cell::RefCell;
+    use std::num::NonZeroU32;
+    use std::rc::Rc;
+    use imgcat::bitmap::Bitmap;
+    use softbuffer::{SoftBufferError, Surface};
+    use winit::application::ApplicationHandler;
+    use winit::dpi::LogicalSize;
+    use winit::event::{ElementState, KeyEvent, WindowEvent};
+    use winit::event_loop::{ActiveEventLoop, EventLoop};
+    use winit::keyboard::{Key, NamedKey};
+    use winit::platform::run_on_demand::EventLoopExtRunOnDemand;
+    use winit::window::{Window, WindowId};
+
+    /// Largest size a window opens with, in logical pixels; larger images are scaled down.
+    const MAX_WIDTH: f64 = 1200.0;
+    const MAX_HEIGHT: f64 = 900.0;
+
+    /// Gray the transparent parts of the images are drawn over, and the window around them.
+    const BACKGROUND: u32 = 0x20;
+
+    thread_local! {
+        /// The event loop of the windows, created with the first one: winit allows a
+        /// single one in a process, run again for every window. When it can't be created,
+        /// the error is kept for the next windows, as it can't be tried again.
+        static EVENT_LOOP: RefCell<Option<Result<EventLoop<()>, String>>> = const { RefCell::new(None) };
+    }
+
+    pub fn show(bitmap: Bitmap, title: String) -> anyhow::Result<()> {
+        EVENT_LOOP.with(|event_loop| {
+            let mut event_loop = event_loop.borrow_mut();
+            let event_loop = match &mut *event_loop {
+                Some(event_loop) => event_loop,
+                None => event_loop.insert(EventLoop::new().map_err(|e| e.to_string())),
+            };
+            let event_loop = event_loop.as_mut().map_err(|e| anyhow::anyhow!("{e}"))?;
+            let mut viewer = Viewer {bitmap, title, scaled: None, window: None, error: None};
+            event_loop.run_app_on_demand(&mut viewer)?;
+            viewer.error.map_or(Ok(()), Err)
+        })
+    }
+
+    /// An error of softbuffer, which may hold handles of the display that can't be sent
+    /// to other threads, as its message.
+    fn failed(e: SoftBufferError) -> anyhow::Error {
+        anyhow::anyhow!("{e}")
+    }
+
+    /// The pixels of a window, drawn by softbuffer.
+    type WindowSurface = Surface<Rc<Window>, Rc<Window>>;
+
+    /// A window showing a bitmap.
+    struct Viewer {
+        bitmap: Bitmap,
+        title: String,
+        /// the bitmap scaled to the size it was last drawn at
+        scaled: Option<Bitmap>,
+        window: Option<(Rc<Window>, WindowSurface)>,
+        /// the error that closed the window
+        error: Option<anyhow::Error>,
+    }
+
+    impl Viewer {
+        fn create(&mut self, event_loop: &ActiveEventLoop) -> anyhow::Result<()> {
+            let (width, height) = (self.bitmap.width as f64, self.bitmap.height as f64);
+            let scale = (MAX_WIDTH / width).min(MAX_HEIGHT / height).min(1.0);
+            let attributes = Window::default_attributes()
+                .with_title(&self.title)
+                .with_inner_size(LogicalSize::new((width * scale).max(1.0), (height * scale).max(1.0)));
+            let window = Rc::new(event_loop.create_window(attributes)?);
+            let context = softbuffer::Context::new(window.clone()).map_err(failed)?;
+            let surface = Surface::new(&context, window.clone()).map_err(failed)?;
+            self.window = Some((window, surface));
+            Ok(())
+        }
+
+        /// Draw the bitmap fitted into the window and centered.
+        fn draw(&mut self) -> anyhow::Result<()> {
+            let Self {bitmap, scaled, window, ..} = self;
+            let Some((window, surface)) = window else {
+                return Ok(());
+            };
+            let size = window.inner_size();
+            let (Some(width), Some(height)) = (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) else {
+                return Ok(());
+            };
+            surface.resize(width, height).map_err(failed)?;
+            let (width, height) = (width.get(), height.get());
+            let scale = (width as f64 / bitmap.width as f64).min(height as f64 / bitmap.height as f64);
+            let fitted_width = ((bitmap.width as f64 * scale).round() as u32).clamp(1, width);
+            let fitted_height = ((bitmap.height as f64 * scale).round() as u32).clamp(1, height);
+            // 只在窗口大小改变时重新缩放。
+            let scaled = match scaled {
+                Some(s) if (s.width, s.height) == (fitted_width, fitted_height) => s,
+                _ => scaled.insert(bitmap.resize(fitted_width, fitted_height)),
+            };
+            let left = (width - fitted_width) / 2;
+            let top = (height - fitted_height) / 2;
+            let mut buffer = surface.buffer_mut().map_err(failed)?;
+            buffer.fill(BACKGROUND << 16 | BACKGROUND << 8 | BACKGROUND);
+            for y in 0..fitted_height {
+                let row = ((top + y) * width + left) as usize;
+                for x in 0..fitted_width {
+                    let [r, g, b, a] = scaled.get(x as usize, y as usize);
+                    let blend = |c: u8| (c as u32 * a as u32 + BACKGROUND * (255 - a as u32)) / 255;
+                    buffer[row + x as usize] = blend(r) << 16 | blend(g) << 8 | blend(b);
+                }
+            }
+            buffer.present().map_err(failed)?;
+            Ok(())
+        }
+
+        /// Close the window, ending the run of the event loop.
+        fn close(&mut self, event_loop: &ActiveEventLoop) {
+            self.window = None;
+            event_loop.exit();
+        }
+    }
+
+    impl ApplicationHandler for Viewer {
+        fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+            if self.window.is_some() {
+                return;
+            }
+            if let Err(e) = self.create(event_loop) {
+                self.error = Some(e);
+                event_loop.exit();
+            }
+        }
+
+        fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+            match event {
+                WindowEvent::CloseRequested => self.close(event_loop),
+                WindowEvent::KeyboardInput {event: KeyEvent {logical_key, state: ElementState::Pressed, ..}, ..} => {
+                    if matches!(logical_key.as_ref(), Key::Named(NamedKey::Escape) | Key::Character("q")) {
+                        self.close(event_loop);
+                    }
+                }
+                WindowEvent::Resized(_) => {
+                    if let Some((window, _)) = &self.window {
+                        window.request_redraw();
+                    }
+                }
+                WindowEvent::RedrawRequested => {
+                    if let Err(e) = self.draw() {
+                        self.error = Some(e);
+                        self.close(event_loop);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}